@@ -9,6 +9,7 @@ use std::error::Error;
 use std::i32;
 
 use hound::WavReader;
+use vox_box::resample::{ResampleQuality, Resampler};
 use vox_box::spectrum::Resonance;
 use vox_box::periodic::{Hanning, Pitched};
 use sample::{window, ToSampleSlice};
@@ -47,6 +48,7 @@ fn go() -> Result<(), Box<dyn Error>> {
     // let sig = samples.from_hz_to_hz(linear, sample_rate, new_sample_rate);
     // let len_upper_bound = sig.size_hint().1.unwrap();
 
+    let resampler = Resampler::new(sample_rate, new_sample_rate, ResampleQuality::Balanced)?;
     let n_coeffs = 13;
     let bin = (new_sample_rate * 0.05).ceil() as usize;
     let hop = (new_sample_rate * 0.01).ceil() as usize;
@@ -69,10 +71,10 @@ fn go() -> Result<(), Box<dyn Error>> {
             frame_buffer.push(s[0]); 
         }
         let pitch: f64 = frame_buffer.to_sample_slice().pitch::<Hanning>(new_sample_rate, 0.2, 0.05, 1.0, 50., 200.)[0].frequency;
-        let mut resample_buf: Vec<f64> = vec![0f64; (resample_ratio * frame_buffer.len() as f64).ceil() as usize];
+        let mut resample_buf: Vec<f64> = vec![0f64; resampler.output_len(frame_buffer.len())];
 
         vox_box::find_formants(&mut frame_buffer[..], new_sample_rate, 
-                               resample_ratio, &mut resample_buf[..],
+                               Some(&resampler), &mut resample_buf[..],
                                n_coeffs, &mut work[..], &mut complex_work[..],
                                &mut formants[..]).unwrap();
         all_formants.push(formants.clone());
@@ -0,0 +1,61 @@
+//! Feeds arbitrary sizes and samples, including NaN and infinity, to the validated entry points.
+//! None of them may panic; bad input has to come back as an error.
+//!
+//! Run with `cargo fuzz run validated` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use num_complex::Complex;
+
+use vox_box::periodic::Autocorrelate;
+use vox_box::polynomial::Polynomial;
+use vox_box::spectrum::{dct_mut, Resonance, LPC};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let n_coeffs = data[0] as usize % 24;
+    let work_len = data[1] as usize;
+    let samples: Vec<f64> = data[2..]
+        .chunks_exact(8)
+        .map(|c| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(c);
+            f64::from_le_bytes(bytes)
+        })
+        .collect();
+
+    let _ = samples.lpc(n_coeffs);
+    let _ = samples.lpc_praat(n_coeffs);
+
+    let mut coeffs = vec![0f64; n_coeffs];
+    let mut work = vec![0f64; work_len];
+    let _ = samples.lpc_praat_mut(n_coeffs, &mut coeffs[..], &mut work[..]);
+    let _ = dct_mut(&samples[..], &mut work[..]);
+
+    let mut poly: Vec<Complex<f64>> = samples.iter().map(|s| Complex::new(*s, 0.)).collect();
+    let _ = poly.find_roots();
+    let mut complex_work = vec![Complex::new(0f64, 0.); work_len];
+    let _ = poly.find_roots_mut(&mut complex_work[..]);
+
+    let _ = samples.autocorrelate(n_coeffs + work_len);
+
+    let mut buf = samples.clone();
+    let mut resampled_buf = vec![0f64; work_len];
+    let mut formants: Vec<Resonance<f64>> = vox_box::MALE_FORMANT_ESTIMATES
+        .iter()
+        .map(|f| Resonance::new(*f, 1.0))
+        .collect();
+    let _ = vox_box::find_formants(
+        &mut buf[..],
+        8000.,
+        1.0,
+        &mut resampled_buf[..],
+        n_coeffs,
+        &mut work[..],
+        &mut complex_work[..],
+        &mut formants[..],
+    );
+});
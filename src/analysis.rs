@@ -0,0 +1,455 @@
+//! A one-call analysis of a whole signal, for when the individual analyzers are more control
+//! than you need:
+//!
+//! ```
+//! let signal: Vec<f64> = (0..8000).map(|n| (n as f64 * 0.12).sin() * 0.5).collect();
+//! let analysis = vox_box::analyze(&signal, 8000., vox_box::AnalyzeOptions::default()).unwrap();
+//! println!("pitch: {:?}", analysis.pitch);
+//! ```
+
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+#[cfg(any(feature = "formant", feature = "mfcc"))]
+use crate::features::FeatureMatrix;
+#[cfg(feature = "formant")]
+use crate::formant::FormantFinder;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::segment::{centered_frame, SegmentLabel, Segmenter};
+#[cfg(feature = "formant")]
+use crate::spectrum::Resonance;
+#[cfg(feature = "mfcc")]
+use crate::spectrum::{MelFilterBank, RealFft};
+use crate::units::{Db, Hz, Seconds};
+use crate::vad::SohnVad;
+use crate::validate;
+use crate::waves::{to_f64_samples, WindowType};
+
+/// Power floor for the intensity, so that digital silence comes out at -120 dB.
+const MIN_POWER: f64 = 1.0e-12;
+
+/// Settings for `analyze`. The defaults suit adult speech.
+#[derive(Clone, Debug)]
+pub struct AnalyzeOptions {
+    /// Length of the frames for intensity, formants and MFCCs
+    pub frame_duration: Seconds,
+    /// Time between frames
+    pub hop_duration: Seconds,
+    pub window: WindowType,
+    pub min_pitch: Hz,
+    pub max_pitch: Hz,
+    /// Normalized autocorrelation above which a speech frame counts as voiced
+    pub voicing_threshold: f64,
+    /// Formants are looked for below this frequency
+    #[cfg(feature = "formant")]
+    pub max_formant: Hz,
+    #[cfg(feature = "formant")]
+    pub lpc_order: usize,
+    /// Starting estimates for the formant tracker, in Hz. One formant is tracked per estimate.
+    #[cfg(feature = "formant")]
+    pub formant_estimates: Vec<f64>,
+    #[cfg(feature = "mfcc")]
+    pub n_mfcc: usize,
+    /// Range covered by the mel filterbank
+    #[cfg(feature = "mfcc")]
+    pub mfcc_range: (Hz, Hz),
+}
+
+impl Default for AnalyzeOptions {
+    /// 25 ms frames every 10 ms, pitch between 75 and 600 Hz, four formants below 5500 Hz and
+    /// 13 MFCCs.
+    fn default() -> Self {
+        AnalyzeOptions {
+            frame_duration: Seconds(0.025),
+            hop_duration: Seconds(0.01),
+            window: WindowType::Hanning,
+            min_pitch: Hz(75.),
+            max_pitch: Hz(600.),
+            voicing_threshold: 0.45,
+            #[cfg(feature = "formant")]
+            max_formant: Hz(5500.),
+            #[cfg(feature = "formant")]
+            lpc_order: 10,
+            #[cfg(feature = "formant")]
+            formant_estimates: crate::MALE_FORMANT_ESTIMATES.to_vec(),
+            #[cfg(feature = "mfcc")]
+            n_mfcc: 13,
+            #[cfg(feature = "mfcc")]
+            mfcc_range: (Hz(133.), Hz(6855.)),
+        }
+    }
+}
+
+/// Time-aligned features of a signal: entry `k` of every track belongs to the frame centered at
+/// `times[k]`.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub frame_period: Seconds,
+    pub times: Vec<Seconds>,
+    pub voicing: Vec<SegmentLabel>,
+    /// Fundamental frequency in Hz, NaN where the frame isn't voiced
+    pub pitch: Vec<f64>,
+    /// Level in dB relative to full scale
+    pub intensity: Vec<Db>,
+    /// Tracked formants of each speech frame, empty for silence and frames where LPC failed
+    #[cfg(feature = "formant")]
+    pub formants: Vec<Vec<Resonance<f64>>>,
+    #[cfg(feature = "mfcc")]
+    pub mfcc: Vec<Vec<f64>>,
+}
+
+impl Analysis {
+    /// Number of frames.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// The MFCCs as a matrix, one row per frame. Frames without coefficients are NaN.
+    #[cfg(feature = "mfcc")]
+    pub fn mfcc_matrix(&self) -> FeatureMatrix {
+        let n_mfcc = self.mfcc.iter().map(|m| m.len()).max().unwrap_or(0);
+        let mut matrix = FeatureMatrix::new(n_mfcc);
+        for mfcc in self.mfcc.iter() {
+            let mut row = mfcc.clone();
+            row.resize(n_mfcc, f64::NAN);
+            matrix.push_row(&row).unwrap();
+        }
+        matrix
+    }
+
+    /// Frequencies of the first `n_formants` formants as a matrix, one row per frame. Formants a
+    /// frame doesn't have are NaN.
+    #[cfg(feature = "formant")]
+    pub fn formant_matrix(&self, n_formants: usize) -> FeatureMatrix {
+        let mut matrix = FeatureMatrix::new(n_formants);
+        for formants in self.formants.iter() {
+            let row: Vec<f64> = (0..n_formants)
+                .map(|i| formants.get(i).map_or(f64::NAN, |r| r.frequency))
+                .collect();
+            matrix.push_row(&row).unwrap();
+        }
+        matrix
+    }
+}
+
+/// Analyzes pitch, voicing, intensity and, with their features enabled, formants and MFCCs of
+/// a whole signal.
+pub fn analyze<S, R: Into<Hz>>(
+    signal: &[S],
+    sample_rate: R,
+    options: AnalyzeOptions,
+) -> VoxBoxResult<Analysis>
+where
+    S: Sample + ToSample<f64>,
+{
+    analyze_with_progress(signal, sample_rate, options, progress::ignore)
+}
+
+/// Like `analyze`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled` if the
+/// callback breaks.
+pub fn analyze_with_progress<S, F, R: Into<Hz>>(
+    signal: &[S],
+    sample_rate: R,
+    options: AnalyzeOptions,
+    progress: F,
+) -> VoxBoxResult<Analysis>
+where
+    S: Sample + ToSample<f64>,
+    F: FnMut(Progress) -> ControlFlow<()>,
+{
+    let sample_rate = sample_rate.into().0;
+    analyze_frames(signal, sample_rate, &options, None, progress)
+}
+
+/// Frame settings for analyzing with `options`, once they are checked.
+pub(crate) fn frame_config(
+    sample_rate: f64,
+    options: &AnalyzeOptions,
+) -> VoxBoxResult<AnalysisConfig> {
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    if !(options.min_pitch.0 > 0. && options.min_pitch < options.max_pitch) {
+        return Err(VoxBoxError::Config(
+            "Pitch range must be positive and increasing",
+        ));
+    }
+    let config = AnalysisConfig::new(sample_rate)
+        .frame_duration(options.frame_duration)
+        .hop_duration(options.hop_duration)
+        .window(options.window);
+    config.validate()?;
+    Ok(config)
+}
+
+/// Analyzes every frame, or with `speech` given, only the frames it marks as speech. Speech
+/// frames skip the segmenter's own detector. Frames labeled silence, by either, have no pitch,
+/// formants or MFCCs, though they still have an intensity.
+pub(crate) fn analyze_frames<S, F>(
+    signal: &[S],
+    sample_rate: f64,
+    options: &AnalyzeOptions,
+    speech: Option<&[bool]>,
+    mut progress: F,
+) -> VoxBoxResult<Analysis>
+where
+    S: Sample + ToSample<f64>,
+    F: FnMut(Progress) -> ControlFlow<()>,
+{
+    validate::non_empty(signal)?;
+    let config = frame_config(sample_rate, options)?;
+    let signal = to_f64_samples(signal);
+    validate::finite(&signal)?;
+    let n_frames = signal.len().div_ceil(config.hop);
+    if speech.is_some_and(|speech| speech.len() != n_frames) {
+        return Err(VoxBoxError::Input(
+            "There must be one speech label per frame",
+        ));
+    }
+
+    // Pitch frames hold three periods of the lowest pitch
+    let pitch_frame_len = (3. * sample_rate / options.min_pitch.0).ceil() as usize;
+    let mut segmenter = Segmenter {
+        hop: config.hop,
+        frame_len: pitch_frame_len,
+        min_pitch: options.min_pitch.0,
+        max_pitch: options.max_pitch.0,
+        voicing_threshold: options.voicing_threshold,
+        vad: SohnVad::new(pitch_frame_len),
+        ..Segmenter::new(sample_rate)
+    };
+    let setup = segmenter.setup();
+    let window = config.window.window(config.frame_len);
+    let window_power: f64 = window.iter().map(|w| w * w).sum();
+
+    #[cfg(feature = "formant")]
+    let mut tracker = FormantTracker::new(options, &config)?;
+    #[cfg(feature = "mfcc")]
+    let mfcc = MfccFrames::new(options, &config)?;
+
+    let mut analysis = Analysis {
+        frame_period: config.frame_period(),
+        times: Vec::with_capacity(n_frames),
+        voicing: Vec::with_capacity(n_frames),
+        pitch: Vec::with_capacity(n_frames),
+        intensity: Vec::with_capacity(n_frames),
+        #[cfg(feature = "formant")]
+        formants: Vec::with_capacity(n_frames),
+        #[cfg(feature = "mfcc")]
+        mfcc: Vec::with_capacity(n_frames),
+    };
+    for k in 0..n_frames {
+        let (label, period) = match speech {
+            Some(speech) if !speech[k] => (SegmentLabel::Silence, 0.),
+            Some(_) => segmenter.label_speech(&signal[..], k, &setup),
+            None => segmenter.label_frame(&signal[..], k, &setup),
+        };
+        let frame = centered_frame(&signal[..], k, config.hop, config.frame_len);
+        let windowed: Vec<f64> = frame
+            .iter()
+            .zip(window.iter())
+            .map(|(s, w)| s * w)
+            .collect();
+        let power = if window_power > 0. {
+            windowed.iter().map(|s| s * s).sum::<f64>() / window_power
+        } else {
+            0.
+        };
+
+        analysis.times.push(Seconds(
+            (k * config.hop + config.hop / 2) as f64 / sample_rate,
+        ));
+        analysis.voicing.push(label);
+        analysis
+            .pitch
+            .push(if label == SegmentLabel::Voiced && period > 0. {
+                sample_rate / period
+            } else {
+                f64::NAN
+            });
+        analysis
+            .intensity
+            .push(Db::from_power(power.max(MIN_POWER)));
+        #[cfg(feature = "formant")]
+        analysis.formants.push(if label == SegmentLabel::Silence {
+            Vec::new()
+        } else {
+            tracker.next(frame)
+        });
+        #[cfg(feature = "mfcc")]
+        analysis.mfcc.push(if label == SegmentLabel::Silence {
+            Vec::new()
+        } else {
+            mfcc.next(&windowed)
+        });
+
+        progress::report(&mut progress, k + 1, n_frames)?;
+    }
+    Ok(analysis)
+}
+
+/// Formants of frame after frame, each frame's search starting from the last one's.
+#[cfg(feature = "formant")]
+struct FormantTracker {
+    finder: FormantFinder,
+    estimates: Vec<Resonance<f64>>,
+}
+
+#[cfg(feature = "formant")]
+impl FormantTracker {
+    fn new(options: &AnalyzeOptions, config: &AnalysisConfig) -> VoxBoxResult<Self> {
+        Ok(FormantTracker {
+            finder: FormantFinder::from_config(config, options.max_formant, options.lpc_order)?,
+            estimates: options
+                .formant_estimates
+                .iter()
+                .map(|f| Resonance::new(*f, 1.))
+                .collect(),
+        })
+    }
+
+    /// Updates the tracked formants with the next frame. Returns no formants if LPC fails, as
+    /// it does on frames that are too short, and keeps the previous estimates for the next one.
+    fn next(&mut self, mut frame: Vec<f64>) -> Vec<Resonance<f64>> {
+        match self.finder.find(&mut frame[..], &mut self.estimates[..]) {
+            Ok(()) => self.estimates.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// MFCCs of frame after frame, through one filterbank and FFT built for the frame length.
+#[cfg(feature = "mfcc")]
+struct MfccFrames {
+    fft: RealFft,
+    filterbank: MelFilterBank,
+    n_mfcc: usize,
+}
+
+#[cfg(feature = "mfcc")]
+impl MfccFrames {
+    /// `n_mfcc` HTK filters over `mfcc_range`, clamped to Nyquist.
+    fn new(options: &AnalyzeOptions, config: &AnalysisConfig) -> VoxBoxResult<Self> {
+        let (low, high) = options.mfcc_range;
+        let nyquist = config.sample_rate.0 / 2.;
+        Ok(MfccFrames {
+            fft: RealFft::new(config.frame_len),
+            filterbank: MelFilterBank::new(
+                options.n_mfcc,
+                config.frame_len,
+                (low.0.max(0.), high.0.min(nyquist)),
+                config.sample_rate,
+            )?,
+            n_mfcc: options.n_mfcc,
+        })
+    }
+
+    fn next(&self, windowed: &[f64]) -> Vec<f64> {
+        let power = self.fft.power_spectrum(windowed);
+        self.filterbank
+            .mfcc(&power, self.n_mfcc)
+            .unwrap_or_else(|_| vec![f64::NAN; self.n_mfcc])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_analyze() {
+        let sample_rate = 8000.;
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+        let background = Normal::new(0., 0.001);
+        // 0.3 s of background, then 0.5 s of a 120 Hz buzz with a strong third harmonic
+        let signal: Vec<f64> = (0..6400)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                let noise = background.ind_sample(&mut rng);
+                if t < 0.3 {
+                    noise
+                } else {
+                    noise
+                        + (1..20)
+                            .map(|h| {
+                                let gain = if h == 3 { 1. } else { 0.2 / h as f64 };
+                                (2. * PI * 120. * h as f64 * t).sin() * gain * 0.3
+                            })
+                            .sum::<f64>()
+                }
+            })
+            .collect();
+
+        let analysis = analyze(&signal, sample_rate, AnalyzeOptions::default()).unwrap();
+        assert_eq!(analysis.len(), 80);
+        assert_eq!(analysis.pitch.len(), analysis.len());
+        assert_eq!(analysis.intensity.len(), analysis.len());
+        assert!((analysis.times[50].0 - 0.505).abs() < 1.0e-9);
+
+        for k in 5..25 {
+            assert_eq!(analysis.voicing[k], SegmentLabel::Silence);
+            assert!(analysis.pitch[k].is_nan());
+        }
+        for k in 40..75 {
+            println!(
+                "{:?} {} {}",
+                analysis.voicing[k], analysis.pitch[k], analysis.intensity[k]
+            );
+            assert_eq!(analysis.voicing[k], SegmentLabel::Voiced);
+            assert!((analysis.pitch[k] - 120.).abs() < 2.);
+            assert!(analysis.intensity[k].0 > analysis.intensity[10].0 + 30.);
+        }
+
+        #[cfg(feature = "formant")]
+        {
+            assert!(analysis.formants[10].is_empty());
+            let f1 = analysis.formants[60][0].frequency;
+            println!("formants: {:?}", analysis.formants[60]);
+            assert!((f1 - 360.).abs() < 60.);
+            let formants = analysis.formant_matrix(2);
+            assert_eq!((formants.n_rows(), formants.n_cols()), (80, 2));
+            assert_eq!(formants.row(60).unwrap()[0], f1);
+            assert!(formants.row(10).unwrap()[0].is_nan());
+        }
+        #[cfg(feature = "mfcc")]
+        {
+            assert!(
+                analysis.mfcc[60].iter().all(|c| c.is_finite()) && analysis.mfcc[60].len() == 13
+            );
+            assert!(analysis.mfcc[10].is_empty());
+            let mfcc = analysis.mfcc_matrix();
+            assert_eq!((mfcc.n_rows(), mfcc.n_cols()), (80, 13));
+            assert_eq!(mfcc.row(60).unwrap(), &analysis.mfcc[60][..]);
+            assert!(mfcc.row(10).unwrap()[0].is_nan());
+        }
+    }
+
+    #[test]
+    fn test_analyze_bad_input() {
+        assert!(analyze::<f64, f64>(&[], 8000., AnalyzeOptions::default()).is_err());
+        assert!(analyze(&[0f64; 100], 0., AnalyzeOptions::default()).is_err());
+        assert!(analyze(&[f64::NAN; 100], 8000., AnalyzeOptions::default()).is_err());
+        let options = AnalyzeOptions {
+            min_pitch: Hz(500.),
+            max_pitch: Hz(100.),
+            ..AnalyzeOptions::default()
+        };
+        assert!(analyze(&[0f64; 100], 8000., options).is_err());
+        #[cfg(feature = "mfcc")]
+        {
+            let options = AnalyzeOptions {
+                mfcc_range: (Hz(5000.), Hz(6855.)),
+                ..AnalyzeOptions::default()
+            };
+            assert!(analyze(&[0f64; 100], 8000., options).is_err());
+        }
+    }
+}
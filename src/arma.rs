@@ -0,0 +1,281 @@
+//! Pole-zero (ARMA) modeling, for spectra that have antiformants as well as formants.
+//!
+//! All-pole LPC can only build a spectrum out of peaks, so the zeros that the nasal cavity adds
+//! to nasals and nasalized vowels get smeared into the poles. `PoleZero` fits both, treating a
+//! frame as the impulse response of a filter `B(z) / A(z)`, by the Steiglitz-McBride method.
+
+use num::{Float, FromPrimitive};
+use num_complex::Complex;
+
+use crate::error::*;
+use crate::polynomial::Polynomial;
+use crate::spectrum::{Resonance, ToResonance};
+use crate::validate;
+
+/// A zero pair of a filter: a dip in the spectrum rather than a peak.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Antiresonance<T> {
+    /// Center frequency in Hz
+    pub frequency: T,
+    /// 3 dB bandwidth of the dip in Hz
+    pub bandwidth: T,
+}
+
+impl<T: Float + FromPrimitive> Antiresonance<T> {
+    /// The antiresonance of a zero, found as for a pole by `Resonance::from_root`.
+    pub fn from_root(root: &Complex<T>, sample_rate: T) -> Option<Antiresonance<T>> {
+        Resonance::from_root(root, sample_rate).map(|r| Antiresonance {
+            frequency: r.frequency,
+            bandwidth: r.bandwidth,
+        })
+    }
+}
+
+/// The filter `B(z) / A(z)` fitted by `PoleZero::pole_zero`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoleZeroModel<T> {
+    /// `b[0] + b[1] z^-1 + ... + b[q] z^-q`
+    pub numerator: Vec<T>,
+    /// `A(z) = 1 + a[0] z^-1 + ... + a[p-1] z^-p`, without the leading one as in LPC
+    pub denominator: Vec<T>,
+}
+
+impl<T: Float + FromPrimitive> PoleZeroModel<T> {
+    /// The first `len` samples of the impulse response of the filter.
+    pub fn impulse_response(&self, len: usize) -> Vec<T> {
+        let numerator: Vec<f64> = self.numerator.iter().map(|b| b.to_f64().unwrap()).collect();
+        let denominator: Vec<f64> = self
+            .denominator
+            .iter()
+            .map(|a| a.to_f64().unwrap())
+            .collect();
+        let mut impulse = vec![0.; len];
+        if len > 0 {
+            impulse[0] = 1.;
+        }
+        filter(&numerator, &denominator, &impulse)
+            .into_iter()
+            .map(|h| T::from(h).unwrap())
+            .collect()
+    }
+
+    /// Resonances of the poles, sorted by frequency.
+    pub fn resonances(&self, sample_rate: T) -> VoxBoxResult<Vec<Resonance<T>>> {
+        let poly = ascending(&[T::one()], &self.denominator);
+        Ok(roots(poly)?.to_resonance(sample_rate))
+    }
+
+    /// Antiresonances of the zeros, sorted by frequency.
+    pub fn antiresonances(&self, sample_rate: T) -> VoxBoxResult<Vec<Antiresonance<T>>> {
+        let poly = ascending(&[], &self.numerator);
+        Ok(roots(poly)?
+            .to_resonance(sample_rate)
+            .into_iter()
+            .map(|r| Antiresonance {
+                frequency: r.frequency,
+                bandwidth: r.bandwidth,
+            })
+            .collect())
+    }
+}
+
+/// The coefficients of a polynomial in `z^-1` as one in `z`, in ascending powers as
+/// `Polynomial` wants them.
+fn ascending<T: Float>(leading: &[T], coeffs: &[T]) -> Vec<Complex<T>> {
+    leading
+        .iter()
+        .chain(coeffs.iter())
+        .rev()
+        .map(|c| Complex::new(*c, T::zero()))
+        .collect()
+}
+
+fn roots<T: Float + FromPrimitive>(poly: Vec<Complex<T>>) -> VoxBoxResult<Vec<Complex<T>>> {
+    if poly.len() < 2 {
+        return Ok(Vec::new());
+    }
+    poly.find_roots()
+}
+
+pub trait PoleZero<T> {
+    /// Fits a filter with `n_poles` poles and `n_zeros` zeros whose impulse response matches
+    /// the frame. The first estimate minimizes the equation error, as Prony's method does; each
+    /// of the `iterations` after it prefilters by the last denominator, which converges towards
+    /// the least-squares fit of the impulse response itself.
+    ///
+    /// The frame must be longer than `n_poles + n_zeros + 1`.
+    fn pole_zero(
+        &self,
+        n_poles: usize,
+        n_zeros: usize,
+        iterations: usize,
+    ) -> VoxBoxResult<PoleZeroModel<T>>;
+}
+
+impl<T: Float + FromPrimitive> PoleZero<T> for [T] {
+    fn pole_zero(
+        &self,
+        n_poles: usize,
+        n_zeros: usize,
+        iterations: usize,
+    ) -> VoxBoxResult<PoleZeroModel<T>> {
+        if self.len() <= n_poles + n_zeros + 1 {
+            return Err(VoxBoxError::Input(
+                "Frame must be longer than n_poles + n_zeros + 1",
+            ));
+        }
+        validate::finite(self)?;
+        let output: Vec<f64> = self.iter().map(|s| s.to_f64().unwrap()).collect();
+        let mut impulse = vec![0.; output.len()];
+        impulse[0] = 1.;
+
+        let mut denominator = vec![0.; n_poles];
+        let mut numerator = vec![0.; n_zeros + 1];
+        for _ in 0..=iterations {
+            let prefiltered_output = filter(&[1.], &denominator, &output);
+            let prefiltered_input = filter(&[1.], &denominator, &impulse);
+            if prefiltered_output.iter().any(|y| !y.is_finite()) {
+                // An unstable denominator; keep the last fit
+                break;
+            }
+
+            // y[n] = -a[0] y[n-1] - ... + b[0] x[n] + ... with both prefiltered
+            let delayed = |signal: &[f64], n: usize, k: usize| {
+                if n >= k {
+                    signal[n - k]
+                } else {
+                    0.
+                }
+            };
+            let rows: Vec<Vec<f64>> = (0..output.len())
+                .map(|n| {
+                    (1..=n_poles)
+                        .map(|k| -delayed(&prefiltered_output, n, k))
+                        .chain((0..=n_zeros).map(|k| delayed(&prefiltered_input, n, k)))
+                        .collect()
+                })
+                .collect();
+            let solution = least_squares(&rows, &prefiltered_output)
+                .ok_or(VoxBoxError::LPC("Pole-zero fit is singular"))?;
+            denominator = solution[..n_poles].to_vec();
+            numerator = solution[n_poles..].to_vec();
+        }
+
+        Ok(PoleZeroModel {
+            numerator: numerator.into_iter().map(|b| T::from(b).unwrap()).collect(),
+            denominator: denominator
+                .into_iter()
+                .map(|a| T::from(a).unwrap())
+                .collect(),
+        })
+    }
+}
+
+/// Runs `input` through `B(z) / A(z)`, with `denominator` lacking the leading one.
+fn filter(numerator: &[f64], denominator: &[f64], input: &[f64]) -> Vec<f64> {
+    let mut output = vec![0.; input.len()];
+    for n in 0..input.len() {
+        let feedforward = numerator
+            .iter()
+            .take(n + 1)
+            .enumerate()
+            .fold(0., |acc, (k, b)| acc + b * input[n - k]);
+        output[n] = denominator
+            .iter()
+            .take(n)
+            .enumerate()
+            .fold(feedforward, |acc, (k, a)| acc - a * output[n - k - 1]);
+    }
+    output
+}
+
+/// Solves `rows * x = targets` in the least-squares sense, by the normal equations. `None` if
+/// they are singular.
+pub(crate) fn least_squares(rows: &[Vec<f64>], targets: &[f64]) -> Option<Vec<f64>> {
+    let n = rows.first().map_or(0, |r| r.len());
+    // Augmented normal equations [R^T R | R^T t]
+    let mut system = vec![vec![0.; n + 1]; n];
+    for (row, t) in rows.iter().zip(targets.iter()) {
+        for i in 0..n {
+            for j in 0..n {
+                system[i][j] += row[i] * row[j];
+            }
+            system[i][n] += row[i] * t;
+        }
+    }
+
+    // Gaussian elimination with partial pivoting
+    let scale = (0..n).fold(0f64, |m, i| m.max(system[i][i].abs()));
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| {
+            system[a][col]
+                .abs()
+                .partial_cmp(&system[b][col].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+        if system[pivot][col].abs() <= scale * 1.0e-14 {
+            return None;
+        }
+        system.swap(col, pivot);
+        for row in col + 1..n {
+            let (upper, lower) = system.split_at_mut(row);
+            let (pivot_row, target) = (&upper[col], &mut lower[0]);
+            let factor = target[col] / pivot_row[col];
+            for (t, p) in target[col..].iter_mut().zip(pivot_row[col..].iter()) {
+                *t -= factor * p;
+            }
+        }
+    }
+    let mut solution = vec![0.; n];
+    for row in (0..n).rev() {
+        let known = (row + 1..n).fold(0., |acc, k| acc + system[row][k] * solution[k]);
+        solution[row] = (system[row][n] - known) / system[row][row];
+    }
+    Some(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn pair(frequency: f64, radius: f64, sample_rate: f64) -> [f64; 2] {
+        let w = 2. * PI * frequency / sample_rate;
+        [-2. * radius * w.cos(), radius * radius]
+    }
+
+    #[test]
+    fn test_pole_zero() {
+        // Formants at 500 and 1500 Hz, an antiformant at 1000 Hz
+        let (f1, f2) = (pair(500., 0.97, 8000.), pair(1500., 0.95, 8000.));
+        let zero = pair(1000., 0.9, 8000.);
+        let model = PoleZeroModel {
+            numerator: vec![1., zero[0], zero[1]],
+            denominator: vec![
+                f1[0] + f2[0],
+                f1[1] + f2[1] + f1[0] * f2[0],
+                f1[0] * f2[1] + f1[1] * f2[0],
+                f1[1] * f2[1],
+            ],
+        };
+        let response = model.impulse_response(200);
+        let fitted = response.pole_zero(4, 2, 3).unwrap();
+        println!("fitted: {:?}", fitted);
+
+        let resonances = fitted.resonances(8000.).unwrap();
+        let antiresonances = fitted.antiresonances(8000.).unwrap();
+        println!("resonances: {:?}", resonances);
+        println!("antiresonances: {:?}", antiresonances);
+        assert!((resonances[0].frequency - 500.).abs() < 1e-6);
+        assert!((resonances[1].frequency - 1500.).abs() < 1e-6);
+        assert!((resonances[0].bandwidth - -(0.97f64).ln() * 8000. / PI).abs() < 1e-6);
+        assert_eq!(antiresonances.len(), 1);
+        assert!((antiresonances[0].frequency - 1000.).abs() < 1e-6);
+
+        // The all-zero and all-pole corners
+        let fir = [1., 0.5, 0.25, 0., 0., 0.].pole_zero(0, 2, 0).unwrap();
+        assert_eq!(fir.numerator, vec![1., 0.5, 0.25]);
+        assert!(response.pole_zero(100, 100, 0).is_err());
+        assert!([0f64; 20].pole_zero(2, 2, 0).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Acoustic change detection by the Bayesian Information Criterion: a window of feature frames
+//! is split wherever two full-covariance Gaussians, one either side, fit it better than one
+//! Gaussian does by more than the cost of the extra parameters. Changes between speakers show
+//! up this way, so the segments between change points are a first step toward diarization.
+//!
+//! ```
+//! use vox_box::bic::Bic;
+//! use vox_box::features::FeatureMatrix;
+//!
+//! let rows: Vec<[f64; 1]> = (0..200)
+//!     .map(|n| [(n % 7) as f64 + if n < 120 { 0. } else { 20. }])
+//!     .collect();
+//! let frames = FeatureMatrix::from_rows(1, &rows).unwrap();
+//! assert_eq!(Bic::new().change_points(&frames).unwrap(), vec![120]);
+//! ```
+
+use std::ops::Range;
+
+use crate::distance::cholesky;
+use crate::error::*;
+use crate::features::FeatureMatrix;
+use crate::units::Seconds;
+
+/// Settings for finding change points, with window sizes in frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bic {
+    penalty: f64,
+    min_window: usize,
+    max_window: usize,
+    step: usize,
+    min_segment: usize,
+}
+
+impl Default for Bic {
+    fn default() -> Self {
+        Bic::new()
+    }
+}
+
+impl Bic {
+    pub fn new() -> Self {
+        Bic {
+            penalty: 1.,
+            min_window: 100,
+            max_window: 500,
+            step: 10,
+            min_segment: 25,
+        }
+    }
+
+    /// Weight of the penalty for the second Gaussian's parameters. Higher values find fewer
+    /// changes; 1 is the criterion proper.
+    pub fn penalty(mut self, penalty: f64) -> Self {
+        self.penalty = penalty;
+        self
+    }
+
+    /// Frames searched for a change after the last one. The window grows by `step` frames
+    /// until it holds `max_window`, then slides.
+    pub fn window(mut self, min_window: usize, max_window: usize) -> Self {
+        self.min_window = min_window;
+        self.max_window = max_window;
+        self
+    }
+
+    pub fn step(mut self, step: usize) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Fewest frames either side of a change. It should be well above the number of
+    /// coefficients per frame, or covariances estimated from so few frames are unreliable.
+    pub fn min_segment(mut self, min_segment: usize) -> Self {
+        self.min_segment = min_segment;
+        self
+    }
+
+    /// Change in BIC from splitting `frames` before frame `split`. Positive values favor a
+    /// change.
+    pub fn delta(&self, frames: &FeatureMatrix, split: usize) -> VoxBoxResult<f64> {
+        check_frames(frames)?;
+        if split == 0 || split >= frames.n_rows() {
+            return Err(VoxBoxError::Input("Split must leave frames on both sides"));
+        }
+        Ok(self.delta_in(&Window::new(frames, 0..frames.n_rows()), split))
+    }
+
+    /// Index of the first frame after each change, in order.
+    pub fn change_points(&self, frames: &FeatureMatrix) -> VoxBoxResult<Vec<usize>> {
+        self.check()?;
+        check_frames(frames)?;
+        let n = frames.n_rows();
+        let mut changes = Vec::new();
+        let mut start = 0;
+        let mut end = self.min_window.min(n);
+        while end - start >= 2 * self.min_segment {
+            let window = Window::new(frames, start..end);
+            let (split, delta) = (self.min_segment..=end - start - self.min_segment)
+                .map(|split| (split, self.delta_in(&window, split)))
+                .fold((0, f64::NEG_INFINITY), |best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                });
+            // A change found against the end of the window may lie beyond it, so the window
+            // grows until the best split is clear of the end
+            let pinned = split == end - start - self.min_segment && end < n;
+            if delta > 0. && !pinned {
+                start += split;
+                changes.push(start);
+                end = (start + self.min_window).min(n);
+            } else if end == n {
+                break;
+            } else {
+                end = (end + self.step).min(n);
+                start = start.max(end.saturating_sub(self.max_window));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Time of each change, for frames `frame_period` apart.
+    pub fn change_times(
+        &self,
+        frames: &FeatureMatrix,
+        frame_period: Seconds,
+    ) -> VoxBoxResult<Vec<Seconds>> {
+        Ok(self
+            .change_points(frames)?
+            .iter()
+            .map(|k| Seconds(*k as f64 * frame_period.0))
+            .collect())
+    }
+
+    fn check(&self) -> VoxBoxResult<()> {
+        if self.min_segment == 0 {
+            return Err(VoxBoxError::Config("Segments must be at least one frame"));
+        }
+        if self.min_window < 2 * self.min_segment {
+            return Err(VoxBoxError::Config(
+                "Window must hold two of the shortest segments",
+            ));
+        }
+        if self.max_window < self.min_window {
+            return Err(VoxBoxError::Config(
+                "Maximum window must be at least the minimum window",
+            ));
+        }
+        if self.step == 0 {
+            return Err(VoxBoxError::Config("Step must be at least one frame"));
+        }
+        if self.penalty.is_nan() || self.penalty < 0. {
+            return Err(VoxBoxError::Config("Penalty must not be negative"));
+        }
+        Ok(())
+    }
+
+    fn delta_in(&self, window: &Window, split: usize) -> f64 {
+        let (n, d) = (window.len() as f64, window.n_cols as f64);
+        let fit = 0.5
+            * (n * window.log_det(0..window.len())
+                - split as f64 * window.log_det(0..split)
+                - (n - split as f64) * window.log_det(split..window.len()));
+        fit - self.penalty * 0.5 * (d + 0.5 * d * (d + 1.)) * n.ln()
+    }
+}
+
+fn check_frames(frames: &FeatureMatrix) -> VoxBoxResult<()> {
+    if frames.n_cols() == 0 {
+        return Err(VoxBoxError::Input("Frames must have at least one value"));
+    }
+    if frames.as_slice().iter().any(|x| !x.is_finite()) {
+        return Err(VoxBoxError::Input("Frames must be finite"));
+    }
+    Ok(())
+}
+
+/// Running sums of a window's frames and of their outer products, centered on the window's
+/// mean, so that the covariance of any stretch of it takes work independent of its length.
+struct Window {
+    n_cols: usize,
+    sums: Vec<f64>,
+    products: Vec<f64>,
+    /// Added to every variance, so that constant or too few frames still give a covariance
+    /// with a determinant
+    ridge: f64,
+}
+
+impl Window {
+    fn new(frames: &FeatureMatrix, rows: Range<usize>) -> Self {
+        let d = frames.n_cols();
+        let n = rows.len();
+        let mut mean = vec![0.; d];
+        for k in rows.clone() {
+            for (m, x) in mean.iter_mut().zip(frames.row(k).unwrap()) {
+                *m += x / n as f64;
+            }
+        }
+        let mut sums = vec![0.; (n + 1) * d];
+        let mut products = vec![0.; (n + 1) * d * d];
+        for (t, k) in rows.enumerate() {
+            let x: Vec<f64> = frames
+                .row(k)
+                .unwrap()
+                .iter()
+                .zip(&mean)
+                .map(|(x, m)| x - m)
+                .collect();
+            for i in 0..d {
+                sums[(t + 1) * d + i] = sums[t * d + i] + x[i];
+                for j in 0..d {
+                    products[(t + 1) * d * d + i * d + j] =
+                        products[t * d * d + i * d + j] + x[i] * x[j];
+                }
+            }
+        }
+        let variance =
+            (0..d).map(|i| products[n * d * d + i * d + i]).sum::<f64>() / (n * d) as f64;
+        Window {
+            n_cols: d,
+            sums,
+            products,
+            ridge: 1.0e-6 * variance + f64::MIN_POSITIVE,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sums.len() / self.n_cols - 1
+    }
+
+    /// Natural log of the determinant of the covariance of frames `rows` of the window.
+    fn log_det(&self, rows: Range<usize>) -> f64 {
+        let d = self.n_cols;
+        let n = rows.len() as f64;
+        let mean: Vec<f64> = (0..d)
+            .map(|i| (self.sums[rows.end * d + i] - self.sums[rows.start * d + i]) / n)
+            .collect();
+        let mut covariance = vec![0.; d * d];
+        for i in 0..d {
+            for j in 0..d {
+                let product = self.products[rows.end * d * d + i * d + j]
+                    - self.products[rows.start * d * d + i * d + j];
+                covariance[i * d + j] = product / n - mean[i] * mean[j];
+            }
+            covariance[i * d + i] += self.ridge;
+        }
+        match cholesky(&covariance, d) {
+            Some(l) => 2. * (0..d).map(|i| l[i * d + i].ln()).sum::<f64>(),
+            None => self.ridge.ln() * d as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+
+    /// Frames from one 2-dimensional Gaussian per `(mean, deviation, frames)` segment.
+    fn segments(specs: &[(f64, f64, usize)]) -> FeatureMatrix {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut frames = FeatureMatrix::new(2);
+        for (mean, deviation, n) in specs.iter() {
+            let normal = Normal::new(*mean, *deviation);
+            for _ in 0..*n {
+                frames
+                    .push_row(&[normal.ind_sample(&mut rng), -normal.ind_sample(&mut rng)])
+                    .unwrap();
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn test_change_points() {
+        // A change of mean, then a change of spread alone
+        let frames = segments(&[(0., 1., 300), (3., 1., 250), (3., 4., 300)]);
+        let changes = Bic::new().change_points(&frames).unwrap();
+        println!("{:?}", changes);
+        assert_eq!(changes.len(), 2);
+        assert!((changes[0] as isize - 300).abs() <= 5);
+        assert!((changes[1] as isize - 550).abs() <= 10);
+
+        let times = Bic::new().change_times(&frames, Seconds(0.01)).unwrap();
+        assert!((times[0].0 - changes[0] as f64 * 0.01).abs() < 1e-12);
+
+        // One speaker throughout
+        let steady = segments(&[(1., 2., 1000)]);
+        assert!(Bic::new().change_points(&steady).unwrap().is_empty());
+        assert!(Bic::new().delta(&steady, 500).unwrap() < 0.);
+        assert!(Bic::new().delta(&frames, 300).unwrap() > 0.);
+
+        // A heavy enough penalty hides the changes
+        assert!(Bic::new()
+            .penalty(1000.)
+            .change_points(&frames)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_bic_errors() {
+        let frames = segments(&[(0., 1., 100)]);
+        assert!(Bic::new().min_segment(0).change_points(&frames).is_err());
+        assert!(Bic::new().window(40, 500).change_points(&frames).is_err());
+        assert!(Bic::new().window(100, 50).change_points(&frames).is_err());
+        assert!(Bic::new().step(0).change_points(&frames).is_err());
+        assert!(Bic::new().penalty(-1.).change_points(&frames).is_err());
+        assert!(Bic::new().delta(&frames, 0).is_err());
+        assert!(Bic::new().delta(&frames, 100).is_err());
+        let mut nan = frames.clone();
+        nan.push_row(&[f64::NAN, 0.]).unwrap();
+        assert!(Bic::new().change_points(&nan).is_err());
+
+        // Too few frames for a change is not an error
+        assert!(Bic::new()
+            .change_points(&segments(&[(0., 1., 10)]))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+//! Real and complex cepstra, and the signals they come from.
+//!
+//! The real cepstrum keeps only the log magnitude spectrum, so it is even and its inverse is
+//! the minimum phase signal with that magnitude. The complex cepstrum keeps the unwrapped phase
+//! as well, and its inverse gives back the frame itself.
+
+use num::Float;
+use num_complex::Complex;
+use std::f64::consts::{LOG10_E, PI};
+
+use crate::error::*;
+use crate::spectrum::RealFft;
+
+/// Power below the spectrum's peak, as a ratio, at which the log spectrum is floored so that
+/// empty bins don't swamp the cepstrum with -inf.
+const LOG_FLOOR: f64 = 1.0e-12;
+
+/// Real cepstrum of a frame, zero-padded to `fft_len`: the inverse Fourier transform of its log
+/// magnitude spectrum. Returns `fft_len / 2 + 1` quefrency bins, one per sample of lag. A silent
+/// frame gives all zeros.
+pub fn real_cepstrum<T: Float>(frame: &[T], fft_len: usize) -> VoxBoxResult<Vec<f64>> {
+    if fft_len == 0 || fft_len < frame.len() {
+        return Err(VoxBoxError::Config(
+            "FFT length must be nonzero and at least the frame length",
+        ));
+    }
+    Ok(real_cepstrum_of(&RealFft::new(fft_len), frame))
+}
+
+/// `real_cepstrum` with a transform that has already been planned, whose length must be at
+/// least the frame's.
+pub(crate) fn real_cepstrum_of<T: Float>(fft: &RealFft, frame: &[T]) -> Vec<f64> {
+    let fft_len = fft.len();
+    let frame: Vec<f64> = frame.iter().map(|s| s.to_f64().unwrap_or(0.)).collect();
+    let spectrum = fft.transform(&frame);
+    let peak = spectrum.iter().map(|bin| bin.norm_sqr()).fold(0., f64::max);
+    if peak <= 0. || !peak.is_finite() {
+        return vec![0.; fft_len / 2 + 1];
+    }
+    let log_magnitude: Vec<Complex<f64>> = spectrum
+        .iter()
+        .map(|bin| Complex::new(0.5 * bin.norm_sqr().max(peak * LOG_FLOOR).ln(), 0.))
+        .collect();
+    let mut cepstrum = fft.inverse(&log_magnitude);
+    cepstrum.truncate(fft_len / 2 + 1);
+    cepstrum
+}
+
+/// The minimum phase signal of `fft_len` samples whose real cepstrum is `cepstrum`, which must
+/// hold `fft_len / 2 + 1` quefrencies as from `real_cepstrum`.
+///
+/// A minimum phase frame comes back as it went in, up to the aliasing of its cepstrum; any
+/// other frame comes back with the same magnitude spectrum.
+pub fn inverse_real_cepstrum(cepstrum: &[f64], fft_len: usize) -> VoxBoxResult<Vec<f64>> {
+    if fft_len == 0 || cepstrum.len() != fft_len / 2 + 1 {
+        return Err(VoxBoxError::Input(
+            "Real cepstrum must hold half the FFT length plus one quefrencies",
+        ));
+    }
+    // Fold the negative quefrencies onto the positive ones, which makes the cepstrum causal
+    let folded: Vec<f64> = (0..fft_len)
+        .map(|n| {
+            if n == 0 || 2 * n == fft_len {
+                cepstrum[n]
+            } else if 2 * n < fft_len {
+                2. * cepstrum[n]
+            } else {
+                0.
+            }
+        })
+        .collect();
+    let fft = RealFft::new(fft_len);
+    let spectrum: Vec<Complex<f64>> = fft.transform(&folded).iter().map(|c| c.exp()).collect();
+    Ok(fft.inverse(&spectrum))
+}
+
+/// Spectral envelope of a frame by cepstral smoothing, in dB: its log magnitude spectrum, zero-
+/// padded to `fft_len`, with the real cepstrum cut off at `cutoff` quefrencies. Returns
+/// `fft_len / 2 + 1` bins. A silent frame gives a flat envelope at 0 dB.
+///
+/// With a cutoff shorter than the pitch period in samples, the harmonics are smoothed away and
+/// the resonances of the vocal tract remain. Unlike the LPC envelope this doesn't rely on the
+/// harmonics sampling the resonances closely, so it holds up better for high voices.
+pub fn cepstral_envelope<T: Float>(
+    frame: &[T],
+    fft_len: usize,
+    cutoff: usize,
+) -> VoxBoxResult<Vec<f64>> {
+    let mut cepstrum = real_cepstrum(frame, fft_len)?;
+    for c in cepstrum.iter_mut().skip(cutoff) {
+        *c = 0.;
+    }
+    let even: Vec<f64> = (0..fft_len).map(|n| cepstrum[n.min(fft_len - n)]).collect();
+    Ok(RealFft::new(fft_len)
+        .transform(&even)
+        .iter()
+        .map(|bin| 20. * bin.re * LOG10_E)
+        .collect())
+}
+
+/// A complex cepstrum, as from `complex_cepstrum`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComplexCepstrum {
+    /// One per sample of the FFT length. Quefrencies past the middle are negative ones.
+    pub quefrencies: Vec<f64>,
+    /// Samples of circular delay taken out of the frame so that its unwrapped phase ends at
+    /// zero at Nyquist.
+    pub delay: isize,
+}
+
+/// Complex cepstrum of a frame, zero-padded to `fft_len`, which must be even: the inverse
+/// Fourier transform of its log spectrum with the phase unwrapped.
+///
+/// The log magnitude is floored as in `real_cepstrum`, so spectra with empty bins don't come
+/// back exactly from `inverse_complex_cepstrum`. Fails on a silent frame.
+pub fn complex_cepstrum<T: Float>(frame: &[T], fft_len: usize) -> VoxBoxResult<ComplexCepstrum> {
+    if fft_len < 2 || !fft_len.is_multiple_of(2) || fft_len < frame.len() {
+        return Err(VoxBoxError::Config(
+            "FFT length must be even and at least the frame length",
+        ));
+    }
+    let frame: Vec<f64> = frame.iter().map(|s| s.to_f64().unwrap_or(0.)).collect();
+    let fft = RealFft::new(fft_len);
+    let spectrum = fft.transform(&frame);
+    let peak = spectrum.iter().map(|bin| bin.norm_sqr()).fold(0., f64::max);
+    if peak <= 0. || !peak.is_finite() {
+        return Err(VoxBoxError::Input("Frame must not be silent"));
+    }
+
+    let half = fft_len / 2;
+    let mut phase = unwrap_phase(&spectrum);
+    let delay = -(phase[half] / PI).round();
+    for (k, p) in phase.iter_mut().enumerate() {
+        *p += PI * delay * k as f64 / half as f64;
+    }
+    let log_spectrum: Vec<Complex<f64>> = spectrum
+        .iter()
+        .zip(phase.iter())
+        .map(|(bin, p)| Complex::new(0.5 * bin.norm_sqr().max(peak * LOG_FLOOR).ln(), *p))
+        .collect();
+    Ok(ComplexCepstrum {
+        quefrencies: fft.inverse(&log_spectrum),
+        delay: delay as isize,
+    })
+}
+
+/// The frame whose complex cepstrum is `cepstrum`, as many samples long as it has
+/// quefrencies.
+pub fn inverse_complex_cepstrum(cepstrum: &ComplexCepstrum) -> VoxBoxResult<Vec<f64>> {
+    let fft_len = cepstrum.quefrencies.len();
+    if fft_len < 2 || !fft_len.is_multiple_of(2) {
+        return Err(VoxBoxError::Input(
+            "Complex cepstrum must have an even number of quefrencies",
+        ));
+    }
+    let half = fft_len / 2;
+    let fft = RealFft::new(fft_len);
+    let spectrum: Vec<Complex<f64>> = fft
+        .transform(&cepstrum.quefrencies)
+        .iter()
+        .enumerate()
+        .map(|(k, log)| {
+            let delay = -PI * cepstrum.delay as f64 * k as f64 / half as f64;
+            (log + Complex::new(0., delay)).exp()
+        })
+        .collect();
+    Ok(fft.inverse(&spectrum))
+}
+
+/// Phase of each bin, with jumps of more than pi between neighbours taken as wraps.
+fn unwrap_phase(spectrum: &[Complex<f64>]) -> Vec<f64> {
+    let mut offset = 0.;
+    let mut previous = 0.;
+    spectrum
+        .iter()
+        .map(|bin| {
+            let wrapped = bin.arg();
+            offset -= 2. * PI * ((wrapped - previous) / (2. * PI)).round();
+            previous = wrapped;
+            wrapped + offset
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_real_cepstrum() {
+        // A decaying exponential has all its zeros inside the unit circle
+        let frame: Vec<f64> = (0..64).map(|n| 0.8f64.powi(n)).collect();
+        let cepstrum = real_cepstrum(&frame, 1024).unwrap();
+        assert_eq!(cepstrum.len(), 513);
+        // log(1 / (1 - 0.8 / z)) = sum(0.8^n / n z^-n), split evenly between +n and -n
+        assert!((cepstrum[1] - 0.4).abs() < 1e-6);
+        assert!((cepstrum[2] - 0.16).abs() < 1e-6);
+        let back = inverse_real_cepstrum(&cepstrum, 1024).unwrap();
+        for (a, b) in back.iter().zip(frame.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        assert!(back[64..].iter().all(|s| s.abs() < 1e-6));
+        assert!(inverse_real_cepstrum(&cepstrum, 1000).is_err());
+        assert!(real_cepstrum(&frame, 32).is_err());
+        assert!(real_cepstrum(&[0f64; 0], 0).is_err());
+    }
+
+    #[test]
+    fn test_cepstral_envelope() {
+        // A 400 Hz pulse train through a resonance at 1 kHz
+        let (sample_rate, len) = (16000., 1024);
+        let (r, theta) = (
+            (-PI * 100. / sample_rate).exp(),
+            2. * PI * 1000. / sample_rate,
+        );
+        let mut frame = vec![0f64; len];
+        for n in 0..len {
+            let pulse = if n % 40 == 0 { 1. } else { 0. };
+            let past = |k: usize| if n >= k { frame[n - k] } else { 0. };
+            frame[n] = pulse + 2. * r * theta.cos() * past(1) - r * r * past(2);
+        }
+        let window = crate::waves::WindowType::Hanning.window(len);
+        for (s, w) in frame.iter_mut().zip(window.iter()) {
+            *s *= w;
+        }
+
+        let envelope = cepstral_envelope(&frame, len, 30).unwrap();
+        assert_eq!(envelope.len(), len / 2 + 1);
+        let peak = (0..envelope.len())
+            .max_by(|a, b| envelope[*a].partial_cmp(&envelope[*b]).unwrap())
+            .unwrap();
+        let bin_width = sample_rate / len as f64;
+        println!("envelope peak: {} Hz", peak as f64 * bin_width);
+        assert!((peak as f64 * bin_width - 1000.).abs() < 150.);
+        // The raw spectrum dips between harmonics, the envelope doesn't
+        let raw = cepstral_envelope(&frame, len, len / 2 + 1).unwrap();
+        let largest_step = |spectrum: &[f64]| {
+            spectrum[..256]
+                .windows(2)
+                .map(|w| (w[1] - w[0]).abs())
+                .fold(0., f64::max)
+        };
+        println!(
+            "largest steps: {} dB raw, {} dB smoothed",
+            largest_step(&raw),
+            largest_step(&envelope)
+        );
+        assert!(largest_step(&envelope) < 4.);
+        assert!(largest_step(&raw) > 10.);
+        assert!(cepstral_envelope(&[0.; 8], 8, 4)
+            .unwrap()
+            .iter()
+            .all(|e| *e == 0.));
+        assert!(cepstral_envelope(&frame, len / 2, 30).is_err());
+    }
+
+    #[test]
+    fn test_complex_cepstrum() {
+        // 1 + 0.5 / z delayed by three samples
+        let frame = [0., 0., 0., 1., 0.5];
+        let cepstrum = complex_cepstrum(&frame, 256).unwrap();
+        println!("complex cepstrum: {:?}", &cepstrum.quefrencies[..4]);
+        assert_eq!(cepstrum.delay, 3);
+        assert!(cepstrum.quefrencies[0].abs() < 1e-9);
+        assert!((cepstrum.quefrencies[1] - 0.5).abs() < 1e-9);
+        assert!((cepstrum.quefrencies[2] + 0.125).abs() < 1e-9);
+        let back = inverse_complex_cepstrum(&cepstrum).unwrap();
+        for (a, b) in back.iter().zip(frame.iter().chain(std::iter::repeat(&0.))) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        // A longer frame with no particular structure
+        let frame: Vec<f64> = (0..100)
+            .map(|n| (n as f64 * 0.3).sin() * (n as f64 / 99. * PI).sin() + 0.01 * n as f64)
+            .collect();
+        let cepstrum = complex_cepstrum(&frame, 256).unwrap();
+        let back = inverse_complex_cepstrum(&cepstrum).unwrap();
+        for (a, b) in back.iter().zip(frame.iter().chain(std::iter::repeat(&0.))) {
+            assert!((a - b).abs() < 1e-9);
+        }
+        // The real cepstrum is the even part of the complex one
+        let real = real_cepstrum(&frame, 256).unwrap();
+        for (q, r) in real.iter().enumerate() {
+            let even = 0.5 * (cepstrum.quefrencies[q] + cepstrum.quefrencies[(256 - q) % 256]);
+            assert!((r - even).abs() < 1e-9);
+        }
+
+        assert!(complex_cepstrum(&[0.; 8], 8).is_err());
+        assert!(complex_cepstrum(&frame, 255).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use sample::{Sample, ToSample};
+
+use crate::error::*;
+use crate::units::{Hz, Seconds};
+use crate::waves::WindowType;
+
+/// Framing settings shared by the analyzers.
+///
+/// Settings are chained onto `AnalysisConfig::new`:
+///
+/// ```
+/// use vox_box::config::AnalysisConfig;
+/// use vox_box::units::{Hz, Seconds};
+/// use vox_box::waves::WindowType;
+///
+/// let config = AnalysisConfig::new(Hz(16000.))
+///     .frame_duration(Seconds(0.032))
+///     .hop_duration(Seconds(0.01))
+///     .window(WindowType::Hamming)
+///     .preemphasis(0.97);
+/// assert_eq!(config.frame_len, 512);
+/// assert_eq!(config.hop, 160);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalysisConfig {
+    pub sample_rate: Hz,
+    /// Length of each frame, in samples
+    pub frame_len: usize,
+    /// Distance between the starts of consecutive frames, in samples
+    pub hop: usize,
+    pub window: WindowType,
+    /// Coefficient of the first-order pre-emphasis filter `y[n] = x[n] - a * x[n - 1]`, if any
+    pub preemphasis: Option<f64>,
+}
+
+impl AnalysisConfig {
+    /// Default settings: 25 ms Hanning frames every 10 ms, without pre-emphasis.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into();
+        AnalysisConfig {
+            sample_rate,
+            frame_len: Seconds(0.025).to_samples(sample_rate),
+            hop: Seconds(0.01).to_samples(sample_rate),
+            window: WindowType::Hanning,
+            preemphasis: None,
+        }
+    }
+
+    pub fn frame_len(mut self, frame_len: usize) -> Self {
+        self.frame_len = frame_len;
+        self
+    }
+
+    pub fn hop(mut self, hop: usize) -> Self {
+        self.hop = hop;
+        self
+    }
+
+    /// Sets the frame length as a duration.
+    pub fn frame_duration<D: Into<Seconds>>(mut self, duration: D) -> Self {
+        self.frame_len = duration.into().to_samples(self.sample_rate);
+        self
+    }
+
+    /// Sets the hop as a duration.
+    pub fn hop_duration<D: Into<Seconds>>(mut self, duration: D) -> Self {
+        self.hop = duration.into().to_samples(self.sample_rate);
+        self
+    }
+
+    pub fn window(mut self, window: WindowType) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn preemphasis(mut self, coefficient: f64) -> Self {
+        self.preemphasis = Some(coefficient);
+        self
+    }
+
+    /// Checks that the settings describe a usable framing.
+    pub fn validate(&self) -> VoxBoxResult<()> {
+        if self.sample_rate.0.is_nan() || self.sample_rate.0 <= 0. {
+            return Err(VoxBoxError::Config("Sample rate must be positive"));
+        }
+        if self.frame_len == 0 || self.hop == 0 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        Ok(())
+    }
+
+    /// Time between consecutive frames.
+    pub fn frame_period(&self) -> Seconds {
+        Seconds::from_samples(self.hop, self.sample_rate)
+    }
+
+    /// Number of frames needed to cover a signal of `len` samples.
+    pub fn n_frames(&self, len: usize) -> usize {
+        len.div_ceil(self.hop.max(1))
+    }
+
+    /// Frame `k` of the signal, pre-emphasized and windowed. Samples past the end of the signal
+    /// are zero.
+    pub fn frame<S: Sample + ToSample<f64>>(&self, signal: &[S], k: usize) -> Vec<f64> {
+        let start = k * self.hop;
+        (0..self.frame_len)
+            .map(|i| {
+                let idx = start + i;
+                if idx >= signal.len() {
+                    return 0.;
+                }
+                let x = signal[idx].to_sample::<f64>();
+                let x = match self.preemphasis {
+                    Some(a) if idx > 0 => x - a * signal[idx - 1].to_sample::<f64>(),
+                    _ => x,
+                };
+                x * self.window.at(i, self.frame_len)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let config = AnalysisConfig::new(1000.)
+            .frame_len(4)
+            .hop(2)
+            .window(WindowType::Rectangular)
+            .preemphasis(0.5);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.n_frames(5), 3);
+        assert_eq!(config.frame_period(), Seconds(0.002));
+
+        let signal = [2., 2., 2., 2., 2.];
+        assert_eq!(config.frame(&signal, 0), vec![2., 1., 1., 1.]);
+        assert_eq!(config.frame(&signal, 2), vec![1., 0., 0., 0.]);
+        assert!(config.hop(0).validate().is_err());
+    }
+}
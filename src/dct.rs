@@ -0,0 +1,269 @@
+//! Discrete cosine transforms of types II, III and IV, with the conventions of SciPy's `dct`.
+//!
+//! Power-of-two lengths go through a radix-2 FFT in O(n log n) time: type II by Makhoul's
+//! reordering, type III as the inverse of that, and type IV through a complex FFT of half the
+//! length. Other lengths are summed directly.
+
+use num::{Float, FromPrimitive};
+use num_complex::Complex;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DctType {
+    /// `y[k] = 2 sum(x[n] cos(pi k (2n + 1) / 2N))`, the usual "DCT"
+    II,
+    /// `y[k] = x[0] + 2 sum(x[n] cos(pi n (2k + 1) / 2N))` for `n` from 1, the transpose of II
+    III,
+    /// `y[k] = 2 sum(x[n] cos(pi (2n + 1) (2k + 1) / 4N))`
+    IV,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DctNorm {
+    /// The sums as written on `DctType`. Transforming a length `N` signal and then taking the
+    /// other type of the pair (II and III, or IV and IV) scales it by `2N`.
+    Unnormalized,
+    /// Scaled so that the transform is orthonormal: it preserves energy, and its inverse is
+    /// its transpose.
+    Orthonormal,
+}
+
+/// The DCT of `signal`.
+pub fn transform<T: Float + FromPrimitive>(signal: &[T], kind: DctType, norm: DctNorm) -> Vec<T> {
+    let x: Vec<f64> = signal.iter().map(|s| s.to_f64().unwrap()).collect();
+    let n = x.len() as f64;
+    let y = match (kind, norm) {
+        (DctType::II, DctNorm::Unnormalized) => dct2(&x),
+        (DctType::II, DctNorm::Orthonormal) => {
+            let mut y = dct2(&x);
+            for (k, c) in y.iter_mut().enumerate() {
+                *c /= if k == 0 {
+                    (4. * n).sqrt()
+                } else {
+                    (2. * n).sqrt()
+                };
+            }
+            y
+        }
+        (DctType::III, DctNorm::Unnormalized) => dct3(&x),
+        (DctType::III, DctNorm::Orthonormal) => {
+            // The transpose of the orthonormal type II
+            let scaled: Vec<f64> = x
+                .iter()
+                .enumerate()
+                .map(|(k, c)| {
+                    if k == 0 {
+                        c / n.sqrt()
+                    } else {
+                        c / (2. * n).sqrt()
+                    }
+                })
+                .collect();
+            dct3(&scaled)
+        }
+        (DctType::IV, DctNorm::Unnormalized) => dct4(&x),
+        (DctType::IV, DctNorm::Orthonormal) => {
+            dct4(&x).into_iter().map(|c| c / (2. * n).sqrt()).collect()
+        }
+    };
+    y.into_iter().map(|c| T::from_f64(c).unwrap()).collect()
+}
+
+/// The signal whose `transform` of type `kind` is `coeffs`.
+pub fn inverse<T: Float + FromPrimitive>(coeffs: &[T], kind: DctType, norm: DctNorm) -> Vec<T> {
+    let transpose = match kind {
+        DctType::II => DctType::III,
+        DctType::III => DctType::II,
+        DctType::IV => DctType::IV,
+    };
+    let signal = transform(coeffs, transpose, norm);
+    match norm {
+        DctNorm::Orthonormal => signal,
+        DctNorm::Unnormalized => {
+            let scale = T::from_usize(2 * coeffs.len()).unwrap();
+            signal.into_iter().map(|s| s / scale).collect()
+        }
+    }
+}
+
+fn dct2(x: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    if n < 2 || !n.is_power_of_two() {
+        return direct(x, |k, i| {
+            (PI * k as f64 * (2 * i + 1) as f64 / (2 * n) as f64).cos()
+        });
+    }
+    // Even samples forwards, then odd samples backwards
+    let mut v: Vec<Complex<f64>> = x
+        .iter()
+        .step_by(2)
+        .chain(x.iter().skip(1).step_by(2).rev())
+        .map(|s| Complex::new(*s, 0.))
+        .collect();
+    fft(&mut v, false);
+    v.iter()
+        .enumerate()
+        .map(|(k, c)| 2. * (twiddle(-(k as f64) / (4 * n) as f64) * c).re)
+        .collect()
+}
+
+fn dct3(y: &[f64]) -> Vec<f64> {
+    let n = y.len();
+    if n < 2 || !n.is_power_of_two() {
+        return direct(y, |k, i| {
+            let c = (PI * i as f64 * (2 * k + 1) as f64 / (2 * n) as f64).cos();
+            if i == 0 {
+                c / 2.
+            } else {
+                c
+            }
+        });
+    }
+    // Undo the type II, which leaves the signal scaled up by 2N: rebuild the spectrum of the
+    // reordered signal and transform it back
+    let mut v: Vec<Complex<f64>> = (0..n)
+        .map(|k| {
+            let mirrored = if k == 0 { 0. } else { y[n - k] };
+            twiddle(k as f64 / (4 * n) as f64) * Complex::new(y[k], -mirrored)
+        })
+        .collect();
+    fft(&mut v, true);
+    let mut x = vec![0.; n];
+    for i in 0..n / 2 {
+        x[2 * i] = v[i].re;
+        x[2 * i + 1] = v[n - 1 - i].re;
+    }
+    x
+}
+
+fn dct4(x: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    if n < 2 || !n.is_power_of_two() {
+        return direct(x, |k, i| {
+            (PI * (2 * i + 1) as f64 * (2 * k + 1) as f64 / (4 * n) as f64).cos()
+        });
+    }
+    // Pair the even samples with the odd ones read backwards
+    let half = n / 2;
+    let mut v: Vec<Complex<f64>> = (0..half)
+        .map(|i| {
+            Complex::new(x[2 * i], x[n - 1 - 2 * i]) * twiddle(-(i as f64 + 0.25) / (2 * n) as f64)
+        })
+        .collect();
+    fft(&mut v, false);
+    let mut y = vec![0.; n];
+    for (k, c) in v.iter().enumerate() {
+        let d = c * twiddle(-(k as f64) / (2 * n) as f64);
+        y[2 * k] = 2. * d.re;
+        y[n - 1 - 2 * k] = -2. * d.im;
+    }
+    y
+}
+
+/// `exp(2 pi i f)`
+fn twiddle(f: f64) -> Complex<f64> {
+    Complex::from_polar(&1., &(2. * PI * f))
+}
+
+/// `y[k] = 2 sum(x[i] basis(k, i))`, in O(n^2).
+fn direct<F: Fn(usize, usize) -> f64>(x: &[f64], basis: F) -> Vec<f64> {
+    (0..x.len())
+        .map(|k| {
+            2. * x
+                .iter()
+                .enumerate()
+                .fold(0., |acc, (i, s)| acc + s * basis(k, i))
+        })
+        .collect()
+}
+
+/// In-place radix-2 FFT of a power-of-two length, unscaled in both directions.
+fn fft(buf: &mut [Complex<f64>], inverse: bool) {
+    let n = buf.len();
+    if n < 2 {
+        return;
+    }
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits) as usize;
+        if j > i {
+            buf.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1. } else { -1. };
+    let mut len = 2;
+    while len <= n {
+        let step = twiddle(sign / len as f64);
+        for chunk in buf.chunks_mut(len) {
+            let (low, high) = chunk.split_at_mut(len / 2);
+            let mut w = Complex::new(1., 0.);
+            for (a, b) in low.iter_mut().zip(high.iter_mut()) {
+                let t = w * *b;
+                *b = *a - t;
+                *a += t;
+                w *= step;
+            }
+        }
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dct() {
+        let signal: Vec<f64> = (0..16)
+            .map(|n| (n as f64 * 0.9).sin() + 0.2 * n as f64)
+            .collect();
+        // The fast paths against the direct sums
+        let direct_ii = direct(&signal, |k, i| {
+            (PI * k as f64 * (2 * i + 1) as f64 / 32.).cos()
+        });
+        let direct_iv = direct(&signal, |k, i| {
+            (PI * (2 * i + 1) as f64 * (2 * k + 1) as f64 / 64.).cos()
+        });
+        let fast_ii = transform(&signal, DctType::II, DctNorm::Unnormalized);
+        let fast_iv = transform(&signal, DctType::IV, DctNorm::Unnormalized);
+        for (a, b) in fast_ii.iter().zip(direct_ii.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+        for (a, b) in fast_iv.iter().zip(direct_iv.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+        // Type III is the transpose of type II, here applied to a unit vector
+        let mut unit = vec![0.; 16];
+        unit[3] = 1.;
+        let column = transform(&unit, DctType::III, DctNorm::Unnormalized);
+        let row: Vec<f64> = (0..16)
+            .map(|k| {
+                let mut basis = vec![0.; 16];
+                basis[k] = 1.;
+                transform(&basis, DctType::II, DctNorm::Unnormalized)[3]
+            })
+            .collect();
+        for (a, b) in column.iter().zip(row.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        // Round trips, fast and direct, and energy preservation
+        for len in [16, 6, 2, 1].iter() {
+            let x = &signal[..*len];
+            for kind in [DctType::II, DctType::III, DctType::IV].iter() {
+                for norm in [DctNorm::Unnormalized, DctNorm::Orthonormal].iter() {
+                    let coeffs = transform(x, *kind, *norm);
+                    let back = inverse(&coeffs, *kind, *norm);
+                    for (a, b) in back.iter().zip(x.iter()) {
+                        assert!((a - b).abs() < 1e-9);
+                    }
+                    if *norm == DctNorm::Orthonormal {
+                        let energy = |v: &[f64]| v.iter().map(|s| s * s).sum::<f64>();
+                        assert!((energy(&coeffs) - energy(x)).abs() < 1e-9);
+                    }
+                }
+            }
+        }
+        assert!(transform::<f64>(&[], DctType::II, DctNorm::Orthonormal).is_empty());
+    }
+}
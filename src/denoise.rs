@@ -0,0 +1,546 @@
+//! Noise reduction on the short-time spectrum, resynthesized by the inverse STFT, for cleaning
+//! recordings before measuring them.
+//!
+//! ```
+//! use vox_box::denoise::SpectralSubtraction;
+//! use vox_box::spectrum::Stft;
+//!
+//! let hum: Vec<f64> = (0..4000).map(|n| 0.01 * (n as f64 * 0.3).sin()).collect();
+//! let cleaned = SpectralSubtraction::new(Stft::new(256, 64)).process(&hum).unwrap();
+//! assert_eq!(cleaned.len(), hum.len());
+//! ```
+
+use num_complex::Complex;
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::noise::NoiseFloor;
+use crate::spectrum::Stft;
+use crate::units::Db;
+use crate::vad::SohnVad;
+use crate::validate;
+use crate::waves::to_f64_samples;
+
+/// Spectral subtraction (Boll 1979, with Berouti et al.'s over-subtraction and floor): the
+/// noise magnitude spectrum is taken away from every frame, keeping each bin's phase.
+///
+/// Subtracting `over_subtraction` times the noise rather than just the noise clears away the
+/// peaks of the noise that would otherwise be left as "musical" tones, and keeping every bin at
+/// least `floor` times the noise leaves a little of the background in place of the holes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectralSubtraction {
+    stft: Stft,
+    over_subtraction: f64,
+    floor: f64,
+    profile: Option<Vec<f64>>,
+}
+
+impl SpectralSubtraction {
+    /// Subtraction on the frames of `stft`, estimating the noise from the signal.
+    pub fn new(stft: Stft) -> Self {
+        SpectralSubtraction {
+            stft,
+            over_subtraction: 2.,
+            floor: 0.02,
+            profile: None,
+        }
+    }
+
+    /// Subtraction on the framing of `config`.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        SpectralSubtraction::new(Stft::from_config(config))
+    }
+
+    pub fn over_subtraction(mut self, factor: f64) -> Self {
+        self.over_subtraction = factor;
+        self
+    }
+
+    /// Fraction of the noise magnitude every bin keeps, between 0 and 1.
+    pub fn floor(mut self, floor: f64) -> Self {
+        self.floor = floor;
+        self
+    }
+
+    /// Noise magnitude spectrum to subtract, one value per bin of the STFT, as from
+    /// `noise_profile` on a recording of the background alone.
+    pub fn profile(mut self, magnitudes: Vec<f64>) -> Self {
+        self.profile = Some(magnitudes);
+        self
+    }
+
+    /// Mean magnitude spectrum of the frames of `signal` that a `SohnVad` finds aren't speech.
+    pub fn noise_profile<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<f64>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        noise_profile(&self.stft, signal)
+    }
+
+    /// The signal with the noise taken out, as long as `signal`.
+    pub fn process<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<f64>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        if self.over_subtraction.is_nan() || self.over_subtraction < 0. {
+            return Err(VoxBoxError::Config("Over-subtraction must not be negative"));
+        }
+        if !(0. ..=1.).contains(&self.floor) {
+            return Err(VoxBoxError::Config("Floor must be between 0 and 1"));
+        }
+        let noise = match self.profile {
+            Some(ref profile) => {
+                check_profile(&self.stft, profile)?;
+                profile.clone()
+            }
+            None => self.noise_profile(signal)?,
+        };
+        let spectra: Vec<Vec<Complex<f64>>> = self
+            .stft
+            .frames(signal)?
+            .map(|spectrum| {
+                spectrum
+                    .iter()
+                    .zip(noise.iter())
+                    .map(|(bin, n)| {
+                        let magnitude = bin.norm();
+                        let cleaned = (magnitude - self.over_subtraction * n).max(self.floor * n);
+                        if magnitude > 0. {
+                            bin * (cleaned / magnitude)
+                        } else {
+                            *bin
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.stft.inverse(&spectra, signal.len())
+    }
+}
+
+/// Mean magnitude spectrum of the frames of `signal` that a `SohnVad` finds aren't speech.
+fn noise_profile<S>(stft: &Stft, signal: &[S]) -> VoxBoxResult<Vec<f64>>
+where
+    S: Sample + ToSample<f64>,
+{
+    validate::non_empty(signal)?;
+    let samples = to_f64_samples(signal);
+    validate::finite(&samples)?;
+    let mut vad = SohnVad::new(stft.frame_len);
+    let mut sum = vec![0.; stft.n_bins()];
+    let mut count = 0;
+    for (k, spectrum) in stft.frames(&samples)?.enumerate() {
+        let start = k * stft.hop;
+        let mut frame = samples[start..(start + stft.frame_len).min(samples.len())].to_vec();
+        frame.resize(stft.frame_len, 0.);
+        if !vad.process(&frame[..]).speech {
+            for (s, bin) in sum.iter_mut().zip(spectrum.iter()) {
+                *s += bin.norm();
+            }
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return Err(VoxBoxError::Input(
+            "No frames without speech to estimate the noise from",
+        ));
+    }
+    Ok(sum.iter().map(|s| s / count as f64).collect())
+}
+
+fn check_profile(stft: &Stft, profile: &[f64]) -> VoxBoxResult<()> {
+    if profile.len() != stft.n_bins() {
+        return Err(VoxBoxError::Config(
+            "Noise profile must have one value per bin",
+        ));
+    }
+    validate::finite(profile)?;
+    if profile.iter().any(|n| *n < 0.) {
+        return Err(VoxBoxError::Config("Noise profile must not be negative"));
+    }
+    Ok(())
+}
+
+/// How `Suppressor` turns a bin's estimated SNRs into a gain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SuppressionRule {
+    /// The Wiener gain `xi / (1 + xi)` of the a priori SNR `xi`
+    Wiener,
+    /// The MMSE log-spectral amplitude estimator (Ephraim and Malah 1985), which takes the a
+    /// posteriori SNR into account too and suppresses a little less where speech is weak
+    LogSpectralAmplitude,
+}
+
+/// Noise suppression by a gain on every bin from its a priori SNR, estimated decision-directed
+/// (Ephraim and Malah 1984): a weighted sum of the previous frame's cleaned power and this
+/// frame's excess over the noise. The smoothing keeps the gains from jumping about between
+/// frames, so there is much less of the musical noise that spectral subtraction leaves. The
+/// noise power is tracked through the signal by a `NoiseFloor`, unless a profile is given.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suppressor {
+    stft: Stft,
+    rule: SuppressionRule,
+    smoothing: f64,
+    min_prior_snr: Db,
+    tracker_frames: usize,
+    profile: Option<Vec<f64>>,
+}
+
+impl Suppressor {
+    /// Log-spectral amplitude suppression on the frames of `stft`, whose transform length must
+    /// be a power of two for the noise tracker.
+    pub fn new(stft: Stft) -> Self {
+        Suppressor {
+            stft,
+            rule: SuppressionRule::LogSpectralAmplitude,
+            smoothing: 0.98,
+            min_prior_snr: Db(-25.),
+            tracker_frames: 150,
+            profile: None,
+        }
+    }
+
+    /// Suppression on the framing of `config`.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Suppressor::new(Stft::from_config(config))
+    }
+
+    pub fn rule(mut self, rule: SuppressionRule) -> Self {
+        self.rule = rule;
+        self
+    }
+
+    /// Weight of the previous frame in the a priori SNR, between 0 and 1. Higher values leave
+    /// less musical noise but smear onsets.
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Least a priori SNR, which bounds how far noise is pushed down.
+    pub fn min_prior_snr(mut self, snr: Db) -> Self {
+        self.min_prior_snr = snr;
+        self
+    }
+
+    /// Frames the noise tracker looks back over for the minimum power. It should span longer
+    /// than any sound that holds steady in one bin, about a second and a half for speech.
+    pub fn tracker_frames(mut self, frames: usize) -> Self {
+        self.tracker_frames = frames;
+        self
+    }
+
+    /// Steady noise magnitude spectrum to use instead of tracking the noise, one value per bin,
+    /// as from `SpectralSubtraction::noise_profile`.
+    pub fn profile(mut self, magnitudes: Vec<f64>) -> Self {
+        self.profile = Some(magnitudes);
+        self
+    }
+
+    /// The signal with the noise suppressed, as long as `signal`.
+    pub fn process<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<f64>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        if !(0. ..=1.).contains(&self.smoothing) {
+            return Err(VoxBoxError::Config("Smoothing must be between 0 and 1"));
+        }
+        if !self.min_prior_snr.0.is_finite() {
+            return Err(VoxBoxError::Config("Least a priori SNR must be finite"));
+        }
+        let mut tracker = match self.profile {
+            Some(ref profile) => {
+                check_profile(&self.stft, profile)?;
+                None
+            }
+            None => {
+                if !self.stft.fft_len.is_power_of_two() {
+                    return Err(VoxBoxError::Config(
+                        "FFT length must be a power of two to track the noise",
+                    ));
+                }
+                if self.tracker_frames == 0 {
+                    return Err(VoxBoxError::Config(
+                        "Noise tracker must look back over at least one frame",
+                    ));
+                }
+                Some(NoiseFloor::new(self.stft.fft_len, self.tracker_frames))
+            }
+        };
+        let profile: Vec<f64> = self.profile.iter().flatten().map(|n| n * n).collect();
+        validate::non_empty(signal)?;
+        validate::finite(&to_f64_samples(signal))?;
+
+        let min_prior_snr = self.min_prior_snr.to_power();
+        let mut previous: Option<Vec<f64>> = None;
+        let mut spectra = Vec::with_capacity(self.stft.n_frames(signal.len()));
+        for mut spectrum in self.stft.frames(signal)? {
+            let power: Vec<f64> = spectrum.iter().map(|bin| bin.norm_sqr()).collect();
+            let noise = match tracker {
+                Some(ref mut tracker) => tracker.update_power(&power)?,
+                None => &profile[..],
+            };
+            let mut cleaned = Vec::with_capacity(power.len());
+            for (k, bin) in spectrum.iter_mut().enumerate() {
+                let noise = noise[k].max(f64::MIN_POSITIVE);
+                let posterior = power[k] / noise;
+                let excess = (posterior - 1.).max(0.);
+                let prior = match previous {
+                    Some(ref previous) => {
+                        self.smoothing * previous[k] / noise + (1. - self.smoothing) * excess
+                    }
+                    None => excess,
+                }
+                .max(min_prior_snr);
+                let gain = match self.rule {
+                    SuppressionRule::Wiener => prior / (1. + prior),
+                    SuppressionRule::LogSpectralAmplitude => {
+                        let v = (prior * posterior / (1. + prior)).max(1.0e-10);
+                        (prior / (1. + prior) * (0.5 * exponential_integral(v)).exp()).min(1.)
+                    }
+                };
+                *bin *= gain;
+                cleaned.push(gain * gain * power[k]);
+            }
+            previous = Some(cleaned);
+            spectra.push(spectrum);
+        }
+        self.stft.inverse(&spectra, signal.len())
+    }
+}
+
+/// The exponential integral `E1(x)` for positive `x`, by its power series below 1 and its
+/// continued fraction above.
+fn exponential_integral(x: f64) -> f64 {
+    const EULER: f64 = 0.577_215_664_901_532_9;
+    if x < 1. {
+        let mut sum = 0.;
+        let mut term = 1.;
+        for k in 1..100 {
+            term *= -x / k as f64;
+            sum -= term / k as f64;
+            if term.abs() < 1.0e-17 {
+                break;
+            }
+        }
+        -EULER - x.ln() + sum
+    } else {
+        // Modified Lentz's method, as in Numerical Recipes
+        let tiny = 1.0e-300;
+        let mut b = x + 1.;
+        let mut c = 1. / tiny;
+        let mut d = 1. / b;
+        let mut h = d;
+        for i in 1..100 {
+            let a = -((i * i) as f64);
+            b += 2.;
+            d = 1. / (a * d + b);
+            c = b + a / c;
+            let delta = c * d;
+            h *= delta;
+            if (delta - 1.).abs() < 1.0e-15 {
+                break;
+            }
+        }
+        h * (-x).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+    use std::f64::consts::PI;
+
+    /// 0.25 s of white noise alone, then 0.75 s of a 300 Hz tone in the same noise, at 8 kHz.
+    /// Returns the noisy signal and the clean one.
+    fn noisy_tone() -> (Vec<f64>, Vec<f64>) {
+        let mut rng = XorShiftRng::from_seed([9, 8, 7, 6]);
+        let noise = Normal::new(0., 0.05);
+        let clean: Vec<f64> = (0..8000)
+            .map(|n| {
+                if n < 2000 {
+                    0.
+                } else {
+                    0.5 * (2. * PI * 300. * n as f64 / 8000.).sin()
+                }
+            })
+            .collect();
+        let noisy = clean
+            .iter()
+            .map(|s| s + noise.ind_sample(&mut rng))
+            .collect();
+        (noisy, clean)
+    }
+
+    /// Mean square difference between two signals over `range`.
+    fn error(a: &[f64], b: &[f64], range: std::ops::Range<usize>) -> f64 {
+        a[range.clone()]
+            .iter()
+            .zip(b[range.clone()].iter())
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum::<f64>()
+            / range.len() as f64
+    }
+
+    #[test]
+    fn test_spectral_subtraction() {
+        let (noisy, clean) = noisy_tone();
+        let stft = Stft::new(256, 64);
+        let subtraction = SpectralSubtraction::new(stft);
+        let cleaned = subtraction.process(&noisy).unwrap();
+        assert_eq!(cleaned.len(), noisy.len());
+
+        // The noise alone drops a lot, and the tone comes through with less noise on it
+        let before = error(&noisy, &clean, 300..1700);
+        let after = error(&cleaned, &clean, 300..1700);
+        println!("noise alone: {} -> {}", before, after);
+        assert!(after < before / 10.);
+        let before = error(&noisy, &clean, 3000..7000);
+        let after = error(&cleaned, &clean, 3000..7000);
+        println!("tone: {} -> {}", before, after);
+        assert!(after < before / 2.);
+
+        // The noise estimated from the start of the recording serves as a profile just as well
+        let profile = subtraction.noise_profile(&noisy[..2000]).unwrap();
+        assert_eq!(profile.len(), stft.n_bins());
+        let with_profile = subtraction
+            .clone()
+            .profile(profile)
+            .process(&noisy)
+            .unwrap();
+        assert!(error(&with_profile, &clean, 3000..7000) < before / 2.);
+
+        // No subtraction gives the signal back
+        let untouched = subtraction
+            .clone()
+            .over_subtraction(0.)
+            .floor(0.)
+            .process(&noisy)
+            .unwrap();
+        assert!(error(&untouched, &noisy, 1..8000) < 1e-20);
+    }
+
+    #[test]
+    fn test_spectral_subtraction_errors() {
+        let (noisy, _) = noisy_tone();
+        let subtraction = SpectralSubtraction::new(Stft::new(256, 64));
+        assert!(subtraction
+            .clone()
+            .over_subtraction(-1.)
+            .process(&noisy)
+            .is_err());
+        assert!(subtraction.clone().floor(2.).process(&noisy).is_err());
+        assert!(subtraction
+            .clone()
+            .profile(vec![0.; 10])
+            .process(&noisy)
+            .is_err());
+        assert!(subtraction
+            .clone()
+            .profile(vec![-1.; 129])
+            .process(&noisy)
+            .is_err());
+        assert!(subtraction.process::<f64>(&[]).is_err());
+        assert!(subtraction.process(&[f64::NAN; 100]).is_err());
+    }
+
+    /// 0.5 s of white noise, then bursts of a 300 Hz tone in the same noise, 0.15 s on and
+    /// 0.15 s off, for 2 s at 8 kHz. Returns the noisy signal and the clean one.
+    fn noisy_bursts() -> (Vec<f64>, Vec<f64>) {
+        let mut rng = XorShiftRng::from_seed([3, 1, 4, 1]);
+        let noise = Normal::new(0., 0.05);
+        let clean: Vec<f64> = (0..16000)
+            .map(|n| {
+                if n < 4000 || (n - 4000) % 2400 >= 1200 {
+                    0.
+                } else {
+                    0.5 * (2. * PI * 300. * n as f64 / 8000.).sin()
+                }
+            })
+            .collect();
+        let noisy = clean
+            .iter()
+            .map(|s| s + noise.ind_sample(&mut rng))
+            .collect();
+        (noisy, clean)
+    }
+
+    #[test]
+    fn test_suppressor() {
+        let (noisy, clean) = noisy_bursts();
+        let stft = Stft::new(256, 64);
+        for rule in [
+            SuppressionRule::Wiener,
+            SuppressionRule::LogSpectralAmplitude,
+        ]
+        .iter()
+        {
+            let cleaned = Suppressor::new(stft).rule(*rule).process(&noisy).unwrap();
+            assert_eq!(cleaned.len(), noisy.len());
+            // Past the tracker's first look at the noise, everything is much nearer the clean
+            // signal
+            let before = error(&noisy, &clean, 2000..16000);
+            let after = error(&cleaned, &clean, 2000..16000);
+            println!("{:?}: {} -> {}", rule, before, after);
+            assert!(after < before / 4.);
+        }
+
+        // A profile of the noise does as well from the start
+        let profile = SpectralSubtraction::new(stft)
+            .noise_profile(&noisy[..4000])
+            .unwrap();
+        let cleaned = Suppressor::new(stft)
+            .profile(profile)
+            .process(&noisy)
+            .unwrap();
+        assert!(error(&cleaned, &clean, 300..16000) < error(&noisy, &clean, 300..16000) / 4.);
+    }
+
+    #[test]
+    fn test_suppressor_errors() {
+        let (noisy, _) = noisy_bursts();
+        let suppressor = Suppressor::new(Stft::new(256, 64));
+        assert!(suppressor.clone().smoothing(1.5).process(&noisy).is_err());
+        assert!(suppressor
+            .clone()
+            .min_prior_snr(Db(f64::NEG_INFINITY))
+            .process(&noisy)
+            .is_err());
+        assert!(suppressor
+            .clone()
+            .tracker_frames(0)
+            .process(&noisy)
+            .is_err());
+        assert!(suppressor
+            .clone()
+            .profile(vec![1.; 3])
+            .process(&noisy)
+            .is_err());
+        assert!(suppressor.process::<f64>(&[]).is_err());
+        let stft = Stft {
+            fft_len: 300,
+            ..Stft::new(256, 64)
+        };
+        assert!(Suppressor::new(stft).process(&noisy).is_err());
+        assert!(Suppressor::new(stft)
+            .profile(vec![0.01; 151])
+            .process(&noisy)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_exponential_integral() {
+        for (x, e1) in [
+            (0.1, 1.822_923_958_419_390_6),
+            (1., 0.219_383_934_395_520_27),
+            (5., 0.001_148_295_591_275_325_5),
+        ]
+        .iter()
+        {
+            assert!((exponential_integral(*x) - e1).abs() < 1e-12 * e1.max(1.));
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! Fast f0 estimation in the style of DIO and Harvest (Morise 2009, 2017), from the WORLD
+//! vocoder.
+//!
+//! The signal is low-passed at a ladder of cutoffs, and the zero crossings, peaks and dips of
+//! each copy are timed. Below a cutoff between one and two times f0 only the fundamental is
+//! left, a near sinusoid whose four kinds of intervals agree, with each other and from one
+//! period to the next; copies that keep harmonics, that filtered the fundamental away, or of
+//! noise give intervals that disagree. Each frame takes the candidate whose intervals agree
+//! best, and, as in Harvest, each voiced stretch is then followed outward from its most
+//! typical frame, swapping in other candidates of frames that jump an octave. No
+//! correlations are computed, which makes it fast on long recordings.
+
+use sample::{Sample, ToSample};
+use std::f64::consts::PI;
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::periodic::PitchFrame;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::units::Hz;
+use crate::validate;
+use crate::waves::to_f64_samples;
+
+/// An f0 estimate from one low-passed copy of the signal, and the spread, relative to it, of the
+/// interval estimates it is the mean of.
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    f0: f64,
+    spread: f64,
+}
+
+/// Settings for DIO pitch tracking. The defaults are WORLD's.
+#[derive(Clone, Debug)]
+pub struct Dio {
+    pub sample_rate: f64,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+    /// Low-pass cutoffs per octave of the pitch range, at least one
+    pub bands_per_octave: f64,
+    /// Largest relative spread of a candidate's interval estimates for the frame to be voiced
+    pub max_spread: f64,
+    /// Largest relative change in f0 from one voiced frame to the next before the contour
+    /// looks for another candidate
+    pub allowed_range: f64,
+    /// Voiced stretches of fewer frames are made unvoiced
+    pub min_voiced: usize,
+}
+
+impl Dio {
+    /// Default settings: 5 ms hops, pitch between 71 and 800 Hz.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        Dio {
+            sample_rate,
+            hop: (sample_rate * 0.005).round() as usize,
+            min_pitch: 71.,
+            max_pitch: 800.,
+            bands_per_octave: 2.,
+            max_spread: 0.1,
+            allowed_range: 0.1,
+            min_voiced: 3,
+        }
+    }
+
+    /// Takes the hop from `config`.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Dio {
+            hop: config.hop,
+            ..Dio::new(config.sample_rate)
+        }
+    }
+
+    /// Tracks the pitch of the signal, one frame per hop. Unvoiced frames still report their
+    /// most consistent candidate, if they have one.
+    pub fn track<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<PitchFrame<f64>>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.track_with_progress(signal, progress::ignore)
+    }
+
+    /// Like `track`, calling `progress` after every low-passed copy of the signal. Returns
+    /// `VoxBoxError::Cancelled` if the callback breaks.
+    pub fn track_with_progress<S, F>(
+        &self,
+        signal: &[S],
+        mut progress: F,
+    ) -> VoxBoxResult<Vec<PitchFrame<f64>>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        validate::positive(self.sample_rate, "Sample rate must be positive")?;
+        if !(self.min_pitch > 0. && self.min_pitch < self.max_pitch) {
+            return Err(VoxBoxError::Config(
+                "Pitch range must be positive and increasing",
+            ));
+        }
+        if self.hop == 0 {
+            return Err(VoxBoxError::Config("Hop must be nonzero"));
+        }
+        if self.bands_per_octave.is_nan() || self.bands_per_octave < 1. {
+            return Err(VoxBoxError::Config(
+                "There must be at least one band per octave",
+            ));
+        }
+        let signal = to_f64_samples(signal);
+        validate::finite(&signal)?;
+
+        // Nothing above twice the highest pitch is needed
+        let factor = ((self.sample_rate / (4. * self.max_pitch)).floor() as usize).max(1);
+        let decimated = low_pass(
+            &signal,
+            0.45 * self.sample_rate / factor as f64,
+            self.sample_rate,
+            factor,
+        );
+        let rate = self.sample_rate / factor as f64;
+
+        let n_frames = signal.len().div_ceil(self.hop);
+        let times: Vec<f64> = (0..n_frames)
+            .map(|k| (k * self.hop + self.hop / 2) as f64 / factor as f64)
+            .collect();
+        let n_bands =
+            ((self.max_pitch / self.min_pitch).log2() * self.bands_per_octave).ceil() as usize;
+        let mut candidates = vec![Vec::new(); n_frames];
+        for band in 0..n_bands {
+            // Candidates from this copy must lie between half the cutoff and the cutoff
+            let cutoff = self.min_pitch * 2f64.powf((band + 1) as f64 / self.bands_per_octave);
+            let filtered = low_pass(&decimated, cutoff.min(0.45 * rate), rate, 1);
+            let slope: Vec<f64> = filtered.windows(2).map(|w| w[1] - w[0]).collect();
+            let intervals = [
+                intervals_at(&rising_crossings(&filtered), &times),
+                intervals_at(&falling_crossings(&filtered), &times),
+                intervals_at(&falling_crossings(&slope), &times),
+                intervals_at(&rising_crossings(&slope), &times),
+            ];
+            for (k, frame) in candidates.iter_mut().enumerate() {
+                let around: Vec<(f64, f64)> =
+                    intervals.iter().filter_map(|series| series[k]).collect();
+                if around.len() < intervals.len() {
+                    continue;
+                }
+                let f0s: Vec<f64> = around
+                    .iter()
+                    .flat_map(|(before, after)| vec![rate / before, rate / after])
+                    .collect();
+                let f0 = f0s.iter().sum::<f64>() / f0s.len() as f64;
+                let variance =
+                    f0s.iter().map(|f| (f - f0) * (f - f0)).sum::<f64>() / f0s.len() as f64;
+                if f0 >= (cutoff / 2.).max(self.min_pitch) && f0 <= cutoff.min(self.max_pitch) {
+                    frame.push(Candidate {
+                        f0,
+                        spread: variance.sqrt() / f0,
+                    });
+                }
+            }
+            progress::report(&mut progress, band + 1, n_bands)?;
+        }
+        for frame in candidates.iter_mut() {
+            frame.sort_by(|a, b| a.spread.total_cmp(&b.spread));
+        }
+
+        let mut f0: Vec<Option<f64>> = candidates
+            .iter()
+            .map(|frame| {
+                frame
+                    .first()
+                    .filter(|c| c.spread <= self.max_spread)
+                    .map(|c| c.f0)
+            })
+            .collect();
+        self.fix_contour(&candidates, &mut f0);
+        Ok(candidates
+            .iter()
+            .zip(f0)
+            .map(|(frame, f0)| match f0 {
+                Some(f0) => PitchFrame {
+                    f0,
+                    voiced: true,
+                    strength: frame
+                        .iter()
+                        .find(|c| c.f0 == f0)
+                        .map_or(0., |c| (1. - c.spread).max(0.)),
+                },
+                None => frame.first().map_or(
+                    PitchFrame {
+                        f0: 0.,
+                        voiced: false,
+                        strength: 0.,
+                    },
+                    |c| PitchFrame {
+                        f0: c.f0,
+                        voiced: false,
+                        strength: (1. - c.spread).max(0.),
+                    },
+                ),
+            })
+            .collect())
+    }
+
+    /// Follows each voiced stretch of `f0` out from the frame nearest its median. Frames that
+    /// jump by more than `allowed_range` take their nearest candidate within range of the
+    /// frame before, or are made unvoiced if they have none. Stretches left shorter than
+    /// `min_voiced` are then made unvoiced.
+    fn fix_contour(&self, candidates: &[Vec<Candidate>], f0: &mut [Option<f64>]) {
+        let close =
+            |f: f64, reference: f64| (f - reference).abs() <= self.allowed_range * reference;
+        let fix = |k: usize, reference: f64, f0: &mut [Option<f64>]| -> f64 {
+            match f0[k] {
+                Some(f) if close(f, reference) => f,
+                _ => {
+                    f0[k] = candidates[k]
+                        .iter()
+                        .filter(|c| c.spread <= self.max_spread && close(c.f0, reference))
+                        .map(|c| c.f0)
+                        .fold(None, |best: Option<f64>, f| match best {
+                            Some(b) if (b - reference).abs() <= (f - reference).abs() => Some(b),
+                            _ => Some(f),
+                        });
+                    f0[k].unwrap_or(reference)
+                }
+            }
+        };
+
+        for stretch in voiced_stretches(f0) {
+            let mut sorted: Vec<f64> = f0[stretch.clone()].iter().map(|f| f.unwrap()).collect();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            let median = sorted[sorted.len() / 2];
+            let anchor = stretch
+                .clone()
+                .min_by(|a, b| {
+                    let distance = |k: usize| (f0[k].unwrap() - median).abs();
+                    distance(*a).total_cmp(&distance(*b))
+                })
+                .unwrap();
+            let mut reference = f0[anchor].unwrap();
+            for k in anchor + 1..stretch.end {
+                reference = fix(k, reference, f0);
+            }
+            reference = f0[anchor].unwrap();
+            for k in (stretch.start..anchor).rev() {
+                reference = fix(k, reference, f0);
+            }
+        }
+
+        for stretch in voiced_stretches(f0) {
+            if stretch.len() < self.min_voiced {
+                for f in f0[stretch].iter_mut() {
+                    *f = None;
+                }
+            }
+        }
+    }
+}
+
+/// Ranges of consecutive voiced frames.
+fn voiced_stretches(f0: &[Option<f64>]) -> Vec<std::ops::Range<usize>> {
+    let mut stretches = Vec::new();
+    let mut start = None;
+    for (k, f) in f0.iter().enumerate() {
+        match (f, start) {
+            (Some(_), None) => start = Some(k),
+            (None, Some(s)) => {
+                stretches.push(s..k);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        stretches.push(s..f0.len());
+    }
+    stretches
+}
+
+/// Low-passes `signal` at `cutoff` with a Blackman-windowed sinc four periods of the cutoff
+/// long on either side, centered so that it adds no delay, keeping every `step`th sample.
+fn low_pass(signal: &[f64], cutoff: f64, rate: f64, step: usize) -> Vec<f64> {
+    let half = (4. * rate / cutoff).round() as usize;
+    let n = 2 * half + 1;
+    let mut taps: Vec<f64> = (0..n)
+        .map(|i| {
+            let m = i as f64 - half as f64;
+            let ideal = if m == 0. {
+                2. * cutoff / rate
+            } else {
+                (2. * PI * cutoff / rate * m).sin() / (PI * m)
+            };
+            let phase = 2. * PI * i as f64 / (n - 1) as f64;
+            ideal * (0.42 - 0.5 * phase.cos() + 0.08 * (2. * phase).cos())
+        })
+        .collect();
+    let gain: f64 = taps.iter().sum();
+    for t in taps.iter_mut() {
+        *t /= gain;
+    }
+    (0..signal.len())
+        .step_by(step)
+        .map(|j| {
+            let start = j.saturating_sub(half);
+            let end = (j + half + 1).min(signal.len());
+            signal[start..end]
+                .iter()
+                .zip(&taps[start + half - j..])
+                .map(|(s, t)| s * t)
+                .sum()
+        })
+        .collect()
+}
+
+/// Times, in fractional samples, at which `x` crosses zero going up.
+fn rising_crossings(x: &[f64]) -> Vec<f64> {
+    x.windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[0] < 0. && w[1] >= 0.)
+        .map(|(i, w)| i as f64 + w[0] / (w[0] - w[1]))
+        .collect()
+}
+
+fn falling_crossings(x: &[f64]) -> Vec<f64> {
+    rising_crossings(&x.iter().map(|s| -s).collect::<Vec<f64>>())
+}
+
+/// The intervals between consecutive `events` either side of each of `times`, both in
+/// increasing order, placing each interval at its midpoint. `None` outside the first and last
+/// midpoints.
+fn intervals_at(events: &[f64], times: &[f64]) -> Vec<Option<(f64, f64)>> {
+    let points: Vec<(f64, f64)> = events
+        .windows(2)
+        .map(|e| ((e[0] + e[1]) / 2., e[1] - e[0]))
+        .collect();
+    let mut j = 0;
+    times
+        .iter()
+        .map(|t| {
+            while j + 1 < points.len() && points[j + 1].0 <= *t {
+                j += 1;
+            }
+            if points.len() < 2 || *t < points[j].0 || j + 1 == points.len() {
+                return None;
+            }
+            Some((points[j].1, points[j + 1].1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_dio() {
+        let sample_rate = 16000.;
+        let mut state = 7u32;
+        // 250 ms of silence, 500 ms of a buzz gliding from 120 to 180 Hz, then 250 ms of noise
+        let mut phase = 0.;
+        let signal: Vec<f64> = (0..16000)
+            .map(|n| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (state as f64 / u32::MAX as f64 - 0.5) * 0.01;
+                if (4000..12000).contains(&n) {
+                    let f0 = 120. + 60. * (n - 4000) as f64 / 8000.;
+                    phase += 2. * PI * f0 / sample_rate;
+                    (1..6)
+                        .map(|h| (phase * h as f64).sin() / h as f64)
+                        .sum::<f64>()
+                        * 0.3
+                        + noise
+                } else if n >= 12000 {
+                    noise * 20.
+                } else {
+                    noise * 0.01
+                }
+            })
+            .collect();
+
+        let frames = Dio::new(sample_rate).track(&signal).unwrap();
+        assert_eq!(frames.len(), 200);
+        for (k, frame) in frames.iter().enumerate().take(145).skip(55) {
+            let expected = 120. + 60. * (k as f64 * 80. + 40. - 4000.) / 8000.;
+            assert!(frame.voiced);
+            assert!((frame.f0 - expected).abs() < 3.);
+        }
+        assert!(frames[..40].iter().all(|f| !f.voiced));
+        assert!(frames[160..].iter().filter(|f| f.voiced).count() < 5);
+    }
+
+    #[test]
+    fn test_dio_weak_fundamental() {
+        // The second harmonic is five times stronger than the fundamental
+        let sample_rate = 16000.;
+        let signal: Vec<f64> = (0..8000)
+            .map(|n| {
+                let phase = 2. * PI * 150. * n as f64 / sample_rate;
+                0.1 * phase.sin() + 0.5 * (2. * phase).sin() + 0.2 * (3. * phase).sin()
+            })
+            .collect();
+        let frames = Dio::new(sample_rate).track(&signal).unwrap();
+        println!("{:?}", frames.iter().map(|f| f.f0).collect::<Vec<f64>>());
+        assert!(frames[10..90]
+            .iter()
+            .all(|f| f.voiced && (f.f0 - 150.).abs() < 3.));
+    }
+
+    #[test]
+    fn test_dio_errors() {
+        let signal = [0f64; 1600];
+        let mut calls = 0;
+        let result = Dio::new(16000.).track_with_progress(&signal, |p| {
+            calls += 1;
+            if p.done == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(result, Err(VoxBoxError::Cancelled)));
+        assert_eq!(calls, 3);
+
+        // Silence has no crossings to time
+        let frames = Dio::new(16000.).track(&signal).unwrap();
+        assert!(frames.iter().all(|f| !f.voiced && f.f0 == 0.));
+
+        let mut dio = Dio::new(16000.);
+        dio.min_pitch = 0.;
+        assert!(dio.track(&signal).is_err());
+        let mut dio = Dio::new(16000.);
+        dio.bands_per_octave = 0.5;
+        assert!(dio.track(&signal).is_err());
+        assert!(Dio::new(16000.).track(&[f64::NAN]).is_err());
+    }
+}
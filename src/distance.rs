@@ -0,0 +1,216 @@
+//! Distances between feature frames, shared by DTW, vector quantization and nearest-neighbor
+//! searches, so that any of them can compare frames by whichever measure suits the features.
+//!
+//! Closures of two frames implement `Distance` too:
+//!
+//! ```
+//! use vox_box::distance::{Cosine, Distance};
+//!
+//! assert!(Cosine.distance(&[1., 0.], &[2., 0.]) < 1e-12);
+//! let manhattan = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum::<f64>();
+//! assert_eq!(manhattan.distance(&[0., 0.], &[1., 2.]), 3.);
+//! ```
+
+use crate::error::*;
+use crate::features::FeatureMatrix;
+
+pub trait Distance {
+    /// Distance between two frames of the same length. Zero for identical frames.
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64;
+}
+
+impl<F> Distance for F
+where
+    F: Fn(&[f64], &[f64]) -> f64,
+{
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        self(a, b)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Euclidean;
+
+impl Distance for Euclidean {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        SquaredEuclidean.distance(a, b).sqrt()
+    }
+}
+
+/// Euclidean distance squared, which the mean of a set of frames is the closest point to by, as
+/// k-means and LBG assume.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SquaredEuclidean;
+
+impl Distance for SquaredEuclidean {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+    }
+}
+
+/// One minus the cosine of the angle between the frames, which ignores their overall level. Zero
+/// frames are at distance one from everything.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cosine;
+
+impl Distance for Cosine {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        let dot: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        let norms = a.iter().map(|x| x * x).sum::<f64>().sqrt()
+            * b.iter().map(|y| y * y).sum::<f64>().sqrt();
+        if norms == 0. {
+            1.
+        } else {
+            1. - dot / norms
+        }
+    }
+}
+
+/// Euclidean distance after whitening by a covariance, so that dimensions with more spread and
+/// correlated dimensions count for less.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mahalanobis {
+    /// Lower-triangular Cholesky factor of the covariance, row by row
+    cholesky: FeatureMatrix,
+}
+
+impl Mahalanobis {
+    /// Distance for a covariance matrix, which must be symmetric and positive definite.
+    pub fn new(covariance: &FeatureMatrix) -> VoxBoxResult<Self> {
+        let n = covariance.n_cols();
+        if covariance.n_rows() != n || n == 0 {
+            return Err(VoxBoxError::Config("Covariance must be a square matrix"));
+        }
+        let c = covariance.as_slice();
+        if (0..n).any(|i| {
+            (0..i).any(|j| {
+                (c[i * n + j] - c[j * n + i]).abs() > 1.0e-12 * (c[i * n + i] * c[j * n + j]).sqrt()
+            })
+        }) {
+            return Err(VoxBoxError::Config("Covariance must be symmetric"));
+        }
+        let l =
+            cholesky(c, n).ok_or(VoxBoxError::Config("Covariance must be positive definite"))?;
+        Ok(Mahalanobis {
+            cholesky: FeatureMatrix::from_vec(n, l)?,
+        })
+    }
+
+    /// Distance for independent dimensions with the given variances, a standardized Euclidean
+    /// distance.
+    pub fn diagonal(variances: &[f64]) -> VoxBoxResult<Self> {
+        let n = variances.len();
+        let mut covariance = vec![0.; n * n];
+        for (i, v) in variances.iter().enumerate() {
+            covariance[i * n + i] = *v;
+        }
+        Mahalanobis::new(&FeatureMatrix::from_vec(n, covariance)?)
+    }
+}
+
+impl Distance for Mahalanobis {
+    /// Frames must be as long as the covariance is wide.
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        // Solve L y = a - b by forward substitution; the distance is the length of y
+        let n = self.cholesky.n_cols();
+        let mut y = vec![0.; n];
+        for i in 0..n {
+            let row = self.cholesky.row(i).unwrap();
+            let known: f64 = (0..i).map(|k| row[k] * y[k]).sum();
+            y[i] = (a[i] - b[i] - known) / row[i];
+        }
+        y.iter().map(|v| v * v).sum::<f64>().sqrt()
+    }
+}
+
+/// Lower-triangular factor `L` of an `n` by `n` symmetric matrix, row by row, such that
+/// `L L^T` is the matrix, or `None` if it isn't positive definite.
+pub(crate) fn cholesky(matrix: &[f64], n: usize) -> Option<Vec<f64>> {
+    let mut l = vec![0.; n * n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum = matrix[i * n + j] - (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum::<f64>();
+            if i == j {
+                if sum.is_nan() || sum <= 0. {
+                    return None;
+                }
+                l[i * n + i] = sum.sqrt();
+            } else {
+                l[i * n + j] = sum / l[j * n + j];
+            }
+        }
+    }
+    Some(l)
+}
+
+/// Index of the row of `candidates` nearest to `frame`, and its distance, or `None` if there are
+/// no candidates.
+pub fn nearest<D: Distance>(
+    candidates: &FeatureMatrix,
+    frame: &[f64],
+    distance: &D,
+) -> Option<(usize, f64)> {
+    candidates
+        .rows()
+        .map(|candidate| distance.distance(candidate, frame))
+        .enumerate()
+        .fold(None, |best, (k, d)| match best {
+            Some((_, best_d)) if best_d <= d => best,
+            _ => Some((k, d)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distances() {
+        assert_eq!(Euclidean.distance(&[0., 0.], &[3., 4.]), 5.);
+        assert_eq!(SquaredEuclidean.distance(&[0., 0.], &[3., 4.]), 25.);
+        assert!((Cosine.distance(&[1., 0.], &[0., 2.]) - 1.).abs() < 1e-12);
+        assert!((Cosine.distance(&[1., 1.], &[-1., -1.]) - 2.).abs() < 1e-12);
+        assert_eq!(Cosine.distance(&[0., 0.], &[1., 0.]), 1.);
+    }
+
+    #[test]
+    fn test_mahalanobis() {
+        // Variance 4 along the first dimension halves distances along it
+        let diagonal = Mahalanobis::diagonal(&[4., 1.]).unwrap();
+        assert!((diagonal.distance(&[2., 0.], &[0., 0.]) - 1.).abs() < 1e-12);
+        assert!((diagonal.distance(&[0., 2.], &[0., 0.]) - 2.).abs() < 1e-12);
+
+        // With correlated dimensions, moving along the correlation is cheap
+        let covariance = FeatureMatrix::from_vec(2, vec![1., 0.9, 0.9, 1.]).unwrap();
+        let correlated = Mahalanobis::new(&covariance).unwrap();
+        let along = correlated.distance(&[1., 1.], &[0., 0.]);
+        let across = correlated.distance(&[1., -1.], &[0., 0.]);
+        println!("along {}, across {}", along, across);
+        // x^T S^-1 x for eigenvalues 1.9 and 0.1
+        assert!((along - (2f64 / 1.9).sqrt()).abs() < 1e-12);
+        assert!((across - (2f64 / 0.1).sqrt()).abs() < 1e-9);
+
+        assert!(
+            Mahalanobis::new(&FeatureMatrix::from_vec(2, vec![1., 2., 2., 1.]).unwrap()).is_err()
+        );
+        assert!(
+            Mahalanobis::new(&FeatureMatrix::from_vec(2, vec![1., 0.5, 0., 1.]).unwrap()).is_err()
+        );
+        assert!(Mahalanobis::new(&FeatureMatrix::from_vec(3, vec![1.; 6]).unwrap()).is_err());
+        assert!(Mahalanobis::diagonal(&[1., 0.]).is_err());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let candidates = FeatureMatrix::from_rows(2, &[[0., 0.], [10., 0.], [0., 3.]]).unwrap();
+        assert_eq!(
+            nearest(&candidates, &[1., 2.5], &Euclidean),
+            Some((2, 1.25f64.sqrt()))
+        );
+        assert_eq!(
+            nearest(&candidates, &[9., 0.], &SquaredEuclidean),
+            Some((1, 1.))
+        );
+        assert_eq!(nearest(&FeatureMatrix::new(2), &[0., 0.], &Euclidean), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! Dynamic time warping: the cheapest alignment of two feature sequences, such as the MFCCs of two
+//! utterances of the same word, matching every frame of each to one or more frames of the other.
+//!
+//! ```
+//! use vox_box::distance::Euclidean;
+//! use vox_box::dtw::Dtw;
+//! use vox_box::features::FeatureMatrix;
+//!
+//! let a = FeatureMatrix::from_rows(1, &[[0.], [1.], [2.]]).unwrap();
+//! let b = FeatureMatrix::from_rows(1, &[[0.], [0.], [1.], [2.]]).unwrap();
+//! let alignment = Dtw::new().band(2).align(&a, &b, Euclidean).unwrap();
+//! assert_eq!(alignment.cost, 0.);
+//! assert_eq!(alignment.path, vec![(0, 0), (0, 1), (1, 2), (2, 3)]);
+//! ```
+
+use crate::distance::Distance;
+use crate::error::*;
+use crate::features::FeatureMatrix;
+
+/// An alignment of two sequences.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alignment {
+    /// Pairs of matched frames, from `(0, 0)` to the last frame of each, every step moving on
+    /// by one frame in either sequence or both
+    pub path: Vec<(usize, usize)>,
+    /// Sum of the frame distances along the path
+    pub cost: f64,
+}
+
+impl Alignment {
+    /// Cost divided by the total number of frames, for comparing alignments of sequences of
+    /// different lengths.
+    pub fn normalized_cost(&self, len_a: usize, len_b: usize) -> f64 {
+        self.cost / (len_a + len_b) as f64
+    }
+}
+
+/// Settings for aligning sequences.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dtw {
+    band: Option<usize>,
+}
+
+impl Dtw {
+    /// Alignment with no constraint, so that any frame may match any other.
+    pub fn new() -> Self {
+        Dtw::default()
+    }
+
+    /// Sakoe-Chiba band: frames only match if they are within `width` frames of the diagonal
+    /// from the start of both sequences to the end of both, which stops the path from taking
+    /// implausible detours and cuts the work to a strip around the diagonal.
+    pub fn band(mut self, width: usize) -> Self {
+        self.band = Some(width);
+        self
+    }
+
+    /// Aligns the rows of `a` with the rows of `b`, measuring the difference between two frames
+    /// with `distance`.
+    pub fn align<D: Distance>(
+        &self,
+        a: &FeatureMatrix,
+        b: &FeatureMatrix,
+        distance: D,
+    ) -> VoxBoxResult<Alignment> {
+        if a.is_empty() || b.is_empty() {
+            return Err(VoxBoxError::Input("Sequences must not be empty"));
+        }
+        if a.n_cols() != b.n_cols() {
+            return Err(VoxBoxError::Input(
+                "Frames must have the same number of values",
+            ));
+        }
+        let (n, m) = (a.n_rows(), b.n_rows());
+        // Column range of each row inside the band
+        let slope = if n > 1 {
+            (m - 1) as f64 / (n - 1) as f64
+        } else {
+            0.
+        };
+        let columns = |i: usize| match self.band {
+            Some(width) => {
+                let center = i as f64 * slope;
+                let lo = (center - width as f64).ceil().max(0.) as usize;
+                let hi = ((center + width as f64).floor() as usize).min(m - 1);
+                (lo, hi)
+            }
+            None => (0, m - 1),
+        };
+
+        // Cheapest cost of reaching each cell, row by row
+        let mut cost = vec![f64::INFINITY; n * m];
+        for i in 0..n {
+            let (lo, hi) = columns(i);
+            let row = a.row(i).unwrap();
+            for j in lo..=hi {
+                let previous = if i == 0 && j == 0 {
+                    0.
+                } else {
+                    let up = if i > 0 {
+                        cost[(i - 1) * m + j]
+                    } else {
+                        f64::INFINITY
+                    };
+                    let left = if j > 0 {
+                        cost[i * m + j - 1]
+                    } else {
+                        f64::INFINITY
+                    };
+                    let diagonal = if i > 0 && j > 0 {
+                        cost[(i - 1) * m + j - 1]
+                    } else {
+                        f64::INFINITY
+                    };
+                    up.min(left).min(diagonal)
+                };
+                cost[i * m + j] = previous + distance.distance(row, b.row(j).unwrap());
+            }
+        }
+        let total = cost[n * m - 1];
+        if total.is_infinite() {
+            return Err(VoxBoxError::Config("No alignment fits within the band"));
+        }
+        if total.is_nan() {
+            return Err(VoxBoxError::Input("Frame distance must not be NaN"));
+        }
+
+        // Trace back from the end, preferring the diagonal on ties
+        let mut path = vec![(n - 1, m - 1)];
+        let (mut i, mut j) = (n - 1, m - 1);
+        while i > 0 || j > 0 {
+            let mut step = (i, j);
+            let mut best = f64::INFINITY;
+            let candidates = [
+                (i > 0 && j > 0, i.wrapping_sub(1), j.wrapping_sub(1)),
+                (i > 0, i.wrapping_sub(1), j),
+                (j > 0, i, j.wrapping_sub(1)),
+            ];
+            for (valid, ci, cj) in candidates.iter() {
+                if *valid && cost[ci * m + cj] < best {
+                    best = cost[ci * m + cj];
+                    step = (*ci, *cj);
+                }
+            }
+            i = step.0;
+            j = step.1;
+            path.push(step);
+        }
+        path.reverse();
+        Ok(Alignment { path, cost: total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::Euclidean;
+
+    fn sequence(values: &[f64]) -> FeatureMatrix {
+        let rows: Vec<[f64; 2]> = values.iter().map(|v| [*v, -v]).collect();
+        FeatureMatrix::from_rows(2, &rows).unwrap()
+    }
+
+    #[test]
+    fn test_dtw() {
+        // A slowed-down copy aligns at no cost
+        let a = sequence(&[0., 1., 2., 3., 2., 1.]);
+        let b = sequence(&[0., 0., 1., 1., 2., 3., 3., 2., 1.]);
+        let alignment = Dtw::new().align(&a, &b, Euclidean).unwrap();
+        println!("{:?}", alignment);
+        assert_eq!(alignment.cost, 0.);
+        assert_eq!(alignment.path.first(), Some(&(0, 0)));
+        assert_eq!(alignment.path.last(), Some(&(5, 8)));
+        for pair in alignment.path.windows(2) {
+            let (di, dj) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+            assert!(di <= 1 && dj <= 1 && di + dj > 0);
+        }
+        assert!(alignment.normalized_cost(6, 9) == 0.);
+
+        // Different sequences cost more, and a distance of our own is used
+        let c = sequence(&[3., 2., 1., 0., 1., 2.]);
+        let manhattan = |x: &[f64], y: &[f64]| x.iter().zip(y).map(|(p, q)| (p - q).abs()).sum();
+        let far = Dtw::new().align(&a, &c, manhattan).unwrap();
+        assert!(far.cost > 4.);
+        assert_eq!(Dtw::new().align(&a, &a, manhattan).unwrap().path.len(), 6);
+    }
+
+    #[test]
+    fn test_dtw_band() {
+        // The band keeps the path near the diagonal, at some cost
+        let a = sequence(&[0., 0., 0., 0., 0., 5.]);
+        let b = sequence(&[0., 5., 5., 5., 5., 5.]);
+        let free = Dtw::new().align(&a, &b, Euclidean).unwrap();
+        let banded = Dtw::new().band(1).align(&a, &b, Euclidean).unwrap();
+        println!("free {:?}\nbanded {:?}", free, banded);
+        assert!(banded.cost > free.cost);
+        assert!(banded
+            .path
+            .iter()
+            .all(|(i, j)| (*i as isize - *j as isize).abs() <= 1));
+
+        assert!(Dtw::new()
+            .band(0)
+            .align(&a, &sequence(&[0., 1.]), Euclidean)
+            .is_err());
+        assert!(Dtw::new()
+            .align(&a, &FeatureMatrix::new(2), Euclidean)
+            .is_err());
+        assert!(Dtw::new()
+            .align(
+                &a,
+                &FeatureMatrix::from_rows(1, &[[0.]]).unwrap(),
+                Euclidean
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,513 @@
+//! Streaming dynamics processors, which change the gain of a signal according to its level,
+//! for cleaning up and leveling recordings before they are measured.
+//!
+//! ```
+//! use vox_box::dynamics::NoiseGate;
+//! use vox_box::processor::Processor;
+//! use vox_box::units::Db;
+//!
+//! let hiss = vec![0.001f64; 800];
+//! let mut gate = NoiseGate::new(Db(-40.), 8000.).unwrap();
+//! let mut output = vec![0.; 800];
+//! gate.process(&hiss, &mut output).unwrap();
+//! assert_eq!(output[799], 0.);
+//! ```
+
+use num::{Float, FromPrimitive};
+
+use crate::error::*;
+use crate::processor::Processor;
+use crate::units::{Db, Hz, Seconds};
+use crate::validate;
+
+/// Time for the gate's level detector to fall by a factor of e, long enough to ride over the
+/// zero crossings of low voices.
+const GATE_DETECTOR_DECAY: Seconds = Seconds(0.01);
+
+/// Coefficient of a one-pole smoother that moves a factor of e towards its target in `time`,
+/// or 0, which jumps straight there, for no time at all.
+fn smoothing_coefficient(time: Seconds, sample_rate: f64) -> f64 {
+    if time.0 > 0. {
+        (-1. / (time.0 * sample_rate)).exp()
+    } else {
+        0.
+    }
+}
+
+/// Fails unless an attack or release time is finite and not negative.
+fn smoothing_time(time: Seconds) -> VoxBoxResult<Seconds> {
+    if !(time.0 >= 0. && time.0.is_finite()) {
+        return Err(VoxBoxError::Config(
+            "Attack and release must be finite and not negative",
+        ));
+    }
+    Ok(time)
+}
+
+/// A noise gate: the signal passes while its level is above `threshold`, and is turned down
+/// by `range` while it is quiet, which takes out hiss and room noise between utterances.
+///
+/// The gate opens when the peak level rises above the threshold and closes once it has stayed
+/// `hysteresis` below it for the hold time, so a level hovering around the threshold doesn't
+/// make it chatter. Opening fades the gain in over the attack time and closing fades it out
+/// over the release time, both linearly.
+#[derive(Clone, Debug)]
+pub struct NoiseGate<T> {
+    sample_rate: f64,
+    threshold: Db,
+    hysteresis: Db,
+    attack: Seconds,
+    hold: Seconds,
+    release: Seconds,
+    range: Db,
+    level: f64,
+    gain: f64,
+    open: bool,
+    /// Samples left before the gate may close
+    holding: usize,
+    _sample: std::marker::PhantomData<T>,
+}
+
+impl<T: Float + FromPrimitive> NoiseGate<T> {
+    /// A gate at `threshold` relative to full scale, with 1 ms attack, 50 ms hold, 100 ms
+    /// release, 6 dB of hysteresis and silence while closed.
+    pub fn new<R: Into<Hz>>(threshold: Db, sample_rate: R) -> VoxBoxResult<Self> {
+        let sample_rate = sample_rate.into().0;
+        validate::positive(sample_rate, "Sample rate must be positive")?;
+        if !threshold.0.is_finite() {
+            return Err(VoxBoxError::Config("Threshold must be finite"));
+        }
+        Ok(NoiseGate {
+            sample_rate,
+            threshold,
+            hysteresis: Db(6.),
+            attack: Seconds(0.001),
+            hold: Seconds(0.05),
+            release: Seconds(0.1),
+            range: Db(f64::NEG_INFINITY),
+            level: 0.,
+            gain: 0.,
+            open: false,
+            holding: 0,
+            _sample: std::marker::PhantomData,
+        })
+    }
+
+    /// How far below the threshold the level must fall before the gate closes.
+    pub fn hysteresis(mut self, hysteresis: Db) -> VoxBoxResult<Self> {
+        if !(hysteresis.0 >= 0. && hysteresis.0.is_finite()) {
+            return Err(VoxBoxError::Config(
+                "Hysteresis must be finite and not negative",
+            ));
+        }
+        self.hysteresis = hysteresis;
+        Ok(self)
+    }
+
+    pub fn attack(mut self, attack: Seconds) -> VoxBoxResult<Self> {
+        self.attack = smoothing_time(attack)?;
+        Ok(self)
+    }
+
+    /// How long the gate stays open after the level falls, which bridges short pauses.
+    pub fn hold(mut self, hold: Seconds) -> VoxBoxResult<Self> {
+        if !(hold.0 >= 0. && hold.0.is_finite()) {
+            return Err(VoxBoxError::Config("Hold must be finite and not negative"));
+        }
+        self.hold = hold;
+        Ok(self)
+    }
+
+    pub fn release(mut self, release: Seconds) -> VoxBoxResult<Self> {
+        self.release = smoothing_time(release)?;
+        Ok(self)
+    }
+
+    /// Gain while closed, at most 0 dB. Minus infinity silences.
+    pub fn range(mut self, range: Db) -> VoxBoxResult<Self> {
+        if range.0.is_nan() || range.0 > 0. {
+            return Err(VoxBoxError::Config("Range must be at most 0 dB"));
+        }
+        self.range = range;
+        Ok(self)
+    }
+
+    /// Whether the gate is letting the signal through.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+impl<T: Float + FromPrimitive> Processor<T> for NoiseGate<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, input.len())?;
+        let open_level = self.threshold.to_amplitude();
+        let close_level = Db(self.threshold.0 - self.hysteresis.0).to_amplitude();
+        let floor = self.range.to_amplitude();
+        let decay = smoothing_coefficient(GATE_DETECTOR_DECAY, self.sample_rate);
+        let hold = self.hold.0 * self.sample_rate;
+        // Gain change per sample while fading in and out
+        let fade = |time: Seconds| {
+            let samples = time.0 * self.sample_rate;
+            if samples >= 1. {
+                (1. - floor) / samples
+            } else {
+                1.
+            }
+        };
+        let (attack, release) = (fade(self.attack), fade(self.release));
+        for (y, x) in output.iter_mut().zip(input.iter()) {
+            let sample = x.to_f64().unwrap_or(0.);
+            self.level = sample.abs().max(decay * self.level);
+            if self.level > open_level {
+                self.open = true;
+                self.holding = hold.round() as usize;
+            } else if self.level >= close_level && self.open {
+                self.holding = hold.round() as usize;
+            } else if self.holding > 0 {
+                self.holding -= 1;
+            } else {
+                self.open = false;
+            }
+            self.gain = if self.open {
+                (self.gain + attack).min(1.)
+            } else {
+                (self.gain - release).max(floor)
+            };
+            *y = *x * T::from_f64(self.gain).unwrap();
+        }
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {
+        self.level = 0.;
+        self.gain = 0.;
+        self.open = false;
+        self.holding = 0;
+    }
+}
+
+/// How `Compressor` measures the level it compares with the threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Detection {
+    /// The magnitude of each sample, which catches every peak, as a limiter needs
+    Peak,
+    /// The root mean square over about the given time, which follows loudness more closely
+    Rms(Seconds),
+}
+
+/// A feed-forward compressor: above `threshold`, every `ratio` dB of input level gives 1 dB of
+/// output level, which evens out loud and soft passages. With an infinite ratio and peak
+/// detection, from `Compressor::limiter`, nothing gets above the threshold.
+///
+/// The gain is worked out in decibels (Giannoulis et al. 2012): a soft knee eases the ratio in
+/// over `knee` dB around the threshold, the gain reduction follows rises in level over the
+/// attack time and falls over the release time, and `makeup` gain then brings the level back
+/// up.
+#[derive(Clone, Debug)]
+pub struct Compressor<T> {
+    sample_rate: f64,
+    threshold: Db,
+    ratio: f64,
+    knee: Db,
+    attack: Seconds,
+    release: Seconds,
+    makeup: Db,
+    detection: Detection,
+    /// Mean square of the input, for RMS detection
+    mean_square: f64,
+    /// Smoothed gain reduction, in dB
+    reduction: f64,
+    _sample: std::marker::PhantomData<T>,
+}
+
+impl<T: Float + FromPrimitive> Compressor<T> {
+    /// A compressor at `threshold` relative to full scale, with a hard knee, 5 ms attack,
+    /// 100 ms release, no makeup gain and RMS detection over 10 ms. The ratio must be at
+    /// least 1.
+    pub fn new<R: Into<Hz>>(threshold: Db, ratio: f64, sample_rate: R) -> VoxBoxResult<Self> {
+        let sample_rate = sample_rate.into().0;
+        validate::positive(sample_rate, "Sample rate must be positive")?;
+        if !threshold.0.is_finite() {
+            return Err(VoxBoxError::Config("Threshold must be finite"));
+        }
+        if ratio.is_nan() || ratio < 1. {
+            return Err(VoxBoxError::Config("Ratio must be at least 1"));
+        }
+        Ok(Compressor {
+            sample_rate,
+            threshold,
+            ratio,
+            knee: Db(0.),
+            attack: Seconds(0.005),
+            release: Seconds(0.1),
+            makeup: Db(0.),
+            detection: Detection::Rms(Seconds(0.01)),
+            mean_square: 0.,
+            reduction: 0.,
+            _sample: std::marker::PhantomData,
+        })
+    }
+
+    /// A limiter: an infinite ratio on the peak level, with no attack time so that no peak
+    /// gets through, and 50 ms release.
+    pub fn limiter<R: Into<Hz>>(threshold: Db, sample_rate: R) -> VoxBoxResult<Self> {
+        Compressor::new(threshold, f64::INFINITY, sample_rate)?
+            .detection(Detection::Peak)?
+            .attack(Seconds(0.))?
+            .release(Seconds(0.05))
+    }
+
+    /// Width of the soft knee, centered on the threshold. 0 dB is a hard knee.
+    pub fn knee(mut self, knee: Db) -> VoxBoxResult<Self> {
+        if !(knee.0 >= 0. && knee.0.is_finite()) {
+            return Err(VoxBoxError::Config("Knee must be finite and not negative"));
+        }
+        self.knee = knee;
+        Ok(self)
+    }
+
+    pub fn attack(mut self, attack: Seconds) -> VoxBoxResult<Self> {
+        self.attack = smoothing_time(attack)?;
+        Ok(self)
+    }
+
+    pub fn release(mut self, release: Seconds) -> VoxBoxResult<Self> {
+        self.release = smoothing_time(release)?;
+        Ok(self)
+    }
+
+    /// Gain applied after compression.
+    pub fn makeup(mut self, makeup: Db) -> VoxBoxResult<Self> {
+        if !makeup.0.is_finite() {
+            return Err(VoxBoxError::Config("Makeup gain must be finite"));
+        }
+        self.makeup = makeup;
+        Ok(self)
+    }
+
+    pub fn detection(mut self, detection: Detection) -> VoxBoxResult<Self> {
+        if let Detection::Rms(time) = detection {
+            if !(time.0 > 0. && time.0.is_finite()) {
+                return Err(VoxBoxError::Config("RMS time must be positive"));
+            }
+        }
+        self.detection = detection;
+        Ok(self)
+    }
+
+    /// How far the gain is currently turned down, not counting the makeup gain.
+    pub fn gain_reduction(&self) -> Db {
+        Db(-self.reduction)
+    }
+
+    /// Output level for a steady input `level`, before makeup gain, both in dB.
+    fn curve(&self, level: f64) -> f64 {
+        let (threshold, knee) = (self.threshold.0, self.knee.0);
+        let slope = 1. / self.ratio - 1.;
+        let over = level - threshold;
+        if 2. * over < -knee {
+            level
+        } else if 2. * over <= knee {
+            level + slope * (over + knee / 2.).powi(2) / (2. * knee)
+        } else {
+            level + slope * over
+        }
+    }
+}
+
+impl<T: Float + FromPrimitive> Processor<T> for Compressor<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, input.len())?;
+        let attack = smoothing_coefficient(self.attack, self.sample_rate);
+        let release = smoothing_coefficient(self.release, self.sample_rate);
+        let rms = match self.detection {
+            Detection::Rms(time) => Some(smoothing_coefficient(time, self.sample_rate)),
+            Detection::Peak => None,
+        };
+        for (y, x) in output.iter_mut().zip(input.iter()) {
+            let sample = x.to_f64().unwrap_or(0.);
+            let level = match rms {
+                Some(coefficient) => {
+                    self.mean_square =
+                        coefficient * self.mean_square + (1. - coefficient) * sample * sample;
+                    self.mean_square.sqrt()
+                }
+                None => sample.abs(),
+            };
+            let level = Db::from_amplitude(level.max(1.0e-10)).0;
+            let target = level - self.curve(level);
+            let coefficient = if target > self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction = coefficient * self.reduction + (1. - coefficient) * target;
+            let gain = Db(self.makeup.0 - self.reduction).to_amplitude();
+            *y = *x * T::from_f64(gain).unwrap();
+        }
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {
+        self.mean_square = 0.;
+        self.reduction = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// A 200 Hz tone at 8 kHz of `level` relative to full scale, `len` samples long.
+    fn tone(level: Db, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| level.to_amplitude() * (2. * PI * 200. * n as f64 / 8000.).sin())
+            .collect()
+    }
+
+    fn run<P: Processor<f64>>(processor: &mut P, signal: &[f64]) -> Vec<f64> {
+        let mut output = vec![0.; signal.len()];
+        for (input, out) in signal.chunks(100).zip(output.chunks_mut(100)) {
+            processor.process(input, out).unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn test_noise_gate() {
+        // Quiet, loud, then quiet again
+        let mut signal = tone(Db(-50.), 4000);
+        signal.extend(tone(Db(-10.), 4000));
+        signal.extend(tone(Db(-50.), 4000));
+        let mut gate = NoiseGate::new(Db(-30.), 8000.).unwrap();
+        let output = run(&mut gate, &signal);
+
+        assert!(output[..4000].iter().all(|y| *y == 0.));
+        // Open within a period plus the 1 ms attack, then passing the tone untouched
+        assert!(output[4000 + 60..8000]
+            .iter()
+            .zip(signal[4000 + 60..8000].iter())
+            .all(|(y, x)| y == x));
+        // Held for 50 ms once the level has fallen, then released over 100 ms
+        let peak = |range: std::ops::Range<usize>| {
+            output[range].iter().fold(0., |m: f64, y| m.max(y.abs()))
+        };
+        let quiet = Db(-50.).to_amplitude();
+        assert!((peak(8100..8300) - quiet).abs() < 1e-9);
+        assert!(peak(9200..9300) < quiet / 2.);
+        assert!(output[10000..].iter().all(|y| *y == 0.));
+        assert!(!gate.is_open());
+
+        // With some range, the quiet parts are only turned down
+        let mut gate = NoiseGate::new(Db(-30.), 8000.)
+            .and_then(|g| g.range(Db(-20.)))
+            .unwrap();
+        let output = run(&mut gate, &signal);
+        assert!((output[1000] - signal[1000] * 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_noise_gate_hysteresis() {
+        // A level that dips a little below the threshold keeps the gate open, but a bigger dip
+        // closes it, even after the hold
+        let mut signal = tone(Db(-20.), 2000);
+        signal.extend(tone(Db(-24.), 4000));
+        let mut gate = NoiseGate::new(Db(-22.), 8000.).unwrap();
+        run(&mut gate, &signal);
+        assert!(gate.is_open());
+        let mut gate = NoiseGate::new(Db(-22.), 8000.)
+            .and_then(|g| g.hysteresis(Db(1.)))
+            .unwrap();
+        run(&mut gate, &signal);
+        assert!(!gate.is_open());
+
+        let gate = || NoiseGate::<f64>::new(Db(-20.), 8000.).unwrap();
+        assert!(NoiseGate::<f64>::new(Db(f64::NAN), 8000.).is_err());
+        assert!(NoiseGate::<f64>::new(Db(-20.), 0.).is_err());
+        assert!(gate().hysteresis(Db(-1.)).is_err());
+        assert!(gate().attack(Seconds(f64::INFINITY)).is_err());
+        assert!(gate().hold(Seconds(-1.)).is_err());
+        assert!(gate().range(Db(3.)).is_err());
+        let mut output = vec![0.; 10];
+        let mut gate = NoiseGate::new(Db(-20.), 8000.).unwrap();
+        assert!(gate.process(&signal[..10], &mut output[..5]).is_err());
+        gate.process(&signal[..10], &mut output).unwrap();
+        gate.reset();
+        assert!(!gate.is_open());
+    }
+
+    /// Level in dB of the largest sample of `signal`.
+    fn peak_level(signal: &[f64]) -> f64 {
+        Db::from_amplitude(signal.iter().fold(0., |m: f64, y| m.max(y.abs()))).0
+    }
+
+    #[test]
+    fn test_compressor_curve() {
+        // Steady levels, so the static curve shows through exactly
+        let dc = |level: f64| vec![Db(level).to_amplitude(); 4000];
+        let mut compressor = Compressor::new(Db(-20.), 4., 8000.)
+            .and_then(|c| c.detection(Detection::Peak))
+            .unwrap();
+        let output = run(&mut compressor, &dc(-8.));
+        assert!((peak_level(&output[3000..]) - -17.).abs() < 1e-6);
+        assert!((compressor.gain_reduction().0 - -9.).abs() < 1e-6);
+        compressor.reset();
+        let output = run(&mut compressor, &dc(-30.));
+        assert!((peak_level(&output) - -30.).abs() < 1e-9);
+
+        // A soft knee eases in around the threshold
+        let mut soft = Compressor::new(Db(-20.), 4., 8000.)
+            .and_then(|c| c.detection(Detection::Peak))
+            .and_then(|c| c.knee(Db(10.)))
+            .unwrap();
+        let output = run(&mut soft, &dc(-20.));
+        assert!((peak_level(&output[3000..]) - (-20. - 0.75 * 25. / 20.)).abs() < 1e-6);
+        soft.reset();
+        let output = run(&mut soft, &dc(-26.));
+        assert!((peak_level(&output[3000..]) - -26.).abs() < 1e-9);
+
+        // Makeup gain lifts everything
+        let mut lifted = Compressor::new(Db(-20.), 4., 8000.)
+            .and_then(|c| c.makeup(Db(6.)))
+            .unwrap();
+        let output = run(&mut lifted, &dc(-30.));
+        assert!((peak_level(&output) - -24.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_compressor_timing() {
+        // A tone 20 dB over the threshold, after a quiet stretch
+        let mut signal = tone(Db(-40.), 800);
+        signal.extend(tone(Db(0.), 8000));
+        let mut compressor = Compressor::new(Db(-20.), 2., 8000.)
+            .and_then(|c| c.attack(Seconds(0.01)))
+            .unwrap();
+        let output = run(&mut compressor, &signal);
+        // The RMS of the tone is 3 dB under its peak, so 17 dB over, and comes out 8.5 dB over
+        let settled = peak_level(&output[4800..]);
+        println!("settled peak {}", settled);
+        assert!((settled - (-20. + 8.5 + 3.)).abs() < 0.3);
+        // The attack lets the start of the tone through louder
+        assert!(peak_level(&output[800..880]) > settled + 3.);
+
+        // A limiter stops every sample at the threshold
+        let mut limiter = Compressor::limiter(Db(-6.), 8000.).unwrap();
+        let output = run(&mut limiter, &signal);
+        let threshold = Db(-6.).to_amplitude();
+        assert!(output.iter().all(|y| y.abs() <= threshold + 1e-12));
+        assert!(peak_level(&output) > -6.1);
+
+        let compressor = || Compressor::<f64>::new(Db(-20.), 2., 8000.).unwrap();
+        assert!(Compressor::<f64>::new(Db(-20.), 0.5, 8000.).is_err());
+        assert!(Compressor::<f64>::new(Db(f64::NAN), 2., 8000.).is_err());
+        assert!(Compressor::<f64>::new(Db(-20.), 2., 0.).is_err());
+        assert!(compressor().knee(Db(-1.)).is_err());
+        assert!(compressor().release(Seconds(-1.)).is_err());
+        assert!(compressor().makeup(Db(f64::INFINITY)).is_err());
+        assert!(compressor().detection(Detection::Rms(Seconds(0.))).is_err());
+        let mut output = vec![0.; 5];
+        assert!(compressor().process(&signal[..10], &mut output).is_err());
+    }
+}
@@ -1,20 +1,51 @@
 use std::error::Error;
 use std::fmt;
+use std::io;
 
 pub type VoxBoxResult<T> = Result<T, VoxBoxError>;
 
 #[derive(Debug)]
 pub enum VoxBoxError {
+    /// Invalid analysis settings
+    Config(&'static str),
+    /// Empty, too short or non-finite input
+    Input(&'static str),
     /// LPC calculation error
     LPC(&'static str),
     /// Pitch calculation error
     Pitch(&'static str),
     /// Polynomial calculation error
     Polynomial(&'static str),
+    /// Resynthesis error
+    Synthesis(&'static str),
+    /// Analysis stopped by a progress callback
+    Cancelled,
+    /// Vocoder analysis or synthesis error
+    Vocoder(&'static str),
+    /// Audio file could not be read or written
+    Io(&'static str),
     /// Not enough workspace allocated
     Workspace,
 }
 
+impl From<io::Error> for VoxBoxError {
+    /// Keeps the kind of failure, as far as a fixed message can.
+    fn from(e: io::Error) -> Self {
+        VoxBoxError::Io(match e.kind() {
+            io::ErrorKind::NotFound => "File not found",
+            io::ErrorKind::PermissionDenied => "Permission denied",
+            io::ErrorKind::AlreadyExists => "File already exists",
+            io::ErrorKind::IsADirectory => "Path is a directory",
+            io::ErrorKind::UnexpectedEof => "File ended unexpectedly",
+            io::ErrorKind::InvalidData => "File holds invalid data",
+            io::ErrorKind::WriteZero | io::ErrorKind::StorageFull => {
+                "Could not write the whole file"
+            }
+            _ => "Could not read or write the file",
+        })
+    }
+}
+
 impl fmt::Display for VoxBoxError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         fmt.write_str(self.description())
@@ -25,9 +56,15 @@ impl Error for VoxBoxError {
     fn description(&self) -> &str {
         use self::VoxBoxError::*;
         match *self {
+            Config(s) => s,
+            Input(s) => s,
             LPC(s) => s,
             Pitch(s) => s,
             Polynomial(s) => s,
+            Synthesis(s) => s,
+            Vocoder(s) => s,
+            Io(s) => s,
+            Cancelled => "Analysis cancelled",
             Workspace => "Not enough workspace allocated",
         }
     }
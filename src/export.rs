@@ -0,0 +1,267 @@
+//! Writes the time-aligned tracks of an `Analysis` to CSV or JSON, one row or object per frame.
+//!
+//! ```
+//! use vox_box::export::{Column, Export};
+//!
+//! let signal: Vec<f64> = (0..8000).map(|n| (n as f64 * 0.12).sin() * 0.5).collect();
+//! let analysis = vox_box::analyze(&signal, 8000., vox_box::AnalyzeOptions::default()).unwrap();
+//! let mut csv = Vec::new();
+//! Export::new()
+//!     .columns(&[Column::Time, Column::Pitch, Column::Intensity])
+//!     .write_csv(&analysis, &mut csv)
+//!     .unwrap();
+//! assert!(String::from_utf8(csv).unwrap().starts_with("time,f0,intensity\n"));
+//! ```
+//!
+//! Values that are missing, such as the pitch of an unvoiced frame, are left empty in CSV and
+//! written as `null` in JSON.
+
+use std::io::Write;
+
+use crate::analysis::Analysis;
+use crate::error::*;
+use crate::segment::SegmentLabel;
+
+/// A track to export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// Center of the frame, in seconds
+    Time,
+    /// Fundamental frequency in Hz, as `f0`
+    Pitch,
+    /// Level in dB relative to full scale
+    Intensity,
+    /// `silence`, `unvoiced` or `voiced`
+    Voicing,
+    /// Frequency of a formant, counting from 1, as `F1`, `F2` and so on
+    #[cfg(feature = "formant")]
+    Formant(usize),
+    /// Bandwidth of a formant, counting from 1, as `B1`, `B2` and so on
+    #[cfg(feature = "formant")]
+    Bandwidth(usize),
+    /// One MFCC, counting from 0, as `mfcc0`, `mfcc1` and so on
+    #[cfg(feature = "mfcc")]
+    Mfcc(usize),
+    /// Every MFCC the analysis has
+    #[cfg(feature = "mfcc")]
+    Mfccs,
+}
+
+/// A value in the output.
+enum Cell {
+    Number(f64),
+    Text(&'static str),
+}
+
+/// Which columns to write, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Export {
+    columns: Vec<Column>,
+}
+
+impl Default for Export {
+    /// Time, pitch, the first four formants, intensity and the MFCCs, as far as the enabled
+    /// features allow.
+    fn default() -> Self {
+        let mut columns = vec![Column::Time, Column::Pitch];
+        #[cfg(feature = "formant")]
+        columns.extend((1..=4).map(Column::Formant));
+        columns.push(Column::Intensity);
+        #[cfg(feature = "mfcc")]
+        columns.push(Column::Mfccs);
+        Export { columns }
+    }
+}
+
+impl Export {
+    pub fn new() -> Self {
+        Export::default()
+    }
+
+    pub fn columns(mut self, columns: &[Column]) -> Self {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    /// Writes a header row of the column names, then a row per frame.
+    pub fn write_csv<W: Write>(&self, analysis: &Analysis, mut writer: W) -> VoxBoxResult<()> {
+        let columns = self.expand(analysis)?;
+        let header: Vec<String> = columns.iter().map(|c| name(*c)).collect();
+        writeln!(writer, "{}", header.join(",")).map_err(write_error)?;
+        for k in 0..analysis.len() {
+            let row: Vec<String> = columns
+                .iter()
+                .map(|c| match cell(*c, analysis, k) {
+                    Cell::Number(x) if x.is_finite() => x.to_string(),
+                    Cell::Number(_) => String::new(),
+                    Cell::Text(s) => s.to_string(),
+                })
+                .collect();
+            writeln!(writer, "{}", row.join(",")).map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an array of an object per frame, keyed by the column names.
+    pub fn write_json<W: Write>(&self, analysis: &Analysis, mut writer: W) -> VoxBoxResult<()> {
+        let columns = self.expand(analysis)?;
+        let frames: Vec<String> = (0..analysis.len())
+            .map(|k| {
+                let fields: Vec<String> = columns
+                    .iter()
+                    .map(|c| {
+                        let value = match cell(*c, analysis, k) {
+                            Cell::Number(x) if x.is_finite() => x.to_string(),
+                            Cell::Number(_) => "null".to_string(),
+                            Cell::Text(s) => format!("\"{}\"", s),
+                        };
+                        format!("\"{}\":{}", name(*c), value)
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            })
+            .collect();
+        writeln!(writer, "[{}]", frames.join(",\n")).map_err(write_error)
+    }
+
+    /// The columns with `Mfccs` replaced by one column per coefficient.
+    #[cfg_attr(not(feature = "mfcc"), allow(unused_variables))]
+    fn expand(&self, analysis: &Analysis) -> VoxBoxResult<Vec<Column>> {
+        let mut columns = Vec::with_capacity(self.columns.len());
+        for column in self.columns.iter() {
+            match *column {
+                #[cfg(feature = "formant")]
+                Column::Formant(0) | Column::Bandwidth(0) => {
+                    return Err(VoxBoxError::Config("Formants are counted from 1"));
+                }
+                #[cfg(feature = "mfcc")]
+                Column::Mfccs => {
+                    let n_mfcc = analysis.mfcc.iter().map(|m| m.len()).max().unwrap_or(0);
+                    columns.extend((0..n_mfcc).map(Column::Mfcc));
+                }
+                c => columns.push(c),
+            }
+        }
+        Ok(columns)
+    }
+}
+
+fn name(column: Column) -> String {
+    match column {
+        Column::Time => "time".to_string(),
+        Column::Pitch => "f0".to_string(),
+        Column::Intensity => "intensity".to_string(),
+        Column::Voicing => "voicing".to_string(),
+        #[cfg(feature = "formant")]
+        Column::Formant(n) => format!("F{}", n),
+        #[cfg(feature = "formant")]
+        Column::Bandwidth(n) => format!("B{}", n),
+        #[cfg(feature = "mfcc")]
+        Column::Mfcc(n) => format!("mfcc{}", n),
+        #[cfg(feature = "mfcc")]
+        Column::Mfccs => "mfcc".to_string(),
+    }
+}
+
+/// The value of `column` in frame `k`, NaN if the frame doesn't have one.
+fn cell(column: Column, analysis: &Analysis, k: usize) -> Cell {
+    match column {
+        Column::Time => Cell::Number(analysis.times[k].0),
+        Column::Pitch => Cell::Number(analysis.pitch[k]),
+        Column::Intensity => Cell::Number(analysis.intensity[k].0),
+        Column::Voicing => Cell::Text(match analysis.voicing[k] {
+            SegmentLabel::Silence => "silence",
+            SegmentLabel::Unvoiced => "unvoiced",
+            SegmentLabel::Voiced => "voiced",
+        }),
+        #[cfg(feature = "formant")]
+        Column::Formant(n) => Cell::Number(
+            analysis.formants[k]
+                .get(n - 1)
+                .map_or(f64::NAN, |r| r.frequency),
+        ),
+        #[cfg(feature = "formant")]
+        Column::Bandwidth(n) => Cell::Number(
+            analysis.formants[k]
+                .get(n - 1)
+                .map_or(f64::NAN, |r| r.bandwidth),
+        ),
+        #[cfg(feature = "mfcc")]
+        Column::Mfcc(n) => Cell::Number(analysis.mfcc[k].get(n).cloned().unwrap_or(f64::NAN)),
+        #[cfg(feature = "mfcc")]
+        Column::Mfccs => Cell::Number(f64::NAN),
+    }
+}
+
+fn write_error(_: std::io::Error) -> VoxBoxError {
+    VoxBoxError::Io("Could not write the export")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Db, Seconds};
+
+    fn analysis() -> Analysis {
+        Analysis {
+            frame_period: Seconds(0.01),
+            times: vec![Seconds(0.0125), Seconds(0.0225)],
+            voicing: vec![SegmentLabel::Silence, SegmentLabel::Voiced],
+            pitch: vec![f64::NAN, 120.5],
+            intensity: vec![Db(-60.), Db(-20.25)],
+            #[cfg(feature = "formant")]
+            formants: vec![vec![], vec![crate::spectrum::Resonance::new(700., 80.)]],
+            #[cfg(feature = "mfcc")]
+            mfcc: vec![vec![1., 2.], vec![3., 4.]],
+        }
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let mut csv = Vec::new();
+        Export::new()
+            .columns(&[
+                Column::Time,
+                Column::Pitch,
+                Column::Voicing,
+                Column::Intensity,
+            ])
+            .write_csv(&analysis(), &mut csv)
+            .unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        println!("{}", csv);
+        assert_eq!(
+            csv,
+            "time,f0,voicing,intensity\n0.0125,,silence,-60\n0.0225,120.5,voiced,-20.25\n"
+        );
+    }
+
+    #[test]
+    fn test_export_json() {
+        let mut json = Vec::new();
+        Export::new()
+            .columns(&[Column::Time, Column::Pitch, Column::Voicing])
+            .write_json(&analysis(), &mut json)
+            .unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(
+            json,
+            "[{\"time\":0.0125,\"f0\":null,\"voicing\":\"silence\"},\n\
+             {\"time\":0.0225,\"f0\":120.5,\"voicing\":\"voiced\"}]\n"
+        );
+    }
+
+    #[cfg(all(feature = "formant", feature = "mfcc"))]
+    #[test]
+    fn test_export_default_columns() {
+        let mut csv = Vec::new();
+        Export::new().write_csv(&analysis(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time,f0,F1,F2,F3,F4,intensity,mfcc0,mfcc1");
+        assert_eq!(lines[2], "0.0225,120.5,700,,,,-20.25,3,4");
+
+        let bad = Export::new().columns(&[Column::Formant(0)]);
+        assert!(bad.write_csv(&analysis(), Vec::new()).is_err());
+    }
+}
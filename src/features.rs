@@ -0,0 +1,345 @@
+//! Containers for frame-by-frame features.
+
+use std::ops::Range;
+use std::slice::Chunks;
+
+use crate::error::*;
+use crate::functionals::Functionals;
+use crate::units::Seconds;
+
+/// Features of a signal, one row of `n_cols` values per frame, stored row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureMatrix<T = f64> {
+    data: Vec<T>,
+    n_cols: usize,
+}
+
+impl<T> FeatureMatrix<T> {
+    /// An empty matrix whose rows will hold `n_cols` values.
+    pub fn new(n_cols: usize) -> Self {
+        FeatureMatrix {
+            data: Vec::new(),
+            n_cols,
+        }
+    }
+
+    /// A matrix from its rows, which must all be `n_cols` long.
+    pub fn from_rows<R: AsRef<[T]>>(n_cols: usize, rows: &[R]) -> VoxBoxResult<Self>
+    where
+        T: Clone,
+    {
+        let mut matrix = FeatureMatrix::new(n_cols);
+        for row in rows {
+            matrix.push_row(row.as_ref())?;
+        }
+        Ok(matrix)
+    }
+
+    /// A matrix from its values, frame by frame, which must be a whole number of rows.
+    pub fn from_vec(n_cols: usize, data: Vec<T>) -> VoxBoxResult<Self> {
+        if data.len() != data.len().checked_div(n_cols).unwrap_or(0) * n_cols {
+            return Err(VoxBoxError::Input("Values must be a whole number of rows"));
+        }
+        Ok(FeatureMatrix { data, n_cols })
+    }
+
+    /// Appends a frame, which must be `n_cols` long.
+    pub fn push_row(&mut self, row: &[T]) -> VoxBoxResult<()>
+    where
+        T: Clone,
+    {
+        if row.len() != self.n_cols {
+            return Err(VoxBoxError::Input("Row length must match the matrix"));
+        }
+        self.data.extend_from_slice(row);
+        Ok(())
+    }
+
+    /// Number of frames.
+    pub fn n_rows(&self) -> usize {
+        self.data.len().checked_div(self.n_cols).unwrap_or(0)
+    }
+
+    /// Number of values per frame.
+    pub fn n_cols(&self) -> usize {
+        self.n_cols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The values of frame `k`, if there is one.
+    pub fn row(&self, k: usize) -> Option<&[T]> {
+        if k < self.n_rows() {
+            Some(&self.data[k * self.n_cols..(k + 1) * self.n_cols])
+        } else {
+            None
+        }
+    }
+
+    pub fn row_mut(&mut self, k: usize) -> Option<&mut [T]> {
+        if k < self.n_rows() {
+            Some(&mut self.data[k * self.n_cols..(k + 1) * self.n_cols])
+        } else {
+            None
+        }
+    }
+
+    /// The frames in order.
+    pub fn rows(&self) -> Chunks<'_, T> {
+        self.data.chunks(self.n_cols.max(1))
+    }
+
+    /// The values of column `j` down the frames, if there is one.
+    pub fn column(&self, j: usize) -> Option<Vec<T>>
+    where
+        T: Clone,
+    {
+        if j < self.n_cols {
+            Some(self.rows().map(|row| row[j].clone()).collect())
+        } else {
+            None
+        }
+    }
+
+    /// All values, frame by frame.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// The frames in `rows`, which must lie within the matrix.
+    pub fn slice_rows(&self, rows: Range<usize>) -> VoxBoxResult<Self>
+    where
+        T: Clone,
+    {
+        if rows.start > rows.end || rows.end > self.n_rows() {
+            return Err(VoxBoxError::Input("Rows out of range"));
+        }
+        Ok(FeatureMatrix {
+            data: self.data[rows.start * self.n_cols..rows.end * self.n_cols].to_vec(),
+            n_cols: self.n_cols,
+        })
+    }
+
+    /// Keeps only `columns` of each frame, in the order given, such as the first few MFCCs.
+    pub fn select_columns(&self, columns: &[usize]) -> VoxBoxResult<Self>
+    where
+        T: Clone,
+    {
+        if columns.iter().any(|j| *j >= self.n_cols) {
+            return Err(VoxBoxError::Input("Column out of range"));
+        }
+        let mut selected = FeatureMatrix::new(columns.len());
+        for row in self.rows() {
+            selected
+                .data
+                .extend(columns.iter().map(|j| row[*j].clone()));
+        }
+        Ok(selected)
+    }
+
+    /// Columns as rows, so that each row is one coefficient's track.
+    pub fn transpose(&self) -> Self
+    where
+        T: Clone,
+    {
+        let mut transposed = FeatureMatrix::new(self.n_rows());
+        for j in 0..self.n_cols {
+            transposed
+                .data
+                .extend(self.rows().map(|row| row[j].clone()));
+        }
+        transposed
+    }
+
+    /// The values, frame by frame, giving up the matrix.
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    /// The frames as separate `Vec`s, as most analyzers return them.
+    pub fn to_rows(&self) -> Vec<Vec<T>>
+    where
+        T: Clone,
+    {
+        self.rows().map(|row| row.to_vec()).collect()
+    }
+}
+
+impl FeatureMatrix<f64> {
+    /// Mean of each column over its finite values, NaN for a column with none.
+    pub fn column_means(&self) -> Vec<f64> {
+        (0..self.n_cols)
+            .map(|j| {
+                let (sum, count) = self
+                    .finite_column(j)
+                    .fold((0., 0), |(s, n), x| (s + x, n + 1));
+                if count == 0 {
+                    f64::NAN
+                } else {
+                    sum / count as f64
+                }
+            })
+            .collect()
+    }
+
+    /// Population variance of each column over its finite values, NaN for a column with none.
+    pub fn column_variances(&self) -> Vec<f64> {
+        self.column_means()
+            .iter()
+            .enumerate()
+            .map(|(j, mean)| {
+                let (sum, count) = self
+                    .finite_column(j)
+                    .fold((0., 0), |(s, n), x| (s + (x - mean).powi(2), n + 1));
+                if count == 0 {
+                    f64::NAN
+                } else {
+                    sum / count as f64
+                }
+            })
+            .collect()
+    }
+
+    /// The full set of `Functionals` of each column, `None` for a column with no finite values.
+    pub fn column_functionals(&self, frame_period: Seconds) -> Vec<Option<Functionals>> {
+        (0..self.n_cols)
+            .map(|j| Functionals::from_frames(&self.column(j).unwrap_or_default(), frame_period))
+            .collect()
+    }
+
+    fn finite_column(&self, j: usize) -> impl Iterator<Item = f64> + '_ {
+        self.rows().map(move |row| row[j]).filter(|x| x.is_finite())
+    }
+}
+
+impl<T> From<FeatureMatrix<T>> for Vec<Vec<T>>
+where
+    T: Clone,
+{
+    fn from(matrix: FeatureMatrix<T>) -> Self {
+        matrix.to_rows()
+    }
+}
+
+/// Regression coefficients of every column over `window` frames either side, as in HTK:
+/// `d[t] = sum(n * (c[t + n] - c[t - n])) / (2 * sum(n * n))` for `n` from 1 to `window`. The
+/// first and last frames are repeated past the ends.
+pub fn deltas(features: &FeatureMatrix, window: usize) -> VoxBoxResult<FeatureMatrix> {
+    if window == 0 {
+        return Err(VoxBoxError::Config("Delta window must be nonzero"));
+    }
+    let n_rows = features.n_rows();
+    let mut deltas = FeatureMatrix::new(features.n_cols());
+    if n_rows == 0 {
+        return Ok(deltas);
+    }
+    let norm = 2. * (1..=window).fold(0., |acc, n| acc + (n * n) as f64);
+    let row = |t: isize| {
+        let t = t.clamp(0, n_rows as isize - 1) as usize;
+        &features.data[t * features.n_cols..(t + 1) * features.n_cols]
+    };
+    for t in 0..n_rows as isize {
+        let mut delta = vec![0.; features.n_cols()];
+        for n in 1..=window as isize {
+            for ((d, later), earlier) in delta.iter_mut().zip(row(t + n)).zip(row(t - n)) {
+                *d += n as f64 * (later - earlier);
+            }
+        }
+        for d in delta.iter_mut() {
+            *d /= norm;
+        }
+        deltas.push_row(&delta)?;
+    }
+    Ok(deltas)
+}
+
+/// Each frame followed by its deltas and delta-deltas, both over `window` frames as in
+/// `deltas`, tripling the number of columns.
+pub fn with_deltas(features: &FeatureMatrix, window: usize) -> VoxBoxResult<FeatureMatrix> {
+    let first = deltas(features, window)?;
+    let second = deltas(&first, window)?;
+    let mut stacked = FeatureMatrix::new(3 * features.n_cols());
+    for t in 0..features.n_rows() {
+        let row: Vec<f64> = [features, &first, &second]
+            .iter()
+            .flat_map(|m| m.row(t).unwrap_or(&[]).iter().cloned())
+            .collect();
+        stacked.push_row(&row)?;
+    }
+    Ok(stacked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_matrix() {
+        let mut matrix = FeatureMatrix::from_rows(2, &[[1., 2.], [3., 4.]]).unwrap();
+        matrix.push_row(&[5., 6.]).unwrap();
+        assert_eq!(matrix.n_rows(), 3);
+        assert_eq!(matrix.row(1), Some(&[3., 4.][..]));
+        assert_eq!(matrix.row(3), None);
+        assert!(matrix.push_row(&[7.]).is_err());
+        assert_eq!(FeatureMatrix::<f64>::new(0).n_rows(), 0);
+    }
+
+    #[test]
+    fn test_feature_matrix_access() {
+        let mut matrix = FeatureMatrix::from_vec(3, vec![1., 2., 3., 4., 5., 6.]).unwrap();
+        assert!(FeatureMatrix::from_vec(4, vec![0.; 6]).is_err());
+        assert_eq!(matrix.rows().count(), 2);
+        assert_eq!(matrix.column(1), Some(vec![2., 5.]));
+        assert_eq!(matrix.column(3), None);
+        matrix.row_mut(1).unwrap()[2] = 7.;
+
+        let tail = matrix.slice_rows(1..2).unwrap();
+        assert_eq!(tail.as_slice(), &[4., 5., 7.]);
+        assert!(matrix.slice_rows(1..3).is_err());
+        let selected = matrix.select_columns(&[2, 0]).unwrap();
+        assert_eq!(selected.to_rows(), vec![vec![3., 1.], vec![7., 4.]]);
+        assert!(matrix.select_columns(&[3]).is_err());
+        let transposed = matrix.transpose();
+        assert_eq!((transposed.n_rows(), transposed.n_cols()), (3, 2));
+        assert_eq!(transposed.row(2), Some(&[3., 7.][..]));
+        let rows: Vec<Vec<f64>> = matrix.clone().into();
+        assert_eq!(rows[1], vec![4., 5., 7.]);
+        assert_eq!(matrix.into_vec().len(), 6);
+    }
+
+    #[test]
+    fn test_column_statistics() {
+        let matrix =
+            FeatureMatrix::from_rows(3, &[[1., f64::NAN, f64::NAN], [3., 2., f64::NAN]]).unwrap();
+        let means = matrix.column_means();
+        assert_eq!(&means[..2], &[2., 2.]);
+        assert!(means[2].is_nan());
+        assert_eq!(&matrix.column_variances()[..2], &[1., 0.]);
+        let functionals = matrix.column_functionals(Seconds(0.01));
+        assert_eq!(functionals[0].unwrap().max, 3.);
+        assert!(functionals[2].is_none());
+    }
+
+    #[test]
+    fn test_deltas() {
+        // A ramp and a parabola
+        let rows: Vec<[f64; 2]> = (0..10).map(|t| [t as f64, (t * t) as f64]).collect();
+        let features = FeatureMatrix::from_rows(2, &rows).unwrap();
+        let stacked = with_deltas(&features, 2).unwrap();
+        assert_eq!((stacked.n_rows(), stacked.n_cols()), (10, 6));
+        let middle = stacked.row(5).unwrap();
+        println!("frame 5: {:?}", middle);
+        assert_eq!(&middle[..2], &[5., 25.]);
+        assert!((middle[2] - 1.).abs() < 1e-12);
+        assert!((middle[3] - 10.).abs() < 1e-12);
+        assert!(middle[4].abs() < 1e-12);
+        assert!((middle[5] - 2.).abs() < 1e-12);
+        // Edges are padded by repetition, which flattens the slope
+        assert!(stacked.row(0).unwrap()[2] < 1.);
+
+        assert!(deltas(&features, 0).is_err());
+        assert!(deltas(&FeatureMatrix::new(3), 2).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! C-compatible entry points. Each function returns 0 on success and -1 on bad arguments or a
+//! failed analysis, and writes its results through the output pointers.
+
+#[cfg(any(feature = "pitch", feature = "formant"))]
+use libc::{c_double, c_int, size_t};
+
+#[cfg(feature = "formant")]
+use crate::spectrum::Resonance;
+
+/// Builds a slice from a C pointer and length, or `None` for a null pointer or zero length.
+#[cfg(any(feature = "pitch", feature = "formant"))]
+unsafe fn slice<'a, T>(ptr: *const T, len: size_t) -> Option<&'a [T]> {
+    if ptr.is_null() || len == 0 {
+        None
+    } else {
+        Some(std::slice::from_raw_parts(ptr, len))
+    }
+}
+
+/// Estimates the pitch of one frame with YIN (see `Pitcher::yin`), writing the frequency in Hz
+/// and a strength from 0 to 1.
+///
+/// # Safety
+///
+/// `samples` must point to `len` readable doubles, and `frequency` and `strength` must be
+/// valid for writes.
+#[cfg(feature = "pitch")]
+#[no_mangle]
+pub unsafe extern "C" fn vox_box_yin(
+    samples: *const c_double,
+    len: size_t,
+    sample_rate: c_double,
+    threshold: c_double,
+    min: c_double,
+    max: c_double,
+    frequency: *mut c_double,
+    strength: *mut c_double,
+) -> c_int {
+    use crate::periodic::Pitcher;
+
+    let samples = match slice(samples, len) {
+        Some(samples) => samples,
+        None => return -1,
+    };
+    if frequency.is_null() || strength.is_null() || !(sample_rate > 0. && sample_rate.is_finite()) {
+        return -1;
+    }
+    let pitch = samples.yin(sample_rate, threshold, min, max);
+    *frequency = pitch.frequency;
+    *strength = pitch.strength;
+    0
+}
+
+/// Tracks the formants of one frame with `find_formants`. `formants` holds `num_formants`
+/// estimates on entry, such as `MALE_FORMANT_ESTIMATES`, and the new formants on return.
+///
+/// # Safety
+///
+/// `samples` must point to `len` readable doubles and `formants` to `num_formants` readable
+/// and writable resonances.
+#[cfg(feature = "formant")]
+#[no_mangle]
+pub unsafe extern "C" fn vox_box_find_formants(
+    samples: *const c_double,
+    len: size_t,
+    sample_rate: c_double,
+    n_coeffs: size_t,
+    formants: *mut Resonance<c_double>,
+    num_formants: size_t,
+) -> c_int {
+    use num_complex::Complex;
+
+    let mut buf = match slice(samples, len) {
+        Some(samples) => samples.to_vec(),
+        None => return -1,
+    };
+    if formants.is_null() || num_formants == 0 {
+        return -1;
+    }
+    let formants = std::slice::from_raw_parts_mut(formants, num_formants);
+    let mut resampled = vec![0.; len];
+    let mut work = vec![0.; crate::find_formants_real_work_size(len, n_coeffs)];
+    let mut complex_work =
+        vec![Complex::new(0., 0.); crate::find_formants_complex_work_size(n_coeffs)];
+    match crate::find_formants(
+        &mut buf,
+        sample_rate,
+        None,
+        &mut resampled,
+        n_coeffs,
+        &mut work,
+        &mut complex_work,
+        formants,
+    ) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+#[cfg(all(test, any(feature = "pitch", feature = "formant")))]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[cfg(feature = "pitch")]
+    #[test]
+    fn test_yin() {
+        let sample_rate = 8000.;
+        let samples: Vec<f64> = (0..800)
+            .map(|i| (2. * std::f64::consts::PI * 200. * i as f64 / sample_rate).sin())
+            .collect();
+        let (mut frequency, mut strength) = (0., 0.);
+        let status = unsafe {
+            vox_box_yin(
+                samples.as_ptr(),
+                samples.len(),
+                sample_rate,
+                0.1,
+                75.,
+                500.,
+                &mut frequency,
+                &mut strength,
+            )
+        };
+        println!("frequency: {}, strength: {}", frequency, strength);
+        assert_eq!(status, 0);
+        assert!((frequency - 200.).abs() < 1.);
+
+        let status = unsafe {
+            vox_box_yin(
+                ptr::null(),
+                800,
+                sample_rate,
+                0.1,
+                75.,
+                500.,
+                &mut frequency,
+                &mut strength,
+            )
+        };
+        assert_eq!(status, -1);
+    }
+
+    #[cfg(feature = "formant")]
+    #[test]
+    fn test_find_formants() {
+        let sample_rate = 10000.;
+        let samples: Vec<f64> = (0..512)
+            .map(|i| {
+                let t = i as f64 / sample_rate;
+                (2. * std::f64::consts::PI * 500. * t).sin()
+                    + 0.5 * (2. * std::f64::consts::PI * 1500. * t).sin()
+            })
+            .collect();
+        let mut formants: Vec<Resonance<f64>> = crate::MALE_FORMANT_ESTIMATES
+            .iter()
+            .map(|f| Resonance::new(*f, 1.))
+            .collect();
+        let status = unsafe {
+            vox_box_find_formants(
+                samples.as_ptr(),
+                samples.len(),
+                sample_rate,
+                10,
+                formants.as_mut_ptr(),
+                formants.len(),
+            )
+        };
+        println!("formants: {:?}", formants);
+        assert_eq!(status, 0);
+
+        let status = unsafe {
+            vox_box_find_formants(
+                samples.as_ptr(),
+                samples.len(),
+                sample_rate,
+                10,
+                ptr::null_mut(),
+                4,
+            )
+        };
+        assert_eq!(status, -1);
+    }
+}
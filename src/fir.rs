@@ -0,0 +1,575 @@
+//! Linear-phase FIR filters: design by windowed sinc or by the Parks-McClellan algorithm, and
+//! filtering with the resulting taps.
+//!
+//! Designed filters have an odd number of taps and are symmetric, so they delay every
+//! frequency by the same `(n_taps - 1) / 2` samples.
+
+use num_complex::Complex;
+use std::f64::consts::PI;
+
+use crate::error::*;
+use crate::processor::Processor;
+use crate::spectrum::RealFft;
+use crate::units::{Db, Hz};
+use crate::validate;
+use crate::waves::WindowType;
+
+/// Shape of an ideal filter to approximate, with cutoffs in Hz.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FirResponse {
+    LowPass(f64),
+    HighPass(f64),
+    BandPass(f64, f64),
+    BandStop(f64, f64),
+}
+
+impl FirResponse {
+    /// The cutoffs, which must be ordered and strictly between 0 and Nyquist.
+    fn validate(&self, sample_rate: f64) -> VoxBoxResult<()> {
+        let nyquist = sample_rate / 2.;
+        let inside = |f: f64| f > 0. && f < nyquist;
+        let valid = match *self {
+            FirResponse::LowPass(f) | FirResponse::HighPass(f) => inside(f),
+            FirResponse::BandPass(low, high) | FirResponse::BandStop(low, high) => {
+                inside(low) && inside(high) && low < high
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(VoxBoxError::Config(
+                "Cutoffs must be ordered and between 0 and Nyquist",
+            ))
+        }
+    }
+
+    /// A frequency in the passband, where the designed gain is scaled to one.
+    fn reference(&self, sample_rate: f64) -> f64 {
+        match *self {
+            FirResponse::LowPass(_) | FirResponse::BandStop(..) => 0.,
+            FirResponse::HighPass(_) => sample_rate / 2.,
+            FirResponse::BandPass(low, high) => (low + high) / 2.,
+        }
+    }
+}
+
+/// Designs a filter of `n_taps` by windowing the ideal response with a symmetric `window`,
+/// scaled to unit gain in the middle of the passband, or at DC or Nyquist for the filters
+/// that pass them. `n_taps` must be odd.
+pub fn windowed_sinc<R: Into<Hz>>(
+    response: FirResponse,
+    n_taps: usize,
+    window: WindowType,
+    sample_rate: R,
+) -> VoxBoxResult<Vec<f64>> {
+    let sample_rate = sample_rate.into().0;
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    response.validate(sample_rate)?;
+    if n_taps.is_multiple_of(2) {
+        return Err(VoxBoxError::Config("Number of taps must be odd"));
+    }
+    let center = (n_taps / 2) as isize;
+    // Ideal low-pass with a cutoff in cycles per sample
+    let low_pass = |cutoff: f64, n: isize| {
+        let m = (n - center) as f64;
+        if m == 0. {
+            2. * cutoff
+        } else {
+            (2. * PI * cutoff * m).sin() / (PI * m)
+        }
+    };
+    let impulse = |n: isize| if n == center { 1. } else { 0. };
+    let window: Vec<f64> = match window {
+        WindowType::Gaussian => window.window(n_taps),
+        _ if n_taps == 1 => vec![1.],
+        // The periodic windows one sample shorter, closed off with their first sample
+        _ => (0..n_taps).map(|i| window.at(i, n_taps - 1)).collect(),
+    };
+    let norm = |f: f64| f / sample_rate;
+    let mut taps: Vec<f64> = (0..n_taps as isize)
+        .zip(window.iter())
+        .map(|(n, w)| {
+            let ideal = match response {
+                FirResponse::LowPass(f) => low_pass(norm(f), n),
+                FirResponse::HighPass(f) => impulse(n) - low_pass(norm(f), n),
+                FirResponse::BandPass(low, high) => {
+                    low_pass(norm(high), n) - low_pass(norm(low), n)
+                }
+                FirResponse::BandStop(low, high) => {
+                    impulse(n) - low_pass(norm(high), n) + low_pass(norm(low), n)
+                }
+            };
+            ideal * w
+        })
+        .collect();
+    let gain = frequency_response(&taps, response.reference(sample_rate), sample_rate).norm();
+    for t in taps.iter_mut() {
+        *t /= gain;
+    }
+    Ok(taps)
+}
+
+/// Number of taps and Kaiser `beta` for a windowed-sinc filter with the given stopband
+/// attenuation and transition width in Hz, by Kaiser's formulas. The number of taps is odd.
+pub fn kaiser_parameters<W: Into<Hz>, R: Into<Hz>>(
+    attenuation: Db,
+    transition: W,
+    sample_rate: R,
+) -> (usize, f64) {
+    let transition = transition.into().0;
+    let sample_rate = sample_rate.into().0;
+    let a = attenuation.0;
+    let beta = if a > 50. {
+        0.1102 * (a - 8.7)
+    } else if a >= 21. {
+        0.5842 * (a - 21.).powf(0.4) + 0.07886 * (a - 21.)
+    } else {
+        0.
+    };
+    let width = 2. * PI * transition / sample_rate;
+    let n_taps = ((a - 7.95) / (2.285 * width)).ceil().max(0.) as usize + 1;
+    (n_taps | 1, beta)
+}
+
+/// Designs a windowed-sinc filter with a Kaiser window, as short as Kaiser's formulas allow
+/// for stopbands `attenuation` down and transitions `transition` Hz wide centered on the
+/// cutoffs.
+pub fn kaiser_fir<W: Into<Hz>, R: Into<Hz>>(
+    response: FirResponse,
+    attenuation: Db,
+    transition: W,
+    sample_rate: R,
+) -> VoxBoxResult<Vec<f64>> {
+    let transition = transition.into().0;
+    let sample_rate = sample_rate.into().0;
+    validate::positive(transition, "Transition width must be positive")?;
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    let (n_taps, beta) = kaiser_parameters(attenuation, transition, sample_rate);
+    windowed_sinc(response, n_taps, WindowType::Kaiser(beta), sample_rate)
+}
+
+/// Grid points per coefficient that `remez` searches for extremal frequencies.
+const REMEZ_GRID_DENSITY: usize = 16;
+const REMEZ_MAX_ITERATIONS: usize = 50;
+
+/// Designs the filter of `n_taps`, which must be odd, whose weighted error from a piecewise
+/// constant response is smallest at its largest, by the Parks-McClellan algorithm.
+///
+/// `bands` are the `(low, high)` edges in Hz of the bands the response is specified over,
+/// ordered and not overlapping, with gaps between them left free for the transitions. Each has
+/// a gain from `desired` and a weight on its error from `weights`.
+pub fn remez(
+    n_taps: usize,
+    bands: &[(f64, f64)],
+    desired: &[f64],
+    weights: &[f64],
+    sample_rate: f64,
+) -> VoxBoxResult<Vec<f64>> {
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    if n_taps.is_multiple_of(2) {
+        return Err(VoxBoxError::Config("Number of taps must be odd"));
+    }
+    if bands.is_empty() || desired.len() != bands.len() || weights.len() != bands.len() {
+        return Err(VoxBoxError::Config(
+            "Need a gain and a weight for each of at least one band",
+        ));
+    }
+    let mut previous = 0.;
+    for (i, &(low, high)) in bands.iter().enumerate() {
+        if low < previous || (i > 0 && low == previous) || high < low || high > sample_rate / 2. {
+            return Err(VoxBoxError::Config(
+                "Bands must be ordered, separate and below Nyquist",
+            ));
+        }
+        previous = high;
+    }
+    if weights.iter().any(|w| w.is_nan() || *w <= 0.) {
+        return Err(VoxBoxError::Config("Band weights must be positive"));
+    }
+
+    // Dense grid in cycles per sample, with the desired gain and weight at each point
+    let n_coeffs = n_taps / 2 + 1;
+    let n_extremals = n_coeffs + 1;
+    let total_width: f64 = bands.iter().map(|(low, high)| high - low).sum();
+    let spacing = total_width / sample_rate / (REMEZ_GRID_DENSITY * n_coeffs) as f64;
+    let mut grid: Vec<(f64, f64, f64)> = Vec::new();
+    for ((&(low, high), d), w) in bands.iter().zip(desired.iter()).zip(weights.iter()) {
+        let (low, high) = (low / sample_rate, high / sample_rate);
+        let n = (((high - low) / spacing).round() as usize).max(1);
+        for i in 0..=n {
+            grid.push((low + (high - low) * i as f64 / n as f64, *d, *w));
+        }
+    }
+    if grid.len() < n_extremals {
+        return Err(VoxBoxError::Config("Bands are too narrow for the filter"));
+    }
+
+    let mut extremals: Vec<usize> = (0..n_extremals)
+        .map(|k| k * (grid.len() - 1) / (n_extremals - 1))
+        .collect();
+    let mut response = RemezResponse::fit(&grid, &extremals);
+    for _ in 0..REMEZ_MAX_ITERATIONS {
+        let errors: Vec<f64> = grid
+            .iter()
+            .map(|&(f, d, w)| w * (d - response.at(f)))
+            .collect();
+        let next = match remez_extremals(&errors, n_extremals) {
+            Some(next) => next,
+            None => break,
+        };
+        let largest = next.iter().map(|i| errors[*i].abs()).fold(0., f64::max);
+        let done = next == extremals || largest - response.delta.abs() < 1e-9 * largest;
+        extremals = next;
+        response = RemezResponse::fit(&grid, &extremals);
+        if done {
+            break;
+        }
+    }
+
+    // Sample the cosine response at the DFT frequencies and transform back
+    let gains: Vec<f64> = (0..n_coeffs)
+        .map(|k| response.at(k as f64 / n_taps as f64))
+        .collect();
+    let center = n_taps / 2;
+    Ok((0..n_taps)
+        .map(|n| {
+            let m = n as f64 - center as f64;
+            let sum = gains
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(k, a)| 2. * a * (2. * PI * k as f64 * m / n_taps as f64).cos())
+                .sum::<f64>();
+            (gains[0] + sum) / n_taps as f64
+        })
+        .collect())
+}
+
+/// The cosine polynomial through one set of extremal frequencies, in barycentric form.
+struct RemezResponse {
+    /// Signed error at the extremals
+    delta: f64,
+    /// `cos(2 pi f)` at all but the last extremal, and the response and weight there
+    x: Vec<f64>,
+    values: Vec<f64>,
+    barycentric: Vec<f64>,
+}
+
+impl RemezResponse {
+    fn fit(grid: &[(f64, f64, f64)], extremals: &[usize]) -> Self {
+        let x: Vec<f64> = extremals
+            .iter()
+            .map(|i| (2. * PI * grid[*i].0).cos())
+            .collect();
+        let weights = |x: &[f64]| -> Vec<f64> {
+            (0..x.len())
+                .map(|k| {
+                    1. / (0..x.len())
+                        .filter(|j| *j != k)
+                        .map(|j| x[k] - x[j])
+                        .product::<f64>()
+                })
+                .collect()
+        };
+        let all = weights(&x);
+        let (mut numerator, mut denominator) = (0., 0.);
+        for (k, (b, i)) in all.iter().zip(extremals.iter()).enumerate() {
+            let (_, d, w) = grid[*i];
+            let sign = if k % 2 == 0 { 1. } else { -1. };
+            numerator += b * d;
+            denominator += sign * b / w;
+        }
+        let delta = numerator / denominator;
+        let n = extremals.len() - 1;
+        let values: Vec<f64> = extremals[..n]
+            .iter()
+            .enumerate()
+            .map(|(k, i)| {
+                let (_, d, w) = grid[*i];
+                let sign = if k % 2 == 0 { 1. } else { -1. };
+                d - sign * delta / w
+            })
+            .collect();
+        let x = x[..n].to_vec();
+        RemezResponse {
+            delta,
+            barycentric: weights(&x),
+            x,
+            values,
+        }
+    }
+
+    /// Response at `f` cycles per sample.
+    fn at(&self, f: f64) -> f64 {
+        let x = (2. * PI * f).cos();
+        let (mut numerator, mut denominator) = (0., 0.);
+        for ((xk, v), b) in self
+            .x
+            .iter()
+            .zip(self.values.iter())
+            .zip(self.barycentric.iter())
+        {
+            let dx = x - xk;
+            if dx.abs() < 1e-14 {
+                return *v;
+            }
+            numerator += b / dx * v;
+            denominator += b / dx;
+        }
+        numerator / denominator
+    }
+}
+
+/// Grid indices of `n` alternating extrema of `errors`, or none if there aren't enough.
+fn remez_extremals(errors: &[f64], n: usize) -> Option<Vec<usize>> {
+    // The largest error of each run of errors with the same sign
+    let mut alternating: Vec<usize> = Vec::new();
+    for (i, e) in errors.iter().enumerate() {
+        match alternating.last_mut() {
+            Some(j) if (*e > 0.) == (errors[*j] > 0.) => {
+                if e.abs() > errors[*j].abs() {
+                    *j = i;
+                }
+            }
+            _ => alternating.push(i),
+        }
+    }
+    if alternating.len() < n {
+        return None;
+    }
+    // Drop the smallest until there are `n`, merging the neighbours of one taken from inside
+    while alternating.len() > n {
+        let smallest = (0..alternating.len())
+            .min_by(|a, b| {
+                let (a, b) = (errors[alternating[*a]], errors[alternating[*b]]);
+                a.abs().total_cmp(&b.abs())
+            })
+            .unwrap();
+        let last = alternating.len() - 1;
+        if alternating.len() == n + 1 {
+            // One too many: only dropping an end keeps the signs alternating
+            if errors[alternating[0]].abs() < errors[alternating[last]].abs() {
+                alternating.remove(0);
+            } else {
+                alternating.pop();
+            }
+        } else if smallest == 0 || smallest == last {
+            alternating.remove(smallest);
+        } else {
+            let (before, after) = (alternating[smallest - 1], alternating[smallest + 1]);
+            let larger = if errors[before].abs() >= errors[after].abs() {
+                before
+            } else {
+                after
+            };
+            alternating.splice(smallest - 1..=smallest + 1, Some(larger));
+        }
+    }
+    Some(alternating)
+}
+
+/// Complex gain of the filter `taps` at `frequency` Hz.
+pub fn frequency_response<F: Into<Hz>, R: Into<Hz>>(
+    taps: &[f64],
+    frequency: F,
+    sample_rate: R,
+) -> Complex<f64> {
+    let frequency = frequency.into().0;
+    let sample_rate = sample_rate.into().0;
+    taps.iter()
+        .enumerate()
+        .fold(Complex::new(0., 0.), |acc, (n, t)| {
+            acc + Complex::from_polar(t, &(-2. * PI * frequency * n as f64 / sample_rate))
+        })
+}
+
+/// Longest filter `convolve` applies directly rather than through FFTs.
+const DIRECT_CONVOLUTION_TAPS: usize = 64;
+
+/// Filters `signal` by `taps`, `y[n] = sum(taps[k] * x[n - k])`, starting from silence and
+/// giving as many samples as the signal. Long filters go through FFTs by overlap-add.
+pub fn convolve(signal: &[f64], taps: &[f64]) -> Vec<f64> {
+    if taps.len() <= DIRECT_CONVOLUTION_TAPS || signal.len() <= DIRECT_CONVOLUTION_TAPS {
+        return (0..signal.len())
+            .map(|n| {
+                taps.iter()
+                    .zip(signal[..=n].iter().rev())
+                    .map(|(t, s)| t * s)
+                    .sum()
+            })
+            .collect();
+    }
+
+    let fft_len = (2 * taps.len()).next_power_of_two();
+    let block = fft_len - taps.len() + 1;
+    let fft = RealFft::new(fft_len);
+    let taps_spectrum = fft.transform(taps);
+    let mut out = vec![0.; signal.len() + fft_len];
+    for (k, chunk) in signal.chunks(block).enumerate() {
+        let spectrum: Vec<Complex<f64>> = fft
+            .transform(chunk)
+            .iter()
+            .zip(taps_spectrum.iter())
+            .map(|(x, h)| x * h)
+            .collect();
+        for (o, y) in out[k * block..].iter_mut().zip(fft.inverse(&spectrum)) {
+            *o += y;
+        }
+    }
+    out.truncate(signal.len());
+    out
+}
+
+/// A FIR filter run on a stream, carrying the last samples of each block into the next.
+#[derive(Clone, Debug)]
+pub struct FirFilter {
+    taps: Vec<f64>,
+    /// The last `taps.len() - 1` input samples, oldest first
+    history: Vec<f64>,
+}
+
+impl FirFilter {
+    pub fn new(taps: Vec<f64>) -> VoxBoxResult<Self> {
+        validate::non_empty(&taps)?;
+        Ok(FirFilter {
+            history: vec![0.; taps.len() - 1],
+            taps,
+        })
+    }
+
+    pub fn taps(&self) -> &[f64] {
+        &self.taps
+    }
+}
+
+impl Processor<f64> for FirFilter {
+    fn process(&mut self, input: &[f64], output: &mut [f64]) -> VoxBoxResult<usize> {
+        validate::workspace(output, input.len())?;
+        let n_history = self.history.len();
+        let mut extended = Vec::with_capacity(n_history + input.len());
+        extended.extend_from_slice(&self.history);
+        extended.extend_from_slice(input);
+        let filtered = convolve(&extended, &self.taps);
+        output[..input.len()].copy_from_slice(&filtered[n_history..]);
+        let keep = extended.len() - n_history;
+        self.history.copy_from_slice(&extended[keep..]);
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {
+        for h in self.history.iter_mut() {
+            *h = 0.;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gain_db(taps: &[f64], frequency: f64, sample_rate: f64) -> f64 {
+        20. * frequency_response(taps, frequency, sample_rate)
+            .norm()
+            .log10()
+    }
+
+    #[test]
+    fn test_kaiser_fir() {
+        let sr = 16000.;
+        let taps = kaiser_fir(FirResponse::LowPass(2000.), Db(60.), 400., sr).unwrap();
+        println!("{} taps", taps.len());
+        assert_eq!(taps.len() % 2, 1);
+        for i in 0..taps.len() / 2 {
+            assert!((taps[i] - taps[taps.len() - 1 - i]).abs() < 1e-15);
+        }
+        for f in [0., 500., 1500., 1790.].iter() {
+            assert!(gain_db(&taps, *f, sr).abs() < 0.02);
+        }
+        for f in [2210., 3000., 6000., 8000.].iter() {
+            assert!(gain_db(&taps, *f, sr) < -59.);
+        }
+
+        let taps = kaiser_fir(FirResponse::BandPass(1000., 3000.), Db(50.), 300., sr).unwrap();
+        assert!(gain_db(&taps, 2000., sr).abs() < 1e-9);
+        assert!(gain_db(&taps, 500., sr) < -49.);
+        assert!(gain_db(&taps, 4000., sr) < -49.);
+        let taps =
+            windowed_sinc(FirResponse::HighPass(4000.), 101, WindowType::Hamming, sr).unwrap();
+        assert!(gain_db(&taps, 8000., sr).abs() < 1e-9);
+        assert!(gain_db(&taps, 1000., sr) < -50.);
+        let taps = windowed_sinc(
+            FirResponse::BandStop(2000., 4000.),
+            101,
+            WindowType::Blackman,
+            sr,
+        )
+        .unwrap();
+        assert!(gain_db(&taps, 3000., sr) < -50.);
+
+        assert!(windowed_sinc(FirResponse::LowPass(2000.), 100, WindowType::Hamming, sr).is_err());
+        assert!(windowed_sinc(
+            FirResponse::BandPass(3000., 1000.),
+            11,
+            WindowType::Hamming,
+            sr
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_remez() {
+        let sr = 1.;
+        let taps = remez(31, &[(0., 0.1), (0.15, 0.5)], &[1., 0.], &[1., 1.], sr).unwrap();
+        for i in 0..15 {
+            assert!((taps[i] - taps[30 - i]).abs() < 1e-12);
+        }
+        let gains: Vec<f64> = (0..=500)
+            .map(|i| frequency_response(&taps, i as f64 / 1000., sr).norm())
+            .collect();
+        let ripple = gains[..=100]
+            .iter()
+            .map(|g| (g - 1.).abs())
+            .fold(0., f64::max);
+        let stop = gains[150..].iter().cloned().fold(0., f64::max);
+        println!("ripple {}, stopband {}", ripple, stop);
+        // Equal weights give equal ripple in both bands
+        assert!((ripple - stop).abs() < 0.05 * stop);
+        assert!(stop < 0.03);
+        // A windowed sinc of the same length does worse at its largest error
+        let sinc = windowed_sinc(FirResponse::LowPass(0.125), 31, WindowType::Hamming, sr).unwrap();
+        let sinc_stop = (150..=500)
+            .map(|i| frequency_response(&sinc, i as f64 / 1000., sr).norm())
+            .fold(0., f64::max);
+        assert!(stop < sinc_stop);
+
+        assert!(remez(30, &[(0., 0.1), (0.15, 0.5)], &[1., 0.], &[1., 1.], sr).is_err());
+        assert!(remez(31, &[(0.15, 0.5), (0., 0.1)], &[1., 0.], &[1., 1.], sr).is_err());
+    }
+
+    #[test]
+    fn test_convolve() {
+        let signal: Vec<f64> = (0..1000)
+            .map(|n| (n as f64 * 0.37).sin() + 0.2 * (n as f64 * 2.1).cos())
+            .collect();
+        let taps = kaiser_fir(FirResponse::LowPass(1000.), Db(70.), 200., 16000.).unwrap();
+        assert!(taps.len() > DIRECT_CONVOLUTION_TAPS);
+        let fast = convolve(&signal, &taps);
+        assert_eq!(fast.len(), signal.len());
+        for (n, y) in fast.iter().enumerate() {
+            let direct: f64 = (0..=n.min(taps.len() - 1))
+                .map(|k| taps[k] * signal[n - k])
+                .sum();
+            assert!((y - direct).abs() < 1e-9);
+        }
+
+        // Streamed in blocks, the filter gives the same
+        let mut filter = FirFilter::new(taps).unwrap();
+        let mut streamed = vec![0.; signal.len()];
+        for (input, output) in signal.chunks(77).zip(streamed.chunks_mut(77)) {
+            filter.process(input, output).unwrap();
+        }
+        for (a, b) in streamed.iter().zip(fast.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Formant analysis of a whole signal in one call.
+//!
+//! `Formants` wires together what would otherwise take several steps across modules:
+//! resampling so that Nyquist is the highest formant looked for, pre-emphasis, windowing, Burg
+//! LPC, root finding, `to_resonance` and tracking.
+//!
+//! ```
+//! use vox_box::formant::Formants;
+//!
+//! let signal: Vec<f64> = (0..8000).map(|n| (n as f64 * 0.3).sin()).collect();
+//! let track = Formants::new(16000.).analyze(&signal).unwrap();
+//! assert_eq!(track.len(), 50);
+//! assert!(track.frames.iter().all(|f| f.formants.len() == 4));
+//! ```
+
+use num_complex::Complex;
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::polynomial::Polynomial;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::resample::{ResampleQuality, Resampler};
+use crate::spectrum::{FormantCosts, FormantExtractor, FormantFrame, Resonance, ToResonance, LPC};
+use crate::units::{Hz, Seconds};
+use crate::validate;
+use crate::waves::{to_f64_samples, Filter, WindowType};
+
+/// Formants of a signal, one frame per `frame_period`, each timed at the middle of its hop.
+/// Every frame holds one resonance per tracked formant, with NaN for formants that weren't
+/// found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormantTrack {
+    pub frame_period: Seconds,
+    pub frames: Vec<FormantFrame<f64>>,
+}
+
+impl FormantTrack {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Time of the middle of each frame.
+    pub fn times(&self) -> Vec<Seconds> {
+        self.frames.iter().map(|f| Seconds(f.time)).collect()
+    }
+
+    /// Frequency of formant `n` (0 for F1) in every frame, NaN where it is missing.
+    pub fn frequencies(&self, n: usize) -> Vec<f64> {
+        self.frames
+            .iter()
+            .map(|f| f.formants.get(n).map_or(f64::NAN, |r| r.frequency))
+            .collect()
+    }
+}
+
+/// How `Formants` turns each frame's resonances into formants.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormantTracking {
+    /// The lowest resonances in order, as Praat reports them, without tracking
+    Sorted,
+    /// Tracked frame by frame from the estimates, as `FormantExtractor` does
+    Greedy,
+    /// Tracked over the whole signal at once, as `FormantExtractor::track` does
+    Viterbi(FormantCosts),
+}
+
+/// Settings for formant analysis. The defaults suit an adult male voice.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formants {
+    pub sample_rate: Hz,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    /// Length of each frame, in samples
+    pub frame_len: usize,
+    pub window: WindowType,
+    /// Formants are looked for below this frequency, in Hz. The signal is resampled to twice
+    /// this rate, if that is lower than its own.
+    pub max_formant: Hz,
+    /// Order of the Burg LPC, usually twice the number of formants expected below
+    /// `max_formant`
+    pub lpc_order: usize,
+    /// Frequency in Hz above which pre-emphasis boosts the spectrum by 6 dB per octave, if any
+    pub preemphasis_from: Option<f64>,
+    /// Starting estimates for the tracker, in Hz. One formant is reported per estimate.
+    pub estimates: Vec<f64>,
+    pub tracking: FormantTracking,
+}
+
+impl Formants {
+    /// 25 ms Hanning frames every 10 ms, five formants below 5500 Hz from an LPC of order 10,
+    /// pre-emphasis from 50 Hz and Viterbi tracking of `MALE_FORMANT_ESTIMATES`.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into();
+        Formants {
+            sample_rate,
+            hop: Seconds(0.01).to_samples(sample_rate),
+            frame_len: Seconds(0.025).to_samples(sample_rate),
+            window: WindowType::Hanning,
+            max_formant: Hz(5500.),
+            lpc_order: 10,
+            preemphasis_from: Some(50.),
+            estimates: crate::MALE_FORMANT_ESTIMATES.to_vec(),
+            tracking: FormantTracking::Viterbi(FormantCosts::default()),
+        }
+    }
+
+    /// Praat's `To Formant (burg)` with its default settings: five formants below 5500 Hz, a
+    /// Gaussian window of twice the 25 ms window length, a time step of a quarter of the window
+    /// length, pre-emphasis from 50 Hz, and the formants reported in order without tracking.
+    ///
+    /// Frames are centered on each hop across the whole signal as in the rest of this crate,
+    /// whereas Praat only analyzes frames that fit inside the signal.
+    pub fn praat<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        Formants {
+            hop: (sample_rate * 0.025 / 4.).round() as usize,
+            frame_len: (sample_rate * 0.05).round() as usize,
+            window: WindowType::Gaussian,
+            estimates: vec![550., 1650., 2750., 3850., 4950.],
+            tracking: FormantTracking::Sorted,
+            ..Formants::new(sample_rate)
+        }
+    }
+
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Formants {
+            hop: config.hop,
+            frame_len: config.frame_len,
+            window: config.window,
+            ..Formants::new(config.sample_rate)
+        }
+    }
+
+    /// Formants of every frame, one per hop.
+    pub fn analyze<S>(&self, signal: &[S]) -> VoxBoxResult<FormantTrack>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.analyze_with_progress(signal, progress::ignore)
+    }
+
+    /// Like `analyze`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled`
+    /// if the callback breaks.
+    pub fn analyze_with_progress<S, F>(
+        &self,
+        signal: &[S],
+        mut progress: F,
+    ) -> VoxBoxResult<FormantTrack>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        let (sample_rate, max_formant) = (self.sample_rate.0, self.max_formant.0);
+        validate::positive(sample_rate, "Sample rate must be positive")?;
+        validate::positive(max_formant, "Formant ceiling must be positive")?;
+        if self.hop == 0 || self.frame_len == 0 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        if self.lpc_order == 0 || self.lpc_order > 2 * crate::MAX_RESONANCES {
+            return Err(VoxBoxError::Config(
+                "LPC order must be between 1 and twice MAX_RESONANCES",
+            ));
+        }
+        let signal = to_f64_samples(signal);
+        validate::finite(&signal)?;
+
+        let rate = (2. * max_formant).min(sample_rate);
+        let ratio = rate / sample_rate;
+        let mut resampled = if (ratio - 1.).abs() < 1.0e-9 {
+            signal.clone()
+        } else {
+            Resampler::new(sample_rate, rate, ResampleQuality::Best)?.resample(&signal)
+        };
+        if let Some(from) = self.preemphasis_from {
+            resampled.preemphasis_from(from, rate);
+        }
+
+        let frame_len = ((self.frame_len as f64 * ratio).round() as usize).max(1);
+        let window = self.window.window(frame_len);
+        let n_frames = signal.len().div_ceil(self.hop);
+        let mut candidates: Vec<Vec<Resonance<f64>>> = Vec::with_capacity(n_frames);
+        for k in 0..n_frames {
+            // Centered on the middle of hop k, as in the original signal
+            let center = (k * self.hop) as f64 + self.hop as f64 / 2.;
+            let start = (center * ratio).round() as isize - (frame_len / 2) as isize;
+            let frame: Vec<f64> = window
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    let idx = start + i as isize;
+                    if idx < 0 || idx as usize >= resampled.len() {
+                        0.
+                    } else {
+                        resampled[idx as usize] * w
+                    }
+                })
+                .collect();
+            candidates.push(resonances(&frame, self.lpc_order, rate));
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+
+        let estimates: Vec<Resonance<f64>> = self
+            .estimates
+            .iter()
+            .map(|f| Resonance::new(*f, 1.))
+            .collect();
+        let n_formants = self.estimates.len();
+        let period = self.hop as f64 / sample_rate;
+        let extractor = FormantExtractor::new(n_formants, candidates.iter(), estimates)
+            .timing(period / 2., period);
+        let frames = match self.tracking {
+            FormantTracking::Sorted => {
+                let missing = Resonance::new(f64::NAN, f64::NAN);
+                candidates
+                    .iter()
+                    .enumerate()
+                    .map(|(k, c)| {
+                        let formants = (0..n_formants)
+                            .map(|n| c.get(n).cloned().unwrap_or(missing))
+                            .collect();
+                        FormantFrame::new((k as f64 + 0.5) * period, formants, c)
+                    })
+                    .collect()
+            }
+            FormantTracking::Greedy => extractor.collect(),
+            FormantTracking::Viterbi(ref costs) => extractor.track(costs)?,
+        };
+        Ok(FormantTrack {
+            frame_period: Seconds(period),
+            frames,
+        })
+    }
+}
+
+/// `find_formants` frame after frame, with its workspaces allocated once for frames of
+/// `config.frame_len` samples. Frames are resampled so that Nyquist is the highest formant.
+pub struct FormantFinder {
+    rate: f64,
+    resampler: Option<Resampler>,
+    lpc_order: usize,
+    resampled: Vec<f64>,
+    work: Vec<f64>,
+    complex_work: Vec<Complex<f64>>,
+}
+
+impl FormantFinder {
+    pub fn from_config(
+        config: &AnalysisConfig,
+        max_formant: Hz,
+        lpc_order: usize,
+    ) -> VoxBoxResult<Self> {
+        config.validate()?;
+        validate::positive(max_formant.0, "Formant ceiling must be positive")?;
+        if lpc_order == 0 || lpc_order > 2 * crate::MAX_RESONANCES {
+            return Err(VoxBoxError::Config(
+                "LPC order must be between 1 and twice MAX_RESONANCES",
+            ));
+        }
+        let rate = (2. * max_formant.0).min(config.sample_rate.0);
+        let resampler = if rate < config.sample_rate.0 {
+            Some(Resampler::new(
+                config.sample_rate,
+                rate,
+                ResampleQuality::Balanced,
+            )?)
+        } else {
+            None
+        };
+        let resampled_len = resampler
+            .as_ref()
+            .map_or(config.frame_len, |r| r.output_len(config.frame_len));
+        Ok(FormantFinder {
+            rate,
+            resampler,
+            lpc_order,
+            resampled: vec![0.; resampled_len],
+            work: vec![0.; crate::find_formants_real_work_size(resampled_len, lpc_order)],
+            complex_work: vec![
+                Complex::new(0., 0.);
+                crate::find_formants_complex_work_size(lpc_order)
+            ],
+        })
+    }
+
+    /// The rate frames are analyzed at, twice the highest formant unless the signal's own rate
+    /// is lower.
+    pub fn rate(&self) -> Hz {
+        Hz(self.rate)
+    }
+
+    /// Updates `formants` from the next frame, which must be no longer than the configured
+    /// frame length.
+    pub fn find(&mut self, frame: &mut [f64], formants: &mut [Resonance<f64>]) -> VoxBoxResult<()> {
+        crate::find_formants(
+            frame,
+            self.rate,
+            self.resampler.as_ref(),
+            &mut self.resampled[..],
+            self.lpc_order,
+            &mut self.work[..],
+            &mut self.complex_work[..],
+            formants,
+        )
+    }
+}
+
+/// Resonances of the Burg LPC of a windowed frame, or none if LPC fails, as it does on silence.
+fn resonances(frame: &[f64], lpc_order: usize, sample_rate: f64) -> Vec<Resonance<f64>> {
+    let coeffs = match frame.lpc_praat(lpc_order) {
+        Ok(coeffs) => coeffs,
+        Err(_) => return Vec::new(),
+    };
+    let poly: Vec<Complex<f64>> = [1.]
+        .iter()
+        .chain(coeffs.iter())
+        .rev()
+        .map(|c| Complex::new(*c, 0.))
+        .collect();
+    match poly.find_roots() {
+        Ok(roots) => roots.to_resonance(sample_rate),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// An impulse train at 120 Hz through resonators at `formants`, with 80 Hz bandwidths.
+    fn vowel(formants: &[f64], sample_rate: f64, len: usize) -> Vec<f64> {
+        let period = (sample_rate / 120.).round() as usize;
+        let mut signal: Vec<f64> = (0..len)
+            .map(|n| if n % period == 0 { 1. } else { 0. })
+            .collect();
+        for f in formants {
+            let r = (-PI * 80. / sample_rate).exp();
+            let (a1, a2) = (-2. * r * (2. * PI * f / sample_rate).cos(), r * r);
+            for n in 0..signal.len() {
+                let y1 = if n > 0 { signal[n - 1] } else { 0. };
+                let y2 = if n > 1 { signal[n - 2] } else { 0. };
+                signal[n] -= a1 * y1 + a2 * y2;
+            }
+        }
+        signal
+    }
+
+    #[test]
+    fn test_formants() {
+        let signal = vowel(&[700., 1200., 2600., 3500.], 16000., 8000);
+        let mut analyzer = Formants::new(16000.);
+        analyzer.max_formant = Hz(5000.);
+        analyzer.estimates = vec![500., 1500., 2500.];
+        let modes = [
+            FormantTracking::Viterbi(FormantCosts::default()),
+            FormantTracking::Greedy,
+        ];
+        for tracking in modes.iter() {
+            analyzer.tracking = *tracking;
+            let track = analyzer.analyze(&signal).unwrap();
+            assert_eq!(track.len(), 50);
+            assert_eq!(track.times()[1], Seconds(0.015));
+            // Away from the edges
+            for (n, expected) in [700., 1200., 2600.].iter().enumerate() {
+                let freqs = track.frequencies(n);
+                println!("F{}: {:?}", n + 1, &freqs[5..10]);
+                assert!(freqs[5..45].iter().all(|f| (f - expected).abs() < 60.));
+            }
+        }
+
+        let mut cancelled = 0;
+        let result = analyzer.analyze_with_progress(&signal, |p| {
+            cancelled = p.done;
+            ControlFlow::Break(())
+        });
+        assert!(matches!(result, Err(VoxBoxError::Cancelled)));
+        assert_eq!(cancelled, 1);
+        analyzer.lpc_order = 0;
+        assert!(analyzer.analyze(&signal).is_err());
+    }
+
+    #[test]
+    fn test_praat_formants() {
+        let signal = vowel(&[700., 1200., 2600., 3500., 4500.], 16000., 8000);
+        let analyzer = Formants::praat(16000.);
+        assert_eq!(analyzer.hop, 100);
+        let track = analyzer.analyze(&signal).unwrap();
+        assert_eq!(track.len(), 80);
+        assert!(track.frames.iter().all(|f| f.formants.len() == 5));
+        assert!(track.frames[40].amplitudes.iter().all(|a| *a <= 0.));
+        for (n, expected) in [700., 1200., 2600., 3500.].iter().enumerate() {
+            let freqs = track.frequencies(n);
+            println!("F{}: {:?}", n + 1, &freqs[10..14]);
+            assert!(freqs[10..70].iter().all(|f| (f - expected).abs() < 60.));
+        }
+    }
+
+    #[test]
+    fn test_formant_finder() {
+        let signal = vowel(&[700., 1200., 2600., 3500.], 16000., 8000);
+        let config = AnalysisConfig::new(16000.);
+        let mut finder = FormantFinder::from_config(&config, Hz(5500.), 10).unwrap();
+        assert_eq!(finder.rate(), Hz(11000.));
+        let mut formants: Vec<Resonance<f64>> = crate::MALE_FORMANT_ESTIMATES
+            .iter()
+            .map(|f| Resonance::new(*f, 1.))
+            .collect();
+        let mut direct = formants.clone();
+        let resampler = Resampler::new(16000., 11000., ResampleQuality::Balanced).unwrap();
+        let (mut resampled, mut work, mut complex_work) = (
+            vec![0.; 275],
+            vec![0.; crate::find_formants_real_work_size(275, 10)],
+            vec![Complex::new(0., 0.); crate::find_formants_complex_work_size(10)],
+        );
+        for k in 10..20 {
+            let frame = &signal[k * config.hop..k * config.hop + config.frame_len];
+            finder.find(&mut frame.to_vec(), &mut formants).unwrap();
+            crate::find_formants(
+                &mut frame.to_vec(),
+                11000.,
+                Some(&resampler),
+                &mut resampled,
+                10,
+                &mut work,
+                &mut complex_work,
+                &mut direct,
+            )
+            .unwrap();
+        }
+        println!("{:?}", formants);
+        assert_eq!(formants, direct);
+        assert!(formants.iter().any(|f| (f.frequency - 2600.).abs() < 60.));
+
+        let mut long = vec![0.1; config.frame_len * 2];
+        assert!(finder.find(&mut long, &mut formants).is_err());
+        assert!(FormantFinder::from_config(&config, Hz(5500.), 0).is_err());
+        assert!(FormantFinder::from_config(&config, Hz(0.), 10).is_err());
+    }
+}
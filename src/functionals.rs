@@ -0,0 +1,170 @@
+use std::cmp::Ordering;
+
+use crate::segment::Segment;
+use crate::units::Seconds;
+
+/// Returns the `p`th percentile (0 to 100) of the finite values in a slice, interpolating
+/// linearly between the closest ranks. Returns `None` if there are no finite values.
+pub fn percentile(values: &[f64], p: f64) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().cloned().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Some(percentile_sorted(&sorted, p))
+}
+
+fn percentile_sorted(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p.clamp(0., 100.) / 100.) * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// Statistics of a per-frame feature over a stretch of frames.
+///
+/// Frames with non-finite values, such as the pitch of unvoiced frames, are left out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Functionals {
+    /// Number of frames that went into the statistics
+    pub count: usize,
+    pub mean: f64,
+    /// Population standard deviation
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+    pub range: f64,
+    pub median: f64,
+    /// 25th percentile
+    pub lower_quartile: f64,
+    /// 75th percentile
+    pub upper_quartile: f64,
+    /// Slope of the least squares line through the values, in units per second
+    pub slope: f64,
+}
+
+impl Functionals {
+    /// Computes the statistics of `values`, one per frame, with frames `frame_period` apart.
+    /// Returns `None` if no value is finite.
+    pub fn from_frames(values: &[f64], frame_period: Seconds) -> Option<Functionals> {
+        let frame_period = frame_period.0;
+        let points: Vec<(f64, f64)> = values
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_finite())
+            .map(|(i, v)| (i as f64 * frame_period, *v))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+
+        let n = points.len() as f64;
+        let mean = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let variance = points.iter().map(|p| (p.1 - mean).powi(2)).sum::<f64>() / n;
+        let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let var_t = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum::<f64>();
+        let slope = if var_t > 0. {
+            points
+                .iter()
+                .map(|p| (p.0 - mean_t) * (p.1 - mean))
+                .sum::<f64>()
+                / var_t
+        } else {
+            0.
+        };
+
+        let mut sorted: Vec<f64> = points.iter().map(|p| p.1).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let min = sorted[0];
+        let max = sorted[sorted.len() - 1];
+
+        Some(Functionals {
+            count: points.len(),
+            mean,
+            std: variance.sqrt(),
+            min,
+            max,
+            range: max - min,
+            median: percentile_sorted(&sorted, 50.),
+            lower_quartile: percentile_sorted(&sorted, 25.),
+            upper_quartile: percentile_sorted(&sorted, 75.),
+            slope,
+        })
+    }
+
+    /// Computes the statistics of `values` over each segment. Frame `k` is taken to be at
+    /// `k * frame_period` and belongs to the segments that contain that time.
+    pub fn over_segments(
+        values: &[f64],
+        frame_period: Seconds,
+        segments: &[Segment],
+    ) -> Vec<Option<Functionals>> {
+        segments
+            .iter()
+            .map(|segment| {
+                let start = (segment.start.0 / frame_period.0).ceil().max(0.) as usize;
+                let end =
+                    ((segment.end.0 / frame_period.0).ceil().max(0.) as usize).min(values.len());
+                if start >= end {
+                    return None;
+                }
+                Functionals::from_frames(&values[start..end], frame_period)
+            })
+            .collect()
+    }
+
+    /// The spread of the middle half of the values.
+    pub fn interquartile_range(&self) -> f64 {
+        self.upper_quartile - self.lower_quartile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::SegmentLabel;
+
+    #[test]
+    fn test_functionals() {
+        // A line rising by 2 per second, with a gap where the feature is undefined
+        let values = [1., 1.02, f64::NAN, 1.06, 1.08];
+        let f = Functionals::from_frames(&values, Seconds(0.01)).unwrap();
+        println!("{:?}", f);
+        assert_eq!(f.count, 4);
+        assert!((f.mean - 1.04).abs() < 1.0e-9);
+        assert!((f.range - 0.08).abs() < 1.0e-9);
+        assert!((f.median - 1.04).abs() < 1.0e-9);
+        assert!((f.slope - 2.).abs() < 1.0e-9);
+        assert!((f.std - 0.0316227766).abs() < 1.0e-9);
+
+        assert_eq!(percentile(&[3., 1., 2., 4.], 50.), Some(2.5));
+        assert_eq!(percentile(&[f64::NAN], 50.), None);
+    }
+
+    #[test]
+    fn test_over_segments() {
+        let values: Vec<f64> = (0..100).map(|i| if i < 50 { 1. } else { 3. }).collect();
+        let segments = [
+            Segment {
+                label: SegmentLabel::Voiced,
+                start: Seconds(0.),
+                end: Seconds(0.5),
+            },
+            Segment {
+                label: SegmentLabel::Voiced,
+                start: Seconds(0.5),
+                end: Seconds(1.),
+            },
+            Segment {
+                label: SegmentLabel::Silence,
+                start: Seconds(2.),
+                end: Seconds(3.),
+            },
+        ];
+        let stats = Functionals::over_segments(&values, Seconds(0.01), &segments);
+        assert_eq!(stats[0].unwrap().mean, 1.);
+        assert_eq!(stats[0].unwrap().count, 50);
+        assert_eq!(stats[1].unwrap().mean, 3.);
+        assert_eq!(stats[2], None);
+    }
+}
@@ -0,0 +1,273 @@
+//! Gaussian mixture models with diagonal covariances, trained by expectation-maximization, for
+//! speaker verification and phone-class models over MFCC frames.
+//!
+//! ```
+//! use vox_box::features::FeatureMatrix;
+//! use vox_box::gmm::Em;
+//!
+//! let rows: Vec<[f64; 1]> = (0..40).map(|n| [(n % 4) as f64 + 10. * (n % 2) as f64]).collect();
+//! let frames = FeatureMatrix::from_rows(1, &rows).unwrap();
+//! let gmm = Em::new(2).train(&frames).unwrap();
+//! assert!(gmm.log_likelihood(&[11.]).unwrap() > gmm.log_likelihood(&[6.]).unwrap());
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::error::*;
+use crate::features::FeatureMatrix;
+use crate::vq::Lbg;
+
+/// A mixture of Gaussians, one per row of `means` and `variances`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gmm {
+    /// Prior of each component, summing to one
+    pub weights: Vec<f64>,
+    pub means: FeatureMatrix,
+    /// Variance of each dimension of each component
+    pub variances: FeatureMatrix,
+}
+
+impl Gmm {
+    pub fn n_components(&self) -> usize {
+        self.weights.len()
+    }
+
+    /// Natural log of the density of the mixture at `frame`.
+    pub fn log_likelihood(&self, frame: &[f64]) -> VoxBoxResult<f64> {
+        self.check(frame)?;
+        Ok(log_sum_exp(&self.joint_log_likelihoods(frame)))
+    }
+
+    /// Probability of each component given `frame`.
+    pub fn posteriors(&self, frame: &[f64]) -> VoxBoxResult<Vec<f64>> {
+        self.check(frame)?;
+        let joint = self.joint_log_likelihoods(frame);
+        let total = log_sum_exp(&joint);
+        Ok(joint.iter().map(|l| (l - total).exp()).collect())
+    }
+
+    /// Mean log-likelihood per frame, the usual score for comparing how well models fit a
+    /// recording. For verification, compare it against a background model's score.
+    pub fn score(&self, frames: &FeatureMatrix) -> VoxBoxResult<f64> {
+        if frames.is_empty() {
+            return Err(VoxBoxError::Input("No frames to score"));
+        }
+        let mut total = 0.;
+        for frame in frames.rows() {
+            total += self.log_likelihood(frame)?;
+        }
+        Ok(total / frames.n_rows() as f64)
+    }
+
+    fn check(&self, frame: &[f64]) -> VoxBoxResult<()> {
+        if frame.len() != self.means.n_cols() {
+            return Err(VoxBoxError::Input("Frame length must match the model"));
+        }
+        Ok(())
+    }
+
+    /// Log of each component's weight times its density at `frame`.
+    fn joint_log_likelihoods(&self, frame: &[f64]) -> Vec<f64> {
+        self.weights
+            .iter()
+            .zip(self.means.rows().zip(self.variances.rows()))
+            .map(|(w, (mean, variance))| {
+                let exponent: f64 = frame
+                    .iter()
+                    .zip(mean.iter().zip(variance))
+                    .map(|(x, (m, v))| (x - m) * (x - m) / v + (2. * PI * v).ln())
+                    .sum();
+                w.ln() - 0.5 * exponent
+            })
+            .collect()
+    }
+}
+
+fn log_sum_exp(values: &[f64]) -> f64 {
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + values.iter().map(|v| (v - max).exp()).sum::<f64>().ln()
+}
+
+/// Settings for training a `Gmm` by expectation-maximization, starting from an LBG codebook.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Em {
+    n_components: usize,
+    max_iterations: usize,
+    tolerance: f64,
+    variance_floor: f64,
+}
+
+impl Em {
+    pub fn new(n_components: usize) -> Self {
+        Em {
+            n_components,
+            max_iterations: 100,
+            tolerance: 1.0e-4,
+            variance_floor: 1.0e-3,
+        }
+    }
+
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Training stops when the mean log-likelihood per frame improves by less than this.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Least variance of a component, as a fraction of the variance of the training frames,
+    /// which keeps a component from collapsing onto a few frames.
+    pub fn variance_floor(mut self, fraction: f64) -> Self {
+        self.variance_floor = fraction;
+        self
+    }
+
+    pub fn train(&self, frames: &FeatureMatrix) -> VoxBoxResult<Gmm> {
+        if self.variance_floor.is_nan() || self.variance_floor <= 0. {
+            return Err(VoxBoxError::Config("Variance floor must be positive"));
+        }
+        let codebook = Lbg::new(self.n_components).train(frames)?;
+        let floor: Vec<f64> = frames
+            .column_variances()
+            .iter()
+            .map(|v| (v * self.variance_floor).max(f64::MIN_POSITIVE))
+            .collect();
+
+        // Start from the codebook's cells, each frame belonging wholly to its nearest codeword
+        let codes = codebook.encode(frames)?;
+        let responsibilities: Vec<Vec<f64>> = codes
+            .iter()
+            .map(|k| {
+                (0..self.n_components)
+                    .map(|c| if c == *k { 1. } else { 0. })
+                    .collect()
+            })
+            .collect();
+        let mut gmm = Gmm {
+            weights: vec![1. / self.n_components as f64; self.n_components],
+            means: codebook.codewords,
+            variances: FeatureMatrix::from_rows(
+                frames.n_cols(),
+                &vec![floor.clone(); self.n_components],
+            )?,
+        };
+        maximize(&mut gmm, frames, &responsibilities, &floor);
+
+        let mut previous = f64::NEG_INFINITY;
+        for _ in 0..self.max_iterations {
+            let mut total = 0.;
+            let mut responsibilities = Vec::with_capacity(frames.n_rows());
+            for frame in frames.rows() {
+                let joint = gmm.joint_log_likelihoods(frame);
+                let likelihood = log_sum_exp(&joint);
+                total += likelihood;
+                responsibilities.push(joint.iter().map(|l| (l - likelihood).exp()).collect());
+            }
+            maximize(&mut gmm, frames, &responsibilities, &floor);
+            let mean = total / frames.n_rows() as f64;
+            if mean - previous < self.tolerance {
+                break;
+            }
+            previous = mean;
+        }
+        Ok(gmm)
+    }
+}
+
+/// Re-estimates the weights, means and variances from each frame's responsibilities. Components
+/// no frame belongs to keep their old parameters.
+fn maximize(gmm: &mut Gmm, frames: &FeatureMatrix, responsibilities: &[Vec<f64>], floor: &[f64]) {
+    let n_frames = frames.n_rows() as f64;
+    for c in 0..gmm.n_components() {
+        let weight: f64 = responsibilities.iter().map(|r| r[c]).sum();
+        if weight <= f64::EPSILON {
+            continue;
+        }
+        gmm.weights[c] = weight / n_frames;
+        let mean: Vec<f64> = (0..frames.n_cols())
+            .map(|j| {
+                frames
+                    .rows()
+                    .zip(responsibilities)
+                    .map(|(x, r)| r[c] * x[j])
+                    .sum::<f64>()
+                    / weight
+            })
+            .collect();
+        let variance: Vec<f64> = (0..frames.n_cols())
+            .map(|j| {
+                let v = frames
+                    .rows()
+                    .zip(responsibilities)
+                    .map(|(x, r)| r[c] * (x[j] - mean[j]).powi(2))
+                    .sum::<f64>()
+                    / weight;
+                v.max(floor[j])
+            })
+            .collect();
+        gmm.means.row_mut(c).unwrap().copy_from_slice(&mean);
+        gmm.variances.row_mut(c).unwrap().copy_from_slice(&variance);
+    }
+    let total: f64 = gmm.weights.iter().sum();
+    for w in gmm.weights.iter_mut() {
+        *w /= total;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn test_gmm() {
+        // Twice as many frames from a tight cluster as from a wide one
+        let mut rng = XorShiftRng::from_seed([5, 6, 7, 8]);
+        let (tight, wide) = (Normal::new(0., 0.5), Normal::new(0., 2.));
+        let mut frames = FeatureMatrix::new(2);
+        for n in 0..600 {
+            let frame = if n % 3 == 2 {
+                [10. + wide.ind_sample(&mut rng), wide.ind_sample(&mut rng)]
+            } else {
+                [tight.ind_sample(&mut rng), 3. + tight.ind_sample(&mut rng)]
+            };
+            frames.push_row(&frame).unwrap();
+        }
+
+        let gmm = Em::new(2).train(&frames).unwrap();
+        println!("{:?}", gmm);
+        let tight_component = if gmm.means.row(0).unwrap()[0] < 5. {
+            0
+        } else {
+            1
+        };
+        let wide_component = 1 - tight_component;
+        assert!((gmm.weights[tight_component] - 2. / 3.).abs() < 0.03);
+        let mean = gmm.means.row(wide_component).unwrap();
+        assert!((mean[0] - 10.).abs() < 0.3 && mean[1].abs() < 0.3);
+        let variance = gmm.variances.row(tight_component).unwrap();
+        assert!((variance[0] - 0.25).abs() < 0.05 && (variance[1] - 0.25).abs() < 0.05);
+
+        let posteriors = gmm.posteriors(&[10., 0.]).unwrap();
+        assert!(posteriors[wide_component] > 0.99);
+        assert!((posteriors.iter().sum::<f64>() - 1.).abs() < 1e-12);
+
+        // The model fits its own frames better than shifted ones
+        let mut shifted = FeatureMatrix::new(2);
+        for frame in frames.rows() {
+            shifted.push_row(&[frame[0] + 3., frame[1]]).unwrap();
+        }
+        assert!(gmm.score(&frames).unwrap() > gmm.score(&shifted).unwrap() + 1.);
+
+        assert!(gmm.log_likelihood(&[0.]).is_err());
+        assert!(Em::new(0).train(&frames).is_err());
+        assert!(Em::new(2).variance_floor(0.).train(&frames).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Reading and writing audio files, with `hound`. Samples come out as `f64` with full scale at
+//! 1.0, ready for analysis, and go back in on the same scale.
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use sample::{Sample, ToSample};
+use std::path::Path;
+
+use crate::error::*;
+use crate::pcm::{deinterleave, Dither, PcmFormat, Quantizer};
+use crate::units::Hz;
+
+impl From<hound::Error> for VoxBoxError {
+    fn from(e: hound::Error) -> Self {
+        VoxBoxError::Io(match e {
+            hound::Error::IoError(e) => return VoxBoxError::from(e),
+            hound::Error::FormatError(s) => s,
+            hound::Error::TooWide => "Sample is too wide for the format",
+            hound::Error::UnfinishedSample => "Samples are not a whole number of frames",
+            hound::Error::Unsupported => "Unsupported WAV format",
+            hound::Error::InvalidSampleFormat => "Sample format does not match the file",
+        })
+    }
+}
+
+/// Reads a WAV file of 8 to 32-bit integer or 32-bit float samples, mixing its channels down to
+/// one. Returns the samples and the sample rate.
+pub fn read_wav<P: AsRef<Path>>(path: P) -> VoxBoxResult<(Vec<f64>, Hz)> {
+    let (channels, sample_rate) = read_wav_channels(path)?;
+    let scale = 1. / channels.len() as f64;
+    let mut mixed = vec![0.; channels[0].len()];
+    for channel in channels.iter() {
+        for (m, s) in mixed.iter_mut().zip(channel.iter()) {
+            *m += s * scale;
+        }
+    }
+    Ok((mixed, sample_rate))
+}
+
+/// Reads a WAV file as `read_wav` does, keeping each channel apart.
+pub fn read_wav_channels<P: AsRef<Path>>(path: P) -> VoxBoxResult<(Vec<Vec<f64>>, Hz)> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f64> = match spec.sample_format {
+        SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| s.map(|s| s as f64))
+            .collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1. / 2f64.powi(spec.bits_per_sample as i32 - 1);
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f64 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok((
+        deinterleave(&interleaved, spec.channels as usize)?,
+        Hz(spec.sample_rate as f64),
+    ))
+}
+
+/// Sample formats for writing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavFormat {
+    /// Integer samples, rounded with triangular dither
+    Int(PcmFormat),
+    /// 32-bit float samples, written as they are, so that they may go past full scale
+    Float,
+}
+
+/// Writes a mono WAV file, with full scale at 1.0. Integer samples past full scale are clipped.
+/// WAV files only hold whole sample rates, so the rate must be a positive whole number of Hz.
+pub fn write_wav<P, S, R>(
+    path: P,
+    samples: &[S],
+    sample_rate: R,
+    format: WavFormat,
+) -> VoxBoxResult<()>
+where
+    P: AsRef<Path>,
+    S: Sample + ToSample<f64>,
+    R: Into<Hz>,
+{
+    let sample_rate = sample_rate.into().0;
+    if !(sample_rate >= 1. && sample_rate <= u32::MAX as f64 && sample_rate.fract() == 0.) {
+        return Err(VoxBoxError::Config(
+            "Sample rate must be a positive whole number of Hz",
+        ));
+    }
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: match format {
+            WavFormat::Int(pcm) => pcm.bits() as u16,
+            WavFormat::Float => 32,
+        },
+        sample_format: match format {
+            WavFormat::Int(_) => SampleFormat::Int,
+            WavFormat::Float => SampleFormat::Float,
+        },
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    match format {
+        WavFormat::Int(pcm) => {
+            for s in Quantizer::new(pcm, Dither::Triangular).quantize(samples) {
+                writer.write_sample(s)?;
+            }
+        }
+        WavFormat::Float => {
+            for s in samples.iter() {
+                writer.write_sample(s.to_sample::<f64>() as f32)?;
+            }
+        }
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_wav(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vox_box_{}_{}.wav", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_read_wav() {
+        let (samples, sample_rate) = read_wav("./tests/short_sample.wav").unwrap();
+        println!("{} samples at {} Hz", samples.len(), sample_rate);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|s| s.abs() <= 1.));
+
+        // 24-bit stereo, mixed down
+        let path = temp_wav("stereo24");
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for s in [4194304, -8388608, 2097152, 0].iter() {
+            writer.write_sample(*s).unwrap();
+        }
+        writer.finalize().unwrap();
+        let (channels, sample_rate) = read_wav_channels(&path).unwrap();
+        assert_eq!(sample_rate, Hz(8000.));
+        assert_eq!(channels, vec![vec![0.5, 0.25], vec![-1., 0.]]);
+        assert_eq!(read_wav(&path).unwrap().0, vec![-0.25, 0.125]);
+        std::fs::remove_file(&path).unwrap();
+
+        // Float
+        let path = temp_wav("float");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for s in [0.5f32, -0.75].iter() {
+            writer.write_sample(*s).unwrap();
+        }
+        writer.finalize().unwrap();
+        assert_eq!(read_wav(&path).unwrap(), (vec![0.5, -0.75], Hz(44100.)));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            read_wav("./tests/no_such_file.wav"),
+            Err(VoxBoxError::Io("File not found"))
+        ));
+        assert!(matches!(
+            read_wav("./tests"),
+            Err(VoxBoxError::Io("Path is a directory"))
+        ));
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let signal: Vec<f64> = (0..1000).map(|n| 0.8 * (n as f64 * 0.05).sin()).collect();
+        for format in [
+            WavFormat::Int(PcmFormat::I16),
+            WavFormat::Int(PcmFormat::I24),
+            WavFormat::Int(PcmFormat::I32),
+            WavFormat::Float,
+        ]
+        .iter()
+        {
+            let path = temp_wav("write");
+            write_wav(&path, &signal, 16000., *format).unwrap();
+            let (read, sample_rate) = read_wav(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(sample_rate, Hz(16000.));
+            assert_eq!(read.len(), signal.len());
+            let error = read
+                .iter()
+                .zip(signal.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0., f64::max);
+            println!("{:?}: largest error {}", format, error);
+            assert!(error < 1e-4);
+        }
+
+        // Float keeps samples past full scale, integers clip them
+        let path = temp_wav("loud");
+        write_wav(&path, &[1.5f32, -2.], 8000., WavFormat::Float).unwrap();
+        assert_eq!(read_wav(&path).unwrap().0, vec![1.5, -2.]);
+        write_wav(&path, &[1.5f32, -2.], 8000., WavFormat::Int(PcmFormat::I16)).unwrap();
+        assert_eq!(read_wav(&path).unwrap().0, vec![32767. / 32768., -1.]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(write_wav(&path, &[0f64], 0., WavFormat::Float).is_err());
+        assert!(write_wav(&path, &[0f64], 8000.5, WavFormat::Float).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! Writes feature matrices in Kaldi's binary archive (`.ark`) format, keyed by utterance ID,
+//! with an index (`.scp`) of where each one starts, for use in Kaldi recipes as they are.
+//!
+//! ```
+//! use vox_box::features::FeatureMatrix;
+//! use vox_box::kaldi::ArkWriter;
+//!
+//! let mfcc = FeatureMatrix::from_rows(2, &[[1f32, 2.], [3., 4.]]).unwrap();
+//! let mut ark = ArkWriter::new(Vec::new());
+//! ark.write("speaker1-utt1", &mfcc).unwrap();
+//! let mut scp = Vec::new();
+//! ark.write_scp("feats.ark", &mut scp).unwrap();
+//! assert_eq!(String::from_utf8(scp).unwrap(), "speaker1-utt1 feats.ark:14\n");
+//! ```
+
+use std::io::Write;
+
+use crate::error::*;
+use crate::features::FeatureMatrix;
+
+/// Element types Kaldi stores, with the tokens that name their matrices and vectors.
+pub trait KaldiReal: Copy {
+    const MATRIX: &'static [u8; 3];
+    const VECTOR: &'static [u8; 3];
+
+    fn write_le(self, out: &mut Vec<u8>);
+}
+
+impl KaldiReal for f32 {
+    const MATRIX: &'static [u8; 3] = b"FM ";
+    const VECTOR: &'static [u8; 3] = b"FV ";
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl KaldiReal for f64 {
+    const MATRIX: &'static [u8; 3] = b"DM ";
+    const VECTOR: &'static [u8; 3] = b"DV ";
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Writes entries to a binary archive one after another, remembering where each starts.
+#[derive(Debug)]
+pub struct ArkWriter<W> {
+    writer: W,
+    /// Key and byte offset of each entry's data, as the index needs them
+    entries: Vec<(String, usize)>,
+    offset: usize,
+}
+
+impl<W: Write> ArkWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ArkWriter {
+            writer,
+            entries: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Writes a matrix, frames by coefficients, under `key`, which must be a nonempty word
+    /// without whitespace. Returns the offset of the matrix in the archive.
+    pub fn write<T: KaldiReal>(
+        &mut self,
+        key: &str,
+        matrix: &FeatureMatrix<T>,
+    ) -> VoxBoxResult<usize> {
+        let mut data = Vec::with_capacity(15 + 8 * matrix.as_slice().len());
+        data.extend_from_slice(T::MATRIX);
+        push_int(&mut data, matrix.n_rows())?;
+        push_int(&mut data, matrix.n_cols())?;
+        for x in matrix.as_slice().iter() {
+            x.write_le(&mut data);
+        }
+        self.write_entry(key, &data)
+    }
+
+    /// Writes a vector, such as an utterance-level feature, under `key`.
+    pub fn write_vector<T: KaldiReal>(&mut self, key: &str, values: &[T]) -> VoxBoxResult<usize> {
+        let mut data = Vec::with_capacity(10 + 8 * values.len());
+        data.extend_from_slice(T::VECTOR);
+        push_int(&mut data, values.len())?;
+        for x in values.iter() {
+            x.write_le(&mut data);
+        }
+        self.write_entry(key, &data)
+    }
+
+    /// Writes the index of the entries so far, a line of `key ark_path:offset` for each, where
+    /// `ark_path` is where Kaldi will find this archive.
+    pub fn write_scp<S: Write>(&self, ark_path: &str, mut scp: S) -> VoxBoxResult<()> {
+        for (key, offset) in self.entries.iter() {
+            writeln!(scp, "{} {}:{}", key, ark_path, offset).map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes `key`, a space, the binary marker and `data`.
+    fn write_entry(&mut self, key: &str, data: &[u8]) -> VoxBoxResult<usize> {
+        if key.is_empty() || key.chars().any(char::is_whitespace) {
+            return Err(VoxBoxError::Config(
+                "Key must be a nonempty word without whitespace",
+            ));
+        }
+        self.writer.write_all(key.as_bytes()).map_err(write_error)?;
+        self.writer.write_all(b" \0B").map_err(write_error)?;
+        self.writer.write_all(data).map_err(write_error)?;
+        // The index points at the binary marker, just past the key
+        let offset = self.offset + key.len() + 1;
+        self.entries.push((key.to_string(), offset));
+        self.offset = offset + 2 + data.len();
+        Ok(offset)
+    }
+}
+
+/// Appends a size as Kaldi writes integers: its width in bytes, then the value.
+fn push_int(out: &mut Vec<u8>, n: usize) -> VoxBoxResult<()> {
+    if n > i32::MAX as usize {
+        return Err(VoxBoxError::Input("Too many values for a Kaldi matrix"));
+    }
+    out.push(4);
+    out.extend_from_slice(&(n as i32).to_le_bytes());
+    Ok(())
+}
+
+fn write_error(_: std::io::Error) -> VoxBoxError {
+    VoxBoxError::Io("Could not write the archive")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ark_writer() {
+        let matrix = FeatureMatrix::from_rows(3, &[[1f32, 2., 3.]]).unwrap();
+        let mut ark = ArkWriter::new(Vec::new());
+        assert_eq!(ark.write("utt1", &matrix).unwrap(), 5);
+        let second = ark.write_vector("utt2", &[0.5f64]).unwrap();
+        assert!(ark.write("bad key", &matrix).is_err());
+        assert!(ark.write_vector::<f32>("", &[]).is_err());
+
+        let mut scp = Vec::new();
+        ark.write_scp("data/feats.ark", &mut scp).unwrap();
+        assert_eq!(
+            String::from_utf8(scp).unwrap(),
+            format!("utt1 data/feats.ark:5\nutt2 data/feats.ark:{}\n", second)
+        );
+
+        let bytes = ark.into_inner();
+        let mut expected = b"utt1 \0BFM \x04\x01\0\0\0\x04\x03\0\0\0".to_vec();
+        for x in [1f32, 2., 3.].iter() {
+            expected.extend_from_slice(&x.to_le_bytes());
+        }
+        assert_eq!(second, expected.len() + 5);
+        expected.extend_from_slice(b"utt2 \0BDV \x04\x01\0\0\0");
+        expected.extend_from_slice(&0.5f64.to_le_bytes());
+        assert_eq!(bytes, expected);
+    }
+}
@@ -1,43 +1,112 @@
-use num;
-
 // Declare local mods
+pub mod analysis;
+pub mod arma;
+pub mod bic;
+pub mod cepstrum;
 pub mod complex;
+pub mod config;
+pub mod dct;
+pub mod denoise;
+#[cfg(feature = "pitch")]
+pub mod dio;
+pub mod distance;
+pub mod dtw;
+pub mod dynamics;
 pub mod error;
+pub mod export;
+pub mod features;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fir;
+#[cfg(feature = "formant")]
+pub mod formant;
+pub mod functionals;
+pub mod gmm;
+#[cfg(feature = "io")]
+pub mod io;
+pub mod kaldi;
+pub mod loudness;
+pub mod noise;
+pub mod npy;
+pub mod onset;
+pub mod pcm;
+#[cfg(feature = "pitch")]
 pub mod periodic;
+pub mod pipeline;
+#[cfg(feature = "pitch")]
+pub mod pitch_track;
 pub mod polynomial;
+pub mod praat;
+pub mod prelude;
+pub mod processor;
+pub mod progress;
+#[cfg(feature = "pitch")]
+pub mod pyin;
+#[cfg(feature = "pitch")]
+pub mod rapt;
+pub mod resample;
+pub mod ring_buffer;
+pub mod segment;
+pub mod signal;
+pub mod smoothing;
 pub mod spectrum;
+#[cfg(feature = "synthesis")]
+pub mod synthesis;
+pub mod units;
+pub mod vad;
+mod validate;
+#[cfg(feature = "synthesis")]
+pub mod vocoder;
+pub mod voice_quality;
+pub mod vq;
+pub mod wavelet;
 pub mod waves;
 
+pub use analysis::{analyze, analyze_with_progress, Analysis, AnalyzeOptions};
+
+#[cfg(feature = "formant")]
 use sample::conv::Duplex;
-use sample::interpolate::{Converter, Linear};
+#[cfg(feature = "formant")]
 use sample::window::Type;
-use sample::{signal, Sample, Signal};
+#[cfg(feature = "formant")]
+use sample::Sample;
 
+#[cfg(feature = "formant")]
 use error::*;
+#[cfg(feature = "formant")]
 use polynomial::Polynomial;
+#[cfg(feature = "formant")]
+use resample::Resampler;
+#[cfg(feature = "formant")]
 use spectrum::{EstimateFormants, Resonance, LPC};
 
+#[cfg(feature = "formant")]
 use num::{Float, FromPrimitive};
+#[cfg(feature = "formant")]
 use num_complex::Complex;
 
 pub const MAX_RESONANCES: usize = 32;
 pub const MALE_FORMANT_ESTIMATES: [f64; 4] = [320., 1440., 2760., 3200.];
 pub const FEMALE_FORMANT_ESTIMATES: [f64; 4] = [480., 1760., 3200., 3520.];
 
+#[cfg(feature = "formant")]
 pub fn find_formants_real_work_size(buf_len: usize, n_coeffs: usize) -> usize {
     buf_len * 2 + n_coeffs * 23 + 2
 }
 
+#[cfg(feature = "formant")]
 pub fn find_formants_complex_work_size(n_coeffs: usize) -> usize {
     n_coeffs * 7 + 4
 }
 
-/// Calculates the next frame of formants based on given estimates. The user must provide
-/// sufficient workspace to carry out these calculations.
+/// Calculates the next frame of formants based on given estimates. The frame is first passed
+/// through `resampler`, if there is one, and `sample_rate` is the rate after that. The user
+/// must provide sufficient workspace to carry out these calculations.
+#[cfg(feature = "formant")]
 pub fn find_formants<S>(
     buf: &mut [S],
     sample_rate: S,
-    resample_ratio: f64,
+    resampler: Option<&Resampler>,
     resampled_buf: &mut [S],
     n_coeffs: usize,
     work: &mut [S],
@@ -47,27 +116,27 @@ pub fn find_formants<S>(
 where
     S: Sample + Duplex<f64> + Float + FromPrimitive,
 {
-    let resampled_len = (resample_ratio * buf.len() as f64).ceil() as usize;
-
-    if work.len() < find_formants_real_work_size(resampled_len, n_coeffs) {
-        return Err(VoxBoxError::Workspace);
+    validate::non_empty(buf)?;
+    validate::finite(buf)?;
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    if n_coeffs == 0 || n_coeffs > 2 * MAX_RESONANCES {
+        return Err(VoxBoxError::LPC(
+            "n_coeffs must be between 1 and twice MAX_RESONANCES",
+        ));
     }
 
-    // if complex_work.len() < find_formants_complex_work_size(n_coeffs) {
-    //     return Err(VoxBoxError::Workspace);
-    // }
-
-    assert!(resampled_len <= resampled_buf.len());
+    let resampled_len = resampler.map_or(buf.len(), |r| r.output_len(buf.len()));
+    validate::workspace(resampled_buf, resampled_len)?;
+    validate::workspace(
+        work,
+        find_formants_real_work_size(resampled_buf.len(), n_coeffs),
+    )?;
+    validate::workspace(complex_work, find_formants_complex_work_size(n_coeffs))?;
     let mut resonances =
         [Resonance::new(0f64.to_sample::<S>(), 0f64.to_sample::<S>()); MAX_RESONANCES];
     let (mut lpc_coeffs, work) = work.split_at_mut(n_coeffs);
-    if (resample_ratio - 1.0).abs() > 0.0001 {
-        let mut buf_iter = signal::from_iter(buf.iter().map(|b| [*b]));
-        let linear = Linear::new(buf_iter.next(), buf_iter.next());
-        let sig = Converter::scale_sample_hz(buf_iter, linear, resample_ratio);
-        for (r, s) in resampled_buf.iter_mut().zip(sig.take(resampled_len)) {
-            *r = s[0];
-        }
+    if let Some(resampler) = resampler {
+        resampler.resample_into(buf, resampled_buf)?;
     } else {
         for (r, s) in resampled_buf.iter_mut().zip(buf.iter()) {
             *r = *s;
@@ -0,0 +1,207 @@
+//! Loudness by ITU-R BS.1770 and EBU R128: momentary, short-term and integrated loudness in
+//! LUFS, of a single channel.
+//!
+//! The signal is K-weighted, a high shelf for the head's boost above 2 kHz and a high-pass below
+//! about 40 Hz, and loudness is the mean square of the weighted signal in decibels. Integrated
+//! loudness leaves out silence and quiet passages by gating, so pauses in speech don't pull it
+//! down.
+
+use sample::{FromSample, Sample, ToSample};
+use std::f64::consts::PI;
+
+use crate::error::*;
+use crate::units::{Hz, Lufs, Seconds};
+use crate::validate;
+use crate::waves::to_f64_samples;
+
+/// Window of momentary loudness, which is also the gating block of integrated loudness.
+const MOMENTARY_WINDOW: f64 = 0.4;
+const SHORT_TERM_WINDOW: f64 = 3.;
+/// Step between successive windows, 75% overlap of the gating blocks.
+const LOUDNESS_HOP: f64 = 0.1;
+const ABSOLUTE_GATE: f64 = -70.;
+/// How far below the loudness of the blocks over the absolute gate the relative gate sits.
+const RELATIVE_GATE: f64 = -10.;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loudness {
+    /// Loudness of 400 ms windows every `hop`, starting at the beginning of the signal
+    pub momentary: Vec<Lufs>,
+    /// Loudness of 3 s windows every `hop`
+    pub short_term: Vec<Lufs>,
+    /// Loudness of the whole signal after gating, or minus infinity if nothing passes the gates
+    pub integrated: Lufs,
+    pub hop: Seconds,
+}
+
+/// Measures the loudness of a signal, with full scale at 1.0. Windows are only taken where the
+/// signal covers them, so signals shorter than 400 ms have no loudness.
+pub fn loudness<S, R: Into<Hz>>(signal: &[S], sample_rate: R) -> VoxBoxResult<Loudness>
+where
+    S: Sample + ToSample<f64>,
+{
+    let sample_rate = sample_rate.into().0;
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    let signal = to_f64_samples(signal);
+    validate::finite(&signal)?;
+
+    // Running sum of the squared weighted signal, for the mean square of any window
+    let mut energy = Vec::with_capacity(signal.len() + 1);
+    energy.push(0.);
+    let mut total = 0.;
+    for y in k_weight(&signal, sample_rate) {
+        total += y * y;
+        energy.push(total);
+    }
+    let samples = |seconds: f64| (seconds * sample_rate).round() as usize;
+    let hop = samples(LOUDNESS_HOP).max(1);
+    let mean_squares = |window: usize| -> Vec<f64> {
+        (0..)
+            .map(|k| k * hop)
+            .take_while(|start| window > 0 && start + window <= signal.len())
+            .map(|start| (energy[start + window] - energy[start]) / window as f64)
+            .collect()
+    };
+
+    let blocks = mean_squares(samples(MOMENTARY_WINDOW));
+    let loud_enough: Vec<f64> = blocks
+        .iter()
+        .cloned()
+        .filter(|z| lufs(*z) > ABSOLUTE_GATE)
+        .collect();
+    let relative_gate = lufs(mean(&loud_enough)) + RELATIVE_GATE;
+    let gated: Vec<f64> = loud_enough
+        .into_iter()
+        .filter(|z| lufs(*z) > relative_gate)
+        .collect();
+    Ok(Loudness {
+        momentary: blocks.iter().map(|z| Lufs(lufs(*z))).collect(),
+        short_term: mean_squares(samples(SHORT_TERM_WINDOW))
+            .iter()
+            .map(|z| Lufs(lufs(*z)))
+            .collect(),
+        integrated: Lufs(lufs(mean(&gated))),
+        hop: Seconds(hop as f64 / sample_rate),
+    })
+}
+
+/// Scales a signal to an integrated loudness of `target`, as EBU R128 recommends -23 LUFS for,
+/// and returns the gain applied. Signals with no integrated loudness are left as they are, with a
+/// gain of one.
+pub fn normalize_loudness<S, R: Into<Hz>>(
+    signal: &mut [S],
+    sample_rate: R,
+    target: Lufs,
+) -> VoxBoxResult<f64>
+where
+    S: Sample + ToSample<f64> + FromSample<f64>,
+{
+    let integrated = loudness(signal, sample_rate)?.integrated;
+    if !integrated.0.is_finite() {
+        return Ok(1.);
+    }
+    let gain = 10f64.powf((target.0 - integrated.0) / 20.);
+    for s in signal.iter_mut() {
+        *s = (s.to_sample::<f64>() * gain).to_sample::<S>();
+    }
+    Ok(gain)
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10. * mean_square.log10()
+}
+
+/// Mean of the values, or zero if there are none, so that its loudness is minus infinity.
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+/// Filters by the two biquads of the K-weighting, designed for `sample_rate` as in libebur128
+/// so that at 48 kHz they match the coefficients in BS.1770.
+fn k_weight(signal: &[f64], sample_rate: f64) -> Vec<f64> {
+    // High shelf of about +4 dB above 1.7 kHz
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1. + k / q + k * k;
+    let shelf = (
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2. * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+    );
+    // Second-order high-pass at 38 Hz
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1. + k / q + k * k;
+    let high_pass = (
+        [1., -2., 1.],
+        [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+    );
+    biquad(&biquad(signal, shelf), high_pass)
+}
+
+/// Filters by `(b0 + b1 z^-1 + b2 z^-2) / (1 + a1 z^-1 + a2 z^-2)`, from silence.
+fn biquad(signal: &[f64], (b, a): ([f64; 3], [f64; 2])) -> Vec<f64> {
+    let (mut z1, mut z2) = (0., 0.);
+    signal
+        .iter()
+        .map(|x| {
+            let y = b[0] * x + z1;
+            z1 = b[1] * x - a[0] * y + z2;
+            z2 = b[2] * x - a[1] * y;
+            y
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f64, seconds: f64, sample_rate: f64) -> Vec<f64> {
+        (0..(seconds * sample_rate) as usize)
+            .map(|n| amplitude * (2. * PI * 1000. * n as f64 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_loudness() {
+        // A 1 kHz tone 20 dB below full scale is at -23 LUFS, at any sample rate
+        for sr in [48000., 44100., 16000.].iter() {
+            let measured = loudness(&tone(0.1, 5., *sr), *sr).unwrap();
+            println!("{} Hz: {}", sr, measured.integrated);
+            assert!((measured.integrated.0 + 23.01).abs() < 0.05);
+            assert_eq!(measured.momentary.len(), 47);
+            assert_eq!(measured.short_term.len(), 21);
+            assert!((measured.short_term[10].0 + 23.01).abs() < 0.05);
+        }
+
+        // Gating leaves out a quiet second half, and silence has no loudness
+        let sr = 16000.;
+        let mut signal = tone(0.1, 5., sr);
+        signal.extend(tone(0.002, 5., sr));
+        let measured = loudness(&signal, sr).unwrap();
+        assert!((measured.integrated.0 + 23.01).abs() < 0.1);
+        assert!(measured.momentary[90].0 < -50.);
+        let silent = loudness(&[0f64; 16000], sr).unwrap();
+        assert_eq!(silent.integrated.0, f64::NEG_INFINITY);
+        assert!(loudness(&[0f64; 1000], sr).unwrap().momentary.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_loudness() {
+        let sr = 16000.;
+        let mut signal = tone(0.01, 2., sr);
+        let gain = normalize_loudness(&mut signal, sr, Lufs(-23.)).unwrap();
+        assert!((gain - 10.).abs() < 0.1);
+        assert!((loudness(&signal, sr).unwrap().integrated.0 + 23.).abs() < 1e-9);
+        assert_eq!(
+            normalize_loudness(&mut [0f64; 100], sr, Lufs(-23.)).unwrap(),
+            1.
+        );
+    }
+}
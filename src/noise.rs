@@ -0,0 +1,172 @@
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::spectrum::RealFft;
+use crate::waves::WindowType;
+
+/// Tracks the noise power spectrum of a stream of frames by minimum statistics (Martin 2001).
+///
+/// Each bin's power is smoothed over time, and the noise floor is the minimum of the smoothed
+/// power over roughly the last `window_frames` frames, scaled up by `bias` because a minimum
+/// always underestimates the mean. Speech rarely fills every bin for the whole window, so the
+/// estimate keeps following the noise while someone is talking. The window is kept as a few
+/// subwindow minima, so each update is cheap.
+#[derive(Clone, Debug)]
+pub struct NoiseFloor {
+    /// Temporal smoothing of the power spectrum, between 0 and 1
+    pub smoothing: f64,
+    /// Factor compensating for the minimum being below the mean
+    pub bias: f64,
+    fft: RealFft,
+    window: Vec<f64>,
+    subwindow_len: usize,
+    n_subwindows: usize,
+    frames: usize,
+    smoothed: Vec<f64>,
+    current_min: Vec<f64>,
+    subwindow_mins: Vec<Vec<f64>>,
+    noise: Vec<f64>,
+}
+
+impl NoiseFloor {
+    /// Creates an estimator for frames of `frame_len` samples that remembers minima over about
+    /// `window_frames` frames.
+    pub fn new(frame_len: usize, window_frames: usize) -> Self {
+        Self::with_window(WindowType::Hanning.window(frame_len), window_frames)
+    }
+
+    /// Creates an estimator for the frame length and window of `config`.
+    pub fn from_config(config: &AnalysisConfig, window_frames: usize) -> Self {
+        Self::with_window(config.window.window(config.frame_len), window_frames)
+    }
+
+    fn with_window(window: Vec<f64>, window_frames: usize) -> Self {
+        let fft_len = window.len().next_power_of_two();
+        let n_bins = fft_len / 2 + 1;
+        let n_subwindows = 8;
+        NoiseFloor {
+            smoothing: 0.85,
+            bias: 1.5,
+            fft: RealFft::new(fft_len),
+            window,
+            subwindow_len: window_frames.div_ceil(n_subwindows).max(1),
+            n_subwindows,
+            frames: 0,
+            smoothed: vec![0.; n_bins],
+            current_min: vec![f64::INFINITY; n_bins],
+            subwindow_mins: Vec::with_capacity(n_subwindows),
+            noise: vec![0.; n_bins],
+        }
+    }
+
+    /// The current noise power estimate, one value per FFT bin.
+    pub fn spectrum(&self) -> &[f64] {
+        &self.noise[..]
+    }
+
+    /// The mean of the noise power estimate over all bins.
+    pub fn level(&self) -> f64 {
+        self.noise.iter().sum::<f64>() / self.noise.len() as f64
+    }
+
+    /// Number of bins in the spectra this estimator expects.
+    pub fn bins(&self) -> usize {
+        self.fft.len() / 2 + 1
+    }
+
+    /// Windows a frame of samples and updates the estimate with its power spectrum.
+    pub fn update<S: Sample + ToSample<f64>>(&mut self, frame: &[S]) -> &[f64] {
+        let windowed: Vec<f64> = frame
+            .iter()
+            .zip(self.window.iter())
+            .map(|(s, w)| s.to_sample::<f64>() * w)
+            .collect();
+        let power = self.fft.power_spectrum(&windowed);
+        self.track(&power)
+    }
+
+    /// Updates the estimate with a power spectrum that has already been computed, which must
+    /// have `bins()` values.
+    pub fn update_power(&mut self, power: &[f64]) -> VoxBoxResult<&[f64]> {
+        if power.len() != self.bins() {
+            return Err(VoxBoxError::Input(
+                "Power spectrum must have one value per bin",
+            ));
+        }
+        Ok(self.track(power))
+    }
+
+    fn track(&mut self, power: &[f64]) -> &[f64] {
+        let alpha = if self.frames == 0 { 0. } else { self.smoothing };
+        self.frames += 1;
+
+        for (k, p) in power.iter().enumerate() {
+            self.smoothed[k] = alpha * self.smoothed[k] + (1. - alpha) * p;
+            self.current_min[k] = self.current_min[k].min(self.smoothed[k]);
+            let min = self
+                .subwindow_mins
+                .iter()
+                .fold(self.current_min[k], |m, sub| m.min(sub[k]));
+            self.noise[k] = self.bias * min;
+        }
+
+        if self.frames.is_multiple_of(self.subwindow_len) {
+            if self.subwindow_mins.len() == self.n_subwindows {
+                self.subwindow_mins.remove(0);
+            }
+            self.subwindow_mins.push(self.current_min.clone());
+            for m in self.current_min.iter_mut() {
+                *m = f64::INFINITY;
+            }
+        }
+        &self.noise[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_noise_floor() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut floor = NoiseFloor::new(256, 64);
+        let level = |sigma: f64| {
+            let window = &floor.window;
+            sigma * sigma * window.iter().map(|w| w * w).sum::<f64>()
+        };
+        let (quiet, loud) = (level(0.01), level(0.04));
+
+        let mut estimates = vec![];
+        for i in 0..400 {
+            let sigma = if (150..300).contains(&i) { 0.04 } else { 0.01 };
+            let normal = Normal::new(0., sigma);
+            let mut frame: Vec<f64> = (0..256).map(|_| normal.ind_sample(&mut rng)).collect();
+            // Tone bursts on top of the noise shouldn't lift the floor
+            if i % 40 < 10 {
+                for (n, s) in frame.iter_mut().enumerate() {
+                    *s += 0.1 * (2. * PI * 0.05 * n as f64).sin();
+                }
+            }
+            floor.update(&frame[..]);
+            estimates.push(floor.level());
+        }
+
+        println!("quiet: {}, loud: {}", quiet, loud);
+        println!("estimates: {:?}", &estimates[140..150]);
+        // Steady noise, a rise, and a fall, each after the window has caught up
+        assert!(estimates[140] > quiet / 2. && estimates[140] < quiet * 2.);
+        assert!(estimates[290] > loud / 2. && estimates[290] < loud * 2.);
+        assert!(estimates[399] > quiet / 2. && estimates[399] < quiet * 2.);
+
+        let bins = floor.bins();
+        assert!(floor.update_power(&vec![1.; bins - 1]).is_err());
+        assert_eq!(floor.update_power(&vec![1.; bins]).unwrap().len(), bins);
+    }
+}
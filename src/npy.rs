@@ -0,0 +1,293 @@
+//! Writes feature matrices as NumPy `.npy` files, and several named ones as an `.npz` archive, so
+//! that `numpy.load` reads them straight into arrays.
+//!
+//! ```
+//! use vox_box::features::FeatureMatrix;
+//! use vox_box::npy::NpzWriter;
+//!
+//! let mfcc = FeatureMatrix::from_rows(3, &[[1., 2., 3.], [4., 5., 6.]]).unwrap();
+//! let mut npz = NpzWriter::new(Vec::new());
+//! npz.add("mfcc", &mfcc).unwrap();
+//! npz.add_vector("f0", &[120., f64::NAN]).unwrap();
+//! let bytes = npz.finish().unwrap();
+//! assert_eq!(&bytes[..4], b"PK\x03\x04");
+//! ```
+
+use num_complex::Complex;
+use std::io::Write;
+
+use crate::error::*;
+use crate::features::FeatureMatrix;
+
+/// Element types NumPy can read, with their little-endian type descriptions.
+pub trait NpyElement: Copy {
+    const DESCR: &'static str;
+
+    fn write_le(self, out: &mut Vec<u8>);
+}
+
+macro_rules! impl_npy_element {
+    ($($t:ty => $descr:expr),*) => {$(
+        impl NpyElement for $t {
+            const DESCR: &'static str = $descr;
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    )*};
+}
+
+impl_npy_element!(f32 => "<f4", f64 => "<f8", i16 => "<i2", i32 => "<i4", i64 => "<i8");
+
+impl NpyElement for Complex<f32> {
+    const DESCR: &'static str = "<c8";
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        self.re.write_le(out);
+        self.im.write_le(out);
+    }
+}
+
+impl NpyElement for Complex<f64> {
+    const DESCR: &'static str = "<c16";
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        self.re.write_le(out);
+        self.im.write_le(out);
+    }
+}
+
+/// Writes a matrix as a 2-D array of frames by values.
+pub fn write_npy<T: NpyElement, W: Write>(
+    matrix: &FeatureMatrix<T>,
+    mut writer: W,
+) -> VoxBoxResult<()> {
+    let shape = format!("({}, {})", matrix.n_rows(), matrix.n_cols());
+    writer
+        .write_all(&npy_bytes::<T>(&shape, matrix.as_slice()))
+        .map_err(write_error)
+}
+
+/// Writes a track, such as pitch, as a 1-D array.
+pub fn write_npy_vector<T: NpyElement, W: Write>(values: &[T], mut writer: W) -> VoxBoxResult<()> {
+    let shape = format!("({},)", values.len());
+    writer
+        .write_all(&npy_bytes(&shape, values))
+        .map_err(write_error)
+}
+
+/// The whole `.npy` file: magic, version 1.0, the header padded so that the data starts on a
+/// 64-byte boundary, and the data in C order.
+fn npy_bytes<T: NpyElement>(shape: &str, data: &[T]) -> Vec<u8> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        T::DESCR,
+        shape
+    );
+    // Magic, version and header length take 10 bytes, and the header ends in a newline
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + data.len() * 16);
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for x in data.iter() {
+        x.write_le(&mut bytes);
+    }
+    bytes
+}
+
+/// Largest archive the plain zip format can describe, without the ZIP64 extensions.
+const MAX_ZIP_SIZE: usize = u32::MAX as usize;
+
+/// Builds an `.npz` archive, an uncompressed zip of `.npy` files, one array at a time.
+/// `numpy.load` gives back a mapping from each name to its array.
+#[derive(Debug)]
+pub struct NpzWriter<W> {
+    writer: W,
+    /// Name, CRC, size and offset of each file written, for the central directory
+    entries: Vec<(String, u32, u32, u32)>,
+    offset: usize,
+}
+
+impl<W: Write> NpzWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NpzWriter {
+            writer,
+            entries: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Adds a matrix as the array `name`.
+    pub fn add<T: NpyElement>(
+        &mut self,
+        name: &str,
+        matrix: &FeatureMatrix<T>,
+    ) -> VoxBoxResult<()> {
+        let shape = format!("({}, {})", matrix.n_rows(), matrix.n_cols());
+        self.add_file(name, npy_bytes(&shape, matrix.as_slice()))
+    }
+
+    /// Adds a track as the 1-D array `name`.
+    pub fn add_vector<T: NpyElement>(&mut self, name: &str, values: &[T]) -> VoxBoxResult<()> {
+        let shape = format!("({},)", values.len());
+        self.add_file(name, npy_bytes(&shape, values))
+    }
+
+    /// Writes the directory that ends the archive, and returns the writer.
+    pub fn finish(mut self) -> VoxBoxResult<W> {
+        let mut directory = Vec::new();
+        for (name, crc, size, offset) in self.entries.iter() {
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            // Made by and needs version 2.0
+            directory.extend_from_slice(&[20, 0, 20, 0]);
+            file_fields(&mut directory, name, *crc, *size);
+            // Comment length, disk, internal and external attributes
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+        if self.offset + directory.len() > MAX_ZIP_SIZE || self.entries.len() > u16::MAX as usize {
+            return Err(VoxBoxError::Io("Archive is too large for zip"));
+        }
+        let n_entries = (self.entries.len() as u16).to_le_bytes();
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        // This disk, and the disk the directory starts on
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&n_entries);
+        end.extend_from_slice(&n_entries);
+        end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&(self.offset as u32).to_le_bytes());
+        // Comment length
+        end.extend_from_slice(&[0; 2]);
+
+        self.writer.write_all(&directory).map_err(write_error)?;
+        self.writer.write_all(&end).map_err(write_error)?;
+        Ok(self.writer)
+    }
+
+    fn add_file(&mut self, name: &str, npy: Vec<u8>) -> VoxBoxResult<()> {
+        if name.is_empty() {
+            return Err(VoxBoxError::Config("Array name must not be empty"));
+        }
+        let name = format!("{}.npy", name);
+        if self.entries.iter().any(|e| e.0 == name) {
+            return Err(VoxBoxError::Config("Array names must be unique"));
+        }
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        header.extend_from_slice(&[20, 0]);
+        let crc = crc32(&npy);
+        file_fields(&mut header, &name, crc, npy.len() as u32);
+        header.extend_from_slice(name.as_bytes());
+        if self.offset + header.len() + npy.len() > MAX_ZIP_SIZE {
+            return Err(VoxBoxError::Io("Archive is too large for zip"));
+        }
+
+        self.writer.write_all(&header).map_err(write_error)?;
+        self.writer.write_all(&npy).map_err(write_error)?;
+        self.entries
+            .push((name, crc, npy.len() as u32, self.offset as u32));
+        self.offset += header.len() + npy.len();
+        Ok(())
+    }
+}
+
+/// The fields a local header and a directory entry share, from the flags to the extra field
+/// length, for a stored file.
+fn file_fields(out: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+    // No flags, stored without compression
+    out.extend_from_slice(&[0; 4]);
+    // Modified at midnight on 1 January 1980, the earliest time zip can record
+    out.extend_from_slice(&[0, 0, 0x21, 0]);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    // No extra field
+    out.extend_from_slice(&[0; 2]);
+}
+
+/// CRC-32 as zip uses it: reflected, polynomial 0xedb88320, inverted before and after.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes.iter() {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn write_error(_: std::io::Error) -> VoxBoxError {
+    VoxBoxError::Io("Could not write the file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_npy() {
+        let matrix = FeatureMatrix::from_rows(2, &[[1f64, 2.], [3., 4.], [5., 6.]]).unwrap();
+        let mut bytes = Vec::new();
+        write_npy(&matrix, &mut bytes).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        println!("{:?}", header);
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (3, 2), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(bytes.len(), 10 + header_len + 6 * 8);
+        assert_eq!(
+            &bytes[10 + header_len + 8..10 + header_len + 16],
+            &2f64.to_le_bytes()
+        );
+
+        let mut bytes = Vec::new();
+        write_npy_vector(&[1i16, -2], &mut bytes).unwrap();
+        let header = std::str::from_utf8(&bytes[10..bytes.len() - 4]).unwrap();
+        assert!(header.contains("'descr': '<i2'"));
+        assert!(header.contains("'shape': (2,)"));
+        assert_eq!(&bytes[bytes.len() - 2..], &[0xfe, 0xff]);
+    }
+
+    #[test]
+    fn test_write_npz() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let matrix = FeatureMatrix::from_rows(1, &[[0.5f32]]).unwrap();
+        let mut npz = NpzWriter::new(Vec::new());
+        npz.add("mfcc", &matrix).unwrap();
+        npz.add_vector("f0", &[100f64, 110.]).unwrap();
+        assert!(npz.add_vector("f0", &[0f64]).is_err());
+        assert!(npz.add_vector("", &[0f64]).is_err());
+        let bytes = npz.finish().unwrap();
+
+        // Two local headers and their files, then the directory and its end
+        let mfcc_len = 30 + "mfcc.npy".len() + npy_bytes("(1, 1)", &[0.5f32]).len();
+        let f0_len = 30 + "f0.npy".len() + npy_bytes("(2,)", &[100f64, 110.]).len();
+        let directory_len = 46 + "mfcc.npy".len() + 46 + "f0.npy".len();
+        assert_eq!(bytes.len(), mfcc_len + f0_len + directory_len + 22);
+        assert_eq!(&bytes[mfcc_len..mfcc_len + 4], b"PK\x03\x04");
+        assert_eq!(&bytes[mfcc_len + 30..mfcc_len + 36], b"f0.npy");
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(&end[10..12], &[2, 0]);
+        assert_eq!(
+            u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize,
+            mfcc_len + f0_len
+        );
+    }
+}
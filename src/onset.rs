@@ -0,0 +1,275 @@
+use sample::{Sample, ToSample};
+use std::cmp::Ordering;
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::spectrum::Stft;
+use crate::units::{Hz, Seconds};
+use crate::waves::WindowType;
+
+/// Compression applied to magnitudes before taking differences, so that quiet onsets count too.
+const LOG_COMPRESSION: f64 = 100.;
+
+/// Whether an event starts or ends a stretch of sound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Onset,
+    Offset,
+}
+
+/// An onset or offset, and when it happened.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeechEvent {
+    pub kind: EventKind,
+    pub time: Seconds,
+}
+
+/// Log-compressed magnitude spectra of the STFT frames of a signal. Calls `progress` after
+/// every frame.
+fn log_spectra<S, F>(signal: &[S], stft: &Stft, mut progress: F) -> VoxBoxResult<Vec<Vec<f64>>>
+where
+    S: Sample + ToSample<f64>,
+    F: FnMut(Progress) -> ControlFlow<()>,
+{
+    let frames = stft.frames(signal)?;
+    let n_frames = frames.len();
+    let mut spectra = Vec::with_capacity(n_frames);
+    for (k, frame) in frames.enumerate() {
+        spectra.push(
+            frame
+                .iter()
+                .map(|bin| (LOG_COMPRESSION * bin.norm()).ln_1p())
+                .collect(),
+        );
+        progress::report(&mut progress, k + 1, n_frames)?;
+    }
+    Ok(spectra)
+}
+
+/// Number of hops between the frames compared by the flux, so that frames which only
+/// partially overlap an onset don't register as onsets and offsets of their own.
+fn flux_lag(frame_len: usize, hop: usize) -> usize {
+    frame_len.div_ceil(hop.max(1)).max(1)
+}
+
+/// Spectral flux: for each frame, the summed rise (or, if `rising` is false, fall) in log
+/// magnitude over all bins since `lag` frames before. Frames before the start count as silence.
+fn flux(spectra: &[Vec<f64>], lag: usize, rising: bool) -> Vec<f64> {
+    let silence = vec![0.; spectra.first().map_or(0, |s| s.len())];
+    spectra
+        .iter()
+        .enumerate()
+        .map(|(k, cur)| {
+            let prev = if k >= lag {
+                &spectra[k - lag]
+            } else {
+                &silence
+            };
+            cur.iter()
+                .zip(prev.iter())
+                .map(|(c, p)| if rising { c - p } else { p - c }.max(0.))
+                .sum()
+        })
+        .collect()
+}
+
+/// Half-wave rectified spectral flux of every STFT frame: the summed rise in log-compressed
+/// magnitude over all bins since the last frame that doesn't overlap it, or since silence
+/// before the start of the signal.
+pub fn spectral_flux<S>(signal: &[S], stft: &Stft) -> VoxBoxResult<Vec<f64>>
+where
+    S: Sample + ToSample<f64>,
+{
+    let spectra = log_spectra(signal, stft, progress::ignore)?;
+    Ok(flux(&spectra, flux_lag(stft.frame_len, stft.hop), true))
+}
+
+/// Onset strength envelope: the `spectral_flux` of each Hanning-windowed frame.
+pub fn onset_strength<S>(signal: &[S], frame_len: usize, hop: usize) -> Vec<f64>
+where
+    S: Sample + ToSample<f64>,
+{
+    spectral_flux(signal, &Stft::new(frame_len, hop)).unwrap_or_default()
+}
+
+/// Offset strength envelope: like `onset_strength`, but summing falls in energy.
+pub fn offset_strength<S>(signal: &[S], frame_len: usize, hop: usize) -> Vec<f64>
+where
+    S: Sample + ToSample<f64>,
+{
+    let spectra =
+        log_spectra(signal, &Stft::new(frame_len, hop), progress::ignore).unwrap_or_default();
+    flux(&spectra, flux_lag(frame_len, hop), false)
+}
+
+/// Picks peaks from a strength envelope. A frame is a peak if it is the maximum within `wait`
+/// frames either side, exceeds the mean over that neighborhood by `delta`, and is more than
+/// `wait` frames after the previous peak.
+pub fn pick_peaks(envelope: &[f64], wait: usize, delta: f64) -> Vec<usize> {
+    let mut peaks: Vec<usize> = Vec::new();
+    for (i, v) in envelope.iter().enumerate() {
+        let lo = i.saturating_sub(wait);
+        let hi = (i + wait + 1).min(envelope.len());
+        let neighborhood = &envelope[lo..hi];
+        let is_max = neighborhood.iter().all(|n| n <= v);
+        let mean = neighborhood.iter().sum::<f64>() / neighborhood.len() as f64;
+        let spaced = peaks.last().is_none_or(|p| i - p > wait);
+        if is_max && *v > mean + delta && spaced {
+            peaks.push(i);
+        }
+    }
+    peaks
+}
+
+/// Finds onsets and offsets in a signal from its onset and offset strength envelopes.
+#[derive(Clone, Debug)]
+pub struct OnsetDetector {
+    pub sample_rate: f64,
+    pub frame_len: usize,
+    pub hop: usize,
+    pub window: WindowType,
+    /// Half-width of the peak picking neighborhood, in frames
+    pub wait: usize,
+    /// Amount a peak must exceed its neighborhood mean by, relative to the envelope maximum
+    pub delta: f64,
+}
+
+impl OnsetDetector {
+    /// Default settings: 32 ms frames, 10 ms hops and a 50 ms peak picking neighborhood.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        OnsetDetector {
+            sample_rate,
+            frame_len: (sample_rate * 0.032).round() as usize,
+            hop: (sample_rate * 0.01).round() as usize,
+            window: WindowType::Hanning,
+            wait: 5,
+            delta: 0.1,
+        }
+    }
+
+    /// Takes the framing from `config`, keeping the default peak picking settings.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        OnsetDetector {
+            frame_len: config.frame_len,
+            hop: config.hop,
+            window: config.window,
+            ..OnsetDetector::new(config.sample_rate)
+        }
+    }
+
+    /// Times of the onsets in the signal, in order.
+    pub fn onsets<S: Sample + ToSample<f64>>(&self, signal: &[S]) -> Vec<Seconds> {
+        self.detect(signal)
+            .into_iter()
+            .filter(|e| e.kind == EventKind::Onset)
+            .map(|e| e.time)
+            .collect()
+    }
+
+    /// Returns all events in the signal, ordered by time. An event's time is halfway between
+    /// the centers of the two frames that were compared to find it.
+    pub fn detect<S: Sample + ToSample<f64>>(&self, signal: &[S]) -> Vec<SpeechEvent> {
+        // Only the callback can cancel
+        self.detect_with_progress(signal, progress::ignore)
+            .unwrap_or_default()
+    }
+
+    /// Like `detect`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled`
+    /// if the callback breaks.
+    pub fn detect_with_progress<S, F>(
+        &self,
+        signal: &[S],
+        progress: F,
+    ) -> VoxBoxResult<Vec<SpeechEvent>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        let stft = Stft {
+            window: self.window,
+            ..Stft::new(self.frame_len, self.hop)
+        };
+        let spectra = log_spectra(signal, &stft, progress)?;
+        let lag = flux_lag(self.frame_len, self.hop);
+        let mut events: Vec<SpeechEvent> = [(EventKind::Onset, true), (EventKind::Offset, false)]
+            .iter()
+            .flat_map(|&(kind, rising)| {
+                let envelope = flux(&spectra, lag, rising);
+                let max = envelope.iter().cloned().fold(0., f64::max);
+                let normalized: Vec<f64> = if max > 0. {
+                    envelope.iter().map(|e| e / max).collect()
+                } else {
+                    envelope
+                };
+                pick_peaks(&normalized, self.wait, self.delta)
+                    .into_iter()
+                    .map(|k| SpeechEvent {
+                        kind,
+                        time: Seconds(
+                            ((k as f64 - lag as f64 / 2.) * self.hop as f64
+                                + self.frame_len as f64 / 2.)
+                                / self.sample_rate,
+                        ),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_pick_peaks() {
+        let envelope = [0., 0.1, 1., 0.1, 0., 0., 0.05, 0., 0.8, 0.7, 0.];
+        assert_eq!(pick_peaks(&envelope, 2, 0.2), vec![2, 8]);
+    }
+
+    #[test]
+    fn test_onsets() {
+        let sample_rate = 8000.;
+        // Tones from 0.2 to 0.5 s and from 0.8 to 1.0 s, with 20 ms ramps so they don't click
+        let ramp = |t: f64, start: f64, end: f64| {
+            let edge = ((t - start).min(end - t) / 0.02).clamp(0., 1.);
+            0.5 - 0.5 * (PI * edge).cos()
+        };
+        let signal: Vec<f64> = (0..10000)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                let envelope = ramp(t, 0.19, 0.51) + ramp(t, 0.79, 1.01);
+                0.5 * envelope * (2. * PI * 300. * t).sin()
+            })
+            .collect();
+        let detector = OnsetDetector::new(sample_rate);
+        let events = detector.detect(&signal[..]);
+        println!("events: {:?}", events);
+        let onsets = detector.onsets(&signal[..]);
+        assert_eq!(onsets.len(), 2);
+        assert!((onsets[1].0 - 0.8).abs() < 0.03);
+        let flux = spectral_flux(&signal[..], &Stft::new(256, 80)).unwrap();
+        assert_eq!(flux.len(), 125);
+        // Rising into the first tone, and steady within it
+        let rise = flux[15..25].iter().cloned().fold(0., f64::max);
+        let steady = flux[30..60].iter().cloned().fold(0., f64::max);
+        assert!(rise > 10. * steady);
+
+        let expected = [
+            (EventKind::Onset, 0.2),
+            (EventKind::Offset, 0.5),
+            (EventKind::Onset, 0.8),
+            (EventKind::Offset, 1.0),
+        ];
+        assert_eq!(events.len(), expected.len());
+        for (event, (kind, time)) in events.iter().zip(expected.iter()) {
+            assert_eq!(event.kind, *kind);
+            assert!((event.time.0 - time).abs() < 0.03);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Conversion between floating-point samples and integer PCM.
+//!
+//! Rounding a quiet or slowly fading signal to integers leaves an error that follows the signal,
+//! heard as distortion rather than noise. Dither adds a little random noise before rounding so
+//! that the error is independent of the signal, and noise shaping pushes that noise up towards
+//! Nyquist, where it is less audible and mostly outside the speech band.
+//!
+//! Headerless PCM, as C hosts and raw dumps hand it over, can be decoded from bytes of any of the
+//! usual sample formats, and interleaved channels split apart for analysis.
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use sample::{Sample, ToSample};
+use std::path::Path;
+
+use crate::error::*;
+
+/// Integer sample formats. 24-bit samples are carried in the low bits of an `i32`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmFormat {
+    I16,
+    I24,
+    I32,
+}
+
+impl PcmFormat {
+    pub fn bits(self) -> u32 {
+        match self {
+            PcmFormat::I16 => 16,
+            PcmFormat::I24 => 24,
+            PcmFormat::I32 => 32,
+        }
+    }
+
+    /// The integer value of full scale, 1.0, which is one more than the largest sample.
+    pub fn full_scale(self) -> f64 {
+        2f64.powi(self.bits() as i32 - 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Plain rounding
+    None,
+    /// Triangular (TPDF) dither of one step either side, the least that makes the error
+    /// independent of the signal
+    Triangular,
+    /// Triangular dither with the error fed back to shape the noise by `1 - z^-1`, which lowers
+    /// it at low frequencies and raises it towards Nyquist
+    Shaped,
+}
+
+/// Dither seed of `Quantizer::new`.
+const DEFAULT_SEED: [u32; 4] = [0x193a_6754, 0xa8a7_d469, 0x9783_0e05, 0x113b_a7bb];
+
+/// How far the shaped error is allowed to carry over, in steps, so that clipping doesn't make
+/// the feedback run away.
+const MAX_SHAPED_ERROR: f64 = 1.5;
+
+/// Rounds samples to integer PCM, carrying the noise shaping error and the dither generator
+/// across calls so that a stream can be converted block by block.
+#[derive(Clone, Debug)]
+pub struct Quantizer {
+    format: PcmFormat,
+    dither: Dither,
+    rng: XorShiftRng,
+    /// Rounding error of the last sample, in steps
+    error: f64,
+}
+
+impl Quantizer {
+    /// A quantizer whose dither comes from a fixed seed, so that output is reproducible.
+    pub fn new(format: PcmFormat, dither: Dither) -> Self {
+        Quantizer {
+            format,
+            dither,
+            rng: XorShiftRng::from_seed(DEFAULT_SEED),
+            error: 0.,
+        }
+    }
+
+    /// Seeds the dither generator. Xorshift can't run from all zeros, so that seed gives the
+    /// same dither as `new`.
+    pub fn seed(mut self, seed: [u32; 4]) -> Self {
+        let seed = if seed == [0; 4] { DEFAULT_SEED } else { seed };
+        self.rng = XorShiftRng::from_seed(seed);
+        self
+    }
+
+    pub fn format(&self) -> PcmFormat {
+        self.format
+    }
+
+    /// Rounds samples, with full scale at 1.0, to the integer format, clipping any that are out
+    /// of range.
+    pub fn quantize<S>(&mut self, samples: &[S]) -> Vec<i32>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let scale = self.format.full_scale();
+        let (min, max) = (-scale, scale - 1.);
+        samples
+            .iter()
+            .map(|s| {
+                let mut target = s.to_sample::<f64>() * scale;
+                if self.dither == Dither::Shaped {
+                    target -= self.error;
+                }
+                let noise = match self.dither {
+                    Dither::None => 0.,
+                    Dither::Triangular | Dither::Shaped => {
+                        self.rng.gen::<f64>() - self.rng.gen::<f64>()
+                    }
+                };
+                let q = (target + noise).round().clamp(min, max);
+                self.error = (q - target).clamp(-MAX_SHAPED_ERROR, MAX_SHAPED_ERROR);
+                q as i32
+            })
+            .collect()
+    }
+
+    /// Rounds samples to 16 bits, whatever the quantizer's format.
+    pub fn quantize_i16<S>(&mut self, samples: &[S]) -> Vec<i16>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let format = self.format;
+        self.format = PcmFormat::I16;
+        let quantized = self.quantize(samples);
+        self.format = format;
+        quantized.into_iter().map(|q| q as i16).collect()
+    }
+
+    /// Forgets the noise shaping error, as at the start of a new stream.
+    pub fn reset(&mut self) {
+        self.error = 0.;
+    }
+}
+
+/// Sample formats of headerless PCM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawSample {
+    /// Unsigned 8-bit, with silence at 128
+    U8,
+    I16,
+    /// Signed 24-bit, packed in three bytes
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl RawSample {
+    pub fn bytes(self) -> usize {
+        match self {
+            RawSample::U8 => 1,
+            RawSample::I16 => 2,
+            RawSample::I24 => 3,
+            RawSample::I32 | RawSample::F32 => 4,
+            RawSample::F64 => 8,
+        }
+    }
+
+    /// The value of a sample from its big-endian bytes.
+    fn value(self, b: &[u8]) -> f64 {
+        match self {
+            RawSample::U8 => (b[0] as f64 - 128.) / 128.,
+            RawSample::I16 => i16::from_be_bytes([b[0], b[1]]) as f64 / 32768.,
+            // Shifted up to the top of an i32 so that the sign comes along
+            RawSample::I24 => i32::from_be_bytes([b[0], b[1], b[2], 0]) as f64 / 2147483648.,
+            RawSample::I32 => i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2147483648.,
+            RawSample::F32 => f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            RawSample::F64 => f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Layout of headerless PCM: the sample format, byte order and number of interleaved channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawFormat {
+    pub sample: RawSample,
+    pub endianness: Endianness,
+    pub channels: usize,
+}
+
+impl RawFormat {
+    /// Little-endian samples, as most hosts give them.
+    pub fn new(sample: RawSample, channels: usize) -> Self {
+        RawFormat {
+            sample,
+            endianness: Endianness::Little,
+            channels,
+        }
+    }
+
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Decodes bytes into one `Vec` per channel, with full scale at 1.0. The bytes must be a
+    /// whole number of frames.
+    pub fn decode(&self, bytes: &[u8]) -> VoxBoxResult<Vec<Vec<f64>>> {
+        if self.channels == 0 {
+            return Err(VoxBoxError::Config("Channel count must be nonzero"));
+        }
+        let width = self.sample.bytes();
+        if !bytes.len().is_multiple_of(width * self.channels) {
+            return Err(VoxBoxError::Input("Bytes are not a whole number of frames"));
+        }
+        let interleaved: Vec<f64> = bytes
+            .chunks(width)
+            .map(|chunk| {
+                // Most significant byte first
+                let mut word = [0u8; 8];
+                for (w, b) in word.iter_mut().zip(chunk.iter()) {
+                    *w = *b;
+                }
+                if self.endianness == Endianness::Little {
+                    word[..width].reverse();
+                }
+                self.sample.value(&word[..width])
+            })
+            .collect();
+        deinterleave(&interleaved, self.channels)
+    }
+
+    /// Reads a file of headerless PCM, as `decode`.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> VoxBoxResult<Vec<Vec<f64>>> {
+        let bytes = std::fs::read(path)?;
+        self.decode(&bytes)
+    }
+}
+
+/// Splits frames of `n_channels` interleaved samples into one `Vec` per channel.
+pub fn deinterleave<S>(interleaved: &[S], n_channels: usize) -> VoxBoxResult<Vec<Vec<f64>>>
+where
+    S: Sample + ToSample<f64>,
+{
+    if n_channels == 0 {
+        return Err(VoxBoxError::Config("Channel count must be nonzero"));
+    }
+    if !interleaved.len().is_multiple_of(n_channels) {
+        return Err(VoxBoxError::Input(
+            "Samples are not a whole number of frames",
+        ));
+    }
+    Ok((0..n_channels)
+        .map(|c| {
+            interleaved
+                .iter()
+                .skip(c)
+                .step_by(n_channels)
+                .map(|s| s.to_sample::<f64>())
+                .collect()
+        })
+        .collect())
+}
+
+/// Interleaves channels, which must all be the same length, into frames.
+pub fn interleave(channels: &[Vec<f64>]) -> VoxBoxResult<Vec<f64>> {
+    let len = channels.first().map_or(0, |c| c.len());
+    if channels.iter().any(|c| c.len() != len) {
+        return Err(VoxBoxError::Input("Channels must be the same length"));
+    }
+    Ok((0..len)
+        .flat_map(|n| channels.iter().map(move |c| c[n]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_quantize() {
+        let mut plain = Quantizer::new(PcmFormat::I16, Dither::None);
+        assert_eq!(
+            plain.quantize(&[0.5f64, -1., 1., 2., 1.4 / 32768.]),
+            vec![16384, -32768, 32767, 32767, 1]
+        );
+        let mut wide = Quantizer::new(PcmFormat::I24, Dither::None);
+        assert_eq!(wide.quantize(&[-1f32, 0.25]), vec![-8388608, 2097152]);
+        assert_eq!(plain.quantize_i16(&[0.25f64]), vec![8192]);
+
+        // Dither keeps levels below a step: 0.3 of a step averages out to 0.3, not 0
+        let quiet = vec![0.3 / 32768.; 20000];
+        assert!(plain.quantize(&quiet).iter().all(|q| *q == 0));
+        let mut dithered = Quantizer::new(PcmFormat::I16, Dither::Triangular);
+        let mean = dithered.quantize(&quiet).iter().sum::<i32>() as f64 / 20000.;
+        println!("mean: {}", mean);
+        assert!((mean - 0.3).abs() < 0.03);
+
+        // An all-zero seed falls back to the default one instead of panicking
+        let mut zero = Quantizer::new(PcmFormat::I16, Dither::Triangular).seed([0; 4]);
+        let mut fresh = Quantizer::new(PcmFormat::I16, Dither::Triangular);
+        assert_eq!(zero.quantize(&quiet[..100]), fresh.quantize(&quiet[..100]));
+    }
+
+    #[test]
+    fn test_noise_shaping() {
+        // Shaping lowers the error well below Nyquist, at the cost of more overall
+        let signal: Vec<f64> = (0..20000)
+            .map(|n| 0.001 * (2. * PI * 0.01 * n as f64).sin())
+            .collect();
+        let low_error = |dither: Dither| {
+            let quantized = Quantizer::new(PcmFormat::I16, dither).quantize(&signal);
+            let error: Vec<f64> = quantized
+                .iter()
+                .zip(signal.iter())
+                .map(|(q, s)| *q as f64 - s * 32768.)
+                .collect();
+            // Energy of a 16-sample moving average, which keeps the bottom eighth of the band
+            error
+                .windows(16)
+                .map(|w| (w.iter().sum::<f64>() / 16.).powi(2))
+                .sum::<f64>()
+        };
+        let (flat, shaped) = (low_error(Dither::Triangular), low_error(Dither::Shaped));
+        println!("low-frequency error: flat {}, shaped {}", flat, shaped);
+        assert!(shaped * 5. < flat);
+    }
+
+    #[test]
+    fn test_decode_raw() {
+        let stereo = RawFormat::new(RawSample::I16, 2);
+        assert_eq!(
+            stereo
+                .decode(&[0x00, 0x40, 0x00, 0x80, 0xff, 0xff, 0x00, 0x00])
+                .unwrap(),
+            vec![vec![0.5, -1. / 32768.], vec![-1., 0.]]
+        );
+        let big = RawFormat::new(RawSample::I24, 1).endianness(Endianness::Big);
+        assert_eq!(
+            big.decode(&[0x40, 0x00, 0x00, 0xe0, 0x00, 0x00]).unwrap(),
+            vec![vec![0.5, -0.25]]
+        );
+        let floats: Vec<u8> = [0.25f32, -0.5]
+            .iter()
+            .flat_map(|f| f.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(
+            RawFormat::new(RawSample::F32, 1).decode(&floats).unwrap(),
+            vec![vec![0.25, -0.5]]
+        );
+        let doubles: Vec<u8> = [0.125f64]
+            .iter()
+            .flat_map(|f| f.to_be_bytes().to_vec())
+            .collect();
+        let format = RawFormat::new(RawSample::F64, 1).endianness(Endianness::Big);
+        assert_eq!(format.decode(&doubles).unwrap(), vec![vec![0.125]]);
+        assert_eq!(
+            RawFormat::new(RawSample::U8, 1)
+                .decode(&[128, 0, 192])
+                .unwrap(),
+            vec![vec![0., -1., 0.5]]
+        );
+        assert_eq!(
+            RawFormat::new(RawSample::I32, 1)
+                .decode(&[0, 0, 0, 0xc0])
+                .unwrap(),
+            vec![vec![-0.5]]
+        );
+
+        // Partial frames
+        assert!(stereo.decode(&[0, 0, 0, 0, 0, 0]).is_err());
+        assert!(RawFormat::new(RawSample::I16, 0).decode(&[]).is_err());
+    }
+
+    #[test]
+    fn test_interleave() {
+        let channels = deinterleave(&[1i16, 2, 3, 4, 5, 6], 3).unwrap();
+        assert_eq!(channels[1], vec![2. / 32768., 5. / 32768.]);
+        let frames = interleave(&channels).unwrap();
+        assert_eq!(frames[3], 4. / 32768.);
+        assert_eq!(frames.len(), 6);
+        assert!(deinterleave(&[0f64; 5], 2).is_err());
+        assert!(interleave(&[vec![0.], vec![]]).is_err());
+        assert!(interleave(&[]).unwrap().is_empty());
+    }
+}
@@ -1,11 +1,17 @@
 extern crate num;
 
-use super::waves::Normalize;
+use super::waves::{to_f64_samples, Normalize, WindowType};
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::segment::centered_frame;
+use crate::units::{Db, Hz};
+use crate::validate;
 use num::{Float, FromPrimitive, ToPrimitive};
 
 use sample;
 use sample::window::Window;
-use sample::{FromSample, Sample, ToSampleSlice};
+use sample::{FromSample, Sample, ToSample, ToSampleSlice};
 
 pub use sample::window::Hanning;
 
@@ -306,7 +312,7 @@ impl LagType for Hanning {
 
 /// Trait for things that can Autocorrelate. Implement the mutable version,
 /// which takes a slice of coefficients, and receive a version that allocates
-/// its own vector for free.
+/// its own vector for free. Lags at or past the end of the signal are zero.
 ///
 /// ```
 /// extern crate vox_box;
@@ -333,6 +339,10 @@ where
 {
     fn autocorrelate_mut(&self, coeffs: &mut [T]) {
         for (lag, coeff) in coeffs.iter_mut().enumerate() {
+            if lag >= self.len() {
+                *coeff = T::equilibrium();
+                continue;
+            }
             *coeff = self.iter().enumerate().take(self.len() - lag).skip(1).fold(
                 self[0],
                 |accum, (i, sample)| {
@@ -353,6 +363,10 @@ where
 {
     fn autocorrelate_mut(&self, coeffs: &mut [T]) {
         for (lag, coeff) in coeffs.iter_mut().enumerate() {
+            if lag >= self.len() {
+                *coeff = T::equilibrium();
+                continue;
+            }
             *coeff = self.iter().enumerate().take(self.len() - lag).skip(1).fold(
                 self[0],
                 |accum, (i, sample)| {
@@ -367,6 +381,53 @@ where
     }
 }
 
+/// Normalized cross-correlation (NCCF), as used by RAPT (Talkin 1995). Unlike the
+/// autocorrelation, both windows keep the same length, so the NCCF of a periodic signal stays
+/// near 1 at every multiple of the period.
+pub trait NormalizedCrossCorrelate<T: Float> {
+    /// Correlates the first `window_len` samples with the `window_len` samples starting at
+    /// each lag, normalized by the energy of both windows. Lags whose window runs past the end,
+    /// or where either window is silent, are zero.
+    fn nccf_mut(&self, window_len: usize, coeffs: &mut [T]);
+    fn nccf(&self, window_len: usize, n_lags: usize) -> Vec<T> {
+        let mut coeffs = vec![T::zero(); n_lags];
+        self.nccf_mut(window_len, &mut coeffs[..]);
+        coeffs
+    }
+}
+
+impl<T: Float> NormalizedCrossCorrelate<T> for [T] {
+    fn nccf_mut(&self, window_len: usize, coeffs: &mut [T]) {
+        for c in coeffs.iter_mut() {
+            *c = T::zero();
+        }
+        if window_len == 0 || window_len > self.len() {
+            return;
+        }
+        let reference = &self[..window_len];
+        let e0 = reference.iter().fold(T::zero(), |acc, s| acc + *s * *s);
+        // Energy of the lagged window, updated as it slides
+        let mut ek = e0;
+        for (lag, coeff) in coeffs.iter_mut().enumerate() {
+            if lag + window_len > self.len() {
+                break;
+            }
+            if lag > 0 {
+                let (out, inn) = (self[lag - 1], self[lag + window_len - 1]);
+                ek = (ek - out * out + inn * inn).max(T::zero());
+            }
+            let energy = e0 * ek;
+            if energy > T::zero() {
+                let cross = reference
+                    .iter()
+                    .zip(self[lag..].iter())
+                    .fold(T::zero(), |acc, (a, b)| acc + *a * *b);
+                *coeff = cross / energy.sqrt();
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Pitch<T: Float> {
     pub frequency: T,
@@ -385,43 +446,269 @@ where
     }
 }
 
-#[allow(dead_code)]
-pub struct PitchExtractor<'a, T: 'a + Float> {
-    voiced_unvoiced_cost: T,
-    voicing_threshold: T,
-    candidates: &'a [&'a [Pitch<T>]],
+/// The pitch of one frame, as found by `PitchExtractor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchFrame<T: Float> {
+    /// Best candidate in Hz, even for unvoiced frames. Zero for silence.
+    pub f0: T,
+    pub voiced: bool,
+    /// Height of the normalized autocorrelation peak, from 0 to about 1.
+    pub strength: T,
 }
 
-impl<'a, T: 'a + Float> PitchExtractor<'a, T> {
+/// Tracks pitch over a sequence of equally long, overlapping frames, such as
+/// `signal.windows(len).step_by(hop)`.
+///
+/// Each frame is windowed, and its autocorrelation is normalized by that of the window as in
+/// Boersma (1993). The highest peak between the lags for `max_pitch` and `min_pitch` gives the
+/// pitch, refined by parabolic interpolation, and the frame is voiced if the peak reaches
+/// `voicing_threshold`. Peaks at shorter lags get a bonus of `octave_cost` per octave, as in
+/// Praat, so that a periodic frame doesn't come out an octave low. Frames should hold at least two periods of `min_pitch`.
+pub struct PitchExtractor<'a, T: 'a + Float, I: Iterator<Item = &'a [T]>> {
+    pub sample_rate: T,
+    pub min_pitch: T,
+    pub max_pitch: T,
+    pub voicing_threshold: T,
+    pub octave_cost: T,
+    pub window_type: WindowType,
+    frames: I,
+    window: Vec<f64>,
+    window_lag: Vec<f64>,
+}
+
+impl<'a, T, I> PitchExtractor<'a, T, I>
+where
+    T: 'a + Float + FromPrimitive,
+    I: Iterator<Item = &'a [T]>,
+{
+    /// Uses a Hanning window and an octave cost of 0.01, which can be changed through
+    /// `window_type` and `octave_cost`.
     pub fn new(
-        candidates: &'a [&'a [Pitch<T>]],
-        voiced_unvoiced_cost: T,
+        frames: I,
+        sample_rate: T,
+        min_pitch: T,
+        max_pitch: T,
         voicing_threshold: T,
     ) -> Self {
         Self {
-            voiced_unvoiced_cost,
+            sample_rate,
+            min_pitch,
+            max_pitch,
             voicing_threshold,
-            candidates,
+            octave_cost: T::from_f64(0.01).unwrap_or_else(T::zero),
+            window_type: WindowType::Hanning,
+            frames,
+            window: Vec::new(),
+            window_lag: Vec::new(),
         }
     }
-}
 
-impl<'a, T: 'a + Float> Iterator for PitchExtractor<'a, T> {
-    type Item = Pitch<T>;
+    /// Takes the sample rate and window from `config`, for frames of its length.
+    pub fn from_config(
+        frames: I,
+        config: &AnalysisConfig,
+        min_pitch: T,
+        max_pitch: T,
+        voicing_threshold: T,
+    ) -> Self {
+        let sample_rate = T::from_f64(config.sample_rate.0).unwrap_or_else(T::zero);
+        Self {
+            window_type: config.window,
+            ..Self::new(frames, sample_rate, min_pitch, max_pitch, voicing_threshold)
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let n_candidates = self.candidates.len();
-        if n_candidates == 0 {
-            return None;
+    /// Pitch of one frame; what `next` returns for each frame of the iterator.
+    pub(crate) fn frame_pitch(&mut self, frame: &[T]) -> PitchFrame<T> {
+        let unvoiced = PitchFrame {
+            f0: T::zero(),
+            voiced: false,
+            strength: T::zero(),
+        };
+        let sample_rate = self.sample_rate.to_f64().unwrap_or(0.);
+        let min_pitch = self.min_pitch.to_f64().unwrap_or(0.);
+        let max_pitch = self.max_pitch.to_f64().unwrap_or(0.);
+        if !(sample_rate > 0. && min_pitch > 0. && max_pitch > min_pitch) {
+            return unvoiced;
+        }
+        let min_lag = ((sample_rate / max_pitch).floor() as usize).max(1);
+        let max_lag = ((sample_rate / min_pitch).ceil() as usize).min(frame.len() / 2);
+        if max_lag <= min_lag {
+            return unvoiced;
         }
 
-        let candidate = self.candidates[0][0];
-        self.candidates = if n_candidates > 1 {
-            &self.candidates[1..]
+        // One extra lag for interpolating around a peak at `max_lag`
+        let lags = self.normalized_autocorrelation(frame, max_lag + 2);
+        if lags[0] <= 0. {
+            return unvoiced;
+        }
+        let octave_cost = self.octave_cost.to_f64().unwrap_or(0.);
+        let score = |l: usize| lags[l] - octave_cost * (min_pitch * l as f64 / sample_rate).log2();
+        let best = (min_lag..=max_lag).fold(
+            min_lag,
+            |best, l| if score(l) > score(best) { l } else { best },
+        );
+        let (prev, peak, next) = (lags[best - 1], lags[best], lags[best + 1]);
+        let curvature = prev - 2. * peak + next;
+        let shift = if curvature < 0. {
+            (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
         } else {
-            &[]
+            0.
         };
-        Some(candidate)
+        let strength = T::from_f64(peak.max(0.)).unwrap_or_else(T::zero);
+        PitchFrame {
+            f0: T::from_f64(sample_rate / (best as f64 + shift)).unwrap_or_else(T::zero),
+            voiced: strength >= self.voicing_threshold,
+            strength,
+        }
+    }
+
+    /// Windowed autocorrelation of `frame` for lags `0..n_lags`, divided by its value at lag 0
+    /// and by the normalized autocorrelation of the window.
+    fn normalized_autocorrelation(&mut self, frame: &[T], n_lags: usize) -> Vec<f64> {
+        if self.window.len() != frame.len() || self.window_lag.len() < n_lags {
+            self.window = self.window_type.window(frame.len());
+            self.window_lag = self.window.autocorrelate(n_lags);
+        }
+        let windowed: Vec<f64> = frame
+            .iter()
+            .zip(self.window.iter())
+            .map(|(s, w)| s.to_f64().unwrap_or(0.) * w)
+            .collect();
+        let lags = windowed.autocorrelate(n_lags);
+        if lags[0] <= 0. || !lags[0].is_finite() {
+            return vec![0.; n_lags];
+        }
+        lags.iter()
+            .zip(self.window_lag.iter())
+            .map(|(r, w)| {
+                if *w > 0. {
+                    r / lags[0] / (w / self.window_lag[0])
+                } else {
+                    0.
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a, T, I> Iterator for PitchExtractor<'a, T, I>
+where
+    T: 'a + Float + FromPrimitive,
+    I: Iterator<Item = &'a [T]>,
+{
+    type Item = PitchFrame<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.frames.next()?;
+        Some(self.frame_pitch(frame))
+    }
+}
+
+/// HNR reported for silent frames and frames without any periodicity, as in Praat.
+pub const MIN_HNR: Db = Db(-200.);
+
+/// Harmonics-to-noise ratio of a normalized autocorrelation peak `r`, `10 log10(r / (1 - r))`
+/// (Boersma 1993). Peaks at or below zero give `MIN_HNR`.
+pub fn hnr_from_strength(strength: f64) -> Db {
+    if strength <= 0. || strength.is_nan() {
+        return MIN_HNR;
+    }
+    // Perfectly periodic frames would be infinite
+    let r = strength.min(1. - 1.0e-12);
+    Db(Db::from_power(r / (1. - r)).0.max(MIN_HNR.0))
+}
+
+/// Praat-style harmonicity: the harmonics-to-noise ratio of each frame, in dB, from the
+/// strength of its normalized autocorrelation peak as found by `PitchExtractor`.
+#[derive(Clone, Debug)]
+pub struct Harmonicity {
+    pub sample_rate: f64,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+    /// Length of each frame, in periods of `min_pitch`
+    pub periods_per_window: f64,
+    /// Frames whose peak amplitude is below this fraction of the signal's are silent
+    pub silence_threshold: f64,
+}
+
+impl Harmonicity {
+    /// Praat's defaults: 10 ms hops, pitch from 75 Hz, 4.5 periods per window.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        Harmonicity {
+            sample_rate,
+            hop: (sample_rate * 0.01).round() as usize,
+            min_pitch: 75.,
+            max_pitch: sample_rate / 2.,
+            periods_per_window: 4.5,
+            silence_threshold: 0.1,
+        }
+    }
+
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Harmonicity {
+            hop: config.hop,
+            ..Harmonicity::new(config.sample_rate)
+        }
+    }
+
+    /// HNR of every frame, one per hop. Silent frames get `MIN_HNR`.
+    pub fn hnr<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<Db>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.hnr_with_progress(signal, progress::ignore)
+    }
+
+    /// Like `hnr`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled` if
+    /// the callback breaks.
+    pub fn hnr_with_progress<S, F>(&self, signal: &[S], mut progress: F) -> VoxBoxResult<Vec<Db>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        validate::positive(self.sample_rate, "Sample rate must be positive")?;
+        if !(self.min_pitch > 0. && self.min_pitch < self.max_pitch) {
+            return Err(VoxBoxError::Config(
+                "Pitch range must be positive and increasing",
+            ));
+        }
+        if self.hop == 0 || self.periods_per_window.is_nan() || self.periods_per_window < 2. {
+            return Err(VoxBoxError::Config(
+                "Hop must be nonzero and windows must hold two periods",
+            ));
+        }
+        let signal = to_f64_samples(signal);
+        validate::finite(&signal)?;
+
+        let frame_len =
+            (self.periods_per_window * self.sample_rate / self.min_pitch).ceil() as usize;
+        let global_peak = signal.iter().fold(0., |acc: f64, s| acc.max(s.abs()));
+        let n_frames = signal.len().div_ceil(self.hop);
+        let mut hnr = Vec::with_capacity(n_frames);
+        let mut extractor = PitchExtractor::new(
+            std::iter::empty(),
+            self.sample_rate,
+            self.min_pitch,
+            self.max_pitch,
+            0.,
+        );
+        for k in 0..n_frames {
+            let frame = centered_frame(&signal, k, self.hop, frame_len);
+            let peak = frame.iter().fold(0., |acc: f64, s| acc.max(s.abs()));
+            hnr.push(
+                if peak > 0. && peak >= self.silence_threshold * global_peak {
+                    hnr_from_strength(extractor.frame_pitch(&frame).strength)
+                } else {
+                    MIN_HNR
+                },
+            );
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+        Ok(hnr)
     }
 }
 
@@ -475,7 +762,7 @@ where
     /// interpolation and the Brent golden section parabolic maximization algorithm. This results
     /// in a collection of possible pitches and their given HNR ratings.
     ///
-    /// A third pass, using PitchExtractor, should find a path through these candidates that
+    /// A third pass should find a path through these candidates that
     /// maximizes both the smoothness of the pitch contour and the strength of the pitches.
     fn pitch<W: LagType>(
         &self,
@@ -560,12 +847,133 @@ where
                 p
             })
             .collect();
+        // Silent frames normalize to NaN, so candidates that aren't finite are dropped
+        maxima.retain(|p| p.frequency.is_finite() && p.strength.is_finite());
         maxima.push(Pitch::new(T::from_usize(0).unwrap(), threshold)); // Index of 0 == no pitch
-        maxima.sort_by(|a, b| (b.strength).partial_cmp(&a.strength).unwrap());
+        maxima.sort_by(|a, b| {
+            (b.strength)
+                .partial_cmp(&a.strength)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
         maxima
     }
 }
 
+/// Pitch estimators that work on a single frame.
+pub trait Pitcher<T: Float> {
+    /// Estimates the pitch of a frame between `min` and `max` Hz with YIN (de Cheveigné and
+    /// Kawahara 2002). The frame must hold at least two periods of `min`.
+    ///
+    /// The returned strength is a confidence from 0 to 1: one minus the cumulative mean
+    /// normalized difference at the chosen lag. The first dip in the difference function below
+    /// `1 - threshold` is taken, or the lowest point if there is none, so frames with a strength
+    /// under `threshold` are best treated as unvoiced. Silence and frames too short for the
+    /// range give a frequency of zero.
+    fn yin(&self, sample_rate: T, threshold: T, min: T, max: T) -> Pitch<T>;
+}
+
+/// The cumulative mean normalized difference function of YIN, for the lags of a pitch range.
+pub(crate) struct YinDifference {
+    pub cmnd: Vec<f64>,
+    pub tau_min: usize,
+    /// One lag beyond the range, for interpolating around a dip at the top of it
+    pub tau_max: usize,
+}
+
+impl YinDifference {
+    /// Returns `None` for silence, bad ranges and frames shorter than two periods of `min`.
+    pub fn new(x: &[f64], sample_rate: f64, min: f64, max: f64) -> Option<Self> {
+        if !(sample_rate > 0. && min > 0. && max > min) {
+            return None;
+        }
+        let tau_min = ((sample_rate / max).floor() as usize).max(2);
+        let tau_max = (sample_rate / min).ceil() as usize + 1;
+        if tau_max <= tau_min || x.len() < 2 * tau_max {
+            return None;
+        }
+        let width = x.len() - tau_max;
+
+        let mut cmnd = vec![1.; tau_max + 1];
+        let mut running = 0.;
+        for (tau, c) in cmnd.iter_mut().enumerate().skip(1) {
+            let diff: f64 = (0..width).map(|j| (x[j] - x[j + tau]).powi(2)).sum();
+            running += diff;
+            if running > 0. {
+                *c = diff * tau as f64 / running;
+            }
+        }
+        if running <= 0. || !running.is_finite() {
+            return None;
+        }
+        Some(YinDifference {
+            cmnd,
+            tau_min,
+            tau_max,
+        })
+    }
+
+    /// The bottom of the first dip below `threshold`, if any.
+    pub fn first_dip(&self, threshold: f64) -> Option<usize> {
+        let mut tau = (self.tau_min..self.tau_max).find(|&t| self.cmnd[t] < threshold)?;
+        while tau + 1 < self.tau_max && self.cmnd[tau + 1] < self.cmnd[tau] {
+            tau += 1;
+        }
+        Some(tau)
+    }
+
+    /// The lowest point in the range.
+    pub fn global_min(&self) -> usize {
+        (self.tau_min..self.tau_max).fold(self.tau_min, |best, t| {
+            if self.cmnd[t] < self.cmnd[best] {
+                t
+            } else {
+                best
+            }
+        })
+    }
+
+    /// Lag and value of the dip at `tau`, refined by parabolic interpolation.
+    pub fn refine(&self, tau: usize) -> (f64, f64) {
+        let (prev, cur, next) = (self.cmnd[tau - 1], self.cmnd[tau], self.cmnd[tau + 1]);
+        let curvature = prev - 2. * cur + next;
+        let shift = if curvature > 0. {
+            (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.
+        };
+        (tau as f64 + shift, cur)
+    }
+}
+
+impl<T> Pitcher<T> for [T]
+where
+    T: Float + FromPrimitive,
+{
+    fn yin(&self, sample_rate: T, threshold: T, min: T, max: T) -> Pitch<T> {
+        let sample_rate = sample_rate.to_f64().unwrap_or(0.);
+        let x: Vec<f64> = self.iter().map(|s| s.to_f64().unwrap_or(0.)).collect();
+        let difference = match YinDifference::new(
+            &x,
+            sample_rate,
+            min.to_f64().unwrap_or(0.),
+            max.to_f64().unwrap_or(0.),
+        ) {
+            Some(difference) => difference,
+            None => return Pitch::new(T::zero(), T::zero()),
+        };
+
+        let dip = 1. - threshold.to_f64().unwrap_or(0.);
+        let tau = difference
+            .first_dip(dip)
+            .unwrap_or_else(|| difference.global_min());
+        let (lag, value) = difference.refine(tau);
+        Pitch::new(
+            T::from_f64(sample_rate / lag).unwrap_or_else(T::zero),
+            T::from_f64((1. - value).clamp(0., 1.)).unwrap_or_else(T::zero),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate sample;
@@ -591,6 +999,10 @@ mod tests {
         sine.autocorrelate_mut(&mut coeffs[..]);
         let out = sine.autocorrelate(16);
         assert_eq!(coeffs, out);
+
+        let long = sine[..4].autocorrelate(8);
+        assert!(long[4..].iter().all(|c| *c == 0.));
+        assert!(Vec::<f64>::new().autocorrelate(2).iter().all(|c| *c == 0.));
     }
 
     #[test]
@@ -611,4 +1023,138 @@ mod tests {
             assert!((pitch[0].frequency - exp_freq).abs() < 1.0e-2);
         }
     }
+
+    #[test]
+    fn test_pitch_silence() {
+        let silence = vec![0f64; 512];
+        let pitch = silence.pitch::<window::Hanning>(44100., 0.2, 1., 1., 100., 500.);
+        println!("pitch: {:?}", pitch);
+        assert_eq!(pitch.len(), 1);
+        assert_eq!(pitch[0].frequency, 0.);
+    }
+    #[test]
+    fn test_nccf() {
+        let signal: Vec<f64> = (0..100).map(|n| (2. * PI * n as f64 / 20.).sin()).collect();
+        let coeffs = signal.nccf(40, 70);
+        println!("nccf: {:?}", &coeffs[..25]);
+        assert!((coeffs[0] - 1.).abs() < 1e-12);
+        assert!((coeffs[10] + 1.).abs() < 1e-12);
+        assert!((coeffs[20] - 1.).abs() < 1e-12);
+        assert!((coeffs[40] - 1.).abs() < 1e-12);
+        // Past the end
+        assert_eq!(coeffs[61], 0.);
+
+        let silence = [0f64; 50];
+        assert!(silence.nccf(20, 10).iter().all(|c| *c == 0.));
+    }
+
+    #[test]
+    fn test_hnr() {
+        assert_eq!(hnr_from_strength(0.5), Db(0.));
+        assert!((hnr_from_strength(0.99).0 - 19.956).abs() < 1e-3);
+        assert_eq!(hnr_from_strength(-0.2), MIN_HNR);
+
+        let sample_rate = 16000.;
+        let mut state = 11u32;
+        // 150 Hz with noise 20 dB down, then the noise alone at a tenth of the level
+        let signal: Vec<f64> = (0..16000)
+            .map(|n| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (state as f64 / u32::MAX as f64 - 0.5) * 12f64.sqrt() * 0.1;
+                if n < 8000 {
+                    2f64.sqrt() * (2. * PI * 150. * n as f64 / sample_rate).sin() + noise
+                } else {
+                    noise * 0.1
+                }
+            })
+            .collect();
+        let hnr = Harmonicity::new(sample_rate).hnr(&signal).unwrap();
+        println!("hnr: {:?}", &hnr[..]);
+        assert_eq!(hnr.len(), 100);
+        for db in &hnr[10..40] {
+            assert!((db.0 - 20.).abs() < 3.);
+        }
+        assert!(hnr[60..].iter().all(|db| *db == MIN_HNR));
+    }
+
+    #[test]
+    fn test_yin() {
+        let sample_rate = 8000.;
+        // 155 Hz with a weak fundamental, which tempts autocorrelation into octave errors
+        let frame: Vec<f64> = (0..512)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                (1..8)
+                    .map(|h| {
+                        let gain = if h == 1 { 0.2 } else { 1. / h as f64 };
+                        (2. * PI * 155. * h as f64 * t).sin() * gain
+                    })
+                    .sum::<f64>()
+            })
+            .collect();
+        let pitch = frame.yin(sample_rate, 0.9, 75., 600.);
+        println!("yin: {:?}", pitch);
+        assert!((pitch.frequency - 155.).abs() < 0.5);
+        assert!(pitch.strength > 0.9);
+
+        let silence = vec![0f64; 512];
+        assert_eq!(silence.yin(sample_rate, 0.9, 75., 600.).frequency, 0.);
+        assert_eq!(frame[..100].yin(sample_rate, 0.9, 75., 600.).frequency, 0.);
+
+        let mut state = 1u32;
+        let noise: Vec<f64> = (0..512)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                state as f64 / u32::MAX as f64 - 0.5
+            })
+            .collect();
+        let pitch = noise.yin(sample_rate, 0.9, 75., 600.);
+        println!("yin noise: {:?}", pitch);
+        assert!(pitch.strength < 0.9);
+    }
+    #[test]
+    fn test_pitch_extractor() {
+        let sample_rate = 8000.;
+        // 200 Hz for the first half, then silence
+        let signal: Vec<f64> = (0..4000)
+            .map(|n| {
+                if n < 2000 {
+                    (2. * PI * 200. * n as f64 / sample_rate).sin()
+                        + 0.5 * (2. * PI * 400. * n as f64 / sample_rate).sin()
+                } else {
+                    0.
+                }
+            })
+            .collect();
+        let frames: Vec<PitchFrame<f64>> = PitchExtractor::new(
+            signal.windows(320).step_by(80),
+            sample_rate,
+            75.,
+            500.,
+            0.45,
+        )
+        .collect();
+        println!("frames: {:?}", &frames[..3]);
+        assert_eq!(frames.len(), (4000 - 320) / 80 + 1);
+        for frame in &frames[..20] {
+            assert!(frame.voiced);
+            assert!((frame.f0 - 200.).abs() < 1.);
+            assert!(frame.strength > 0.9);
+        }
+        for frame in &frames[30..] {
+            assert!(!frame.voiced);
+            assert_eq!(frame.f0, 0.);
+        }
+
+        let config = AnalysisConfig::new(sample_rate).frame_len(320).hop(80);
+        let configured: Vec<PitchFrame<f64>> = PitchExtractor::from_config(
+            signal.windows(config.frame_len).step_by(config.hop),
+            &config,
+            75.,
+            500.,
+            0.45,
+        )
+        .collect();
+        assert_eq!(configured, frames);
+    }
 }
@@ -0,0 +1,233 @@
+//! Analysis gated by voice activity detection: a detector first decides which frames are
+//! speech, and pitch, formants and MFCCs are only measured on those, so that silence and
+//! background noise don't pollute statistics such as the mean pitch.
+//!
+//! ```
+//! use vox_box::pipeline::{Detector, SpeechPipeline};
+//! use vox_box::AnalyzeOptions;
+//!
+//! let signal: Vec<f64> = (0..8000)
+//!     .map(|n| if n < 3000 { 0. } else { (n as f64 * 0.1).sin() * 0.5 })
+//!     .collect();
+//! let analysis = SpeechPipeline::new(AnalyzeOptions::default())
+//!     .detector(Detector::Energy)
+//!     .run(&signal, 8000.)
+//!     .unwrap();
+//! assert!(analysis.pitch[10].is_nan());
+//! ```
+
+use sample::{Sample, ToSample};
+
+use crate::analysis::{analyze_frames, frame_config, Analysis, AnalyzeOptions};
+use crate::error::*;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::segment::centered_frame;
+use crate::units::Hz;
+use crate::vad::{EnergyVad, GmmVad, SohnVad};
+use crate::validate;
+use crate::waves::to_f64_samples;
+
+/// The voice activity detector that finds the speech frames, set up for the analysis frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detector {
+    /// `SohnVad`, robust to stationary noise
+    Sohn,
+    /// `GmmVad`, for changing backgrounds
+    Gmm,
+    /// `EnergyVad`, the cheapest, for clean recordings
+    Energy,
+}
+
+/// Runs a detector over a signal, then analyzes only its speech frames. Frames that aren't
+/// speech are labeled silence, with NaN pitch, no formants and no MFCCs, which
+/// `Analysis::mfcc_matrix` and `Analysis::formant_matrix` turn into rows of NaN.
+#[derive(Clone, Debug)]
+pub struct SpeechPipeline {
+    options: AnalyzeOptions,
+    detector: Detector,
+}
+
+impl SpeechPipeline {
+    /// A pipeline with a `SohnVad`.
+    pub fn new(options: AnalyzeOptions) -> Self {
+        SpeechPipeline {
+            options,
+            detector: Detector::Sohn,
+        }
+    }
+
+    pub fn detector(mut self, detector: Detector) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Whether each analysis frame is speech, one entry per frame of `run`'s result.
+    pub fn speech_frames<S, R: Into<Hz>>(
+        &self,
+        signal: &[S],
+        sample_rate: R,
+    ) -> VoxBoxResult<Vec<bool>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let sample_rate = sample_rate.into().0;
+        validate::non_empty(signal)?;
+        let config = frame_config(sample_rate, &self.options)?;
+        let signal = to_f64_samples(signal);
+        validate::finite(&signal)?;
+        let n_frames = signal.len().div_ceil(config.hop);
+        let frames =
+            (0..n_frames).map(|k| centered_frame(&signal[..], k, config.hop, config.frame_len));
+        Ok(match self.detector {
+            Detector::Sohn => {
+                let mut vad = SohnVad::from_config(&config);
+                frames.map(|frame| vad.process(&frame[..]).speech).collect()
+            }
+            Detector::Gmm => {
+                let mut vad = GmmVad::from_config(&config);
+                frames.map(|frame| vad.process(&frame[..]).speech).collect()
+            }
+            Detector::Energy => {
+                let mut vad = EnergyVad::new();
+                frames.map(|frame| vad.process(&frame[..])).collect()
+            }
+        })
+    }
+
+    /// Detects speech, then analyzes the speech frames.
+    pub fn run<S, R: Into<Hz>>(&self, signal: &[S], sample_rate: R) -> VoxBoxResult<Analysis>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.run_with_progress(signal, sample_rate, progress::ignore)
+    }
+
+    /// Like `run`, calling `progress` after every analyzed frame. Returns
+    /// `VoxBoxError::Cancelled` if the callback breaks.
+    pub fn run_with_progress<S, F, R: Into<Hz>>(
+        &self,
+        signal: &[S],
+        sample_rate: R,
+        progress: F,
+    ) -> VoxBoxResult<Analysis>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        let sample_rate = sample_rate.into().0;
+        let speech = self.speech_frames(signal, sample_rate)?;
+        analyze_frames(signal, sample_rate, &self.options, Some(&speech), progress)
+    }
+
+    /// Analyzes the frames `speech` marks as speech, for labels from a detector of your own or
+    /// smoothed by an `EndpointDetector`. There must be one label per analysis frame, as
+    /// `speech_frames` returns.
+    pub fn run_with_labels<S, R: Into<Hz>>(
+        &self,
+        signal: &[S],
+        sample_rate: R,
+        speech: &[bool],
+    ) -> VoxBoxResult<Analysis>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let sample_rate = sample_rate.into().0;
+        analyze_frames(
+            signal,
+            sample_rate,
+            &self.options,
+            Some(speech),
+            progress::ignore,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::SegmentLabel;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+    use std::f64::consts::PI;
+
+    /// 0.3 s of background noise, 0.5 s of a 120 Hz buzz, then 0.3 s of noise again.
+    fn buzz(sample_rate: f64) -> Vec<f64> {
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+        let background = Normal::new(0., 0.003);
+        (0..(1.1 * sample_rate) as usize)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                let noise = background.ind_sample(&mut rng);
+                if !(0.3..0.8).contains(&t) {
+                    noise
+                } else {
+                    noise
+                        + (1..20)
+                            .map(|h| (2. * PI * 120. * h as f64 * t).sin() * 0.3 / h as f64)
+                            .sum::<f64>()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_speech_pipeline() {
+        let signal = buzz(8000.);
+        for detector in [Detector::Sohn, Detector::Gmm, Detector::Energy].iter() {
+            let pipeline = SpeechPipeline::new(AnalyzeOptions::default()).detector(*detector);
+            let speech = pipeline.speech_frames(&signal, 8000.).unwrap();
+            let analysis = pipeline.run(&signal, 8000.).unwrap();
+            println!("{:?}: {:?}", detector, speech);
+            assert_eq!(speech.len(), analysis.len());
+            assert!(speech[40..75].iter().all(|s| *s));
+            assert!(speech[15..25].iter().all(|s| !s));
+
+            for (k, is_speech) in speech.iter().enumerate() {
+                if !is_speech {
+                    assert_eq!(analysis.voicing[k], SegmentLabel::Silence);
+                    assert!(analysis.pitch[k].is_nan());
+                    assert!(analysis.intensity[k].0.is_finite());
+                    #[cfg(feature = "mfcc")]
+                    assert!(analysis.mfcc[k].is_empty());
+                    #[cfg(feature = "formant")]
+                    assert!(analysis.formants[k].is_empty());
+                }
+            }
+            // The mean pitch only counts speech
+            let voiced: Vec<f64> = analysis
+                .pitch
+                .iter()
+                .cloned()
+                .filter(|p| !p.is_nan())
+                .collect();
+            let mean = voiced.iter().sum::<f64>() / voiced.len() as f64;
+            assert!((mean - 120.).abs() < 2.);
+            #[cfg(feature = "mfcc")]
+            {
+                let mfcc = analysis.mfcc_matrix();
+                assert!(mfcc.row(20).unwrap().iter().all(|c| c.is_nan()));
+                assert!(mfcc.row(60).unwrap().iter().all(|c| c.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_speech_labels() {
+        let signal = buzz(8000.);
+        let pipeline = SpeechPipeline::new(AnalyzeOptions::default());
+        let n_frames = pipeline.speech_frames(&signal, 8000.).unwrap().len();
+
+        // Frames labeled speech are analyzed even if a detector would disagree
+        let mut speech = vec![false; n_frames];
+        speech[60] = true;
+        let analysis = pipeline.run_with_labels(&signal, 8000., &speech).unwrap();
+        assert_eq!(analysis.voicing[60], SegmentLabel::Voiced);
+        assert_eq!(analysis.pitch.iter().filter(|p| !p.is_nan()).count(), 1);
+
+        assert!(pipeline
+            .run_with_labels(&signal, 8000., &speech[1..])
+            .is_err());
+        assert!(pipeline.run::<f64, f64>(&[], 8000.).is_err());
+        assert!(pipeline.speech_frames(&signal, 0.).is_err());
+    }
+}
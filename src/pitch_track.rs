@@ -0,0 +1,311 @@
+//! Cleanup of frame-by-frame pitch estimates.
+//!
+//! The trackers in this crate estimate each frame more or less on its own, so their output
+//! has the odd octave jump, outlier or one-frame gap. `PitchTrack` holds a contour and has
+//! the usual fixes, which can be chained:
+//!
+//! ```
+//! use vox_box::periodic::PitchFrame;
+//! use vox_box::pitch_track::PitchTrack;
+//! use vox_box::units::Seconds;
+//!
+//! let frames: Vec<PitchFrame<f64>> = [120., 240., 121., 0., 123.]
+//!     .iter()
+//!     .map(|f0| PitchFrame { f0: *f0, voiced: *f0 > 0., strength: 1. })
+//!     .collect();
+//! let track = PitchTrack::new(frames, Seconds(0.01))
+//!     .correct_octave_jumps(5)
+//!     .interpolate_gaps(Seconds(0.02));
+//! assert!(track.frames.iter().all(|f| f.voiced && (f.f0 - 121.).abs() < 3.));
+//! ```
+
+use crate::periodic::PitchFrame;
+use crate::pyin::{PYinFrame, PitchCandidate};
+use crate::units::Seconds;
+
+/// A pitch contour, one frame per `frame_period`, with the candidates of each frame if the
+/// tracker provides them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PitchTrack {
+    pub frame_period: Seconds,
+    pub frames: Vec<PitchFrame<f64>>,
+    /// Empty, or one list of candidates per frame
+    pub candidates: Vec<Vec<PitchCandidate>>,
+}
+
+impl PitchTrack {
+    pub fn new(frames: Vec<PitchFrame<f64>>, frame_period: Seconds) -> Self {
+        PitchTrack {
+            frame_period,
+            frames,
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Keeps the candidates of each frame, for `viterbi`. The strength of each frame is its
+    /// voiced probability.
+    pub fn from_pyin(frames: &[PYinFrame], frame_period: Seconds) -> Self {
+        PitchTrack {
+            frame_period,
+            frames: frames
+                .iter()
+                .map(|f| PitchFrame {
+                    f0: f.f0,
+                    voiced: f.voiced,
+                    strength: f.voiced_probability,
+                })
+                .collect(),
+            candidates: frames.iter().map(|f| f.candidates.clone()).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Time of the middle of each frame.
+    pub fn times(&self) -> Vec<Seconds> {
+        (0..self.frames.len())
+            .map(|k| Seconds((k as f64 + 0.5) * self.frame_period.0))
+            .collect()
+    }
+
+    /// Pitch of each frame, with NaN for unvoiced frames.
+    pub fn f0(&self) -> Vec<f64> {
+        self.frames
+            .iter()
+            .map(|f| if f.voiced { f.f0 } else { f64::NAN })
+            .collect()
+    }
+
+    /// Pitch of the voiced frames within `width / 2` frames of frame `k`.
+    fn voiced_around(&self, k: usize, width: usize) -> Vec<f64> {
+        let low = k.saturating_sub(width / 2);
+        let high = (k + width / 2 + 1).min(self.frames.len());
+        self.frames[low..high]
+            .iter()
+            .filter(|f| f.voiced)
+            .map(|f| f.f0)
+            .collect()
+    }
+
+    /// Replaces the pitch of each voiced frame with the median of the voiced frames in a window
+    /// of `width` frames around it. Unvoiced frames are left alone and don't count.
+    pub fn median_filter(mut self, width: usize) -> Self {
+        let filtered: Vec<f64> = (0..self.frames.len())
+            .map(|k| median(self.voiced_around(k, width)).unwrap_or(self.frames[k].f0))
+            .collect();
+        for (frame, f0) in self.frames.iter_mut().zip(filtered) {
+            if frame.voiced {
+                frame.f0 = f0;
+            }
+        }
+        self
+    }
+
+    /// Moves voiced frames that are about an octave away from the median of the voiced frames
+    /// in a window of `width` frames back to the octave of the median.
+    pub fn correct_octave_jumps(mut self, width: usize) -> Self {
+        let references: Vec<Option<f64>> = (0..self.frames.len())
+            .map(|k| median(self.voiced_around(k, width)))
+            .collect();
+        for (frame, reference) in self.frames.iter_mut().zip(references) {
+            let reference = match reference {
+                Some(r) if frame.voiced && r > 0. && frame.f0 > 0. => r,
+                _ => continue,
+            };
+            let octaves = (frame.f0 / reference).log2();
+            let jump = octaves.round();
+            // A quarter octave either side of a whole number of octaves
+            if jump != 0. && (octaves - jump).abs() < 0.25 {
+                frame.f0 /= 2f64.powf(jump);
+            }
+        }
+        self
+    }
+
+    /// Fills unvoiced gaps of up to `max_gap` between two voiced frames, interpolating the
+    /// pitch linearly in log frequency. The filled frames become voiced with zero strength.
+    pub fn interpolate_gaps(mut self, max_gap: Seconds) -> Self {
+        let max_frames = if self.frame_period.0 > 0. {
+            (max_gap.0 / self.frame_period.0 + 1.0e-9).floor() as usize
+        } else {
+            0
+        };
+        let mut k = 0;
+        while k < self.frames.len() {
+            if self.frames[k].voiced || k == 0 || !self.frames[k - 1].voiced {
+                k += 1;
+                continue;
+            }
+            let end = match (k..self.frames.len()).find(|&j| self.frames[j].voiced) {
+                Some(end) => end,
+                None => break,
+            };
+            let gap = end - k;
+            if gap <= max_frames {
+                let (before, after) = (self.frames[k - 1].f0.ln(), self.frames[end].f0.ln());
+                for (i, frame) in self.frames[k..end].iter_mut().enumerate() {
+                    let t = (i + 1) as f64 / (gap + 1) as f64;
+                    *frame = PitchFrame {
+                        f0: (before + (after - before) * t).exp(),
+                        voiced: true,
+                        strength: 0.,
+                    };
+                }
+            }
+            k = end;
+        }
+        self
+    }
+
+    /// Picks the path through the candidates of each run of voiced frames that best trades
+    /// candidate probability against pitch jumps, costing `jump_cost` per octave. Frames
+    /// without candidates keep their own pitch as the only one. Does nothing without
+    /// candidates.
+    pub fn viterbi(mut self, jump_cost: f64) -> Self {
+        if self.candidates.len() != self.frames.len() {
+            return self;
+        }
+        let states: Vec<Vec<PitchCandidate>> = self
+            .frames
+            .iter()
+            .zip(self.candidates.iter())
+            .map(|(frame, candidates)| {
+                let mut states: Vec<PitchCandidate> =
+                    candidates.iter().filter(|c| c.f0 > 0.).cloned().collect();
+                if states.is_empty() {
+                    states.push(PitchCandidate {
+                        f0: frame.f0,
+                        probability: 1.,
+                    });
+                }
+                states
+            })
+            .collect();
+        let local = |c: &PitchCandidate| -c.probability.max(1.0e-12).ln();
+
+        let mut k = 0;
+        while k < self.frames.len() {
+            if !self.frames[k].voiced {
+                k += 1;
+                continue;
+            }
+            let end = (k..self.frames.len())
+                .find(|&j| !self.frames[j].voiced)
+                .unwrap_or(self.frames.len());
+
+            let mut costs: Vec<f64> = states[k].iter().map(local).collect();
+            let mut back: Vec<Vec<usize>> = vec![Vec::new()];
+            for j in k + 1..end {
+                let (prev, here) = (&states[j - 1], &states[j]);
+                let mut next = Vec::with_capacity(here.len());
+                let mut from = Vec::with_capacity(here.len());
+                for c in here {
+                    let (cost, index) = prev
+                        .iter()
+                        .zip(costs.iter())
+                        .enumerate()
+                        .map(|(i, (p, cost))| (cost + jump_cost * (c.f0 / p.f0).log2().abs(), i))
+                        .fold((f64::INFINITY, 0), |a, b| if b.0 < a.0 { b } else { a });
+                    next.push(cost + local(c));
+                    from.push(index);
+                }
+                costs = next;
+                back.push(from);
+            }
+
+            let mut state = costs
+                .iter()
+                .enumerate()
+                .fold(
+                    (0, f64::INFINITY),
+                    |a, (i, c)| if *c < a.1 { (i, *c) } else { a },
+                )
+                .0;
+            for j in (k..end).rev() {
+                self.frames[j].f0 = states[j][state].f0;
+                if j > k {
+                    state = back[j - k][state];
+                }
+            }
+            k = end;
+        }
+        self
+    }
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.
+    } else {
+        values[mid]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(f0: &[f64]) -> PitchTrack {
+        let frames = f0
+            .iter()
+            .map(|f| PitchFrame {
+                f0: *f,
+                voiced: *f > 0.,
+                strength: 1.,
+            })
+            .collect();
+        PitchTrack::new(frames, Seconds(0.01))
+    }
+
+    #[test]
+    fn test_median_and_octaves() {
+        let filtered = track(&[100., 101., 150., 102., 0., 103.]).median_filter(3);
+        println!("median: {:?}", filtered.f0());
+        assert_eq!(filtered.frames[2].f0, 102.);
+        assert!(!filtered.frames[4].voiced);
+        assert_eq!(filtered.frames[5].f0, 103.);
+
+        let corrected = track(&[200., 202., 101., 204., 410.]).correct_octave_jumps(5);
+        println!("octaves: {:?}", corrected.f0());
+        assert_eq!(corrected.frames[2].f0, 202.);
+        assert_eq!(corrected.frames[4].f0, 205.);
+    }
+
+    #[test]
+    fn test_interpolate_gaps() {
+        let filled = track(&[100., 0., 0., 400., 0., 0., 0., 100.]).interpolate_gaps(Seconds(0.02));
+        println!("filled: {:?}", filled.f0());
+        assert!((filled.frames[1].f0 - 158.74).abs() < 0.01);
+        assert!((filled.frames[2].f0 - 251.98).abs() < 0.01);
+        assert!(filled.frames[4..7].iter().all(|f| !f.voiced));
+        assert_eq!(filled.times()[3], Seconds(0.035));
+    }
+
+    #[test]
+    fn test_viterbi() {
+        let mut smoothed = track(&[100., 200., 100., 0., 300.]);
+        let candidate = |f0, probability| PitchCandidate { f0, probability };
+        smoothed.candidates = vec![
+            vec![candidate(100., 0.9)],
+            vec![candidate(200., 0.5), candidate(100.5, 0.4)],
+            vec![candidate(100., 0.9)],
+            vec![],
+            vec![candidate(300., 0.2), candidate(150., 0.1)],
+        ];
+        let smoothed = smoothed.viterbi(1.);
+        println!("viterbi: {:?}", smoothed.f0());
+        assert_eq!(smoothed.frames[1].f0, 100.5);
+        assert_eq!(smoothed.frames[4].f0, 300.);
+    }
+}
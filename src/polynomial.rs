@@ -4,6 +4,7 @@ use std::iter::*;
 use std::ops::Neg;
 
 use crate::error::*;
+use crate::validate;
 
 use num::{Float, FromPrimitive, One, Zero};
 use num_complex::Complex;
@@ -98,7 +99,7 @@ where
         {
             other.find_roots_mut(&mut work[..])?;
         }
-        while other[other.len() - 1] == Complex::<T>::zero() {
+        while other.last() == Some(&Complex::<T>::zero()) {
             other.pop();
         }
         Ok(other)
@@ -106,6 +107,8 @@ where
 
     /// work must be 3*size+2 for complex floats (meaning 6*size+4 of the buffer)
     fn find_roots_mut<'b>(&'b mut self, work: &'b mut [Complex<T>]) -> VoxBoxResult<()> {
+        validate::finite_complex(self)?;
+
         // Initialize coefficient highs and lows
         let coeff_high = self.degree();
         if coeff_high < 1 {
@@ -116,6 +119,7 @@ where
 
         let coeff_low: usize = self.off_low();
         let mut m = coeff_high - coeff_low;
+        validate::workspace(work, 2 * self.len() + 2 * (m + 1))?;
 
         // work should be 2*self.len()
         let (z_roots, work) = work.split_at_mut(2 * self.len());
@@ -128,7 +132,8 @@ where
         let (mut rem, work) = work.split_at_mut(coeff_high - coeff_low + 1);
         let (coeffs, _) = work.split_at_mut(coeff_high - coeff_low + 1);
 
-        coeffs[coeff_low..=coeff_high].clone_from_slice(&self[coeff_low..=coeff_high]);
+        // Roots at zero are factored out above, leaving a polynomial of degree m
+        coeffs.clone_from_slice(&self[coeff_low..=coeff_high]);
         // println!("&[] coeffs: {:?}", coeffs);
 
         // Use the Laguerre method to factor out a single root
@@ -178,6 +183,12 @@ where
         other: Complex<T>,
         rem: &'a mut [Complex<T>],
     ) -> VoxBoxResult<()> {
+        validate::workspace(rem, self.len())?;
+        if self.degree() < 1 {
+            return Err(VoxBoxError::Polynomial(
+                "Zero degree polynomial: nothing to divide.",
+            ));
+        }
         rem[..self.len()].clone_from_slice(&self[..]);
 
         if other != Complex::<T>::zero() {
@@ -225,6 +236,25 @@ where
     }
 }
 
+/// Builds the monic polynomial whose roots are given, in ascending order of power. This is the
+/// inverse of `find_roots`.
+pub fn from_roots<T: Float>(roots: &[Complex<T>]) -> Vec<Complex<T>> {
+    let mut coeffs = vec![Complex::<T>::zero(); roots.len() + 1];
+    coeffs[0] = Complex::<T>::one();
+    for (n, root) in roots.iter().enumerate() {
+        // Multiply the polynomial so far by (z - root)
+        for k in (0..=n + 1).rev() {
+            let lower = if k > 0 {
+                coeffs[k - 1]
+            } else {
+                Complex::<T>::zero()
+            };
+            coeffs[k] = lower - coeffs[k] * root;
+        }
+    }
+    coeffs
+}
+
 #[cfg(test)]
 mod tests {
     extern crate num;
@@ -438,6 +468,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_roots() {
+        let roots = vec![Complex::<f64>::new(1.0, 0.0), Complex::<f64>::new(2.0, 0.0)];
+        let coeffs = from_roots(&roots);
+        let exp = [2.0, -3.0, 1.0];
+        assert_eq!(coeffs.len(), exp.len());
+        for (c, e) in coeffs.iter().zip(exp.iter()) {
+            assert!((c.re - e).abs() < 1e-12);
+            assert!(c.im.abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_from_roots_round_trip() {
+        let poly: Vec<Complex<f64>> = [1.0, 2.5, -2.0, -3.0]
+            .iter()
+            .map(Complex::<f64>::from)
+            .collect();
+        let roots = poly.find_roots().unwrap();
+        let rebuilt = from_roots(&roots);
+        // find_roots gives the roots of the monic polynomial
+        for (r, p) in rebuilt.iter().zip(poly.iter()) {
+            let diff = r - p / poly[3];
+            assert!(diff.re.abs() < 1e-6);
+            assert!(diff.im.abs() < 1e-6);
+        }
+    }
+
     #[test]
     fn test_f32_roots() {
         let lpc_coeffs: Vec<Complex<f32>> = vec![
@@ -461,4 +519,23 @@ mod tests {
         assert!(roots.re.is_finite());
         assert!(roots.im.is_finite());
     }
+
+    #[test]
+    fn test_roots_validation() {
+        // z^3 - z^2 has roots 0, 0 and 1
+        let poly: Vec<Complex<f64>> = [0., 0., -1., 1.].iter().map(Complex::from).collect();
+        let roots = poly.find_roots().unwrap();
+        println!("roots: {:?}", roots);
+        assert!(roots.iter().any(|r| (r.re - 1.).abs() < 1.0e-9));
+
+        let mut work = [Complex::<f64>::from(0.); 4];
+        assert!(matches!(
+            poly.clone().find_roots_mut(&mut work[..]),
+            Err(VoxBoxError::Workspace)
+        ));
+        let nan: Vec<Complex<f64>> = [1., f64::NAN, 1.].iter().map(Complex::from).collect();
+        assert!(matches!(nan.find_roots(), Err(VoxBoxError::Input(_))));
+        let mut constant = [Complex::<f64>::from(2.)];
+        assert!(constant.div_polynomial(Complex::from(1.)).is_err());
+    }
 }
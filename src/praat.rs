@@ -0,0 +1,133 @@
+//! Writes pitch and formant tracks as Praat short text files, a `PitchTier` and a `Formant`, to
+//! open alongside the sound in Praat for checking or further scripting.
+
+use std::io::Write;
+
+use crate::analysis::Analysis;
+use crate::error::*;
+use crate::units::Seconds;
+
+/// Writes the voiced frames' pitch as a `PitchTier` spanning `duration`, the length of the
+/// analyzed sound. Unvoiced frames have no point.
+pub fn write_pitch_tier<W: Write>(
+    analysis: &Analysis,
+    duration: Seconds,
+    mut writer: W,
+) -> VoxBoxResult<()> {
+    let points: Vec<(f64, f64)> = analysis
+        .times
+        .iter()
+        .zip(analysis.pitch.iter())
+        .filter(|(_, f0)| f0.is_finite())
+        .map(|(t, f0)| (t.0, *f0))
+        .collect();
+    let mut lines = header("PitchTier", duration)?;
+    lines.push(points.len().to_string());
+    for (t, f0) in points {
+        lines.push(t.to_string());
+        lines.push(f0.to_string());
+    }
+    write_lines(&mut writer, &lines)
+}
+
+/// Writes the tracked formants as a `Formant` object spanning `duration`, one frame per analysis
+/// frame. Frames without formants are written with none, as Praat does for frames it found
+/// none in.
+#[cfg(feature = "formant")]
+pub fn write_formant<W: Write>(
+    analysis: &Analysis,
+    duration: Seconds,
+    mut writer: W,
+) -> VoxBoxResult<()> {
+    let mut lines = header("Formant 2", duration)?;
+    lines.push(analysis.len().to_string());
+    lines.push(analysis.frame_period.0.to_string());
+    lines.push(analysis.times.first().map_or(0., |t| t.0).to_string());
+    let max_formants = analysis.formants.iter().map(|f| f.len()).max().unwrap_or(0);
+    lines.push(max_formants.to_string());
+    for (formants, intensity) in analysis.formants.iter().zip(analysis.intensity.iter()) {
+        // Praat keeps the frame's power, not its level
+        lines.push(intensity.to_power().to_string());
+        lines.push(formants.len().to_string());
+        for formant in formants.iter() {
+            lines.push(formant.frequency.to_string());
+            lines.push(formant.bandwidth.to_string());
+        }
+    }
+    write_lines(&mut writer, &lines)
+}
+
+/// The file type, object class and time domain every object starts with.
+fn header(class: &str, duration: Seconds) -> VoxBoxResult<Vec<String>> {
+    if duration.0.is_nan() || duration.0 <= 0. {
+        return Err(VoxBoxError::Config("Duration must be positive"));
+    }
+    Ok(vec![
+        "File type = \"ooTextFile\"".to_string(),
+        format!("Object class = \"{}\"", class),
+        String::new(),
+        "0".to_string(),
+        duration.0.to_string(),
+    ])
+}
+
+fn write_lines<W: Write>(writer: &mut W, lines: &[String]) -> VoxBoxResult<()> {
+    for line in lines.iter() {
+        writeln!(writer, "{}", line).map_err(|_| VoxBoxError::Io("Could not write the file"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::SegmentLabel;
+    use crate::units::Db;
+
+    fn analysis() -> Analysis {
+        Analysis {
+            frame_period: Seconds(0.01),
+            times: vec![Seconds(0.0125), Seconds(0.0225)],
+            voicing: vec![SegmentLabel::Silence, SegmentLabel::Voiced],
+            pitch: vec![f64::NAN, 120.5],
+            intensity: vec![Db(-60.), Db(-20.)],
+            #[cfg(feature = "formant")]
+            formants: vec![
+                vec![],
+                vec![
+                    crate::spectrum::Resonance::new(700., 80.),
+                    crate::spectrum::Resonance::new(1200., 90.),
+                ],
+            ],
+            #[cfg(feature = "mfcc")]
+            mfcc: vec![vec![], vec![]],
+        }
+    }
+
+    #[test]
+    fn test_write_pitch_tier() {
+        let mut text = Vec::new();
+        write_pitch_tier(&analysis(), Seconds(0.035), &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "File type = \"ooTextFile\"\nObject class = \"PitchTier\"\n\n0\n0.035\n1\n0.0225\n120.5\n"
+        );
+        assert!(write_pitch_tier(&analysis(), Seconds(0.), Vec::new()).is_err());
+    }
+
+    #[cfg(feature = "formant")]
+    #[test]
+    fn test_write_formant() {
+        let mut text = Vec::new();
+        write_formant(&analysis(), Seconds(0.035), &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        println!("{}", text);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "Object class = \"Formant 2\"");
+        // nx, dx, x1 and the most formants in a frame
+        assert_eq!(&lines[5..9], &["2", "0.01", "0.0125", "2"]);
+        // The first frame has no formants
+        assert_eq!(&lines[9..11], &["0.000001", "0"]);
+        assert_eq!(&lines[11..], &["0.01", "2", "700", "80", "1200", "90"]);
+    }
+}
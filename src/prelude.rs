@@ -0,0 +1,55 @@
+//! The traits and types most analyses need, for glob importing:
+//!
+//! ```
+//! use vox_box::prelude::*;
+//!
+//! let mut signal = vec![0.5f64, -0.25, 0.125, 0.];
+//! signal.normalize();
+//! assert_eq!(signal.max_amplitude(), 1.0);
+//! ```
+
+pub use crate::arma::PoleZero;
+pub use crate::complex::{SquareRoot, ToComplex, ToComplexVec};
+pub use crate::config::AnalysisConfig;
+pub use crate::error::{VoxBoxError, VoxBoxResult};
+#[cfg(feature = "pitch")]
+pub use crate::periodic::{
+    Autocorrelate, LagType, NormalizedCrossCorrelate, Pitch, PitchExtractor, PitchFrame, Pitched,
+    Pitcher,
+};
+pub use crate::polynomial::Polynomial;
+#[cfg(feature = "formant")]
+pub use crate::spectrum::EstimateFormants;
+#[cfg(feature = "mfcc")]
+pub use crate::spectrum::MFCC;
+pub use crate::spectrum::{AllPoleFilter, InverseFilter, Resonance, ToResonance, LPC, LSF};
+#[cfg(feature = "pitch")]
+pub use crate::spectrum::{CepstralPitch, Swipe};
+#[cfg(feature = "synthesis")]
+pub use crate::synthesis::{FormantShift, PitchShift};
+pub use crate::units::{Db, Hz, Lufs, Seconds};
+pub use crate::waves::{
+    Amplitude, Filter, MaxAmplitude, Normalization, Normalize, WindowType, ZeroCrossingRate, RMS,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+
+    #[test]
+    fn test_prelude_pipeline() {
+        let signal: Vec<f64> = (0..64).map(|i| (i as f64 * 0.4).sin()).collect();
+        let coeffs = signal.lpc_praat(2).unwrap();
+        let poly: Vec<Complex<f64>> = [1.]
+            .iter()
+            .chain(coeffs.iter())
+            .rev()
+            .map(|c| Complex::new(*c, 0.))
+            .collect();
+        let resonances = poly.find_roots().unwrap().to_resonance(1000.);
+        println!("resonances: {:?}", resonances);
+        // 0.4 radians per sample at 1 kHz
+        assert!((resonances[0].frequency - 63.66).abs() < 1.);
+    }
+}
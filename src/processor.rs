@@ -0,0 +1,430 @@
+//! Block processing stages that keep their state between calls, for streaming audio through
+//! an analysis one buffer at a time.
+//!
+//! Stages write into caller-provided buffers, and a `Chain` reuses one scratch buffer between
+//! its two stages, so a pipeline allocates nothing once it has seen its largest block.
+
+use num::{Float, FromPrimitive};
+use std::f64::consts::PI;
+
+use crate::error::*;
+use crate::spectrum::LPC;
+use crate::units::Hz;
+use crate::validate;
+use crate::waves::{preemphasis_coefficient, WindowType};
+
+pub trait Processor<T> {
+    /// Processes the next block of `input` into the start of `output`, which must hold at
+    /// least `max_output(input.len())` samples. Returns the number of samples written.
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize>;
+
+    /// Most samples `process` writes for a block of `input_len`.
+    fn max_output(&self, input_len: usize) -> usize {
+        input_len
+    }
+
+    /// Input samples held back between blocks before they reach the output.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Forgets everything carried over from earlier blocks.
+    fn reset(&mut self);
+
+    /// Feeds the output of this stage into `next`.
+    fn chain<P: Processor<T>>(self, next: P) -> Chain<T, Self, P>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+            scratch: Vec::new(),
+        }
+    }
+}
+
+/// Two stages run one after the other, from `Processor::chain`.
+#[derive(Clone, Debug)]
+pub struct Chain<T, A, B> {
+    first: A,
+    second: B,
+    scratch: Vec<T>,
+}
+
+impl<T, A, B> Processor<T> for Chain<T, A, B>
+where
+    T: Float,
+    A: Processor<T>,
+    B: Processor<T>,
+{
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        let needed = self.first.max_output(input.len());
+        if self.scratch.len() < needed {
+            self.scratch.resize(needed, T::zero());
+        }
+        let n = self.first.process(input, &mut self.scratch[..needed])?;
+        self.second.process(&self.scratch[..n], output)
+    }
+
+    fn max_output(&self, input_len: usize) -> usize {
+        self.second.max_output(self.first.max_output(input_len))
+    }
+
+    fn latency(&self) -> usize {
+        self.first.latency() + self.second.latency()
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+}
+
+/// First-order pre-emphasis, `y[n] = x[n] - coefficient * x[n - 1]`, carrying the last sample
+/// across blocks.
+#[derive(Clone, Debug)]
+pub struct PreEmphasis<T> {
+    pub coefficient: T,
+    last: T,
+}
+
+impl<T: Float> PreEmphasis<T> {
+    pub fn new(coefficient: T) -> Self {
+        PreEmphasis {
+            coefficient,
+            last: T::zero(),
+        }
+    }
+}
+
+impl<T: Float + FromPrimitive> PreEmphasis<T> {
+    /// Pre-emphasis that boosts by 6 dB per octave from about `frequency`, with the coefficient
+    /// from `waves::preemphasis_coefficient`.
+    pub fn from_frequency<F: Into<Hz>, R: Into<Hz>>(frequency: F, sample_rate: R) -> Self {
+        PreEmphasis::new(T::from_f64(preemphasis_coefficient(frequency, sample_rate)).unwrap())
+    }
+}
+
+impl<T: Float> Processor<T> for PreEmphasis<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, input.len())?;
+        for (y, x) in output.iter_mut().zip(input.iter()) {
+            *y = *x - self.coefficient * self.last;
+            self.last = *x;
+        }
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {
+        self.last = T::zero();
+    }
+}
+
+/// De-emphasis, `y[n] = x[n] + coefficient * y[n - 1]`, which undoes `PreEmphasis` with the
+/// same coefficient. Carries the last output across blocks.
+#[derive(Clone, Debug)]
+pub struct DeEmphasis<T> {
+    pub coefficient: T,
+    last: T,
+}
+
+impl<T: Float> DeEmphasis<T> {
+    pub fn new(coefficient: T) -> Self {
+        DeEmphasis {
+            coefficient,
+            last: T::zero(),
+        }
+    }
+}
+
+impl<T: Float> Processor<T> for DeEmphasis<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, input.len())?;
+        for (y, x) in output.iter_mut().zip(input.iter()) {
+            self.last = *x + self.coefficient * self.last;
+            *y = self.last;
+        }
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {
+        self.last = T::zero();
+    }
+}
+
+/// A DC blocker, `y[n] = x[n] - x[n - 1] + coefficient * y[n - 1]`: a zero at DC and a pole
+/// just inside it, which removes a constant or slowly drifting offset and passes everything
+/// well above the cutoff. Carries the last input and output across blocks.
+#[derive(Clone, Debug)]
+pub struct DcBlocker<T> {
+    pub coefficient: T,
+    last_input: T,
+    last_output: T,
+}
+
+impl<T: Float> DcBlocker<T> {
+    pub fn new(coefficient: T) -> Self {
+        DcBlocker {
+            coefficient,
+            last_input: T::zero(),
+            last_output: T::zero(),
+        }
+    }
+}
+
+impl<T: Float + FromPrimitive> DcBlocker<T> {
+    /// A DC blocker with its 3 dB cutoff at about `frequency`, which is small next to the
+    /// sample rate: 20 Hz leaves speech alone.
+    pub fn from_frequency<F: Into<Hz>, R: Into<Hz>>(frequency: F, sample_rate: R) -> Self {
+        let frequency = frequency.into().0;
+        let sample_rate = sample_rate.into().0;
+        DcBlocker::new(T::from_f64((-2. * PI * frequency / sample_rate).exp()).unwrap())
+    }
+}
+
+impl<T: Float> Processor<T> for DcBlocker<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, input.len())?;
+        for (y, x) in output.iter_mut().zip(input.iter()) {
+            self.last_output = *x - self.last_input + self.coefficient * self.last_output;
+            self.last_input = *x;
+            *y = self.last_output;
+        }
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {
+        self.last_input = T::zero();
+        self.last_output = T::zero();
+    }
+}
+
+/// Multiplies frames by a window. Every block must be one frame, as long as the window.
+#[derive(Clone, Debug)]
+pub struct Windowing<T> {
+    window: Vec<T>,
+}
+
+impl<T: Float + FromPrimitive> Windowing<T> {
+    pub fn new(window: WindowType, len: usize) -> Self {
+        Windowing {
+            window: window
+                .window(len)
+                .iter()
+                .map(|w| T::from_f64(*w).unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl<T: Float> Processor<T> for Windowing<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        if input.len() != self.window.len() {
+            return Err(VoxBoxError::Input("Block must be as long as the window"));
+        }
+        validate::workspace(output, input.len())?;
+        for ((y, x), w) in output.iter_mut().zip(input.iter()).zip(self.window.iter()) {
+            *y = *x * *w;
+        }
+        Ok(input.len())
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// LPC analysis of frames by Burg's method, as `LPC::lpc_praat`. Every block must be one frame
+/// of `frame_len` samples, and gives `order` coefficients.
+#[derive(Clone, Debug)]
+pub struct LpcAnalysis<T> {
+    order: usize,
+    frame_len: usize,
+    work: Vec<T>,
+}
+
+impl<T: Float> LpcAnalysis<T> {
+    pub fn new(order: usize, frame_len: usize) -> Self {
+        LpcAnalysis {
+            order,
+            frame_len,
+            work: vec![T::zero(); 2 * frame_len + order],
+        }
+    }
+}
+
+impl<T: Float> Processor<T> for LpcAnalysis<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        if input.len() != self.frame_len {
+            return Err(VoxBoxError::Input("Block must be one frame long"));
+        }
+        input.lpc_praat_mut(self.order, output, &mut self.work)?;
+        Ok(self.order)
+    }
+
+    fn max_output(&self, _input_len: usize) -> usize {
+        self.order
+    }
+
+    fn reset(&mut self) {}
+}
+
+/// Resamples by linear interpolation to `ratio` times the sample rate, carrying the position
+/// of the next output sample and the last input sample across blocks.
+/// Cheap, but it aliases; `resample::StreamResampler` is band-limited.
+#[derive(Clone, Debug)]
+pub struct Resampler<T> {
+    ratio: f64,
+    /// Last sample of the previous block
+    last: T,
+    /// Time of the next output sample in input samples, counted from `last`
+    position: f64,
+}
+
+impl<T: Float + FromPrimitive> Resampler<T> {
+    pub fn new(ratio: f64) -> VoxBoxResult<Self> {
+        validate::positive(ratio, "Resample ratio must be positive")?;
+        Ok(Resampler {
+            ratio,
+            last: T::zero(),
+            position: 1.,
+        })
+    }
+}
+
+impl<T: Float + FromPrimitive> Processor<T> for Resampler<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, self.max_output(input.len()))?;
+        let last = self.last;
+        let sample = |i: usize| if i == 0 { last } else { input[i - 1] };
+        let step = 1. / self.ratio;
+        let mut written = 0;
+        // Each output needs the input sample after it, so stop short of the last one
+        while self.position < input.len() as f64 {
+            let i = self.position.floor() as usize;
+            let frac = T::from_f64(self.position - i as f64).unwrap();
+            output[written] = sample(i) + (sample(i + 1) - sample(i)) * frac;
+            written += 1;
+            self.position += step;
+        }
+        if let Some(last) = input.last() {
+            self.last = *last;
+        }
+        self.position -= input.len() as f64;
+        Ok(written)
+    }
+
+    fn max_output(&self, input_len: usize) -> usize {
+        (input_len as f64 * self.ratio).ceil() as usize + 1
+    }
+
+    fn latency(&self) -> usize {
+        1
+    }
+
+    fn reset(&mut self) {
+        self.last = T::zero();
+        self.position = 1.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        let signal: Vec<f64> = (0..256).map(|n| (n as f64 * 0.2).sin()).collect();
+        let mut pipeline = PreEmphasis::new(0.97).chain(Windowing::new(WindowType::Hanning, 64));
+        let mut output = vec![0.; 64];
+        let window = WindowType::Hanning.window(64);
+        for (k, block) in signal.chunks(64).enumerate() {
+            assert_eq!(pipeline.process(block, &mut output).unwrap(), 64);
+            // Pre-emphasis carries on from the end of the previous block
+            for (i, y) in output.iter().enumerate() {
+                let n = 64 * k + i;
+                let previous = if n > 0 { signal[n - 1] } else { 0. };
+                assert!((y - (signal[n] - 0.97 * previous) * window[i]).abs() < 1e-12);
+            }
+        }
+        pipeline.reset();
+        pipeline.process(&signal[64..128], &mut output).unwrap();
+        assert!((output[1] - (signal[65] - 0.97 * signal[64]) * window[1]).abs() < 1e-12);
+        assert!(pipeline.process(&signal[..10], &mut output).is_err());
+
+        // A whole analysis: resonances of a decaying 0.2 rad/sample oscillation
+        let mut lpc = PreEmphasis::new(0.).chain(LpcAnalysis::new(2, 64));
+        let mut coeffs = vec![0.; lpc.max_output(64)];
+        let decaying: Vec<f64> = signal[..64]
+            .iter()
+            .enumerate()
+            .map(|(n, s)| s * 0.99f64.powi(n as i32))
+            .collect();
+        assert_eq!(lpc.process(&decaying, &mut coeffs).unwrap(), 2);
+        println!("coeffs: {:?}", coeffs);
+        assert!((coeffs[0] + 2. * 0.99 * 0.2f64.cos()).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_deemphasis() {
+        // Pre-emphasis and de-emphasis cancel, across blocks of any size
+        let signal: Vec<f64> = (0..100).map(|n| (n as f64 * 0.37).cos()).collect();
+        let mut pipeline = PreEmphasis::from_frequency(50., 16000.)
+            .chain(DeEmphasis::new(preemphasis_coefficient(50., 16000.)));
+        let mut output = vec![0.; 100];
+        let mut start = 0;
+        for len in [1, 30, 9, 60].iter() {
+            let block = &signal[start..start + len];
+            let n = pipeline.process(block, &mut output[start..]).unwrap();
+            assert_eq!(n, *len);
+            start += len;
+        }
+        for (a, b) in output.iter().zip(signal.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_dc_blocker() {
+        // An offset dies away, and a 200 Hz tone at 16 kHz comes through
+        let sr = 16000.;
+        let signal: Vec<f64> = (0..16000)
+            .map(|n| 0.5 + (2. * PI * 200. * n as f64 / sr).sin())
+            .collect();
+        let mut blocker = DcBlocker::from_frequency(20., sr);
+        let mut output = vec![0.; signal.len()];
+        for (input, out) in signal.chunks(300).zip(output.chunks_mut(300)) {
+            blocker.process(input, out).unwrap();
+        }
+        let tail = &output[8000..];
+        let mean = tail.iter().sum::<f64>() / tail.len() as f64;
+        let peak = tail.iter().fold(0., |m: f64, y| m.max(y.abs()));
+        println!("mean {}, peak {}", mean, peak);
+        assert!(mean.abs() < 1e-3);
+        assert!((peak - 1.).abs() < 0.01);
+        blocker.reset();
+        assert!(blocker.process(&signal[..10], &mut output[..5]).is_err());
+    }
+
+    #[test]
+    fn test_resampler() {
+        // A ramp resampled by 2.5 and by 0.4 in uneven blocks stays a ramp
+        let ramp: Vec<f64> = (0..100).map(|n| n as f64).collect();
+        for ratio in [2.5, 0.4].iter() {
+            let mut resampler = Resampler::new(*ratio).unwrap();
+            assert_eq!(resampler.latency(), 1);
+            let mut output = Vec::new();
+            let mut buf = vec![0.; 128];
+            for block in [&ramp[..7], &ramp[7..8], &ramp[8..50], &ramp[50..]].iter() {
+                let n = resampler.process(block, &mut buf).unwrap();
+                output.extend_from_slice(&buf[..n]);
+            }
+            println!("ratio {}: {} samples", ratio, output.len());
+            for (k, y) in output.iter().enumerate() {
+                assert!((y - k as f64 / ratio).abs() < 1e-9);
+            }
+            // Everything up to the last input sample
+            assert_eq!(output.len(), (99. * ratio).floor() as usize + 1);
+        }
+        assert!(Resampler::<f64>::new(0.).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+//! Progress reporting and cancellation for analyses that run over a whole signal.
+//!
+//! The `*_with_progress` entry points call a `FnMut(Progress) -> ControlFlow<()>` after every
+//! frame. Returning `ControlFlow::Break(())` stops the analysis, which then returns
+//! `VoxBoxError::Cancelled`.
+
+pub use std::ops::ControlFlow;
+
+use crate::error::*;
+
+/// How far an analysis has got, in frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    /// Fraction of the work done, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.
+        } else {
+            self.done as f64 / self.total as f64
+        }
+    }
+}
+
+/// A callback that never cancels, for the entry points without one.
+pub(crate) fn ignore(_: Progress) -> ControlFlow<()> {
+    ControlFlow::Continue(())
+}
+
+/// Reports progress to `callback`, turning a break into `VoxBoxError::Cancelled`.
+pub(crate) fn report<F>(callback: &mut F, done: usize, total: usize) -> VoxBoxResult<()>
+where
+    F: FnMut(Progress) -> ControlFlow<()>,
+{
+    match callback(Progress { done, total }) {
+        ControlFlow::Continue(()) => Ok(()),
+        ControlFlow::Break(()) => Err(VoxBoxError::Cancelled),
+    }
+}
@@ -0,0 +1,368 @@
+//! Probabilistic YIN (Mauch and Dixon 2014).
+//!
+//! Instead of a single threshold, every frame is searched with a spread of YIN thresholds
+//! weighted by a beta distribution, which gives a handful of f0 candidates with probabilities.
+//! A hidden Markov model over pitch bins, each with a voiced and an unvoiced state, then picks
+//! a smooth track through the candidates with Viterbi decoding.
+
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::periodic::YinDifference;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::segment::centered_frame;
+use crate::units::Hz;
+use crate::validate;
+
+/// Number of YIN thresholds tried, evenly spaced from 0.01 to 1.
+const N_THRESHOLDS: usize = 100;
+
+/// A possible f0 for a frame, and the probability that it is the right one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchCandidate {
+    pub f0: f64,
+    pub probability: f64,
+}
+
+/// One frame of a pYIN track.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PYinFrame {
+    /// Pitch of the decoded state. Unvoiced frames keep the pitch of their unvoiced state, which
+    /// follows the neighbouring voiced frames, except for silence, which is zero.
+    pub f0: f64,
+    pub voiced: bool,
+    /// Total probability of the candidates, from 0 to 1
+    pub voiced_probability: f64,
+    pub candidates: Vec<PitchCandidate>,
+}
+
+/// Settings for pYIN pitch tracking. The defaults follow the paper.
+#[derive(Clone, Debug)]
+pub struct PYin {
+    pub sample_rate: f64,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    /// Length of the analysis frame, in samples. Must hold two periods of `min_pitch`.
+    pub frame_len: usize,
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+    /// Parameters of the beta distribution over YIN thresholds
+    pub beta: (f64, f64),
+    /// Weight given to the lowest dip of frames where a threshold finds no dip
+    pub absolute_min_weight: f64,
+    /// Resolution of the pitch states
+    pub bins_per_semitone: usize,
+    /// Largest pitch change between frames, in bins
+    pub max_jump: usize,
+    /// Probability of switching between voiced and unvoiced from one frame to the next
+    pub switch_probability: f64,
+    /// How far the candidate probabilities are trusted in the voicing decision
+    pub yin_trust: f64,
+}
+
+impl PYin {
+    /// Default settings: 10 ms hops, pitch between 75 and 600 Hz, 20 cent pitch states.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        let min_pitch = 75.;
+        PYin {
+            sample_rate,
+            hop: (sample_rate * 0.01).round() as usize,
+            frame_len: 2 * ((sample_rate / min_pitch).ceil() as usize + 1),
+            min_pitch,
+            max_pitch: 600.,
+            beta: (2., 18.),
+            absolute_min_weight: 0.01,
+            bins_per_semitone: 5,
+            max_jump: 25,
+            switch_probability: 0.01,
+            yin_trust: 0.5,
+        }
+    }
+
+    /// Takes the hop from `config`. The frame keeps its default length, which fits the pitch
+    /// range.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        PYin {
+            hop: config.hop,
+            ..PYin::new(config.sample_rate)
+        }
+    }
+
+    /// The f0 candidates of one frame, with their probabilities. The probabilities add up to
+    /// at most 1; the rest is the probability that the frame is unvoiced.
+    pub fn candidates(&self, frame: &[f64]) -> Vec<PitchCandidate> {
+        let difference =
+            match YinDifference::new(frame, self.sample_rate, self.min_pitch, self.max_pitch) {
+                Some(difference) => difference,
+                None => return Vec::new(),
+            };
+
+        let (a, b) = self.beta;
+        let density: Vec<f64> = (1..=N_THRESHOLDS)
+            .map(|i| {
+                let s = i as f64 / N_THRESHOLDS as f64;
+                s.powf(a - 1.) * (1. - s).max(0.).powf(b - 1.)
+            })
+            .collect();
+        let total: f64 = density.iter().sum();
+        if total <= 0. || !total.is_finite() {
+            return Vec::new();
+        }
+
+        // Probability mass per lag, in the order the lags are found
+        let mut mass: Vec<(usize, f64)> = Vec::new();
+        let mut add = |tau: usize, p: f64| match mass.iter_mut().find(|m| m.0 == tau) {
+            Some(m) => m.1 += p,
+            None => mass.push((tau, p)),
+        };
+        for (i, weight) in density.iter().enumerate() {
+            let threshold = (i + 1) as f64 / N_THRESHOLDS as f64;
+            let p = weight / total;
+            match difference.first_dip(threshold) {
+                Some(tau) => add(tau, p),
+                None => add(difference.global_min(), p * self.absolute_min_weight),
+            }
+        }
+        mass.iter()
+            .map(|&(tau, probability)| PitchCandidate {
+                f0: self.sample_rate / difference.refine(tau).0,
+                probability,
+            })
+            .collect()
+    }
+
+    /// Tracks the pitch of the signal, one frame per hop.
+    pub fn track<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<PYinFrame>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.track_with_progress(signal, progress::ignore)
+    }
+
+    /// Like `track`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled` if
+    /// the callback breaks.
+    pub fn track_with_progress<S, F>(
+        &self,
+        signal: &[S],
+        mut progress: F,
+    ) -> VoxBoxResult<Vec<PYinFrame>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        validate::positive(self.sample_rate, "Sample rate must be positive")?;
+        if !(self.min_pitch > 0. && self.min_pitch < self.max_pitch) {
+            return Err(VoxBoxError::Config(
+                "Pitch range must be positive and increasing",
+            ));
+        }
+        if self.hop == 0 || self.bins_per_semitone == 0 {
+            return Err(VoxBoxError::Config(
+                "Hop and pitch resolution must be nonzero",
+            ));
+        }
+        if !(0. ..=1.).contains(&self.switch_probability) {
+            return Err(VoxBoxError::Config(
+                "Switch probability must be between 0 and 1",
+            ));
+        }
+
+        let n_frames = signal.len().div_ceil(self.hop);
+        let mut candidates = Vec::with_capacity(n_frames);
+        for k in 0..n_frames {
+            let frame = centered_frame(signal, k, self.hop, self.frame_len);
+            validate::finite(&frame)?;
+            candidates.push(self.candidates(&frame));
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+
+        let path = self.viterbi(&candidates);
+        Ok(candidates
+            .into_iter()
+            .zip(path)
+            .map(|(candidates, (bin, voiced))| {
+                let voiced_probability = candidates
+                    .iter()
+                    .fold(0., |acc, c| acc + c.probability)
+                    .min(1.);
+                // The candidate in the decoded bin gives a finer pitch than the bin itself
+                let f0 = candidates
+                    .iter()
+                    .filter(|c| voiced && self.bin(c.f0) == bin)
+                    .fold(None, |best: Option<&PitchCandidate>, c| match best {
+                        Some(b) if b.probability >= c.probability => Some(b),
+                        _ => Some(c),
+                    })
+                    .map_or_else(|| self.bin_f0(bin), |c| c.f0);
+                let f0 = if candidates.is_empty() { 0. } else { f0 };
+                PYinFrame {
+                    f0,
+                    voiced,
+                    voiced_probability,
+                    candidates,
+                }
+            })
+            .collect())
+    }
+
+    fn n_bins(&self) -> usize {
+        let semitones = 12. * (self.max_pitch / self.min_pitch).log2();
+        (semitones * self.bins_per_semitone as f64).ceil() as usize + 1
+    }
+
+    /// The pitch state nearest to `f0`.
+    fn bin(&self, f0: f64) -> usize {
+        let bins = 12. * self.bins_per_semitone as f64 * (f0 / self.min_pitch).log2();
+        (bins.round().max(0.) as usize).min(self.n_bins() - 1)
+    }
+
+    fn bin_f0(&self, bin: usize) -> f64 {
+        self.min_pitch * 2f64.powf(bin as f64 / (12. * self.bins_per_semitone as f64))
+    }
+
+    /// Viterbi decoding over the pitch bins, each voiced or unvoiced. Returns the bin and
+    /// voicing of every frame.
+    fn viterbi(&self, candidates: &[Vec<PitchCandidate>]) -> Vec<(usize, bool)> {
+        let n_bins = self.n_bins();
+        // Voiced states are `0..n_bins`, their unvoiced twins `n_bins..2 * n_bins`
+        let n_states = 2 * n_bins;
+
+        // Triangular pitch transitions, normalized over the bins in reach
+        let jump = self.max_jump as isize;
+        let transition_ln: Vec<Vec<(usize, f64)>> = (0..n_bins as isize)
+            .map(|to| {
+                let reach: Vec<(usize, f64)> = (to - jump..=to + jump)
+                    .filter(|from| *from >= 0 && *from < n_bins as isize)
+                    .map(|from| (from as usize, (jump + 1 - (to - from).abs()) as f64))
+                    .collect();
+                let total: f64 = reach.iter().map(|r| r.1).sum();
+                reach
+                    .into_iter()
+                    .map(|(from, w)| (from, (w / total).ln()))
+                    .collect()
+            })
+            .collect();
+        let stay_ln = (1. - self.switch_probability).ln();
+        let switch_ln = self.switch_probability.ln();
+
+        let observation_ln = |frame: &[PitchCandidate]| -> Vec<f64> {
+            let mut observation = vec![0.; n_states];
+            let mut voiced = 0.;
+            for c in frame {
+                let p = c.probability * self.yin_trust;
+                observation[self.bin(c.f0)] += p;
+                voiced += p;
+            }
+            let unvoiced = ((1. - voiced) / n_bins as f64).max(0.);
+            for o in observation[n_bins..].iter_mut() {
+                *o = unvoiced;
+            }
+            observation.iter().map(|o| o.max(1.0e-300).ln()).collect()
+        };
+
+        let mut scores: Vec<f64> = match candidates.first() {
+            Some(first) => observation_ln(first),
+            None => return Vec::new(),
+        };
+        let mut back: Vec<Vec<usize>> = Vec::with_capacity(candidates.len());
+        back.push((0..n_states).collect());
+        for frame in &candidates[1..] {
+            let observation = observation_ln(frame);
+            let mut next = vec![f64::NEG_INFINITY; n_states];
+            let mut from = vec![0; n_states];
+            for state in 0..n_states {
+                let (bin, voiced) = (state % n_bins, state < n_bins);
+                for &(prev_bin, pitch_ln) in &transition_ln[bin] {
+                    for &(prev_voiced, voicing_ln) in &[(voiced, stay_ln), (!voiced, switch_ln)] {
+                        let prev = if prev_voiced {
+                            prev_bin
+                        } else {
+                            prev_bin + n_bins
+                        };
+                        let score = scores[prev] + pitch_ln + voicing_ln;
+                        if score > next[state] {
+                            next[state] = score;
+                            from[state] = prev;
+                        }
+                    }
+                }
+                next[state] += observation[state];
+            }
+            scores = next;
+            back.push(from);
+        }
+
+        let mut state = scores
+            .iter()
+            .enumerate()
+            .fold(
+                (0, f64::NEG_INFINITY),
+                |a, (i, s)| if *s > a.1 { (i, *s) } else { a },
+            )
+            .0;
+        let mut path = vec![(0, false); candidates.len()];
+        for k in (0..candidates.len()).rev() {
+            path[k] = (state % n_bins, state < n_bins);
+            state = back[k][state];
+        }
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_pyin() {
+        let sample_rate = 8000.;
+        // 200 ms of silence, 400 ms of a 220 Hz buzz, then 200 ms of silence
+        let signal: Vec<f64> = (0..6400)
+            .map(|n| {
+                if (1600..4800).contains(&n) {
+                    let t = n as f64 / sample_rate;
+                    (1..6)
+                        .map(|h| (2. * PI * 220. * h as f64 * t).sin() / h as f64)
+                        .sum::<f64>()
+                } else {
+                    0.
+                }
+            })
+            .collect();
+
+        let pyin = PYin::new(sample_rate);
+        let frames = pyin.track(&signal).unwrap();
+        assert_eq!(frames.len(), 80);
+        for (k, frame) in frames.iter().enumerate() {
+            println!(
+                "{}: {} {} {}",
+                k, frame.f0, frame.voiced, frame.voiced_probability
+            );
+        }
+        for frame in &frames[25..55] {
+            assert!(frame.voiced);
+            assert!((frame.f0 - 220.).abs() < 1.);
+            assert!(frame.voiced_probability > 0.9);
+        }
+        assert!(frames[..15]
+            .iter()
+            .all(|f| !f.voiced && f.candidates.is_empty() && f.f0 == 0.));
+        assert!(frames[65..].iter().all(|f| !f.voiced));
+
+        let steady = &signal[2000..2000 + pyin.frame_len];
+        let candidates = pyin.candidates(steady);
+        println!("candidates: {:?}", candidates);
+        let best = candidates.iter().fold(candidates[0], |a, c| {
+            if c.probability > a.probability {
+                *c
+            } else {
+                a
+            }
+        });
+        assert!((best.f0 - 220.).abs() < 1.);
+        assert!(candidates.iter().map(|c| c.probability).sum::<f64>() <= 1. + 1e-9);
+    }
+}
@@ -0,0 +1,459 @@
+//! Pitch tracking with RAPT (Talkin 1995), the Robust Algorithm for Pitch Tracking.
+//!
+//! Each frame gets a handful of pitch candidates from the normalized cross-correlation (NCCF):
+//! a first pass over a decimated copy of the signal finds rough peaks, and a second pass at the
+//! full rate refines them. Dynamic programming then picks one candidate, or unvoiced, per
+//! frame, trading the strength of each candidate against jumps in pitch and voicing.
+
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::periodic::{NormalizedCrossCorrelate, PitchFrame};
+use crate::progress::{self, ControlFlow, Progress};
+use crate::units::Hz;
+use crate::validate;
+use crate::waves::to_f64_samples;
+
+/// A pitch candidate: the lag of an NCCF peak in samples, and its height.
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    lag: f64,
+    strength: f64,
+}
+
+/// Settings for RAPT pitch tracking. The weights and costs are Talkin's defaults. The
+/// spectral stationarity term of the voicing transition cost is left out.
+#[derive(Clone, Debug)]
+pub struct Rapt {
+    pub sample_rate: f64,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    /// Length of the correlation window, in samples
+    pub window_len: usize,
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+    /// Fraction of the highest NCCF peak in a frame that other candidates must reach
+    pub candidate_threshold: f64,
+    /// Most voiced candidates kept per frame
+    pub max_candidates: usize,
+    /// Penalty on long lags, which keeps the tracker off subharmonics
+    pub lag_weight: f64,
+    /// Cost of pitch changes between voiced frames, per unit of log frequency
+    pub frequency_weight: f64,
+    /// Fixed cost of switching between voiced and unvoiced
+    pub transition_cost: f64,
+    /// Cost of voicing onsets in falling energy and offsets in rising energy
+    pub amplitude_weight: f64,
+    /// Cost of octave jumps, on top of the frequency cost
+    pub doubling_cost: f64,
+    /// Added to the cost of unvoiced frames. Positive values favour voicing.
+    pub voicing_bias: f64,
+}
+
+impl Rapt {
+    /// Default settings: 10 ms hops, a 7.5 ms correlation window, pitch between 75 and 600 Hz.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        Rapt {
+            sample_rate,
+            hop: (sample_rate * 0.01).round() as usize,
+            window_len: (sample_rate * 0.0075).round() as usize,
+            min_pitch: 75.,
+            max_pitch: 600.,
+            candidate_threshold: 0.3,
+            max_candidates: 20,
+            lag_weight: 0.3,
+            frequency_weight: 0.02,
+            transition_cost: 0.005,
+            amplitude_weight: 0.5,
+            doubling_cost: 0.35,
+            voicing_bias: 0.,
+        }
+    }
+
+    /// Takes the hop from `config`. The correlation window keeps its default length.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Rapt {
+            hop: config.hop,
+            ..Rapt::new(config.sample_rate)
+        }
+    }
+
+    /// Tracks the pitch of the signal, one frame per hop. Unvoiced frames still report their
+    /// strongest candidate, if they have one.
+    pub fn track<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<PitchFrame<f64>>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.track_with_progress(signal, progress::ignore)
+    }
+
+    /// Like `track`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled` if
+    /// the callback breaks.
+    pub fn track_with_progress<S, F>(
+        &self,
+        signal: &[S],
+        mut progress: F,
+    ) -> VoxBoxResult<Vec<PitchFrame<f64>>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        validate::positive(self.sample_rate, "Sample rate must be positive")?;
+        if !(self.min_pitch > 0. && self.min_pitch < self.max_pitch) {
+            return Err(VoxBoxError::Config(
+                "Pitch range must be positive and increasing",
+            ));
+        }
+        if self.hop == 0 || self.window_len == 0 {
+            return Err(VoxBoxError::Config("Window length and hop must be nonzero"));
+        }
+        let signal = to_f64_samples(signal);
+        validate::finite(&signal)?;
+
+        let decimation = ((self.sample_rate / (4. * self.max_pitch)).floor() as usize).max(1);
+        let decimated: Vec<f64> = signal
+            .chunks(decimation)
+            .map(|c| c.iter().sum::<f64>() / c.len() as f64)
+            .collect();
+
+        let n_frames = signal.len().div_ceil(self.hop);
+        let mut candidates = Vec::with_capacity(n_frames);
+        let mut rms = Vec::with_capacity(n_frames);
+        for k in 0..n_frames {
+            let start = (k * self.hop + self.hop / 2) as isize - (self.window_len / 2) as isize;
+            let reference = excerpt(&signal, start, self.window_len);
+            rms.push(
+                (reference.iter().map(|s| s * s).sum::<f64>() / self.window_len as f64).sqrt(),
+            );
+            candidates.push(self.frame_candidates(&signal, &decimated, decimation, start));
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+
+        let path = self.best_path(&candidates, &rms);
+        Ok(candidates
+            .iter()
+            .zip(path)
+            .map(|(frame, choice)| {
+                let (candidate, voiced) = match choice {
+                    Some(i) => (Some(frame[i]), true),
+                    None => (
+                        frame
+                            .iter()
+                            .cloned()
+                            .fold(None, |best: Option<Candidate>, c| match best {
+                                Some(b) if b.strength >= c.strength => Some(b),
+                                _ => Some(c),
+                            }),
+                        false,
+                    ),
+                };
+                candidate.map_or(
+                    PitchFrame {
+                        f0: 0.,
+                        voiced: false,
+                        strength: 0.,
+                    },
+                    |c| PitchFrame {
+                        f0: self.sample_rate / c.lag,
+                        voiced,
+                        strength: c.strength,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// The lag range for the pitch range at `rate`.
+    fn lag_range(&self, rate: f64) -> (usize, usize) {
+        let min_lag = ((rate / self.max_pitch).floor() as usize).max(1);
+        let max_lag = ((rate / self.min_pitch).ceil() as usize).max(min_lag + 1);
+        (min_lag, max_lag)
+    }
+
+    /// Voiced candidates for the frame whose reference window starts at `start`.
+    fn frame_candidates(
+        &self,
+        signal: &[f64],
+        decimated: &[f64],
+        decimation: usize,
+        start: isize,
+    ) -> Vec<Candidate> {
+        // First pass: rough peaks in the NCCF of the decimated signal
+        let rate = self.sample_rate / decimation as f64;
+        let (min_lag, max_lag) = self.lag_range(rate);
+        let window_len = (self.window_len / decimation).max(2);
+        let mut excerpt_d = excerpt(
+            decimated,
+            start.div_euclid(decimation as isize),
+            window_len + max_lag + 1,
+        );
+        remove_mean(&mut excerpt_d);
+        let coarse = excerpt_d.nccf(window_len, max_lag + 2);
+        let peaks = peaks(
+            &coarse,
+            min_lag,
+            max_lag,
+            self.candidate_threshold,
+            self.max_candidates,
+        );
+
+        // Second pass: refine each peak at the full rate
+        let (min_lag, max_lag) = self.lag_range(self.sample_rate);
+        let mut excerpt_f = excerpt(signal, start, self.window_len + max_lag + 1);
+        remove_mean(&mut excerpt_f);
+        let reference = &excerpt_f[..self.window_len];
+        let e0: f64 = reference.iter().map(|s| s * s).sum();
+        let correlation = |lag: usize| -> f64 {
+            let lagged = &excerpt_f[lag..lag + self.window_len];
+            let energy = e0 * lagged.iter().map(|s| s * s).sum::<f64>();
+            if energy > 0. {
+                reference
+                    .iter()
+                    .zip(lagged)
+                    .map(|(a, b)| a * b)
+                    .sum::<f64>()
+                    / energy.sqrt()
+            } else {
+                0.
+            }
+        };
+
+        let mut refined: Vec<Candidate> = Vec::with_capacity(peaks.len());
+        for peak in peaks {
+            let centre = (peak.lag * decimation as f64).round() as usize;
+            let low = centre.saturating_sub(decimation + 1).max(min_lag);
+            let high = (centre + decimation + 1).min(max_lag);
+            if low > high {
+                continue;
+            }
+            let best = (low..=high)
+                .map(|lag| (lag, correlation(lag)))
+                .fold((low, f64::MIN), |a, b| if b.1 > a.1 { b } else { a });
+            let (lag, strength) = best;
+            let (prev, next) = (correlation(lag - 1), correlation(lag + 1));
+            let curvature = prev - 2. * strength + next;
+            let shift = if curvature < 0. {
+                (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
+            } else {
+                0.
+            };
+            if !refined.iter().any(|c| (c.lag - lag as f64).abs() < 1.) {
+                refined.push(Candidate {
+                    lag: lag as f64 + shift,
+                    strength,
+                });
+            }
+        }
+        let best = refined.iter().fold(0., |acc: f64, c| acc.max(c.strength));
+        refined.retain(|c| c.strength > 0. && c.strength >= self.candidate_threshold * best);
+        refined
+    }
+
+    /// Dynamic programming over the candidates of every frame. Returns the chosen candidate
+    /// of each frame, or `None` for unvoiced.
+    fn best_path(&self, candidates: &[Vec<Candidate>], rms: &[f64]) -> Vec<Option<usize>> {
+        let max_lag = self.sample_rate / self.min_pitch;
+        let ln2 = 2f64.ln();
+        // Per frame, the cost of each voiced candidate, then of unvoiced last
+        let mut costs: Vec<Vec<f64>> = Vec::with_capacity(candidates.len());
+        let mut back: Vec<Vec<usize>> = Vec::with_capacity(candidates.len());
+
+        for (k, frame) in candidates.iter().enumerate() {
+            let peak = frame.iter().fold(0., |acc: f64, c| acc.max(c.strength));
+            let mut local: Vec<f64> = frame
+                .iter()
+                .map(|c| 1. - c.strength * (1. - self.lag_weight * c.lag / max_lag))
+                .collect();
+            local.push(self.voicing_bias + peak);
+
+            if k == 0 {
+                back.push(vec![0; local.len()]);
+                costs.push(local);
+                continue;
+            }
+            let prev = &candidates[k - 1];
+            let prev_costs = &costs[k - 1];
+            let ratio = if rms[k - 1] > 0. {
+                (rms[k] / rms[k - 1]).clamp(0.01, 100.)
+            } else if rms[k] > 0. {
+                100.
+            } else {
+                1.
+            };
+
+            let mut total = Vec::with_capacity(local.len());
+            let mut from = Vec::with_capacity(local.len());
+            for (j, cost) in local.iter().enumerate() {
+                let voiced = j < frame.len();
+                let (best, index) = prev_costs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, prev_cost)| {
+                        let was_voiced = i < prev.len();
+                        let transition = match (was_voiced, voiced) {
+                            (true, true) => {
+                                let jump = (frame[j].lag / prev[i].lag).ln().abs();
+                                self.frequency_weight
+                                    * jump.min(self.doubling_cost + (jump - ln2).abs())
+                            }
+                            (false, false) => 0.,
+                            (false, true) => self.transition_cost + self.amplitude_weight / ratio,
+                            (true, false) => self.transition_cost + self.amplitude_weight * ratio,
+                        };
+                        (prev_cost + transition, i)
+                    })
+                    .fold((f64::MAX, 0), |a, b| if b.0 < a.0 { b } else { a });
+                total.push(cost + best);
+                from.push(index);
+            }
+            costs.push(total);
+            back.push(from);
+        }
+
+        let mut path = vec![None; candidates.len()];
+        let last = match costs.last() {
+            Some(last) => last,
+            None => return path,
+        };
+        let mut state = last
+            .iter()
+            .enumerate()
+            .fold(
+                (0, f64::MAX),
+                |a, (i, c)| if *c < a.1 { (i, *c) } else { a },
+            )
+            .0;
+        for k in (0..candidates.len()).rev() {
+            if state < candidates[k].len() {
+                path[k] = Some(state);
+            }
+            state = back[k][state];
+        }
+        path
+    }
+}
+
+/// `len` samples of `signal` from `start`, which may be negative. Samples past either end are
+/// zero.
+fn excerpt(signal: &[f64], start: isize, len: usize) -> Vec<f64> {
+    (start..start + len as isize)
+        .map(|i| {
+            if i < 0 || i as usize >= signal.len() {
+                0.
+            } else {
+                signal[i as usize]
+            }
+        })
+        .collect()
+}
+
+fn remove_mean(frame: &mut [f64]) {
+    let mean = frame.iter().sum::<f64>() / frame.len().max(1) as f64;
+    for s in frame.iter_mut() {
+        *s -= mean;
+    }
+}
+
+/// The `max` highest local maxima of `coeffs` between `min_lag` and `max_lag` that reach
+/// `threshold` times the highest one, with their lags refined by parabolic interpolation.
+fn peaks(
+    coeffs: &[f64],
+    min_lag: usize,
+    max_lag: usize,
+    threshold: f64,
+    max: usize,
+) -> Vec<Candidate> {
+    let mut peaks: Vec<Candidate> = (min_lag.max(1)..=max_lag)
+        .filter(|&l| coeffs[l] > 0. && coeffs[l] >= coeffs[l - 1] && coeffs[l] >= coeffs[l + 1])
+        .map(|l| {
+            let (prev, peak, next) = (coeffs[l - 1], coeffs[l], coeffs[l + 1]);
+            let curvature = prev - 2. * peak + next;
+            let shift = if curvature < 0. {
+                (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
+            } else {
+                0.
+            };
+            Candidate {
+                lag: l as f64 + shift,
+                strength: peak,
+            }
+        })
+        .collect();
+    let best = peaks.iter().fold(0., |acc: f64, c| acc.max(c.strength));
+    peaks.retain(|c| c.strength >= threshold * best);
+    peaks.sort_by(|a, b| {
+        b.strength
+            .partial_cmp(&a.strength)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    peaks.truncate(max);
+    peaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_rapt() {
+        let sample_rate = 16000.;
+        let mut state = 7u32;
+        // 250 ms of silence, 500 ms of a buzz gliding from 120 to 180 Hz, then 250 ms of noise
+        let mut phase = 0.;
+        let signal: Vec<f64> = (0..16000)
+            .map(|n| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (state as f64 / u32::MAX as f64 - 0.5) * 0.01;
+                if (4000..12000).contains(&n) {
+                    let f0 = 120. + 60. * (n - 4000) as f64 / 8000.;
+                    phase += 2. * PI * f0 / sample_rate;
+                    (1..6)
+                        .map(|h| (phase * h as f64).sin() / h as f64)
+                        .sum::<f64>()
+                        * 0.3
+                        + noise
+                } else if n >= 12000 {
+                    noise * 20.
+                } else {
+                    noise * 0.01
+                }
+            })
+            .collect();
+
+        let frames = Rapt::new(sample_rate).track(&signal).unwrap();
+        assert_eq!(frames.len(), 100);
+        for (k, frame) in frames.iter().enumerate() {
+            println!("{}: {:?}", k, frame);
+        }
+        for (k, frame) in frames.iter().enumerate().take(73).skip(27) {
+            let expected = 120. + 60. * (k as f64 * 160. + 80. - 4000.) / 8000.;
+            assert!(frame.voiced);
+            assert!((frame.f0 - expected).abs() < 3.);
+        }
+        assert!(frames[..20].iter().all(|f| !f.voiced));
+        assert!(frames[80..].iter().filter(|f| f.voiced).count() < 5);
+    }
+
+    #[test]
+    fn test_rapt_cancel() {
+        let signal = [0f64; 1600];
+        let mut calls = 0;
+        let result = Rapt::new(16000.).track_with_progress(&signal, |p| {
+            calls += 1;
+            if p.done == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(result, Err(VoxBoxError::Cancelled)));
+        assert_eq!(calls, 3);
+
+        let mut rapt = Rapt::new(16000.);
+        rapt.min_pitch = 0.;
+        assert!(rapt.track(&signal).is_err());
+    }
+}
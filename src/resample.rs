@@ -0,0 +1,425 @@
+//! Band-limited sample rate conversion by windowed-sinc interpolation.
+//!
+//! Each output sample is a sum of input samples weighted by a Kaiser-windowed sinc centered on
+//! its time, read from a table of the kernel at fine fractional offsets. When downsampling, the
+//! kernel is stretched so that it also low-passes below the new Nyquist frequency, which linear
+//! interpolation doesn't do: everything above it would alias into the spectrum.
+//!
+//! `decimate` and `interpolate` change the rate by whole factors, with FIR low-pass filters.
+
+use num::{Float, FromPrimitive};
+use sample::{FromSample, Sample, ToSample};
+use std::f64::consts::PI;
+use std::marker::PhantomData;
+
+use crate::error::*;
+use crate::fir::{convolve, kaiser_fir, FirResponse};
+use crate::processor::Processor;
+use crate::units::{Db, Hz};
+use crate::validate;
+use crate::waves::{bessel_i0, to_f64_samples};
+
+/// Kernel table entries per zero crossing, between which the kernel is interpolated linearly.
+const TABLE_DENSITY: usize = 128;
+
+/// Trade between speed and how cleanly a `Resampler` removes what it can't represent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResampleQuality {
+    /// 8 zero crossings either side, passband to 80% of Nyquist, stopband about 60 dB down
+    Fast,
+    /// 16 zero crossings either side, passband to 85% of Nyquist, stopband about 85 dB down
+    Balanced,
+    /// 32 zero crossings either side, passband to 90% of Nyquist, stopband about 110 dB down
+    Best,
+}
+
+impl ResampleQuality {
+    /// Zero crossings either side, cutoff as a fraction of Nyquist, and Kaiser beta.
+    fn parameters(self) -> (usize, f64, f64) {
+        match self {
+            ResampleQuality::Fast => (8, 0.8, 5.65),
+            ResampleQuality::Balanced => (16, 0.85, 8.41),
+            ResampleQuality::Best => (32, 0.9, 11.16),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resampler {
+    /// Output samples per input sample
+    ratio: f64,
+    /// Cutoff as a fraction of the input Nyquist frequency
+    cutoff: f64,
+    zeros: usize,
+    /// The windowed sinc at `k / TABLE_DENSITY` zero crossings from its center
+    table: Vec<f64>,
+}
+
+impl Resampler {
+    pub fn new<A: Into<Hz>, B: Into<Hz>>(
+        from_hz: A,
+        to_hz: B,
+        quality: ResampleQuality,
+    ) -> VoxBoxResult<Self> {
+        let from_hz = from_hz.into().0;
+        let to_hz = to_hz.into().0;
+        validate::positive(from_hz, "Sample rates must be positive")?;
+        validate::positive(to_hz, "Sample rates must be positive")?;
+        let (zeros, rolloff, beta) = quality.parameters();
+        let ratio = to_hz / from_hz;
+        let len = zeros * TABLE_DENSITY;
+        let table = (0..=len)
+            .map(|k| {
+                let u = k as f64 / TABLE_DENSITY as f64;
+                let sinc = if k == 0 {
+                    1.
+                } else {
+                    (PI * u).sin() / (PI * u)
+                };
+                let x = u / zeros as f64;
+                sinc * bessel_i0(beta * (1. - x * x).max(0.).sqrt()) / bessel_i0(beta)
+            })
+            .collect();
+        Ok(Resampler {
+            ratio,
+            cutoff: rolloff * ratio.min(1.),
+            zeros,
+            table,
+        })
+    }
+
+    /// Output samples per input sample.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Number of samples resampling `input_len` samples gives: one for every output time from
+    /// the first input sample up to the last.
+    pub fn output_len(&self, input_len: usize) -> usize {
+        (input_len as f64 * self.ratio).ceil() as usize
+    }
+
+    /// Input samples either side of an output time that weigh in on it.
+    pub fn half_width(&self) -> f64 {
+        self.zeros as f64 / self.cutoff
+    }
+
+    /// Weight of an input sample `x` samples away from an output time.
+    pub(crate) fn kernel(&self, x: f64) -> f64 {
+        let position = x.abs() * self.cutoff * TABLE_DENSITY as f64;
+        let k = position.floor() as usize;
+        if k + 1 >= self.table.len() {
+            return 0.;
+        }
+        let frac = position - k as f64;
+        self.cutoff * (self.table[k] + (self.table[k + 1] - self.table[k]) * frac)
+    }
+
+    /// Interpolates `signal`, taken as zero past either end, at `t` input samples.
+    pub(crate) fn at<S>(&self, signal: &[S], t: f64) -> f64
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let half_width = self.half_width();
+        let low = (t - half_width).ceil().max(0.) as usize;
+        let high = ((t + half_width).floor() as usize).min(signal.len().saturating_sub(1));
+        (low..=high).fold(0., |acc, n| {
+            acc + signal[n].to_sample::<f64>() * self.kernel(t - n as f64)
+        })
+    }
+
+    /// Resamples a whole signal, giving `output_len(signal.len())` samples.
+    pub fn resample<S>(&self, signal: &[S]) -> Vec<f64>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        (0..self.output_len(signal.len()))
+            .map(|m| self.at(signal, m as f64 / self.ratio))
+            .collect()
+    }
+
+    /// Resamples a whole signal into `out`, which must hold at least `output_len(signal.len())`
+    /// samples. Returns the number of samples written.
+    pub fn resample_into<S>(&self, signal: &[S], out: &mut [S]) -> VoxBoxResult<usize>
+    where
+        S: Sample + ToSample<f64> + FromSample<f64>,
+    {
+        let len = self.output_len(signal.len());
+        validate::workspace(out, len)?;
+        for (m, o) in out[..len].iter_mut().enumerate() {
+            *o = self.at(signal, m as f64 / self.ratio).to_sample::<S>();
+        }
+        Ok(len)
+    }
+}
+
+/// Stopband attenuation and transition width, as a fraction of the lower Nyquist frequency, of
+/// the low-pass filters of `decimate` and `interpolate`.
+const INTEGER_FACTOR_ATTENUATION: Db = Db(80.);
+const INTEGER_FACTOR_TRANSITION: f64 = 0.1;
+
+/// Low-passes below the Nyquist frequency of a rate `factor` times lower, and filters `signal`
+/// with it, compensating the filter's delay.
+fn integer_factor_low_pass(signal: &[f64], factor: usize) -> VoxBoxResult<Vec<f64>> {
+    let nyquist = 0.5 / factor as f64;
+    let taps = kaiser_fir(
+        FirResponse::LowPass(nyquist * (1. - INTEGER_FACTOR_TRANSITION / 2.)),
+        INTEGER_FACTOR_ATTENUATION,
+        nyquist * INTEGER_FACTOR_TRANSITION,
+        1.,
+    )?;
+    let delay = taps.len() / 2;
+    let mut padded = signal.to_vec();
+    padded.resize(signal.len() + delay, 0.);
+    Ok(convolve(&padded, &taps).split_off(delay))
+}
+
+/// Keeps every `factor`th sample of `signal`, after low-passing below the new Nyquist frequency
+/// so nothing above it aliases, as for downsampling to twice the formant ceiling before LPC.
+/// Gives `ceil(signal.len() / factor)` samples.
+pub fn decimate<S>(signal: &[S], factor: usize) -> VoxBoxResult<Vec<f64>>
+where
+    S: Sample + ToSample<f64>,
+{
+    if factor == 0 {
+        return Err(VoxBoxError::Config("Decimation factor must be nonzero"));
+    }
+    let signal = to_f64_samples(signal);
+    if factor == 1 {
+        return Ok(signal);
+    }
+    let filtered = integer_factor_low_pass(&signal, factor)?;
+    Ok(filtered.into_iter().step_by(factor).collect())
+}
+
+/// Raises the sample rate by `factor`, putting `factor - 1` zeros after each sample and
+/// low-passing away the images of the spectrum that they add. Gives `signal.len() * factor`
+/// samples.
+pub fn interpolate<S>(signal: &[S], factor: usize) -> VoxBoxResult<Vec<f64>>
+where
+    S: Sample + ToSample<f64>,
+{
+    if factor == 0 {
+        return Err(VoxBoxError::Config("Interpolation factor must be nonzero"));
+    }
+    let mut stuffed = vec![0.; signal.len() * factor];
+    for (s, x) in stuffed.iter_mut().step_by(factor).zip(signal.iter()) {
+        *s = x.to_sample::<f64>() * factor as f64;
+    }
+    if factor == 1 {
+        return Ok(stuffed);
+    }
+    integer_factor_low_pass(&stuffed, factor)
+}
+
+/// A `Resampler` run on a stream, as a `Processor` stage. Blocks can be any length, and the
+/// output is the same as resampling the whole stream at once.
+///
+/// Each output sample waits until the input `half_width` samples past its time has arrived, so
+/// the end of the stream comes out of `finish`.
+#[derive(Clone, Debug)]
+pub struct StreamResampler<T> {
+    resampler: Resampler,
+    /// Input samples that later outputs still need
+    history: Vec<f64>,
+    /// Index in the stream of the first sample of `history`
+    start: usize,
+    /// Index of the next output sample, whose time is `next / ratio` input samples
+    next: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: Float + FromPrimitive> StreamResampler<T> {
+    pub fn new<A: Into<Hz>, B: Into<Hz>>(
+        from_hz: A,
+        to_hz: B,
+        quality: ResampleQuality,
+    ) -> VoxBoxResult<Self> {
+        Ok(StreamResampler {
+            resampler: Resampler::new(from_hz, to_hz, quality)?,
+            history: Vec::new(),
+            start: 0,
+            next: 0,
+            marker: PhantomData,
+        })
+    }
+
+    /// Writes the outputs still waiting on input past the end of the stream, taking that input
+    /// as zero, and starts over. `output` must hold at least `max_output(latency())` samples.
+    pub fn finish(&mut self, output: &mut [T]) -> VoxBoxResult<usize> {
+        let end = self.start + self.history.len();
+        let written = self.emit(output, |t| t < end as f64)?;
+        self.reset();
+        Ok(written)
+    }
+
+    /// Writes outputs as long as `ready` accepts their time, and forgets input no output needs.
+    fn emit<F: Fn(f64) -> bool>(&mut self, output: &mut [T], ready: F) -> VoxBoxResult<usize> {
+        let half_width = self.resampler.half_width();
+        let mut written = 0;
+        loop {
+            let t = self.next as f64 / self.resampler.ratio;
+            if !ready(t) {
+                break;
+            }
+            if written == output.len() {
+                return Err(VoxBoxError::Workspace);
+            }
+            let y = self.resampler.at(&self.history, t - self.start as f64);
+            output[written] = T::from_f64(y).unwrap();
+            written += 1;
+            self.next += 1;
+        }
+        let t = self.next as f64 / self.resampler.ratio;
+        let needed = ((t - half_width).ceil().max(0.) as usize).max(self.start);
+        let stale = (needed - self.start).min(self.history.len());
+        self.history.drain(..stale);
+        self.start += stale;
+        Ok(written)
+    }
+}
+
+impl<T: Float + FromPrimitive> Processor<T> for StreamResampler<T> {
+    fn process(&mut self, input: &[T], output: &mut [T]) -> VoxBoxResult<usize> {
+        validate::workspace(output, self.max_output(input.len()))?;
+        self.history
+            .extend(input.iter().map(|x| x.to_f64().unwrap()));
+        let last = (self.start + self.history.len()) as f64 - 1.;
+        let half_width = self.resampler.half_width();
+        self.emit(output, |t| t + half_width <= last)
+    }
+
+    fn max_output(&self, input_len: usize) -> usize {
+        self.resampler.output_len(input_len) + 1
+    }
+
+    fn latency(&self) -> usize {
+        self.resampler.half_width().ceil() as usize
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+        self.start = 0;
+        self.next = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f64, sample_rate: f64, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|n| (2. * PI * frequency * n as f64 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_resampler() {
+        // Down from 48 kHz to 16 kHz: a 1 kHz tone stays, and a 12 kHz one doesn't alias
+        let resampler = Resampler::new(48000., 16000., ResampleQuality::Balanced).unwrap();
+        let down = resampler.resample(&tone(1000., 48000., 4800));
+        assert_eq!(down.len(), 1600);
+        let expected = tone(1000., 16000., 1600);
+        for (s, e) in down[100..1500].iter().zip(expected[100..1500].iter()) {
+            assert!((s - e).abs() < 1e-3);
+        }
+        let aliased = resampler.resample(&tone(12000., 48000., 4800));
+        let peak = aliased[100..1500]
+            .iter()
+            .fold(0., |m: f64, s| m.max(s.abs()));
+        println!("12 kHz at 16 kHz: {}", peak);
+        assert!(peak < 1e-3);
+
+        // Up from 16 kHz to 44.1 kHz, as i16 samples
+        let resampler = Resampler::new(16000., 44100., ResampleQuality::Best).unwrap();
+        let quiet: Vec<i16> = tone(3000., 16000., 1600)
+            .iter()
+            .map(|s| (s * 0.5).to_sample::<i16>())
+            .collect();
+        let mut out = vec![0i16; resampler.output_len(quiet.len())];
+        assert_eq!(resampler.resample_into(&quiet, &mut out).unwrap(), 4410);
+        let expected = tone(3000., 44100., 4410);
+        for (s, e) in out[200..4200].iter().zip(expected[200..4200].iter()) {
+            assert!((s.to_sample::<f64>() - 0.5 * e).abs() < 1e-3);
+        }
+        assert!(resampler.resample_into(&quiet, &mut out[..100]).is_err());
+
+        // Even the fastest keeps aliasing well down
+        let fast = Resampler::new(48000., 16000., ResampleQuality::Fast).unwrap();
+        let down = fast.resample(&tone(12000., 48000., 4800));
+        assert!(down[100..1500].iter().all(|s| s.abs() < 1e-2));
+        assert!(Resampler::new(0., 16000., ResampleQuality::Fast).is_err());
+    }
+
+    #[test]
+    fn test_decimate_interpolate() {
+        // Down by 3 from 48 kHz: 1 kHz passes in time, and 10 kHz, above the new Nyquist, goes
+        let mixed: Vec<f64> = tone(1000., 48000., 4800)
+            .iter()
+            .zip(tone(10000., 48000., 4800).iter())
+            .map(|(a, b)| a + b)
+            .collect();
+        let down = decimate(&mixed, 3).unwrap();
+        assert_eq!(down.len(), 1600);
+        let expected = tone(1000., 16000., 1600);
+        let error = down[100..1500]
+            .iter()
+            .zip(expected[100..1500].iter())
+            .fold(0., |m: f64, (d, e)| m.max((d - e).abs()));
+        println!("decimation error: {}", error);
+        assert!(error < 1e-3);
+        assert_eq!(decimate(&mixed[..10], 3).unwrap().len(), 4);
+
+        // Up by 4 and back down again
+        let up = interpolate(&expected, 4).unwrap();
+        assert_eq!(up.len(), 6400);
+        let fine = tone(1000., 64000., 6400);
+        for (u, e) in up[400..6000].iter().zip(fine[400..6000].iter()) {
+            assert!((u - e).abs() < 1e-3);
+        }
+        let back = decimate(&up, 4).unwrap();
+        for (b, e) in back[100..1500].iter().zip(expected[100..1500].iter()) {
+            assert!((b - e).abs() < 1e-3);
+        }
+        assert_eq!(interpolate(&expected, 1).unwrap(), expected);
+        assert!(decimate(&expected, 0).is_err());
+    }
+
+    #[test]
+    fn test_stream_resampler() {
+        // Uneven blocks give what resampling the whole signal does, at either ratio
+        let signal: Vec<f64> = (0..3000)
+            .map(|n| (n as f64 * 0.05).sin() + 0.3 * (n as f64 * 0.71).cos())
+            .collect();
+        for (from, to) in [(44100., 16000.), (16000., 22050.)].iter() {
+            let whole = Resampler::new(*from, *to, ResampleQuality::Balanced)
+                .unwrap()
+                .resample(&signal);
+            let mut stream = StreamResampler::new(*from, *to, ResampleQuality::Balanced).unwrap();
+            let mut streamed = Vec::new();
+            let mut buf = vec![0.; 2048];
+            let mut start = 0;
+            for len in [1, 17, 500, 3, 1000].iter().cycle() {
+                if start == signal.len() {
+                    break;
+                }
+                let end = (start + len).min(signal.len());
+                let n = stream.process(&signal[start..end], &mut buf).unwrap();
+                streamed.extend_from_slice(&buf[..n]);
+                start = end;
+            }
+            println!("{} of {} before finishing", streamed.len(), whole.len());
+            let n = stream.finish(&mut buf).unwrap();
+            streamed.extend_from_slice(&buf[..n]);
+            assert_eq!(streamed.len(), whole.len());
+            for (a, b) in streamed.iter().zip(whole.iter()) {
+                assert!((a - b).abs() < 1e-12);
+            }
+        }
+
+        let mut stream = StreamResampler::<f32>::new(16000., 8000., ResampleQuality::Fast).unwrap();
+        let mut small = [0f32; 2];
+        assert!(stream.process(&[0.; 100], &mut small).is_err());
+    }
+}
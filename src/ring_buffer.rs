@@ -0,0 +1,221 @@
+//! A single-producer, single-consumer ring buffer of samples for feeding analysis from an
+//! audio callback.
+//!
+//! The audio thread pushes whatever it has into a `Producer`, which never blocks or allocates,
+//! and an analysis thread reads frames from the `Consumer` once enough samples have arrived.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::error::*;
+
+struct Shared<T> {
+    /// A power of two at least `capacity`, so that the counters, which wrap at `usize::MAX`,
+    /// keep mapping to consecutive slots
+    slots: Box<[UnsafeCell<T>]>,
+    capacity: usize,
+    /// Total samples ever read, written only by the consumer
+    read: AtomicUsize,
+    /// Total samples ever written, written only by the producer
+    written: AtomicUsize,
+}
+
+// Each slot is only touched by one side at a time: the producer writes slots between `written`
+// and `read + capacity`, the consumer reads slots between `read` and `written`, and each side
+// publishes its counter only after it is done with the slots it passes over.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn slot(&self, n: usize) -> *mut T {
+        self.slots[n & (self.slots.len() - 1)].get()
+    }
+}
+
+/// Creates a ring buffer holding up to `capacity` samples, split into its two ends.
+pub fn ring_buffer<T: Copy + Default + Send>(
+    capacity: usize,
+) -> VoxBoxResult<(Producer<T>, Consumer<T>)> {
+    if capacity == 0 {
+        return Err(VoxBoxError::Config("Ring buffer capacity must be nonzero"));
+    }
+    let n_slots = capacity
+        .checked_next_power_of_two()
+        .ok_or(VoxBoxError::Config("Ring buffer capacity is too large"))?;
+    let shared = Arc::new(Shared {
+        slots: (0..n_slots)
+            .map(|_| UnsafeCell::new(T::default()))
+            .collect(),
+        capacity,
+        read: AtomicUsize::new(0),
+        written: AtomicUsize::new(0),
+    });
+    Ok((
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    ))
+}
+
+/// The writing end of a `ring_buffer`.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Copy> Producer<T> {
+    /// Number of samples that can be pushed before the buffer is full.
+    pub fn free(&self) -> usize {
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let read = self.shared.read.load(Ordering::Acquire);
+        self.shared.capacity() - written.wrapping_sub(read)
+    }
+
+    /// Appends as many of `samples` as fit, and returns how many that was. The rest are
+    /// dropped, so a consumer that falls behind loses the newest samples rather than blocking
+    /// the audio thread.
+    pub fn push(&mut self, samples: &[T]) -> usize {
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let n = samples.len().min(self.free());
+        for (i, s) in samples[..n].iter().enumerate() {
+            unsafe { *self.shared.slot(written.wrapping_add(i)) = *s };
+        }
+        self.shared
+            .written
+            .store(written.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
+
+/// The reading end of a `ring_buffer`.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Copy> Consumer<T> {
+    /// Number of samples waiting to be read.
+    pub fn available(&self) -> usize {
+        let read = self.shared.read.load(Ordering::Relaxed);
+        self.shared
+            .written
+            .load(Ordering::Acquire)
+            .wrapping_sub(read)
+    }
+
+    /// Fills `frame` with the oldest samples and then drops the first `hop` of them, if at
+    /// least `frame.len()` samples and `hop` samples are waiting. Otherwise leaves everything
+    /// as it is and returns false. A hop shorter than the frame gives overlapping frames.
+    ///
+    /// Fails if the frame or the hop is longer than the buffer, since that many samples can
+    /// never be waiting.
+    pub fn read_frame(&mut self, frame: &mut [T], hop: usize) -> VoxBoxResult<bool> {
+        if frame.len().max(hop) > self.shared.capacity() {
+            return Err(VoxBoxError::Config(
+                "Frame and hop must fit in the ring buffer",
+            ));
+        }
+        if self.available() < frame.len().max(hop) {
+            return Ok(false);
+        }
+        let read = self.shared.read.load(Ordering::Relaxed);
+        for (i, s) in frame.iter_mut().enumerate() {
+            *s = unsafe { *self.shared.slot(read.wrapping_add(i)) };
+        }
+        self.shared
+            .read
+            .store(read.wrapping_add(hop), Ordering::Release);
+        Ok(true)
+    }
+
+    /// Drops up to `n` of the oldest samples, and returns how many were dropped.
+    pub fn skip(&mut self, n: usize) -> usize {
+        let n = n.min(self.available());
+        let read = self.shared.read.load(Ordering::Relaxed);
+        self.shared
+            .read
+            .store(read.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_ring_buffer() {
+        let (mut producer, mut consumer) = ring_buffer::<f32>(8).unwrap();
+        assert_eq!(producer.push(&[1., 2., 3., 4., 5.]), 5);
+        assert_eq!(producer.push(&[6., 7., 8., 9., 10.]), 3);
+        assert_eq!(producer.free(), 0);
+
+        // Overlapping frames of 4 every 2 samples
+        let mut frame = [0f32; 4];
+        assert!(consumer.read_frame(&mut frame, 2).unwrap());
+        assert_eq!(frame, [1., 2., 3., 4.]);
+        assert!(consumer.read_frame(&mut frame, 2).unwrap());
+        assert_eq!(frame, [3., 4., 5., 6.]);
+        assert_eq!(producer.push(&[9., 10., 11.]), 3);
+        assert!(consumer.read_frame(&mut frame, 2).unwrap());
+        assert_eq!(frame, [5., 6., 7., 8.]);
+        assert!(consumer.read_frame(&mut frame, 2).unwrap());
+        assert_eq!(frame, [7., 8., 9., 10.]);
+        assert!(!consumer.read_frame(&mut frame, 2).unwrap());
+        assert_eq!(consumer.skip(10), 3);
+        assert_eq!(consumer.available(), 0);
+        assert!(ring_buffer::<f32>(0).is_err());
+        assert!(ring_buffer::<f32>(usize::MAX).is_err());
+
+        // Frames or hops longer than the buffer could never be read
+        assert!(consumer.read_frame(&mut [0f32; 9], 2).is_err());
+        assert!(consumer.read_frame(&mut frame, 9).is_err());
+    }
+
+    #[test]
+    fn test_ring_buffer_wraparound() {
+        // A capacity that isn't a power of two, with the counters about to wrap
+        let (mut producer, mut consumer) = ring_buffer::<u32>(6).unwrap();
+        let start = usize::MAX - 3;
+        producer.shared.written.store(start, Ordering::Relaxed);
+        producer.shared.read.store(start, Ordering::Relaxed);
+        let mut frame = [0u32; 6];
+        for round in 0..4 {
+            let block: Vec<u32> = (round * 6..round * 6 + 6).collect();
+            assert_eq!(producer.push(&block), 6);
+            assert_eq!(producer.free(), 0);
+            assert!(consumer.read_frame(&mut frame, 6).unwrap());
+            assert_eq!(&frame[..], &block[..]);
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_threads() {
+        let (mut producer, mut consumer) = ring_buffer::<u32>(64).unwrap();
+        let audio = thread::spawn(move || {
+            let mut next = 0;
+            while next < 10_000 {
+                let block: Vec<u32> = (next..(next + 7).min(10_000)).collect();
+                next += producer.push(&block) as u32;
+                thread::yield_now();
+            }
+        });
+        let mut frame = [0u32; 16];
+        let mut expected = 0;
+        while expected + 16 <= 10_000 {
+            if consumer.read_frame(&mut frame, 16).unwrap() {
+                for (i, s) in frame.iter().enumerate() {
+                    assert_eq!(*s, expected + i as u32);
+                }
+                expected += 16;
+            } else {
+                thread::yield_now();
+            }
+        }
+        audio.join().unwrap();
+    }
+}
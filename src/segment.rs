@@ -0,0 +1,383 @@
+use sample::{Sample, ToSample};
+use std::ops::Range;
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::units::{Hz, Seconds};
+use crate::vad::SohnVad;
+use crate::waves::WindowType;
+
+/// Autocorrelation of `frame` for lags `0..lags`.
+fn autocorrelation(frame: &[f64], lags: usize) -> Vec<f64> {
+    (0..lags)
+        .map(|lag| {
+            frame
+                .iter()
+                .zip(frame[lag.min(frame.len())..].iter())
+                .map(|(a, b)| a * b)
+                .sum()
+        })
+        .collect()
+}
+
+/// Highest autocorrelation peak between `min_lag` and `max_lag`, normalized by the
+/// autocorrelation of the window as in Boersma (1993). Returns the lag of the peak, refined by
+/// parabolic interpolation, and its strength, which ranges from 0 to about 1.
+fn periodicity(windowed: &[f64], window_lag: &[f64], min_lag: usize, max_lag: usize) -> (f64, f64) {
+    let lag = autocorrelation(windowed, max_lag + 2);
+    if lag[0] <= 0. || min_lag > max_lag {
+        return (0., 0.);
+    }
+    let normalized = |l: usize| lag[l] / lag[0] / (window_lag[l] / window_lag[0]);
+    let best = (min_lag..=max_lag).fold(min_lag, |best, l| {
+        if normalized(l) > normalized(best) {
+            l
+        } else {
+            best
+        }
+    });
+    let strength = normalized(best);
+    if window_lag[best + 1] <= 0. {
+        return (best as f64, strength);
+    }
+    let (prev, next) = (normalized(best - 1), normalized(best + 1));
+    let curvature = prev - 2. * strength + next;
+    let shift = if curvature < 0. {
+        (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.
+    };
+    (best as f64 + shift, strength)
+}
+
+/// `len` samples of `signal` centered on the middle of hop `k`. Samples past either end are
+/// zero.
+pub(crate) fn centered_frame<S>(signal: &[S], k: usize, hop: usize, len: usize) -> Vec<f64>
+where
+    S: Sample + ToSample<f64>,
+{
+    let start = (k * hop + hop / 2) as isize - (len / 2) as isize;
+    (0..len as isize)
+        .map(|i| {
+            let idx = start + i;
+            if idx < 0 || idx as usize >= signal.len() {
+                0.
+            } else {
+                signal[idx as usize].to_sample::<f64>()
+            }
+        })
+        .collect()
+}
+
+/// The window and lag range shared by every frame of one pass over a signal.
+pub(crate) struct FrameSetup {
+    window: Vec<f64>,
+    window_lag: Vec<f64>,
+    min_lag: usize,
+    max_lag: usize,
+}
+
+/// What a stretch of signal contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentLabel {
+    Silence,
+    Unvoiced,
+    Voiced,
+}
+
+/// A labeled stretch of signal, with start and end times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub label: SegmentLabel,
+    pub start: Seconds,
+    pub end: Seconds,
+}
+
+impl Segment {
+    pub fn duration(&self) -> Seconds {
+        self.end - self.start
+    }
+
+    /// The sample indices covered by this segment, for slicing the analyzed signal.
+    pub fn samples<R: Into<Hz>>(&self, sample_rate: R) -> Range<usize> {
+        let sample_rate = sample_rate.into();
+        self.start.to_samples(sample_rate)..self.end.to_samples(sample_rate)
+    }
+}
+
+/// Splits a signal into silence, unvoiced and voiced segments. Speech is found with a
+/// `SohnVad`, and speech frames whose normalized autocorrelation peaks above
+/// `voicing_threshold` within the pitch range are voiced.
+#[derive(Clone, Debug)]
+pub struct Segmenter {
+    pub sample_rate: f64,
+    /// Distance between decisions, in samples
+    pub hop: usize,
+    /// Length of the analysis frame, in samples. Should hold three periods of `min_pitch`.
+    pub frame_len: usize,
+    pub window: WindowType,
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+    pub voicing_threshold: f64,
+    pub vad: SohnVad,
+}
+
+impl Segmenter {
+    /// Default settings: 10 ms hops, pitch between 75 and 600 Hz.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        let min_pitch = 75.;
+        let frame_len = (3. * sample_rate / min_pitch).ceil() as usize;
+        Segmenter {
+            sample_rate,
+            hop: (sample_rate * 0.01).round() as usize,
+            frame_len,
+            window: WindowType::Hanning,
+            min_pitch,
+            max_pitch: 600.,
+            voicing_threshold: 0.45,
+            vad: SohnVad::new(frame_len),
+        }
+    }
+
+    /// Takes the framing from `config`. The frame should still hold three periods of
+    /// `min_pitch` for the voicing decision to be reliable.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Segmenter {
+            hop: config.hop,
+            frame_len: config.frame_len,
+            window: config.window,
+            vad: SohnVad::from_config(config),
+            ..Segmenter::new(config.sample_rate)
+        }
+    }
+
+    pub(crate) fn setup(&self) -> FrameSetup {
+        let len = self.frame_len;
+        let window = self.window.window(len);
+        // Two extra lags for interpolating around a peak at `max_lag`
+        let window_lag = autocorrelation(&window, len + 2);
+        FrameSetup {
+            window,
+            window_lag,
+            min_lag: (self.sample_rate / self.max_pitch).floor().max(1.) as usize,
+            max_lag: ((self.sample_rate / self.min_pitch).ceil() as usize).min(len / 2),
+        }
+    }
+
+    /// Labels frame `k` of the signal. For voiced frames, also returns the pitch period in
+    /// samples; otherwise the period is zero.
+    pub(crate) fn label_frame<S>(
+        &mut self,
+        signal: &[S],
+        k: usize,
+        setup: &FrameSetup,
+    ) -> (SegmentLabel, f64)
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let frame = centered_frame(signal, k, self.hop.max(1), self.frame_len);
+        if !self.vad.process(&frame[..]).speech {
+            return (SegmentLabel::Silence, 0.);
+        }
+        self.voicing(&frame, setup)
+    }
+
+    /// Labels frame `k` of the signal as voiced or unvoiced, taking it to be speech without
+    /// asking the detector, as when speech was found beforehand.
+    pub(crate) fn label_speech<S>(
+        &self,
+        signal: &[S],
+        k: usize,
+        setup: &FrameSetup,
+    ) -> (SegmentLabel, f64)
+    where
+        S: Sample + ToSample<f64>,
+    {
+        let frame = centered_frame(signal, k, self.hop.max(1), self.frame_len);
+        self.voicing(&frame, setup)
+    }
+
+    fn voicing(&self, frame: &[f64], setup: &FrameSetup) -> (SegmentLabel, f64) {
+        let windowed: Vec<f64> = frame
+            .iter()
+            .zip(setup.window.iter())
+            .map(|(s, w)| s * w)
+            .collect();
+        let (period, strength) =
+            periodicity(&windowed, &setup.window_lag, setup.min_lag, setup.max_lag);
+        if strength > self.voicing_threshold {
+            (SegmentLabel::Voiced, period)
+        } else {
+            (SegmentLabel::Unvoiced, 0.)
+        }
+    }
+
+    /// Labels every hop of the signal, centering an analysis frame on each one.
+    pub fn frame_labels<S>(&mut self, signal: &[S]) -> Vec<SegmentLabel>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        // Only the callback can cancel
+        self.frame_labels_with_progress(signal, progress::ignore)
+            .unwrap_or_default()
+    }
+
+    /// Like `frame_labels`, calling `progress` after every frame. Returns
+    /// `VoxBoxError::Cancelled` if the callback breaks.
+    pub fn frame_labels_with_progress<S, F>(
+        &mut self,
+        signal: &[S],
+        mut progress: F,
+    ) -> VoxBoxResult<Vec<SegmentLabel>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        let setup = self.setup();
+        let n_frames = signal.len().div_ceil(self.hop.max(1));
+        let mut labels = Vec::with_capacity(n_frames);
+        for k in 0..n_frames {
+            labels.push(self.label_frame(signal, k, &setup).0);
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+        Ok(labels)
+    }
+
+    /// Segments the signal, merging runs of frames with the same label.
+    pub fn segment<S: Sample + ToSample<f64>>(&mut self, signal: &[S]) -> Vec<Segment> {
+        let labels = self.frame_labels(signal);
+        self.merge(labels, signal.len())
+    }
+
+    /// Like `segment`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled`
+    /// if the callback breaks.
+    pub fn segment_with_progress<S, F>(
+        &mut self,
+        signal: &[S],
+        progress: F,
+    ) -> VoxBoxResult<Vec<Segment>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        let labels = self.frame_labels_with_progress(signal, progress)?;
+        Ok(self.merge(labels, signal.len()))
+    }
+
+    /// Merges runs of equal frame labels into segments of a signal `len` samples long.
+    fn merge(&self, labels: Vec<SegmentLabel>, len: usize) -> Vec<Segment> {
+        let hop = self.hop.max(1) as f64 / self.sample_rate;
+        let end = len as f64 / self.sample_rate;
+
+        let mut segments: Vec<Segment> = Vec::new();
+        for (k, label) in labels.into_iter().enumerate() {
+            let start = k as f64 * hop;
+            let stop = Seconds((start + hop).min(end));
+            match segments.last_mut() {
+                Some(last) if last.label == label => last.end = stop,
+                _ => segments.push(Segment {
+                    label,
+                    start: Seconds(start),
+                    end: stop,
+                }),
+            }
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_segmenter() {
+        let sample_rate = 8000.;
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let quiet = Normal::new(0., 0.001);
+        let loud = Normal::new(0., 0.3);
+        // 0.5 s of background, 0.3 s of a 150 Hz buzz, 0.3 s of hiss, 0.4 s of background
+        let signal: Vec<f64> = (0..12000)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                let background = quiet.ind_sample(&mut rng);
+                if (0.5..0.8).contains(&t) {
+                    background
+                        + (1..6)
+                            .map(|h| (2. * PI * 150. * h as f64 * t).sin() * 0.3 / h as f64)
+                            .sum::<f64>()
+                } else if (0.8..1.1).contains(&t) {
+                    background + loud.ind_sample(&mut rng)
+                } else {
+                    background
+                }
+            })
+            .collect();
+
+        let mut segmenter = Segmenter::new(sample_rate);
+        segmenter.vad.hangover = 0;
+        let segments = segmenter.segment(&signal[..]);
+        println!("segments: {:?}", segments);
+
+        let label_at = |t: f64| {
+            segments
+                .iter()
+                .find(|s| s.start.0 <= t && t < s.end.0)
+                .unwrap()
+                .label
+        };
+        assert_eq!(label_at(0.3), SegmentLabel::Silence);
+        assert_eq!(label_at(0.65), SegmentLabel::Voiced);
+        assert_eq!(label_at(0.95), SegmentLabel::Unvoiced);
+        assert_eq!(label_at(1.4), SegmentLabel::Silence);
+        assert!((segments.last().unwrap().end.0 - 1.5).abs() < 1.0e-9);
+
+        let voiced = segments
+            .iter()
+            .find(|s| s.label == SegmentLabel::Voiced)
+            .unwrap();
+        assert_eq!(
+            voiced.samples(sample_rate).len(),
+            (voiced.duration().0 * 8000.).round() as usize
+        );
+    }
+    #[test]
+    fn test_segment_progress() {
+        let signal = vec![0f64; 8000];
+        let mut segmenter = Segmenter::new(8000.);
+        let mut last = None;
+        let segments = segmenter
+            .segment_with_progress(&signal[..], |p| {
+                last = Some(p);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(
+            last,
+            Some(Progress {
+                done: 100,
+                total: 100
+            })
+        );
+
+        let mut calls = 0;
+        let cancelled = segmenter.segment_with_progress(&signal[..], |p| {
+            calls += 1;
+            if p.fraction() >= 0.1 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(cancelled, Err(VoxBoxError::Cancelled)));
+        assert_eq!(calls, 10);
+    }
+}
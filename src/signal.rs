@@ -0,0 +1,247 @@
+//! Adaptors between vox_box stages and the `sample` crate's `Signal`s, so that streaming stages
+//! can be dropped into a `Signal` pipeline and analyses can take their frames from one.
+//!
+//! ```
+//! extern crate sample;
+//! extern crate vox_box;
+//!
+//! use sample::Signal;
+//! use vox_box::processor::{Processor, PreEmphasis};
+//! use vox_box::resample::{ResampleQuality, StreamResampler};
+//! use vox_box::signal::{Samples, Stage};
+//! use vox_box::waves::{Frames, WindowType};
+//!
+//! let source = sample::signal::rate(16000.).const_hz(440.).sine();
+//! let stages = PreEmphasis::new(0.97)
+//!     .chain(StreamResampler::new(16000., 8000., ResampleQuality::Balanced).unwrap());
+//! let signal = Stage::new(source, stages, 256).unwrap();
+//! let frames = Frames::new(Samples::new(signal), 200, 80)
+//!     .unwrap()
+//!     .window(WindowType::Hamming);
+//! let energies: Vec<f64> = frames
+//!     .take(10)
+//!     .map(|frame| frame.iter().map(|s| s * s).sum())
+//!     .collect();
+//! assert_eq!(energies.len(), 10);
+//! ```
+
+use sample::conv::Duplex;
+use sample::{Sample, Signal};
+
+use crate::error::*;
+use crate::processor::Processor;
+
+/// Runs a `Processor` over a mono `Signal` or an iterator of samples, `block_len` input samples
+/// at a time.
+///
+/// As a `Signal`, a stage whose `process` fails gives silence from then on. As an iterator, it
+/// stops, and the error is kept in `error`. Either way the last `latency()` samples are held
+/// back by the stage when an iterator ends, so add that much silence to flush them.
+#[derive(Debug)]
+pub struct Stage<S, P> {
+    source: S,
+    stage: P,
+    block_len: usize,
+    input: Vec<f64>,
+    output: Vec<f64>,
+    /// Next sample of `output` to give, and the number written to it
+    position: usize,
+    written: usize,
+    error: Option<VoxBoxError>,
+}
+
+impl<S, P: Processor<f64>> Stage<S, P> {
+    pub fn new(source: S, stage: P, block_len: usize) -> VoxBoxResult<Self> {
+        if block_len == 0 {
+            return Err(VoxBoxError::Config("Block length must be nonzero"));
+        }
+        let output = vec![0.; stage.max_output(block_len)];
+        Ok(Stage {
+            source,
+            stage,
+            block_len,
+            input: Vec::with_capacity(block_len),
+            output,
+            position: 0,
+            written: 0,
+            error: None,
+        })
+    }
+
+    pub fn stage(&self) -> &P {
+        &self.stage
+    }
+
+    /// The error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&VoxBoxError> {
+        self.error.as_ref()
+    }
+
+    /// The next output sample, processing blocks pulled from `pull` until one is ready. `None`
+    /// once `pull` runs dry or processing fails.
+    fn next_sample<F: FnMut(&mut S) -> Option<f64>>(&mut self, mut pull: F) -> Option<f64> {
+        while self.position == self.written {
+            if self.error.is_some() {
+                return None;
+            }
+            self.input.clear();
+            while self.input.len() < self.block_len {
+                match pull(&mut self.source) {
+                    Some(x) => self.input.push(x),
+                    None => break,
+                }
+            }
+            if self.input.is_empty() {
+                return None;
+            }
+            self.position = 0;
+            match self.stage.process(&self.input, &mut self.output) {
+                Ok(written) => self.written = written,
+                Err(e) => {
+                    self.written = 0;
+                    self.error = Some(e);
+                }
+            }
+        }
+        self.position += 1;
+        Some(self.output[self.position - 1])
+    }
+}
+
+impl<S, T, P> Signal for Stage<S, P>
+where
+    S: Signal<Frame = [T; 1]>,
+    T: Sample + Duplex<f64>,
+    P: Processor<f64>,
+{
+    type Frame = [T; 1];
+
+    fn next(&mut self) -> [T; 1] {
+        let y = self.next_sample(|source| Some(source.next()[0].to_sample::<f64>()));
+        [y.unwrap_or(0.).to_sample::<T>()]
+    }
+}
+
+impl<I, P> Iterator for Stage<I, P>
+where
+    I: Iterator,
+    I::Item: Sample + Duplex<f64>,
+    P: Processor<f64>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.next_sample(|source| source.next().map(|x| x.to_sample::<f64>()))
+            .map(|y| y.to_sample())
+    }
+}
+
+/// The samples of a mono `Signal`, as an endless iterator, for `waves::Frames` and other
+/// analyses that read iterators.
+#[derive(Clone, Debug)]
+pub struct Samples<S> {
+    signal: S,
+}
+
+impl<S> Samples<S> {
+    pub fn new(signal: S) -> Self {
+        Samples { signal }
+    }
+
+    pub fn into_signal(self) -> S {
+        self.signal
+    }
+}
+
+impl<S, T> Iterator for Samples<S>
+where
+    S: Signal<Frame = [T; 1]>,
+    T: Sample,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        Some(self.signal.next()[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::{PreEmphasis, Windowing};
+    use crate::resample::{ResampleQuality, Resampler, StreamResampler};
+    use crate::waves::{Frames, WindowType};
+    use std::f64::consts::PI;
+
+    fn tone(len: usize) -> Vec<f64> {
+        (0..len).map(|n| (2. * PI * n as f64 / 37.).sin()).collect()
+    }
+
+    #[test]
+    fn test_signal_stage() {
+        // The same as processing the whole signal at once, whatever the block length
+        let signal = tone(500);
+        let mut whole = vec![0.; 500];
+        PreEmphasis::new(0.9).process(&signal, &mut whole).unwrap();
+        let source = sample::signal::from_iter(signal.iter().map(|s| [*s]));
+        let stage = Stage::new(source, PreEmphasis::new(0.9), 7).unwrap();
+        let streamed: Vec<f64> = stage.take(500).map(|[s]| s).collect();
+        assert_eq!(streamed, whole);
+
+        // Past the end of the source, which reads as silence once the last sample has passed
+        let source = sample::signal::from_iter(signal.iter().map(|s| [*s as f32]));
+        let mut stage = Stage::new(source, PreEmphasis::new(0.9), 64).unwrap();
+        for _ in 0..501 {
+            stage.next();
+        }
+        assert_eq!(stage.next(), [0f32]);
+        assert!(Stage::new(signal.iter().cloned(), PreEmphasis::new(0.9), 0).is_err());
+    }
+
+    #[test]
+    fn test_iterator_stage() {
+        // Resampling a block at a time matches resampling in one go, apart from the tail the
+        // resampler holds back
+        let signal = tone(1000);
+        let whole = Resampler::new(16000., 11025., ResampleQuality::Fast)
+            .unwrap()
+            .resample(&signal);
+        let resampler = StreamResampler::new(16000., 11025., ResampleQuality::Fast).unwrap();
+        let latency = resampler.latency();
+        let streamed: Vec<f64> = Stage::new(signal.iter().cloned(), resampler, 100)
+            .unwrap()
+            .collect();
+        println!("{} of {} samples", streamed.len(), whole.len());
+        assert!(streamed.len() + latency >= whole.len());
+        for (a, b) in streamed.iter().zip(whole.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+
+        // A failing stage ends the iterator and keeps its error
+        let mut stage = Stage::new(
+            signal.iter().cloned(),
+            Windowing::new(WindowType::Hanning, 64),
+            100,
+        )
+        .unwrap();
+        assert_eq!(stage.next(), None);
+        assert!(matches!(stage.error(), Some(VoxBoxError::Input(_))));
+    }
+
+    #[test]
+    fn test_signal_frames() {
+        let signal = tone(300);
+        let source = sample::signal::from_iter(signal.iter().map(|s| [*s]));
+        let from_signal: Vec<Vec<f64>> = Frames::new(Samples::new(source), 64, 32)
+            .unwrap()
+            .window(WindowType::Hanning)
+            .take(7)
+            .collect();
+        let from_slice: Vec<Vec<f64>> = Frames::from_slice(&signal, 64, 32)
+            .unwrap()
+            .window(WindowType::Hanning)
+            .take(7)
+            .collect();
+        assert_eq!(from_signal, from_slice);
+    }
+}
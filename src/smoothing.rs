@@ -0,0 +1,337 @@
+//! Smoothing of per-frame feature tracks, such as formant, pitch or energy contours.
+//!
+//! Windows are centered on each value and shrink at the ends of the track. Non-finite values,
+//! such as the pitch of unvoiced frames, are left out of every window and come through as they
+//! are, so gaps in a track don't spread into their neighbours.
+
+use num::Float;
+use std::cmp::Ordering;
+
+use crate::arma::least_squares;
+use crate::error::*;
+use crate::units::Seconds;
+use crate::validate;
+
+pub trait Smooth<T> {
+    /// Median of the `width / 2` values either side of each value and the value itself.
+    fn median_smooth(&self, width: usize) -> Vec<T>;
+    /// Mean of the `width / 2` values either side of each value and the value itself.
+    fn moving_average(&self, width: usize) -> Vec<T>;
+    /// `y[n] = alpha * x[n] + (1 - alpha) * y[n - 1]`, starting from the first finite value.
+    /// `alpha` must be in (0, 1], and smaller values smooth more.
+    fn exponential_smooth(&self, alpha: T) -> VoxBoxResult<Vec<T>>;
+    /// Savitzky-Golay smoothing: each value of the least-squares polynomial of `order` through
+    /// the `window` values around it. Keeps peaks and slopes that a moving average of the same
+    /// width flattens. `window` must be odd and longer than `order`.
+    ///
+    /// Each run of finite values is fitted on its own, with the polynomial of the first or last
+    /// window at its ends. Runs shorter than the window are fitted whole, with the order cut to
+    /// fit.
+    fn savitzky_golay(&self, window: usize, order: usize) -> VoxBoxResult<Vec<T>>;
+    /// Slope of the polynomials `savitzky_golay` fits, in units per second for values
+    /// `frame_period` apart.
+    fn savitzky_golay_derivative(
+        &self,
+        window: usize,
+        order: usize,
+        frame_period: Seconds,
+    ) -> VoxBoxResult<Vec<T>>;
+}
+
+impl<T: Float> Smooth<T> for [T] {
+    fn median_smooth(&self, width: usize) -> Vec<T> {
+        let span = width / 2;
+        // Finite values of the current window, kept sorted as it slides
+        let mut window: Vec<T> = Vec::with_capacity(2 * span + 1);
+        let insert = |window: &mut Vec<T>, v: T| {
+            if v.is_finite() {
+                let i = window
+                    .binary_search_by(|w| w.partial_cmp(&v).unwrap_or(Ordering::Equal))
+                    .unwrap_or_else(|i| i);
+                window.insert(i, v);
+            }
+        };
+        for v in self.iter().take(span) {
+            insert(&mut window, *v);
+        }
+        let two = T::one() + T::one();
+        (0..self.len())
+            .map(|i| {
+                if let Some(v) = self.get(i + span) {
+                    insert(&mut window, *v);
+                }
+                if i > span && self[i - span - 1].is_finite() {
+                    let leaving = self[i - span - 1];
+                    if let Some(k) = window.iter().position(|w| *w == leaving) {
+                        window.remove(k);
+                    }
+                }
+                let mid = window.len() / 2;
+                if !self[i].is_finite() || window.is_empty() {
+                    self[i]
+                } else if window.len().is_multiple_of(2) {
+                    (window[mid - 1] + window[mid]) / two
+                } else {
+                    window[mid]
+                }
+            })
+            .collect()
+    }
+
+    fn moving_average(&self, width: usize) -> Vec<T> {
+        let span = width / 2;
+        // Sum and count of the finite values of the current window
+        let (mut sum, mut count) = (T::zero(), 0);
+        for v in self.iter().take(span).filter(|v| v.is_finite()) {
+            sum = sum + *v;
+            count += 1;
+        }
+        (0..self.len())
+            .map(|i| {
+                if let Some(v) = self.get(i + span).filter(|v| v.is_finite()) {
+                    sum = sum + *v;
+                    count += 1;
+                }
+                if i > span && self[i - span - 1].is_finite() {
+                    sum = sum - self[i - span - 1];
+                    count -= 1;
+                }
+                if !self[i].is_finite() || count == 0 {
+                    self[i]
+                } else {
+                    sum / T::from(count).unwrap()
+                }
+            })
+            .collect()
+    }
+
+    fn exponential_smooth(&self, alpha: T) -> VoxBoxResult<Vec<T>> {
+        if !(alpha > T::zero() && alpha <= T::one()) {
+            return Err(VoxBoxError::Config("Smoothing alpha must be in (0, 1]"));
+        }
+        let mut last: Option<T> = None;
+        Ok(self
+            .iter()
+            .map(|x| {
+                if !x.is_finite() {
+                    return *x;
+                }
+                let y = match last {
+                    Some(y) => alpha * *x + (T::one() - alpha) * y,
+                    None => *x,
+                };
+                last = Some(y);
+                y
+            })
+            .collect())
+    }
+
+    fn savitzky_golay(&self, window: usize, order: usize) -> VoxBoxResult<Vec<T>> {
+        savitzky_golay(self, window, order, None)
+    }
+
+    fn savitzky_golay_derivative(
+        &self,
+        window: usize,
+        order: usize,
+        frame_period: Seconds,
+    ) -> VoxBoxResult<Vec<T>> {
+        validate::positive(frame_period.0, "Frame period must be positive")?;
+        savitzky_golay(self, window, order, Some(frame_period.0))
+    }
+}
+
+/// Savitzky-Golay values, or slopes per second if given the frame period, of every run of
+/// finite values.
+fn savitzky_golay<T: Float>(
+    values: &[T],
+    window: usize,
+    order: usize,
+    frame_period: Option<f64>,
+) -> VoxBoxResult<Vec<T>> {
+    if window.is_multiple_of(2) || window <= order {
+        return Err(VoxBoxError::Config(
+            "Savitzky-Golay window must be odd and longer than the order",
+        ));
+    }
+    let mut out: Vec<T> = values.to_vec();
+    let mut start = 0;
+    while start < values.len() {
+        if !values[start].is_finite() {
+            start += 1;
+            continue;
+        }
+        let end = (start..values.len())
+            .find(|i| !values[*i].is_finite())
+            .unwrap_or(values.len());
+        let run: Vec<f64> = values[start..end]
+            .iter()
+            .map(|v| v.to_f64().unwrap())
+            .collect();
+        let fitted = savitzky_golay_run(&run, window, order, frame_period);
+        for (o, y) in out[start..end].iter_mut().zip(fitted) {
+            *o = T::from(y).unwrap();
+        }
+        start = end;
+    }
+    Ok(out)
+}
+
+fn savitzky_golay_run(
+    values: &[f64],
+    window: usize,
+    order: usize,
+    frame_period: Option<f64>,
+) -> Vec<f64> {
+    let w = window.min(values.len());
+    let order = order.min(w - 1);
+    // Positions in the window, scaled to [-1, 1] to keep the normal equations well conditioned
+    let center = (w - 1) as f64 / 2.;
+    let scale = center.max(1.);
+    let x = |p: usize| (p as f64 - center) / scale;
+    let rows: Vec<Vec<f64>> = (0..w)
+        .map(|p| (0..=order).map(|j| x(p).powi(j as i32)).collect())
+        .collect();
+    // The polynomial fitted to a unit impulse at each position of the window
+    let fits: Vec<Vec<f64>> = (0..w)
+        .map(|k| {
+            let mut impulse = vec![0.; w];
+            impulse[k] = 1.;
+            least_squares(&rows, &impulse).unwrap_or_else(|| vec![0.; order + 1])
+        })
+        .collect();
+    // weights[p][k]: how much the value at k adds to the fit, or its slope, at p
+    let weights: Vec<Vec<f64>> = (0..w)
+        .map(|p| {
+            fits.iter()
+                .map(|c| match frame_period {
+                    None => c.iter().rev().fold(0., |acc, c| acc * x(p) + c),
+                    Some(period) => {
+                        let slope = (1..=order)
+                            .map(|j| j as f64 * c[j] * x(p).powi(j as i32 - 1))
+                            .sum::<f64>();
+                        slope / (scale * period)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    (0..values.len())
+        .map(|i| {
+            let first = i.saturating_sub(w / 2).min(values.len() - w);
+            weights[i - first]
+                .iter()
+                .zip(values[first..first + w].iter())
+                .map(|(w, v)| w * v)
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_median_smooth() {
+        // A one-frame spike in a formant track goes, and a real step stays sharp
+        let track = [500., 510., 505., 1500., 515., 520., 900., 905., 910., 915.];
+        let smoothed = track.median_smooth(3);
+        println!("{:?}", smoothed);
+        assert_eq!(
+            smoothed,
+            vec![505., 505., 510., 515., 520., 520., 900., 905., 910., 912.5]
+        );
+        // Against sorting every window, with gaps
+        let track: Vec<f64> = (0..50)
+            .map(|n| {
+                if n % 7 == 3 {
+                    f64::NAN
+                } else {
+                    ((n * 37) % 11) as f64
+                }
+            })
+            .collect();
+        for (n, s) in track.median_smooth(5).iter().enumerate() {
+            if track[n].is_nan() {
+                assert!(s.is_nan());
+                continue;
+            }
+            let mut window: Vec<f64> = track[n.saturating_sub(2)..(n + 3).min(50)]
+                .iter()
+                .cloned()
+                .filter(|v| v.is_finite())
+                .collect();
+            window.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mid = window.len() / 2;
+            let expected = if window.len().is_multiple_of(2) {
+                (window[mid - 1] + window[mid]) / 2.
+            } else {
+                window[mid]
+            };
+            assert_eq!(*s, expected);
+        }
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let track = [1f32, 2., 3., f32::NAN, 5., 6., 7.];
+        let smoothed = track.moving_average(3);
+        assert_eq!(smoothed[0], 1.5);
+        assert_eq!(smoothed[1], 2.);
+        assert_eq!(smoothed[2], 2.5);
+        assert!(smoothed[3].is_nan());
+        assert_eq!(smoothed[4], 5.5);
+        assert_eq!(smoothed[6], 6.5);
+        assert_eq!(track.moving_average(1)[..3], track[..3]);
+    }
+
+    #[test]
+    fn test_exponential_smooth() {
+        let track = [f64::NAN, 10., 20., f64::NAN, 20.];
+        let smoothed = track.exponential_smooth(0.5).unwrap();
+        assert!(smoothed[0].is_nan() && smoothed[3].is_nan());
+        assert_eq!(smoothed[1..3], [10., 15.]);
+        assert_eq!(smoothed[4], 17.5);
+        assert_eq!(track.exponential_smooth(1.).unwrap()[2], 20.);
+        assert!(track.exponential_smooth(0.).is_err());
+        assert!(track.exponential_smooth(1.5).is_err());
+    }
+
+    #[test]
+    fn test_savitzky_golay() {
+        // The classic 5-point quadratic weights, -3 12 17 12 -3 over 35
+        let impulse = [0., 0., 0., 0., 1., 0., 0., 0., 0.];
+        let smoothed = impulse.savitzky_golay(5, 2).unwrap();
+        for (s, w) in smoothed[2..7].iter().zip([-3., 12., 17., 12., -3.].iter()) {
+            assert!((s - w / 35.).abs() < 1e-12);
+        }
+
+        // Cubics come through a cubic fit exactly, ends and all, and so do their slopes
+        let period = Seconds(0.01);
+        let cubic = |t: f64| 2. + 3. * t - 40. * t * t + 100. * t * t * t;
+        let track: Vec<f64> = (0..30).map(|n| cubic(n as f64 * 0.01)).collect();
+        let smoothed = track.savitzky_golay(7, 3).unwrap();
+        let slopes = track.savitzky_golay_derivative(7, 3, period).unwrap();
+        for (n, (s, d)) in smoothed.iter().zip(slopes.iter()).enumerate() {
+            let t = n as f64 * 0.01;
+            assert!((s - track[n]).abs() < 1e-9);
+            assert!((d - (3. - 80. * t + 300. * t * t)).abs() < 1e-6);
+        }
+
+        // Noise is reduced, gaps stay, and a run too short for the window is fitted whole
+        let mut noisy: Vec<f64> = (0..24)
+            .map(|n| 100. + if n % 2 == 0 { 1. } else { -1. })
+            .collect();
+        noisy[20] = f64::NAN;
+        let smoothed = noisy.savitzky_golay(9, 2).unwrap();
+        println!("{:?}", smoothed);
+        assert!(smoothed[20].is_nan());
+        assert!(smoothed[5..15].iter().all(|s| (s - 100.).abs() < 0.3));
+        assert!(smoothed[21..].iter().all(|s| s.is_finite()));
+
+        assert!(track.savitzky_golay(6, 2).is_err());
+        assert!(track.savitzky_golay(5, 5).is_err());
+        assert!(track.savitzky_golay_derivative(5, 2, Seconds(0.)).is_err());
+    }
+}
@@ -1,73 +1,208 @@
 extern crate num;
+#[cfg(feature = "rustfft")]
 extern crate rustfft as fft;
 
+#[cfg(feature = "mfcc")]
 use num::traits::{Signed, Zero};
 use num::{Float, FromPrimitive, ToPrimitive};
 use num_complex::Complex;
+#[cfg(feature = "mfcc")]
+use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::default::Default;
 use std::f64::consts::PI;
+use std::fmt;
+#[cfg(feature = "mfcc")]
 use std::fmt::Debug;
-use std::marker::PhantomData;
 
+pub use crate::cepstrum::real_cepstrum;
+use crate::cepstrum::real_cepstrum_of;
+use crate::config::AnalysisConfig;
+use crate::dct::{DctNorm, DctType};
 use crate::error::*;
+use crate::features::FeatureMatrix;
+#[cfg(feature = "pitch")]
+use crate::periodic::Pitch;
+use crate::progress::{self, ControlFlow, Progress};
+use crate::segment::centered_frame;
+use crate::smoothing::Smooth;
+use crate::units::{Db, Hz, Seconds};
+use crate::validate;
+use crate::waves::{to_f64_samples, WindowType};
+use sample::{Sample, ToSample};
 
 pub struct LPCSolver<'a, T: 'a> {
     n_coeffs: usize,
     ac: &'a mut [T],
     kc: &'a mut [T],
     tmp: &'a mut [T],
+    error: T,
 }
 
 impl<'a, T: 'a + Float> LPCSolver<'a, T> {
     /// Constructs an LPCSolver without any allocations required.
     ///
     /// work must be at least length `n_coeffs * 3 + 1`.
-    pub fn new(n_coeffs: usize, work: &'a mut [T]) -> LPCSolver<'a, T> {
-        assert!(work.len() > n_coeffs * 3 + 1);
+    pub fn new(n_coeffs: usize, work: &'a mut [T]) -> VoxBoxResult<LPCSolver<'a, T>> {
+        validate::workspace(work, n_coeffs * 3 + 1)?;
 
         let (ac, work) = work.split_at_mut(n_coeffs + 1);
         let (kc, tmp) = work.split_at_mut(n_coeffs);
 
-        LPCSolver {
+        Ok(LPCSolver {
             n_coeffs,
             ac,
             kc,
             tmp,
-        }
+            error: T::zero(),
+        })
     }
 
     /// Finds the LPC coefficients for the autocorrelated buffer
-    pub fn solve(&mut self, buf: &[T]) {
-        buf.lpc_mut(self.n_coeffs, self.ac, self.kc, self.tmp);
+    pub fn solve(&mut self, buf: &[T]) -> VoxBoxResult<()> {
+        self.error = buf.lpc_mut(self.n_coeffs, self.ac, self.kc, self.tmp)?;
+        Ok(())
     }
 
     /// Returns the slice of LPC coefficients
     pub fn lpc(&self) -> &[T] {
         &self.ac[..]
     }
+
+    /// Returns the reflection coefficients of the last solve
+    pub fn reflection(&self) -> &[T] {
+        &self.kc[..]
+    }
+
+    /// Returns the prediction error of the last solve
+    pub fn prediction_error(&self) -> T {
+        self.error
+    }
+}
+
+/// Everything the Levinson-Durbin recursion finds, as returned by `LPC::lpc_analysis`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LPCAnalysis<T> {
+    /// LPC coefficients, starting with the leading one
+    pub coeffs: Vec<T>,
+    /// Reflection (PARCOR) coefficients, one per order. All have a magnitude below one if the
+    /// filter is stable.
+    pub reflection: Vec<T>,
+    /// Power of the prediction error at the final order. Its square root is the gain of the
+    /// synthesis filter.
+    pub error: T,
+}
+
+/// Conditioning applied by `LPC::lpc_with_options`. The default applies none, which is the same
+/// as `lpc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LPCOptions {
+    /// Bandwidth of a Gaussian lag window applied to the autocorrelation, as a fraction of the
+    /// sample rate. Smooths sharp spectral peaks, such as a single strong harmonic, that make
+    /// the recursion ill-conditioned.
+    pub lag_window: Option<f64>,
+    /// Factor `gamma` below one that the coefficients are scaled by, `a[k] * gamma^k`. Pulls
+    /// every pole towards the origin, widening its bandwidth.
+    pub bandwidth_expansion: Option<f64>,
+}
+
+impl LPCOptions {
+    /// A 60 Hz lag window and a bandwidth expansion of about 15 Hz, at a sample rate of
+    /// `sample_rate`.
+    pub fn stable<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        LPCOptions {
+            lag_window: Some(60. / sample_rate),
+            bandwidth_expansion: Some((-PI * 15. / sample_rate).exp()),
+        }
+    }
+
+    /// `stable` at the sample rate of `config`.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        LPCOptions::stable(config.sample_rate)
+    }
+}
+
+/// Multiplies an autocorrelation by a Gaussian lag window of `bandwidth`, a fraction of the
+/// sample rate.
+pub fn lag_window<T: Float>(autocorrelation: &mut [T], bandwidth: f64) {
+    for (k, r) in autocorrelation.iter_mut().enumerate() {
+        let x = 2. * PI * bandwidth * k as f64;
+        *r = *r * T::from((-0.5 * x * x).exp()).unwrap();
+    }
+}
+
+/// Scales LPC coefficients by `gamma^k`, moving the poles to `gamma` times their radius. The
+/// coefficients don't include the leading one.
+pub fn expand_bandwidth<T: Float>(coeffs: &mut [T], gamma: T) {
+    let mut factor = T::one();
+    for c in coeffs.iter_mut() {
+        factor = factor * gamma;
+        *c = *c * factor;
+    }
 }
 
 pub trait LPC<T> {
-    fn lpc_mut(&self, n_coeffs: usize, ac: &mut [T], kc: &mut [T], tmp: &mut [T]);
-    fn lpc(&self, n_coeffs: usize) -> Vec<T>;
+    fn lpc_mut(
+        &self,
+        n_coeffs: usize,
+        ac: &mut [T],
+        kc: &mut [T],
+        tmp: &mut [T],
+    ) -> VoxBoxResult<T>;
+    fn lpc(&self, n_coeffs: usize) -> VoxBoxResult<Vec<T>>;
+    fn lpc_analysis(&self, n_coeffs: usize) -> VoxBoxResult<LPCAnalysis<T>>;
+    fn lpc_with_options(
+        &self,
+        n_coeffs: usize,
+        options: &LPCOptions,
+    ) -> VoxBoxResult<LPCAnalysis<T>>;
     fn lpc_praat_mut(&self, n_coeffs: usize, coeffs: &mut [T], work: &mut [T]) -> VoxBoxResult<()>;
     fn lpc_praat(&self, n_coeffs: usize) -> VoxBoxResult<Vec<T>>;
 }
 
 impl<T: Float> LPC<T> for [T] {
-    /// Calculates the LPCs, reusing slices of memory for workspace.
+    /// Calculates the LPCs, reusing slices of memory for workspace, and returns the final
+    /// prediction error.
     ///
-    /// ac: size must be at least `n_coeffs + 1`
-    /// kc: size must be at least `n_coeffs`
+    /// ac: size must be at least `n_coeffs + 1`; receives the LPC coefficients
+    /// kc: size must be at least `n_coeffs`; receives the reflection coefficients
     /// tmp: size must be at least `n_coeffs`
-    fn lpc_mut(&self, n_coeffs: usize, ac: &mut [T], kc: &mut [T], tmp: &mut [T]) {
+    ///
+    /// If the prediction error reaches zero, as it does for silence, the recursion stops and the
+    /// remaining coefficients are left at zero rather than dividing by zero.
+    ///
+    /// The autocorrelation must have more than `n_coeffs` finite values.
+    fn lpc_mut(
+        &self,
+        n_coeffs: usize,
+        ac: &mut [T],
+        kc: &mut [T],
+        tmp: &mut [T],
+    ) -> VoxBoxResult<T> {
+        if self.len() <= n_coeffs {
+            return Err(VoxBoxError::LPC(
+                "Autocorrelation must be longer than n_coeffs",
+            ));
+        }
+        validate::finite(&self[..=n_coeffs])?;
+        validate::workspace(ac, n_coeffs + 1)?;
+        validate::workspace(kc, n_coeffs)?;
+        validate::workspace(tmp, n_coeffs)?;
+
         /* order 0 */
         let mut err = self[0];
         ac[0] = T::one();
+        for (a, k) in ac[1..=n_coeffs].iter_mut().zip(kc.iter_mut()) {
+            *a = T::zero();
+            *k = T::zero();
+        }
 
         /* order >= 1 */
         for i in 1..=n_coeffs {
+            if err <= T::zero() || !err.is_finite() {
+                break;
+            }
             let mut acc = self[i];
             for j in 1..i {
                 acc = acc + (ac[j] * self[i - j]);
@@ -82,14 +217,55 @@ impl<T: Float> LPC<T> for [T] {
             }
             err = err * (T::one() - (kc[i - 1] * kc[i - 1]));
         }
+        Ok(err.max(T::zero()))
+    }
+
+    fn lpc(&self, n_coeffs: usize) -> VoxBoxResult<Vec<T>> {
+        self.lpc_analysis(n_coeffs).map(|analysis| analysis.coeffs)
     }
 
-    fn lpc(&self, n_coeffs: usize) -> Vec<T> {
+    fn lpc_analysis(&self, n_coeffs: usize) -> VoxBoxResult<LPCAnalysis<T>> {
         let mut ac: Vec<T> = vec![T::zero(); n_coeffs + 1];
         let mut kc: Vec<T> = vec![T::zero(); n_coeffs];
         let mut tmp: Vec<T> = vec![T::zero(); n_coeffs];
-        self.lpc_mut(n_coeffs, &mut ac[..], &mut kc[..], &mut tmp[..]);
-        ac
+        let error = self.lpc_mut(n_coeffs, &mut ac[..], &mut kc[..], &mut tmp[..])?;
+        Ok(LPCAnalysis {
+            coeffs: ac,
+            reflection: kc,
+            error,
+        })
+    }
+
+    /// Like `lpc_analysis`, with the autocorrelation lag-windowed first and the coefficients
+    /// bandwidth-expanded after. The reflection coefficients and error are from before the
+    /// expansion.
+    fn lpc_with_options(
+        &self,
+        n_coeffs: usize,
+        options: &LPCOptions,
+    ) -> VoxBoxResult<LPCAnalysis<T>> {
+        let mut analysis = match options.lag_window {
+            Some(bandwidth) => {
+                if !bandwidth.is_finite() || bandwidth < 0. {
+                    return Err(VoxBoxError::Config(
+                        "Lag window bandwidth must not be negative",
+                    ));
+                }
+                let mut windowed = self.to_vec();
+                lag_window(&mut windowed[..], bandwidth);
+                windowed.lpc_analysis(n_coeffs)?
+            }
+            None => self.lpc_analysis(n_coeffs)?,
+        };
+        if let Some(gamma) = options.bandwidth_expansion {
+            if gamma <= 0. || gamma > 1. || !gamma.is_finite() {
+                return Err(VoxBoxError::Config(
+                    "Bandwidth expansion must be above zero and at most one",
+                ));
+            }
+            expand_bandwidth(&mut analysis.coeffs[1..], T::from(gamma).unwrap());
+        }
+        Ok(analysis)
     }
 
     fn lpc_praat(&self, n_coeffs: usize) -> VoxBoxResult<Vec<T>> {
@@ -100,8 +276,12 @@ impl<T: Float> LPC<T> for [T] {
     }
 
     fn lpc_praat_mut(&self, n_coeffs: usize, coeffs: &mut [T], work: &mut [T]) -> VoxBoxResult<()> {
-        assert!(coeffs.len() >= n_coeffs);
-        assert!(work.len() >= (self.len() * 2 + n_coeffs));
+        if self.len() < n_coeffs + 2 {
+            return Err(VoxBoxError::LPC("Buffer must be longer than n_coeffs + 1"));
+        }
+        validate::finite(self)?;
+        validate::workspace(coeffs, n_coeffs)?;
+        validate::workspace(work, self.len() * 2 + n_coeffs)?;
         let (b1, work) = work.split_at_mut(self.len());
         let (b2, work) = work.split_at_mut(self.len());
         let (aa, _) = work.split_at_mut(n_coeffs);
@@ -147,10 +327,292 @@ impl<T: Float> LPC<T> for [T] {
     }
 }
 
+/// Criterion for choosing an LPC order in `select_lpc_order`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderCriterion {
+    /// Akaike's information criterion, `N ln(E) + 2p`
+    Akaike,
+    /// Akaike's final prediction error, `E (N + p + 1) / (N - p - 1)`
+    FinalPredictionError,
+}
+
+/// Picks the LPC order up to `max_order` that minimizes `criterion`, from the prediction error
+/// of each order of the Levinson recursion on `autocorrelation`. `n_samples` is the length of
+/// the frame the autocorrelation came from. Silence gets order zero, and a frame that is
+/// predicted exactly gets the first order that does so.
+pub fn select_lpc_order<T: Float>(
+    autocorrelation: &[T],
+    n_samples: usize,
+    max_order: usize,
+    criterion: OrderCriterion,
+) -> VoxBoxResult<usize> {
+    if n_samples <= max_order + 1 {
+        return Err(VoxBoxError::Input(
+            "Frame must be longer than max_order + 1",
+        ));
+    }
+    let analysis = autocorrelation.lpc_analysis(max_order)?;
+    let n = n_samples as f64;
+    let score = |order: usize, error: f64| match criterion {
+        OrderCriterion::Akaike => n * error.ln() + 2. * order as f64,
+        OrderCriterion::FinalPredictionError => {
+            error * (n + order as f64 + 1.) / (n - order as f64 - 1.)
+        }
+    };
+
+    let mut error = autocorrelation[0].to_f64().unwrap();
+    if error <= 0. {
+        return Ok(0);
+    }
+    let (mut best, mut best_score) = (0, score(0, error));
+    for (i, k) in analysis.reflection.iter().enumerate() {
+        let k = k.to_f64().unwrap();
+        error = (error * (1. - k * k)).max(0.);
+        // Once the error reaches zero, later orders score no better than the first to do so
+        let order_score = score(i + 1, error);
+        if order_score < best_score {
+            best = i + 1;
+            best_score = order_score;
+        }
+    }
+    Ok(best)
+}
+
+/// Converts LPC coefficients to line spectral frequencies: the angles, in radians between 0
+/// and pi, of the roots of the symmetric and antisymmetric polynomials built from the filter.
+///
+/// The coefficients are those of `A(z) = 1 + a[0] z^-1 + ... + a[p-1] z^-p`, without the
+/// leading one, as `lpc_praat` gives them; pass `&lpc[1..]` for the output of `lpc`. There is
+/// one frequency per coefficient, in increasing order. Unlike the coefficients, they can be
+/// interpolated and quantized without making the filter unstable.
+pub trait LSF<T> {
+    fn lsf(&self) -> VoxBoxResult<Vec<T>>;
+}
+
+impl<T: Float> LSF<T> for [T] {
+    fn lsf(&self) -> VoxBoxResult<Vec<T>> {
+        validate::finite(self)?;
+        let order = self.len();
+        let coeff = |k: usize| match k {
+            0 => 1.,
+            k if k <= order => self[k - 1].to_f64().unwrap(),
+            _ => 0.,
+        };
+        // P(z) = A(z) + z^-(p+1) A(1/z) and Q(z) = A(z) - z^-(p+1) A(1/z)
+        let sum: Vec<f64> = (0..=order + 1)
+            .map(|k| coeff(k) + coeff(order + 1 - k))
+            .collect();
+        let difference: Vec<f64> = (0..=order + 1)
+            .map(|k| coeff(k) - coeff(order + 1 - k))
+            .collect();
+
+        // Divide out the roots that P and Q always have at z = -1 and z = 1
+        let (sum, difference) = if order.is_multiple_of(2) {
+            (divide_out(&sum, 1, -1.), divide_out(&difference, 1, 1.))
+        } else {
+            (sum, divide_out(&difference, 2, 1.))
+        };
+
+        let mut lsf = cosine_roots(&sum);
+        lsf.extend(cosine_roots(&difference));
+        if lsf.len() != order {
+            return Err(VoxBoxError::LPC("LPC filter is not minimum phase"));
+        }
+        lsf.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        Ok(lsf.into_iter().map(|w| T::from(w).unwrap()).collect())
+    }
+}
+
+/// Converts line spectral frequencies in radians back to LPC coefficients, without the leading
+/// one. The frequencies must be strictly increasing and between 0 and pi, which also makes the
+/// filter stable.
+pub fn lsf_to_lpc<T: Float>(lsf: &[T]) -> VoxBoxResult<Vec<T>> {
+    validate::finite(lsf)?;
+    let lsf: Vec<f64> = lsf.iter().map(|w| w.to_f64().unwrap()).collect();
+    let increasing = lsf.windows(2).all(|w| w[0] < w[1]);
+    if !increasing || lsf.iter().any(|w| *w <= 0. || *w >= PI) {
+        return Err(VoxBoxError::Input(
+            "Line spectral frequencies must be increasing and between 0 and pi",
+        ));
+    }
+    let order = lsf.len();
+
+    // Each frequency is a pair of conjugate roots on the unit circle, alternating between P
+    // and Q
+    let from_roots = |frequencies: Vec<f64>| {
+        frequencies.iter().fold(vec![1.], |poly, w| {
+            multiply(&poly, &[1., -2. * w.cos(), 1.])
+        })
+    };
+    let sum = from_roots(lsf.iter().cloned().step_by(2).collect());
+    let difference = from_roots(lsf.iter().cloned().skip(1).step_by(2).collect());
+    let (sum, difference) = if order.is_multiple_of(2) {
+        (multiply(&sum, &[1., 1.]), multiply(&difference, &[1., -1.]))
+    } else {
+        (sum, multiply(&difference, &[1., 0., -1.]))
+    };
+
+    Ok((1..=order)
+        .map(|k| T::from((sum[k] + difference[k]) / 2.).unwrap())
+        .collect())
+}
+
+fn multiply(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut product = vec![0.; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            product[i + j] += x * y;
+        }
+    }
+    product
+}
+
+/// Divides `poly` by `1 - sign z^-lag`, dropping the remainder.
+fn divide_out(poly: &[f64], lag: usize, sign: f64) -> Vec<f64> {
+    let mut quotient: Vec<f64> = Vec::with_capacity(poly.len() - lag);
+    for k in 0..poly.len() - lag {
+        let carried = if k >= lag {
+            sign * quotient[k - lag]
+        } else {
+            0.
+        };
+        quotient.push(poly[k] + carried);
+    }
+    quotient
+}
+
+/// Angles between 0 and pi of the unit-circle roots of a symmetric polynomial of even degree.
+/// On the unit circle the polynomial is a real sum of cosines, so its roots are found as sign
+/// changes on a grid, refined by bisection.
+fn cosine_roots(poly: &[f64]) -> Vec<f64> {
+    const GRID: usize = 1024;
+    let m = (poly.len() - 1) / 2;
+    let value = |w: f64| {
+        (1..=m).fold(poly[m], |acc, k| {
+            acc + 2. * poly[m - k] * (k as f64 * w).cos()
+        })
+    };
+
+    let mut roots = Vec::with_capacity(m);
+    let (mut low, mut low_value) = (0., value(0.));
+    for i in 1..=GRID {
+        let high = PI * i as f64 / GRID as f64;
+        let high_value = value(high);
+        if low_value * high_value < 0. {
+            let (mut a, mut b, mut a_value) = (low, high, low_value);
+            for _ in 0..50 {
+                let mid = (a + b) / 2.;
+                let mid_value = value(mid);
+                if a_value * mid_value <= 0. {
+                    b = mid;
+                } else {
+                    a = mid;
+                    a_value = mid_value;
+                }
+            }
+            roots.push((a + b) / 2.);
+        }
+        low = high;
+        low_value = high_value;
+    }
+    roots
+}
+
+/// Inverse filtering through the FIR filter `A(z)` defined by LPC coefficients, without the
+/// leading one as `lpc_praat` gives them. The output is the prediction residual, which for
+/// speech approximates the glottal excitation. Samples before the start count as zero.
+pub trait InverseFilter<T> {
+    /// Writes the residual to `residual`, which must be at least as long as the signal.
+    fn lpc_residual_mut(&self, coeffs: &[T], residual: &mut [T]) -> VoxBoxResult<()>;
+    fn lpc_residual(&self, coeffs: &[T]) -> VoxBoxResult<Vec<T>>;
+}
+
+impl<T: Float> InverseFilter<T> for [T] {
+    fn lpc_residual_mut(&self, coeffs: &[T], residual: &mut [T]) -> VoxBoxResult<()> {
+        validate::finite(coeffs)?;
+        validate::workspace(residual, self.len())?;
+        for (i, e) in residual[..self.len()].iter_mut().enumerate() {
+            *e = coeffs
+                .iter()
+                .take(i)
+                .enumerate()
+                .fold(self[i], |acc, (k, c)| acc + *c * self[i - k - 1]);
+        }
+        Ok(())
+    }
+
+    fn lpc_residual(&self, coeffs: &[T]) -> VoxBoxResult<Vec<T>> {
+        let mut residual = vec![T::zero(); self.len()];
+        self.lpc_residual_mut(coeffs, &mut residual[..])?;
+        Ok(residual)
+    }
+}
+
+/// The all-pole synthesis filter `1 / A(z)`, the inverse of `InverseFilter`. It keeps its last
+/// outputs between calls, so that a signal can be resynthesized block by block, with new
+/// coefficients for each block.
+#[derive(Clone, Debug)]
+pub struct AllPoleFilter<T> {
+    /// Most recent output first
+    history: Vec<T>,
+}
+
+impl<T: Float> AllPoleFilter<T> {
+    /// Creates a filter for up to `order` coefficients, starting from silence.
+    pub fn new(order: usize) -> Self {
+        AllPoleFilter {
+            history: vec![T::zero(); order],
+        }
+    }
+
+    pub fn order(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Clears the filter state.
+    pub fn reset(&mut self) {
+        for h in self.history.iter_mut() {
+            *h = T::zero();
+        }
+    }
+
+    /// Filters `buf` in place, from excitation to output. `coeffs` are LPC coefficients
+    /// without the leading one, at most `order` of them.
+    pub fn filter_mut(&mut self, coeffs: &[T], buf: &mut [T]) -> VoxBoxResult<()> {
+        if coeffs.len() > self.history.len() {
+            return Err(VoxBoxError::LPC(
+                "More coefficients than the order of the filter",
+            ));
+        }
+        validate::finite(coeffs)?;
+        for x in buf.iter_mut() {
+            let y = coeffs
+                .iter()
+                .zip(self.history.iter())
+                .fold(*x, |acc, (c, h)| acc - *c * *h);
+            if !self.history.is_empty() {
+                self.history.rotate_right(1);
+                self.history[0] = y;
+            }
+            *x = y;
+        }
+        Ok(())
+    }
+
+    /// Filters a block of excitation and returns the output.
+    pub fn filter(&mut self, coeffs: &[T], excitation: &[T]) -> VoxBoxResult<Vec<T>> {
+        let mut out = excitation.to_vec();
+        self.filter_mut(coeffs, &mut out[..])?;
+        Ok(out)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct Resonance<T> {
+    /// Center frequency in Hz
     pub frequency: T,
+    /// 3 dB bandwidth in Hz
     pub bandwidth: T,
 }
 
@@ -164,6 +626,9 @@ impl<T> Resonance<T> {
 }
 
 impl<T: Float + FromPrimitive> Resonance<T> {
+    /// The resonance of a pole of an all-pole filter. The frequency is the angle of the root and
+    /// the bandwidth `-ln|r| * sample_rate / pi`, after reflecting roots outside the unit circle
+    /// into it. Roots below the real axis, or too close to 0 Hz or Nyquist, give `None`.
     pub fn from_root(root: &Complex<T>, sample_rate: T) -> Option<Resonance<T>> {
         let freq_mul: T = T::from_f64(sample_rate.to_f64().unwrap() / (PI * 2f64)).unwrap();
         if root.im >= T::zero() {
@@ -194,6 +659,8 @@ impl<T: Float + FromPrimitive> Resonance<T> {
     }
 }
 
+/// Converts polynomial roots into resonances, sorted by frequency. Roots that don't give a
+/// finite resonance between 50 Hz and 50 Hz below Nyquist are dropped.
 pub trait ToResonance<T> {
     fn to_resonance(&self, sample_rate: T) -> Vec<Resonance<T>>;
 }
@@ -208,24 +675,84 @@ where
             .iter()
             .filter_map(|r| Resonance::<T>::from_root(r, sample_rate))
             .collect();
-        res.sort_by(|a, b| (a.frequency.partial_cmp(&b.frequency)).unwrap());
+        res.sort_by(|a, b| {
+            a.frequency
+                .partial_cmp(&b.frequency)
+                .unwrap_or(Ordering::Equal)
+        });
         res
     }
 }
 
-pub struct FormantFrame<T: Float> {
-    _frequency: T,
+/// One frame of tracked formants, as `FormantExtractor` yields them.
+#[cfg(feature = "formant")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormantFrame<T> {
+    /// Time of the frame in seconds
+    pub time: T,
+    /// F1 to Fn, with a NaN frequency and bandwidth for formants that weren't found
+    pub formants: Vec<Resonance<T>>,
+    /// Level of each formant in dB relative to the strongest one, NaN for missing formants
+    pub amplitudes: Vec<T>,
+}
+
+#[cfg(feature = "formant")]
+impl<T: Float> FormantFrame<T> {
+    /// Builds a frame from its tracked `formants` and all of the `resonances` it was tracked
+    /// from. The amplitudes are read off the spectral envelope of a cascade of those
+    /// resonances, each normalized to unit gain at 0 Hz, at the frequency of each formant.
+    pub fn new(time: T, formants: Vec<Resonance<T>>, resonances: &[Resonance<T>]) -> Self {
+        let poles: Vec<(f64, f64)> = resonances
+            .iter()
+            .map(|r| (r.frequency.to_f64().unwrap(), r.bandwidth.to_f64().unwrap()))
+            .filter(|(f, b)| f.is_finite() && b.is_finite() && *f > 0.)
+            .collect();
+        let level = |f: f64| {
+            poles.iter().fold(0., |acc, (pf, pb)| {
+                let natural = pf * pf + pb * pb / 4.;
+                let denominator = ((natural - f * f).powi(2) + (pb * f).powi(2)).sqrt();
+                acc + 20. * (natural / denominator).log10()
+            })
+        };
+        let levels: Vec<f64> = formants
+            .iter()
+            .map(|r| r.frequency.to_f64().unwrap())
+            .map(|f| if f.is_finite() { level(f) } else { f64::NAN })
+            .collect();
+        let loudest = levels
+            .iter()
+            .filter(|l| l.is_finite())
+            .fold(f64::NEG_INFINITY, |a, b| a.max(*b));
+        FormantFrame {
+            time,
+            formants,
+            amplitudes: levels
+                .into_iter()
+                .map(|l| T::from(l - loudest).unwrap_or_else(T::nan))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "formant")]
+impl<T> AsRef<[Resonance<T>]> for FormantFrame<T> {
+    fn as_ref(&self) -> &[Resonance<T>] {
+        &self.formants
+    }
 }
 
+#[cfg(feature = "formant")]
 pub trait EstimateFormants<T> {
     type FormantSlots;
     fn estimate_formants(&mut self, resonances: &[Resonance<T>]);
 }
 
+#[cfg(feature = "formant")]
 fn diff_func<T: Float>(a: T, b: &T) -> T {
     (a - *b).abs()
 }
 
+#[cfg(feature = "formant")]
 impl<T: Float> EstimateFormants<T> for [Resonance<T>] {
     /// Let's cap things at 6 formants. Give me a ring if you need extra and I can get my guy to
     /// get a few more.
@@ -233,7 +760,20 @@ impl<T: Float> EstimateFormants<T> for [Resonance<T>] {
 
     /// Assumes that [self] is a sequence of Resonances corresponding to either the previous
     /// formant frame or the estimated formants for the next frame.
+    ///
+    /// Resonances with a non-finite frequency are ignored, and if no resonances are left the
+    /// estimates are kept as they are.
     fn estimate_formants(&mut self, resonances: &[Resonance<T>]) {
+        let finite: Vec<Resonance<T>> = resonances
+            .iter()
+            .filter(|r| r.frequency.is_finite())
+            .cloned()
+            .collect();
+        let resonances = &finite[..];
+        if resonances.is_empty() {
+            return;
+        }
+
         let mut slots = Self::FormantSlots::default();
         // Step 2: Get the nearest resonance index for each estimated value
         for (estimate, slot) in self.iter().zip(slots.iter_mut()) {
@@ -267,7 +807,7 @@ impl<T: Float> EstimateFormants<T> for [Resonance<T>] {
             match slots[r] {
                 Some(v) => {
                     // If this resonance is the same as the previous one...
-                    if v == slots[w].unwrap() {
+                    if Some(v) == slots[w] {
                         if diff_func(v.frequency, &self[r].frequency)
                             < diff_func(v.frequency, &self[w].frequency)
                         {
@@ -354,44 +894,393 @@ impl<T: Float> EstimateFormants<T> for [Resonance<T>] {
     }
 }
 
-pub struct FormantExtractor<'a, T: 'a + Float, I: Iterator<Item = &'a [Resonance<T>]>> {
+/// Updates `estimates` with the next frame of resonances, and returns `num_formants` of them,
+/// with NaN for those the frame has no resonance for.
+#[cfg(feature = "formant")]
+fn track_frame<T: Float>(
+    estimates: &mut [Resonance<T>],
+    num_formants: usize,
+    frame: &[Resonance<T>],
+) -> Vec<Resonance<T>> {
+    estimates.estimate_formants(frame);
+    let missing = Resonance::new(T::nan(), T::nan());
+    (0..num_formants)
+        .map(|i| match estimates.get(i) {
+            Some(estimate) if frame.contains(estimate) => *estimate,
+            _ => missing,
+        })
+        .collect()
+}
+
+/// A `FormantExtractor` that owns its state and is fed one frame of resonances at a time, for
+/// pipelines where frames arrive as they are analyzed.
+#[cfg(feature = "formant")]
+#[derive(Clone, Debug)]
+pub struct StreamingFormantExtractor<T> {
+    pub estimates: Vec<Resonance<T>>,
+    num_formants: usize,
+}
+
+#[cfg(feature = "formant")]
+impl<T: Float> StreamingFormantExtractor<T> {
+    pub fn new(num_formants: usize, starting_estimates: Vec<Resonance<T>>) -> Self {
+        StreamingFormantExtractor {
+            estimates: starting_estimates,
+            num_formants,
+        }
+    }
+
+    /// Tracks the formants into the next frame. The output is as for `FormantExtractor`.
+    pub fn push_frame(&mut self, resonances: &[Resonance<T>]) -> Vec<Resonance<T>> {
+        track_frame(&mut self.estimates, self.num_formants, resonances)
+    }
+}
+
+/// Tracks formants through a sequence of frames of resonances. The frames can be anything that
+/// derefs to a slice of resonances, such as `Vec`s, slices or references to either, and are
+/// only taken from the iterator as they are needed, so they can be found lazily.
+///
+/// Frames are yielded as `FormantFrame`s, timed every 10 ms from 0 unless set with `timing`.
+#[cfg(feature = "formant")]
+pub struct FormantExtractor<T, I> {
     pub estimates: Vec<Resonance<T>>,
-    _num_formants: usize,
+    num_formants: usize,
     resonances: I,
-    phantom: PhantomData<&'a T>,
+    start: T,
+    frame_period: T,
+    frames_seen: usize,
 }
 
-impl<'a, T, I> FormantExtractor<'a, T, I>
+#[cfg(feature = "formant")]
+impl<T, I> FormantExtractor<T, I>
 where
-    T: 'a + Float + PartialEq,
-    I: Iterator<Item = &'a [Resonance<T>]>,
+    T: Float + PartialEq,
+    I: Iterator,
+    I::Item: AsRef<[Resonance<T>]>,
 {
-    pub fn new(num_formants: usize, resonances: I, starting_estimates: Vec<Resonance<T>>) -> Self {
+    pub fn new<R>(num_formants: usize, resonances: R, starting_estimates: Vec<Resonance<T>>) -> Self
+    where
+        R: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
         FormantExtractor {
-            _num_formants: num_formants,
-            resonances,
+            num_formants,
+            resonances: resonances.into_iter(),
             estimates: starting_estimates,
-            phantom: PhantomData,
+            start: T::zero(),
+            frame_period: T::from(0.01).unwrap(),
+            frames_seen: 0,
+        }
+    }
+
+    /// Timed for frames `config.hop` apart, each at the middle of its hop, as `Formants` times
+    /// them.
+    pub fn from_config<R>(
+        config: &AnalysisConfig,
+        num_formants: usize,
+        resonances: R,
+        starting_estimates: Vec<Resonance<T>>,
+    ) -> Self
+    where
+        R: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
+        let period = T::from(config.frame_period().0).unwrap();
+        FormantExtractor::new(num_formants, resonances, starting_estimates)
+            .timing(period / T::from(2.).unwrap(), period)
+    }
+
+    /// Sets the time in seconds of the next frame, and the time between frames.
+    pub fn timing(mut self, start: T, frame_period: T) -> Self {
+        self.start = start;
+        self.frame_period = frame_period;
+        self.frames_seen = 0;
+        self
+    }
+
+    /// Tracks the formants over all remaining frames at once, by Viterbi decoding rather than
+    /// frame by frame. The states of each frame are the ways of assigning its resonances to
+    /// `num_formants` slots in frequency order, each slot taking one of the
+    /// `TRACK_CANDIDATES` resonances that fit it best. They are scored by `costs` against the
+    /// starting estimates, and the path with the lowest total cost wins. Slots left over
+    /// when a frame has too few resonances come out with a NaN frequency and bandwidth.
+    ///
+    /// Fails if a starting estimate is not a positive, finite frequency.
+    pub fn track(self, costs: &FormantCosts) -> VoxBoxResult<Vec<FormantFrame<T>>> {
+        let n_slots = self.num_formants.min(self.estimates.len());
+        let references: Vec<f64> = self.estimates[..n_slots]
+            .iter()
+            .map(|e| e.frequency.to_f64().unwrap_or(f64::NAN))
+            .collect();
+        if references.iter().any(|r| !(*r > 0. && r.is_finite())) {
+            return Err(VoxBoxError::Config(
+                "Formant estimates must be positive and finite",
+            ));
+        }
+        let first = self.frames_seen;
+        let (start, frame_period) = (self.start, self.frame_period);
+        let frames: Vec<Vec<Resonance<T>>> = self
+            .resonances
+            .map(|frame| {
+                let mut candidates: Vec<Resonance<T>> = frame
+                    .as_ref()
+                    .iter()
+                    .filter(|r| r.frequency.is_finite() && r.frequency > T::zero())
+                    .cloned()
+                    .collect();
+                candidates.sort_by(|a, b| {
+                    a.frequency
+                        .partial_cmp(&b.frequency)
+                        .unwrap_or(Ordering::Equal)
+                });
+                candidates
+            })
+            .collect();
+        let value = |r: &Resonance<T>| {
+            (
+                r.frequency.to_f64().unwrap(),
+                r.bandwidth.to_f64().unwrap().abs(),
+            )
+        };
+        // Cost of giving candidate `c` of a frame to `slot`
+        let slot_cost = |frame: usize, slot: usize, c: usize| {
+            let (f, b) = value(&frames[frame][c]);
+            costs.frequency * (f - references[slot]).abs() / references[slot]
+                + costs.bandwidth * b / f
+        };
+        let states: Vec<Vec<Vec<Option<usize>>>> = frames
+            .iter()
+            .enumerate()
+            .map(|(k, candidates)| {
+                slot_assignments(candidates.len(), n_slots, |slot, c| slot_cost(k, slot, c))
+            })
+            .collect();
+
+        let local = |frame: usize, state: &[Option<usize>]| {
+            state
+                .iter()
+                .enumerate()
+                .filter_map(|(slot, c)| c.map(|c| slot_cost(frame, slot, c)))
+                .sum::<f64>()
+        };
+        let transition = |frame: usize, from: &[Option<usize>], to: &[Option<usize>]| {
+            from.iter()
+                .zip(to.iter())
+                .filter_map(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => {
+                        Some((value(&frames[frame - 1][*a]).0, value(&frames[frame][*b]).0))
+                    }
+                    _ => None,
+                })
+                .fold(0., |acc, (a, b)| {
+                    acc + costs.transition * (b / a).ln().abs()
+                })
+        };
+
+        let mut back: Vec<Vec<usize>> = Vec::with_capacity(frames.len());
+        let mut totals: Vec<f64> = Vec::new();
+        for (k, frame_states) in states.iter().enumerate() {
+            if k == 0 {
+                totals = frame_states.iter().map(|s| local(0, s)).collect();
+                back.push(Vec::new());
+                continue;
+            }
+            let mut next = Vec::with_capacity(frame_states.len());
+            let mut from = Vec::with_capacity(frame_states.len());
+            for state in frame_states {
+                let (best, index) = states[k - 1]
+                    .iter()
+                    .zip(totals.iter())
+                    .enumerate()
+                    .map(|(i, (prev, total))| (total + transition(k, prev, state), i))
+                    .fold((f64::INFINITY, 0), |a, b| if b.0 < a.0 { b } else { a });
+                next.push(best + local(k, state));
+                from.push(index);
+            }
+            totals = next;
+            back.push(from);
+        }
+
+        let missing = Resonance::new(T::nan(), T::nan());
+        let mut tracks = vec![Vec::new(); frames.len()];
+        let mut state = totals
+            .iter()
+            .enumerate()
+            .fold(
+                (0, f64::INFINITY),
+                |a, (i, c)| if *c < a.1 { (i, *c) } else { a },
+            )
+            .0;
+        for k in (0..frames.len()).rev() {
+            tracks[k] = states[k][state]
+                .iter()
+                .map(|c| c.map_or(missing, |c| frames[k][c]))
+                .collect();
+            if k > 0 {
+                state = back[k][state];
+            }
         }
+        Ok(tracks
+            .into_iter()
+            .zip(frames.iter())
+            .enumerate()
+            .map(|(k, (track, frame))| {
+                let time = start + T::from(first + k).unwrap() * frame_period;
+                FormantFrame::new(time, track, frame)
+            })
+            .collect())
+    }
+}
+
+/// Weights of the costs that `FormantExtractor::track` trades off, as in Praat's formant
+/// tracker. Each is per formant and unitless.
+#[cfg(feature = "formant")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FormantCosts {
+    /// Relative deviation of a formant from its starting estimate
+    pub frequency: f64,
+    /// Bandwidth of a formant relative to its frequency, which penalizes broad spurious poles
+    pub bandwidth: f64,
+    /// Change of a formant between frames, in natural-log units of frequency
+    pub transition: f64,
+}
+
+#[cfg(feature = "formant")]
+impl Default for FormantCosts {
+    fn default() -> Self {
+        FormantCosts {
+            frequency: 1.,
+            bandwidth: 1.,
+            transition: 1.,
+        }
+    }
+}
+
+/// Resonances each formant slot chooses from in `FormantExtractor::track`, the ones it costs
+/// least to assign it on their own. This bounds the states of a frame by
+/// `(TRACK_CANDIDATES + 1)` to the power of the number of slots.
+#[cfg(feature = "formant")]
+pub const TRACK_CANDIDATES: usize = 2;
+
+/// Ways of assigning `n_candidates` frequency-sorted candidates to `n_slots` formant slots in
+/// order, each slot taking none or one of the `TRACK_CANDIDATES` candidates with the lowest
+/// `cost(slot, candidate)`, and using as many candidates as possible.
+#[cfg(feature = "formant")]
+fn slot_assignments<C>(n_candidates: usize, n_slots: usize, cost: C) -> Vec<Vec<Option<usize>>>
+where
+    C: Fn(usize, usize) -> f64,
+{
+    let nearest: Vec<Vec<usize>> = (0..n_slots)
+        .map(|slot| {
+            let mut indices: Vec<usize> = (0..n_candidates).collect();
+            indices.sort_by(|a, b| cost(slot, *a).total_cmp(&cost(slot, *b)));
+            indices.truncate(TRACK_CANDIDATES);
+            indices
+        })
+        .collect();
+
+    // Each partial assignment keeps the last candidate it used, which later slots must exceed
+    let mut assignments: Vec<(Vec<Option<usize>>, Option<usize>)> = vec![(Vec::new(), None)];
+    for choices in nearest.iter() {
+        assignments = assignments
+            .into_iter()
+            .flat_map(|(assignment, last)| {
+                std::iter::once(None)
+                    .chain(
+                        choices
+                            .iter()
+                            .filter(move |c| last.is_none_or(|last| **c > last))
+                            .map(|c| Some(*c)),
+                    )
+                    .map(move |choice| {
+                        let mut assignment = assignment.clone();
+                        assignment.push(choice);
+                        (assignment, choice.or(last))
+                    })
+            })
+            .collect();
     }
+    let used = |assignment: &[Option<usize>]| assignment.iter().filter(|c| c.is_some()).count();
+    let most = assignments.iter().map(|(a, _)| used(a)).max().unwrap_or(0);
+    assignments
+        .into_iter()
+        .filter(|(a, _)| used(a) == most)
+        .map(|(a, _)| a)
+        .collect()
 }
 
-impl<'a, T, I> Iterator for FormantExtractor<'a, T, I>
+#[cfg(feature = "formant")]
+impl<T, I> Iterator for FormantExtractor<T, I>
 where
-    T: 'a + Float + PartialEq,
-    I: Iterator<Item = &'a [Resonance<T>]>,
+    T: Float + PartialEq,
+    I: Iterator,
+    I::Item: AsRef<[Resonance<T>]>,
 {
-    type Item = Vec<Resonance<T>>;
+    type Item = FormantFrame<T>;
 
+    /// Emits exactly `num_formants` formants per frame. Formants that the frame has no
+    /// resonance for, or that have no starting estimate, come out with a NaN frequency and
+    /// bandwidth; the tracker keeps its last estimate for them.
     fn next(&mut self) -> Option<Self::Item> {
         let frame = self.resonances.next()?;
-        self.estimates[..].estimate_formants(frame);
-        Some(self.estimates.clone())
+        let frame = frame.as_ref();
+        let formants = track_frame(&mut self.estimates, self.num_formants, frame);
+        let time = self.start + T::from(self.frames_seen).unwrap() * self.frame_period;
+        self.frames_seen += 1;
+        Some(FormantFrame::new(time, formants, frame))
     }
 }
 
+#[cfg(feature = "mfcc")]
 pub trait MFCC<T> {
     fn mfcc(&self, num_coeffs: usize, freq_bounds: (f64, f64), sample_rate: f64) -> Vec<T>;
+
+    /// MFCCs of an unwindowed frame: windowed by `config.window`, zero-padded to
+    /// `config.fft_size` and passed through `MelFilterBank::mfcc`. Frames longer than the FFT
+    /// size are an error.
+    fn mfcc_with_config(&self, config: &MfccConfig) -> VoxBoxResult<Vec<T>>;
+}
+
+/// Settings for `MFCC::mfcc_with_config`.
+#[cfg(feature = "mfcc")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MfccConfig {
+    pub sample_rate: f64,
+    /// Length of the transform, at least the frame length
+    pub fft_size: usize,
+    pub window: WindowType,
+    /// Number of mel filters, at least `num_coeffs`
+    pub num_filters: usize,
+    pub num_coeffs: usize,
+    /// Range covered by the filterbank, in Hz
+    pub freq_bounds: (f64, f64),
+    pub scale: MelScale,
+}
+
+#[cfg(feature = "mfcc")]
+impl MfccConfig {
+    /// 13 coefficients from 26 HTK filters between 133 and 6855 Hz, or Nyquist if lower, over
+    /// a 512-point transform of Hanning-windowed frames.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        MfccConfig {
+            sample_rate,
+            fft_size: 512,
+            window: WindowType::Hanning,
+            num_filters: 26,
+            num_coeffs: 13,
+            freq_bounds: (133., (sample_rate / 2.).min(6855.)),
+            scale: MelScale::Htk,
+        }
+    }
+
+    /// The defaults, with the window of `config` and the FFT size rounded up from its frame
+    /// length.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        MfccConfig {
+            fft_size: config.frame_len.next_power_of_two(),
+            window: config.window,
+            ..MfccConfig::new(config.sample_rate)
+        }
+    }
 }
 
 pub fn hz_to_mel(hz: f64) -> f64 {
@@ -402,88 +1291,1207 @@ pub fn mel_to_hz(mel: f64) -> f64 {
     700. * ((mel / 1125.).exp() - 1.)
 }
 
-/// Takes the Discrete Cosine Transform of a slice. Allocates its own output memory.
-pub fn dct<T: FromPrimitive + ToPrimitive + Float>(signal: &[T]) -> Vec<T> {
-    let mut out = vec![T::zero(); signal.len()];
-    dct_mut(signal, &mut out[..]);
-    out
-}
-
-/// Takes the Discrete Cosine Transform and saves coefficients into a mutable slice.
-pub fn dct_mut<T: FromPrimitive + ToPrimitive + Float>(signal: &[T], coeffs: &mut [T]) {
-    assert!(coeffs.len() >= signal.len());
-    for (k, coeff) in coeffs.iter_mut().take(signal.len()).enumerate() {
-        *coeff = T::from_f64(
-            2. * (0..signal.len()).fold(0., |acc, n| {
-                acc + signal[n].to_f64().unwrap()
-                    * (PI * k as f64 * (2. * n as f64 + 1.) / (2. * signal.len() as f64)).cos()
-            }),
-        )
-        .unwrap();
+/// Conventions for the mel scale and the filters spaced on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MelScale {
+    /// `hz_to_mel`, with filters that peak at 1, as in HTK and Kaldi
+    Htk,
+    /// Slaney's Auditory Toolbox scale, linear below 1 kHz and logarithmic above, with filters
+    /// normalized to unit area in Hz, as in librosa's defaults
+    Slaney,
+}
+
+/// Slaney mels per Hz below 1 kHz, and per natural-log unit of frequency above it.
+const SLANEY_LINEAR: f64 = 3. / 200.;
+const SLANEY_LOG: f64 = 27. / 1.856_297_990_365_626_4; // 27 / ln(6.4)
+
+impl MelScale {
+    pub fn to_mel(self, hz: f64) -> f64 {
+        match self {
+            MelScale::Htk => hz_to_mel(hz),
+            MelScale::Slaney if hz < 1000. => hz * SLANEY_LINEAR,
+            MelScale::Slaney => 15. + (hz / 1000.).ln() * SLANEY_LOG,
+        }
+    }
+
+    pub fn to_hz(self, mel: f64) -> f64 {
+        match self {
+            MelScale::Htk => mel_to_hz(mel),
+            MelScale::Slaney if mel < 15. => mel / SLANEY_LINEAR,
+            MelScale::Slaney => 1000. * ((mel - 15.) / SLANEY_LOG).exp(),
+        }
     }
 }
 
-/// MFCC assumes that it is a windowed signal
-impl<T: ?Sized> MFCC<T> for [T]
-where
-    T: fft::FFTnum + Debug + Float + ToPrimitive + FromPrimitive + Into<Complex<T>> + Zero + Signed,
-{
-    fn mfcc(&self, num_coeffs: usize, freq_bounds: (f64, f64), sample_rate: f64) -> Vec<T> {
-        let mel_range = hz_to_mel(freq_bounds.1) - hz_to_mel(freq_bounds.0);
-        // Still an iterator
-        let points = (0..(num_coeffs + 2))
-            .map(|i| (i as f64 / num_coeffs as f64) * mel_range + hz_to_mel(freq_bounds.0));
-        let bins: Vec<usize> = points
-            .map(|point| {
-                ((self.len() + 1) as f64 * mel_to_hz(point) / sample_rate).floor() as usize
+/// Triangular filters spaced evenly on the mel scale, precomputed for one FFT length and
+/// sample rate so that they can be applied to any number of power spectra. Each filter reaches
+/// 0 at the centers of its neighbours, and peaks at 1 at its own or, on the Slaney scale, has
+/// unit area.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MelFilterBank {
+    n_bins: usize,
+    /// First bin and weights of each filter, leaving out the bins where it is zero
+    filters: Vec<(usize, Vec<f64>)>,
+}
+
+impl MelFilterBank {
+    /// `n_filters` HTK filters between `freq_bounds` in Hz, over the `fft_len / 2 + 1` bins of
+    /// an `fft_len`-point power spectrum.
+    pub fn new<R: Into<Hz>>(
+        n_filters: usize,
+        fft_len: usize,
+        freq_bounds: (f64, f64),
+        sample_rate: R,
+    ) -> VoxBoxResult<MelFilterBank> {
+        MelFilterBank::with_scale(n_filters, fft_len, freq_bounds, sample_rate, MelScale::Htk)
+    }
+
+    /// Like `new`, on the given mel scale.
+    pub fn with_scale<R: Into<Hz>>(
+        n_filters: usize,
+        fft_len: usize,
+        freq_bounds: (f64, f64),
+        sample_rate: R,
+        scale: MelScale,
+    ) -> VoxBoxResult<MelFilterBank> {
+        let sample_rate = sample_rate.into().0;
+        validate::positive(sample_rate, "Sample rate must be positive")?;
+        let (low, high) = freq_bounds;
+        if !(low >= 0. && low < high && high <= sample_rate / 2.) {
+            return Err(VoxBoxError::Config(
+                "Mel filterbank must lie between 0 Hz and Nyquist",
+            ));
+        }
+        let (mel_low, mel_high) = (scale.to_mel(low), scale.to_mel(high));
+        let edges: Vec<f64> = (0..n_filters + 2)
+            .map(|i| {
+                scale.to_hz(mel_low + (mel_high - mel_low) * i as f64 / (n_filters + 1) as f64)
             })
             .collect();
+        let n_bins = fft_len / 2 + 1;
+        let filters = edges
+            .windows(3)
+            .map(|edge| {
+                let height = match scale {
+                    MelScale::Htk => 1.,
+                    MelScale::Slaney => 2. / (edge[2] - edge[0]),
+                };
+                let weights: Vec<f64> = (0..n_bins)
+                    .map(|k| {
+                        let f = k as f64 * sample_rate / fft_len as f64;
+                        let rising = (f - edge[0]) / (edge[1] - edge[0]);
+                        let falling = (edge[2] - f) / (edge[2] - edge[1]);
+                        height * rising.min(falling).max(0.)
+                    })
+                    .collect();
+                let first = weights.iter().position(|w| *w > 0.).unwrap_or(0);
+                let last = weights.iter().rposition(|w| *w > 0.).map_or(0, |l| l + 1);
+                (first, weights[first..last.max(first)].to_vec())
+            })
+            .collect();
+        Ok(MelFilterBank { n_bins, filters })
+    }
 
-        let mut spectrum = vec![Complex::<T>::from(T::zero()); self.len()];
-        let fft: Box<dyn fft::FFT<T>> = Box::new(fft::algorithm::Radix4::new(self.len(), false));
-        let mut signal: Vec<Complex<T>> = self.iter().map(Complex::<T>::from).collect();
-        fft.process(signal.as_mut_slice(), spectrum.as_mut_slice());
-
-        let energy_map = |window: &[usize]| -> T {
-            let up = window[1] - window[0];
+    pub fn n_filters(&self) -> usize {
+        self.filters.len()
+    }
 
-            let up_sum = (window[0]..window[1])
-                .enumerate()
-                .fold(0f64, |acc, (i, bin)| {
-                    let multiplier = i as f64 / up as f64;
-                    acc + spectrum[bin].norm_sqr().to_f64().unwrap().abs() * multiplier
-                });
+    /// Number of bins the power spectra must have.
+    pub fn n_bins(&self) -> usize {
+        self.n_bins
+    }
 
-            let down = window[2] - window[1];
-            let down_sum = (window[1]..window[2])
-                .enumerate()
-                .fold(0f64, |acc, (i, bin)| {
-                    let multiplier = i as f64 / down as f64;
-                    acc + spectrum[bin].norm().to_f64().unwrap().abs() * multiplier
-                });
-            T::from_f64((up_sum + down_sum).log10().max(1.0e-10))
-                .unwrap_or_else(|| T::from_f32(1.0e-10).unwrap())
-        };
+    /// The first bin and the nonzero weights of filter `m`.
+    pub fn filter(&self, m: usize) -> Option<(usize, &[f64])> {
+        self.filters.get(m).map(|(first, w)| (*first, &w[..]))
+    }
 
-        let energies: Vec<T> = bins.windows(3).map(&energy_map).collect();
+    /// The power of each band of a power spectrum with `n_bins` bins.
+    pub fn apply(&self, power: &[f64]) -> VoxBoxResult<Vec<f64>> {
+        if power.len() != self.n_bins {
+            return Err(VoxBoxError::Input(
+                "Power spectrum must have one bin per filterbank bin",
+            ));
+        }
+        Ok(self
+            .filters
+            .iter()
+            .map(|(first, weights)| {
+                weights
+                    .iter()
+                    .zip(power[*first..].iter())
+                    .fold(0., |acc, (w, p)| acc + w * p)
+            })
+            .collect())
+    }
 
-        dct(&energies[..])
+    /// The first `num_coeffs` MFCCs of a power spectrum: the `dct` of the log band powers,
+    /// floored at `MEL_LOG_FLOOR`.
+    pub fn mfcc(&self, power: &[f64], num_coeffs: usize) -> VoxBoxResult<Vec<f64>> {
+        if num_coeffs > self.n_filters() {
+            return Err(VoxBoxError::Config(
+                "Cannot have more coefficients than mel filters",
+            ));
+        }
+        let energies: Vec<f64> = self
+            .apply(power)?
+            .into_iter()
+            .map(|e| e.max(MEL_LOG_FLOOR).ln())
+            .collect();
+        let mut coeffs = dct(&energies[..]);
+        coeffs.truncate(num_coeffs);
+        Ok(coeffs)
     }
 }
 
-#[cfg(test)]
-mod test {
-    extern crate rand;
+/// Settings for `mel_spectrogram`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MelConfig {
+    pub sample_rate: f64,
+    pub stft: Stft,
+    pub n_mels: usize,
+    /// Range covered by the filterbank, in Hz
+    pub freq_bounds: (f64, f64),
+    pub scale: MelScale,
+    /// Whether to take the natural log of each band's power, floored at `MEL_LOG_FLOOR`
+    pub log: bool,
+}
+
+/// Smallest power that `mel_spectrogram` takes the log of.
+pub const MEL_LOG_FLOOR: f64 = 1.0e-10;
+
+impl MelConfig {
+    /// 40 log-mel HTK bands from 0 Hz to Nyquist over 25 ms Hanning frames every 10 ms.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        MelConfig::from_config(&AnalysisConfig::new(sample_rate))
+    }
+
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        MelConfig {
+            sample_rate: config.sample_rate.0,
+            stft: Stft::from_config(config),
+            n_mels: 40,
+            freq_bounds: (0., config.sample_rate.0 / 2.),
+            scale: MelScale::Htk,
+            log: true,
+        }
+    }
+}
+
+/// Mel spectrogram of a signal: the power of each STFT frame summed through a `MelFilterBank`,
+/// one row of `n_mels` bands per frame.
+pub fn mel_spectrogram<S>(signal: &[S], config: &MelConfig) -> VoxBoxResult<FeatureMatrix>
+where
+    S: Sample + ToSample<f64>,
+{
+    let filterbank = MelFilterBank::with_scale(
+        config.n_mels,
+        config.stft.fft_len,
+        config.freq_bounds,
+        config.sample_rate,
+        config.scale,
+    )?;
+    let mut spectrogram = FeatureMatrix::new(config.n_mels);
+    for frame in config.stft.frames(signal)? {
+        let power: Vec<f64> = frame.iter().map(|bin| bin.norm_sqr()).collect();
+        let mut bands = filterbank.apply(&power)?;
+        if config.log {
+            for band in bands.iter_mut() {
+                *band = band.max(MEL_LOG_FLOOR).ln();
+            }
+        }
+        spectrogram.push_row(&bands)?;
+    }
+    Ok(spectrogram)
+}
+
+/// Takes the unnormalized type II Discrete Cosine Transform of a slice. Allocates its own
+/// output memory. See the `dct` module for the other types and scalings.
+pub fn dct<T: FromPrimitive + ToPrimitive + Float>(signal: &[T]) -> Vec<T> {
+    crate::dct::transform(signal, DctType::II, DctNorm::Unnormalized)
+}
+
+/// Takes the Discrete Cosine Transform as in `dct` and saves coefficients into a mutable slice,
+/// which must be at least as long as the signal.
+pub fn dct_mut<T: FromPrimitive + ToPrimitive + Float>(
+    signal: &[T],
+    coeffs: &mut [T],
+) -> VoxBoxResult<()> {
+    validate::workspace(coeffs, signal.len())?;
+    coeffs[..signal.len()].copy_from_slice(&dct(signal));
+    Ok(())
+}
+
+/// Power spectrum of a frame, zero-padded to `fft_len`. Returns `fft_len / 2 + 1` bins.
+///
+/// Without rustfft this is a direct DFT, which is slower but fine for short frames.
+pub fn power_spectrum<T: Float + FromPrimitive>(
+    frame: &[T],
+    fft_len: usize,
+) -> VoxBoxResult<Vec<T>> {
+    if fft_len == 0 || fft_len < frame.len() {
+        return Err(VoxBoxError::Config(
+            "FFT length must be nonzero and at least the frame length",
+        ));
+    }
+    let frame: Vec<f64> = frame.iter().map(|s| s.to_f64().unwrap_or(0.)).collect();
+    Ok(RealFft::new(fft_len)
+        .power_spectrum(&frame)
+        .into_iter()
+        .map(|p| T::from_f64(p).unwrap_or_else(T::zero))
+        .collect())
+}
+
+/// An FFT of real frames and its inverse, planned once for a fixed length.
+///
+/// An even length is transformed as a complex FFT of half the length, with the even samples as
+/// the real parts and the odd samples as the imaginary parts; the spectra of the two are then
+/// separated and combined with one butterfly per bin.
+#[derive(Clone)]
+pub(crate) struct RealFft {
+    len: usize,
+    /// `exp(-2 pi i k / len)`
+    twiddles: Vec<Complex<f64>>,
+    #[cfg(feature = "rustfft")]
+    plan: std::sync::Arc<dyn fft::FFT<f64>>,
+}
+
+impl fmt::Debug for RealFft {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RealFft").field("len", &self.len).finish()
+    }
+}
+
+impl RealFft {
+    pub(crate) fn new(len: usize) -> Self {
+        RealFft {
+            len,
+            twiddles: (0..len)
+                .map(|i| Complex::from_polar(&1., &(-2. * PI * i as f64 / len as f64)))
+                .collect(),
+            #[cfg(feature = "rustfft")]
+            plan: fft::FFTplanner::new(false).plan_fft(if RealFft::packs(len) {
+                len / 2
+            } else {
+                len
+            }),
+        }
+    }
+
+    /// The length of the frames it transforms.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the transform can be done at half the length.
+    #[cfg(feature = "rustfft")]
+    fn packs(len: usize) -> bool {
+        len >= 2 && len.is_multiple_of(2)
+    }
+
+    /// The first `len / 2 + 1` bins of the spectrum of `frame`, zero-padded to `len`.
+    #[cfg(feature = "rustfft")]
+    pub(crate) fn transform(&self, frame: &[f64]) -> Vec<Complex<f64>> {
+        let sample = |n: usize| frame.get(n).cloned().unwrap_or(0.);
+        if !RealFft::packs(self.len) {
+            let mut signal: Vec<Complex<f64>> =
+                (0..self.len).map(|n| Complex::new(sample(n), 0.)).collect();
+            let mut spectrum = signal.clone();
+            self.plan.process(&mut signal[..], &mut spectrum[..]);
+            spectrum.truncate(self.len / 2 + 1);
+            return spectrum;
+        }
+
+        let half = self.len / 2;
+        let mut packed: Vec<Complex<f64>> = (0..half)
+            .map(|n| Complex::new(sample(2 * n), sample(2 * n + 1)))
+            .collect();
+        let mut spectrum = packed.clone();
+        self.plan.process(&mut packed[..], &mut spectrum[..]);
+        (0..=half)
+            .map(|k| {
+                let z = spectrum[k % half];
+                let mirrored = spectrum[(half - k) % half].conj();
+                let even = (z + mirrored) * 0.5;
+                let odd = (z - mirrored) * Complex::new(0., -0.5);
+                even + self.twiddles[k] * odd
+            })
+            .collect()
+    }
+
+    /// Without rustfft the transform is a direct DFT, which is slower but fine for short frames.
+    #[cfg(not(feature = "rustfft"))]
+    pub(crate) fn transform(&self, frame: &[f64]) -> Vec<Complex<f64>> {
+        (0..=self.len / 2)
+            .map(|k| {
+                frame
+                    .iter()
+                    .enumerate()
+                    .fold(Complex::new(0., 0.), |acc, (n, s)| {
+                        acc + self.twiddles[(k * n) % self.len] * s
+                    })
+            })
+            .collect()
+    }
+
+    /// Squared magnitudes of the bins of `transform`.
+    pub(crate) fn power_spectrum(&self, frame: &[f64]) -> Vec<f64> {
+        self.transform(frame)
+            .iter()
+            .map(|bin| bin.norm_sqr())
+            .collect()
+    }
+
+    /// The `len` samples whose spectrum starts with the `len / 2 + 1` bins of `spectrum`, the
+    /// rest being their conjugates. Undoes `transform`; the imaginary parts of the DC and Nyquist
+    /// bins are ignored.
+    #[cfg(feature = "rustfft")]
+    pub(crate) fn inverse(&self, spectrum: &[Complex<f64>]) -> Vec<f64> {
+        let bin = |k: usize| {
+            if k == 0 || 2 * k == self.len {
+                Complex::new(spectrum[k].re, 0.)
+            } else if 2 * k < self.len {
+                spectrum[k]
+            } else {
+                spectrum[self.len - k].conj()
+            }
+        };
+        // Inverse transforms are taken as the conjugate of the forward transform of the conjugate
+        if !RealFft::packs(self.len) {
+            let mut conjugated: Vec<Complex<f64>> = (0..self.len).map(|k| bin(k).conj()).collect();
+            let mut signal = conjugated.clone();
+            self.plan.process(&mut conjugated[..], &mut signal[..]);
+            return signal.iter().map(|c| c.re / self.len as f64).collect();
+        }
+
+        // Split the spectrum into those of the even and odd samples, and pack them as in
+        // `transform`
+        let half = self.len / 2;
+        let mut packed: Vec<Complex<f64>> = (0..half)
+            .map(|k| {
+                let z = bin(k);
+                let mirrored = bin(half - k).conj();
+                let even = (z + mirrored) * 0.5;
+                let odd = (z - mirrored) * self.twiddles[k].conj() * 0.5;
+                (even + Complex::new(0., 1.) * odd).conj()
+            })
+            .collect();
+        let mut signal = packed.clone();
+        self.plan.process(&mut packed[..], &mut signal[..]);
+        signal
+            .iter()
+            .flat_map(|c| vec![c.re / half as f64, -c.im / half as f64])
+            .collect()
+    }
+
+    #[cfg(not(feature = "rustfft"))]
+    pub(crate) fn inverse(&self, spectrum: &[Complex<f64>]) -> Vec<f64> {
+        (0..self.len)
+            .map(|n| {
+                let sum = spectrum
+                    .iter()
+                    .take(self.len / 2 + 1)
+                    .enumerate()
+                    .map(|(k, bin)| {
+                        // Bins other than DC and Nyquist stand for a conjugate pair
+                        let weight = if k == 0 || 2 * k == self.len { 1. } else { 2. };
+                        weight * (bin * self.twiddles[(k * n) % self.len].conj()).re
+                    })
+                    .sum::<f64>();
+                sum / self.len as f64
+            })
+            .collect()
+    }
+}
+
+/// Short-time Fourier transform settings. Frame `k` starts at sample `k * hop` and is windowed,
+/// zero-padded to `fft_len` and transformed, as `AnalysisConfig` frames a signal.
+///
+/// ```
+/// use vox_box::spectrum::Stft;
+///
+/// let signal: Vec<f64> = (0..1024).map(|n| (n as f64 * 0.5).sin()).collect();
+/// let frames: Vec<_> = Stft::new(256, 128).frames(&signal).unwrap().collect();
+/// assert_eq!(frames.len(), 8);
+/// assert_eq!(frames[0].len(), 129);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stft {
+    /// Length of each frame, in samples
+    pub frame_len: usize,
+    /// Distance between the starts of consecutive frames, in samples
+    pub hop: usize,
+    /// Length of the transform, at least `frame_len`
+    pub fft_len: usize,
+    pub window: WindowType,
+    pub scaling: SpectrumScaling,
+}
+
+/// How the bins of a spectrum are scaled to correct for the window and the transform length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpectrumScaling {
+    /// The plain transform of the windowed frame
+    Unscaled,
+    /// Corrected for the window's coherent gain, so that a sinusoid on a bin has the magnitude
+    /// of its amplitude
+    Amplitude,
+    /// Corrected for the window's energy, so that the squared magnitudes of all bins sum to the
+    /// mean square of a steady signal
+    Energy,
+}
+
+impl Stft {
+    /// Hanning frames, transformed at the next power of two of their length.
+    pub fn new(frame_len: usize, hop: usize) -> Self {
+        Stft {
+            frame_len,
+            hop,
+            fft_len: frame_len.next_power_of_two(),
+            window: WindowType::Hanning,
+            scaling: SpectrumScaling::Unscaled,
+        }
+    }
+
+    /// The framing of `config`; its pre-emphasis is not applied.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Stft {
+            window: config.window,
+            ..Stft::new(config.frame_len, config.hop)
+        }
+    }
+
+    /// Number of bins in each frame: `fft_len / 2 + 1`, from 0 Hz to Nyquist.
+    pub fn n_bins(&self) -> usize {
+        self.fft_len / 2 + 1
+    }
+
+    /// Center frequency in Hz of bin `k`.
+    pub fn bin_frequency<R: Into<Hz>>(&self, k: usize, sample_rate: R) -> f64 {
+        let sample_rate = sample_rate.into().0;
+        k as f64 * sample_rate / self.fft_len as f64
+    }
+
+    /// Number of frames needed to cover a signal of `len` samples.
+    pub fn n_frames(&self, len: usize) -> usize {
+        len.div_ceil(self.hop.max(1))
+    }
+
+    /// The spectra of the frames of `signal`, computed as the iterator is advanced.
+    pub fn frames<'a, S>(&self, signal: &'a [S]) -> VoxBoxResult<StftFrames<'a, S>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        if self.frame_len == 0 || self.hop == 0 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        if self.fft_len < self.frame_len {
+            return Err(VoxBoxError::Config(
+                "FFT length must be at least the frame length",
+            ));
+        }
+        let window = self.window.window(self.frame_len);
+        Ok(StftFrames {
+            signal,
+            hop: self.hop,
+            fft: RealFft::new(self.fft_len),
+            scale: self.bin_scales(&window),
+            window,
+            next: 0,
+            n_frames: self.n_frames(signal.len()),
+        })
+    }
+
+    /// The signal of `len` samples whose frames have the spectra `spectra`, undoing `frames`
+    /// by weighted overlap-add: each frame is transformed back, windowed again and added in,
+    /// and the sum divided by the summed squared windows. Spectra changed after `frames`, as
+    /// by a noise reducer, come back as the signal nearest to having them. Samples no window
+    /// reaches are zero.
+    pub fn inverse(&self, spectra: &[Vec<Complex<f64>>], len: usize) -> VoxBoxResult<Vec<f64>> {
+        if self.frame_len == 0 || self.hop == 0 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        if self.fft_len < self.frame_len {
+            return Err(VoxBoxError::Config(
+                "FFT length must be at least the frame length",
+            ));
+        }
+        if spectra
+            .iter()
+            .any(|spectrum| spectrum.len() != self.n_bins())
+        {
+            return Err(VoxBoxError::Input("Spectra must have one value per bin"));
+        }
+        let window = self.window.window(self.frame_len);
+        let scale = self.bin_scales(&window);
+        let fft = RealFft::new(self.fft_len);
+        let mut signal = vec![0.; len];
+        let mut weight = vec![0.; len];
+        for (k, spectrum) in spectra.iter().enumerate() {
+            let unscaled: Vec<Complex<f64>> = spectrum
+                .iter()
+                .zip(scale.iter())
+                .map(|(bin, scale)| bin / scale)
+                .collect();
+            let frame = fft.inverse(&unscaled);
+            let start = k * self.hop;
+            for (n, (x, w)) in frame.iter().zip(window.iter()).enumerate() {
+                if let Some(s) = signal.get_mut(start + n) {
+                    *s += x * w;
+                    weight[start + n] += w * w;
+                }
+            }
+        }
+        for (s, w) in signal.iter_mut().zip(weight.iter()) {
+            *s = if *w > 1.0e-10 { *s / w } else { 0. };
+        }
+        Ok(signal)
+    }
+
+    /// Factor each bin is scaled by, for `scaling` with `window`.
+    fn bin_scales(&self, window: &[f64]) -> Vec<f64> {
+        // Bins other than DC and Nyquist stand for a conjugate pair, so carry twice the share
+        let (edge, inner) = match self.scaling {
+            SpectrumScaling::Unscaled => (1., 1.),
+            SpectrumScaling::Amplitude => {
+                let sum = window.iter().sum::<f64>();
+                (1. / sum, 2. / sum)
+            }
+            SpectrumScaling::Energy => {
+                let energy = self.fft_len as f64 * window.iter().map(|w| w * w).sum::<f64>();
+                ((1. / energy).sqrt(), (2. / energy).sqrt())
+            }
+        };
+        let fft_len = self.fft_len;
+        (0..self.n_bins())
+            .map(|k| {
+                if k == 0 || 2 * k == fft_len {
+                    edge
+                } else {
+                    inner
+                }
+            })
+            .collect()
+    }
+}
+
+/// Iterator over the complex spectra of a signal, from `Stft::frames`. Each holds
+/// `Stft::n_bins` bins.
+pub struct StftFrames<'a, S> {
+    signal: &'a [S],
+    hop: usize,
+    window: Vec<f64>,
+    fft: RealFft,
+    /// Factor for each bin, from `Stft::scaling`
+    scale: Vec<f64>,
+    next: usize,
+    n_frames: usize,
+}
+
+impl<'a, S> Iterator for StftFrames<'a, S>
+where
+    S: Sample + ToSample<f64>,
+{
+    type Item = Vec<Complex<f64>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.n_frames {
+            return None;
+        }
+        let start = self.next * self.hop;
+        self.next += 1;
+        let frame: Vec<f64> = self
+            .window
+            .iter()
+            .zip(self.signal.iter().skip(start))
+            .map(|(w, s)| w * s.to_sample::<f64>())
+            .collect();
+        let mut spectrum = self.fft.transform(&frame);
+        for (bin, scale) in spectrum.iter_mut().zip(self.scale.iter()) {
+            *bin *= scale;
+        }
+        Some(spectrum)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.n_frames - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, S> ExactSizeIterator for StftFrames<'a, S> where S: Sample + ToSample<f64> {}
+
+/// The first four moments of a spectrum treated as a distribution over frequency, as used to
+/// characterize fricatives (Forrest et al. 1988).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpectralMoments {
+    /// Mean frequency in Hz
+    pub centroid: f64,
+    /// Standard deviation of the frequency in Hz
+    pub spread: f64,
+    pub skewness: f64,
+    /// Excess kurtosis, 0 for a normal distribution
+    pub kurtosis: f64,
+}
+
+impl SpectralMoments {
+    /// Moments of a one-sided spectrum with bins `bin_width` Hz apart, weighting each bin by its
+    /// value. Pass magnitudes, or a power spectrum for the power-weighted moments that Praat
+    /// reports by default. `None` if the spectrum has no energy.
+    pub fn from_spectrum(spectrum: &[f64], bin_width: f64) -> Option<SpectralMoments> {
+        let total: f64 = spectrum.iter().sum();
+        if total <= 0. || !total.is_finite() {
+            return None;
+        }
+        let central = |power: i32, center: f64| {
+            spectrum.iter().enumerate().fold(0., |acc, (k, w)| {
+                acc + w * (k as f64 * bin_width - center).powi(power)
+            }) / total
+        };
+        let centroid = central(1, 0.);
+        let variance = central(2, centroid);
+        let spread = variance.sqrt();
+        let (skewness, kurtosis) = if variance > 0. {
+            (
+                central(3, centroid) / (variance * spread),
+                central(4, centroid) / (variance * variance) - 3.,
+            )
+        } else {
+            (0., 0.)
+        };
+        Some(SpectralMoments {
+            centroid,
+            spread,
+            skewness,
+            kurtosis,
+        })
+    }
+}
+
+/// Spectral moments of the magnitude spectrum of every STFT frame of a signal. Silent frames
+/// get NaN for every moment, so that the track stays aligned with the frames.
+pub fn spectral_moments<S, R: Into<Hz>>(
+    signal: &[S],
+    stft: &Stft,
+    sample_rate: R,
+) -> VoxBoxResult<Vec<SpectralMoments>>
+where
+    S: Sample + ToSample<f64>,
+{
+    let sample_rate = sample_rate.into().0;
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    let bin_width = stft.bin_frequency(1, sample_rate);
+    let silent = SpectralMoments {
+        centroid: f64::NAN,
+        spread: f64::NAN,
+        skewness: f64::NAN,
+        kurtosis: f64::NAN,
+    };
+    Ok(stft
+        .frames(signal)?
+        .map(|frame| {
+            let magnitudes: Vec<f64> = frame.iter().map(|bin| bin.norm()).collect();
+            SpectralMoments::from_spectrum(&magnitudes, bin_width).unwrap_or(silent)
+        })
+        .collect())
+}
+
+/// The frequency in Hz of the lowest bin of a one-sided power spectrum, with bins `bin_width`
+/// Hz apart, at or below which `fraction` of its energy lies. `None` if the spectrum has no
+/// energy.
+pub fn rolloff(power: &[f64], bin_width: f64, fraction: f64) -> Option<f64> {
+    let total: f64 = power.iter().sum();
+    if total <= 0. || !total.is_finite() {
+        return None;
+    }
+    let mut cumulative = 0.;
+    power
+        .iter()
+        .position(|p| {
+            cumulative += p;
+            cumulative >= fraction * total
+        })
+        .map(|k| k as f64 * bin_width)
+}
+
+/// Spectral rolloff of every STFT frame of a signal, at `fraction` of the energy, typically 0.85
+/// or 0.95. Silent frames get NaN, so that the track stays aligned with the frames.
+pub fn spectral_rolloff<S, R: Into<Hz>>(
+    signal: &[S],
+    stft: &Stft,
+    sample_rate: R,
+    fraction: f64,
+) -> VoxBoxResult<Vec<f64>>
+where
+    S: Sample + ToSample<f64>,
+{
+    let sample_rate = sample_rate.into().0;
+    validate::positive(sample_rate, "Sample rate must be positive")?;
+    if !(fraction > 0. && fraction <= 1.) {
+        return Err(VoxBoxError::Config(
+            "Rolloff fraction must be between 0 and 1",
+        ));
+    }
+    let bin_width = stft.bin_frequency(1, sample_rate);
+    Ok(stft
+        .frames(signal)?
+        .map(|frame| {
+            let power: Vec<f64> = frame.iter().map(|bin| bin.norm_sqr()).collect();
+            rolloff(&power, bin_width, fraction).unwrap_or(f64::NAN)
+        })
+        .collect())
+}
+
+/// Cepstral peak prominence (Hillenbrand et al. 1994): how far the cepstral peak at the pitch
+/// period stands above a regression line through the cepstrum, in dB. With smoothing across
+/// time and quefrency it becomes CPPS, as in Praat.
+#[derive(Clone, Debug)]
+pub struct CepstralPeakProminence {
+    pub sample_rate: f64,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    /// Length of each frame, in samples
+    pub frame_len: usize,
+    pub window: WindowType,
+    /// The peak is looked for between the quefrencies of `max_pitch` and `min_pitch`
+    pub min_pitch: f64,
+    pub max_pitch: f64,
+    /// Quefrencies the regression line is fitted over. Past the end of the cepstrum, the end
+    /// is used.
+    pub regression_range: (Seconds, Seconds),
+    /// Number of frames the cepstra are averaged over, 1 for none
+    pub time_smoothing: usize,
+    /// Number of quefrency bins each cepstrum is averaged over, 1 for none
+    pub quefrency_smoothing: usize,
+}
+
+impl CepstralPeakProminence {
+    /// Praat's CPPS settings: 41 ms Hanning frames every 2 ms, a peak between 60 and 330 Hz, a
+    /// line from 1 ms quefrency onwards, and smoothing over 20 ms and 0.5 ms of quefrency.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        let samples = |seconds: f64| ((seconds * sample_rate).round() as usize).max(1);
+        CepstralPeakProminence {
+            sample_rate,
+            hop: samples(0.002),
+            frame_len: samples(0.041),
+            window: WindowType::Hanning,
+            min_pitch: 60.,
+            max_pitch: 330.,
+            regression_range: (Seconds(0.001), Seconds(f64::INFINITY)),
+            time_smoothing: 11,
+            quefrency_smoothing: samples(0.0005),
+        }
+    }
+
+    /// The cepstrum of a windowed frame in dB, with `fft_len / 2 + 1` quefrency bins.
+    pub fn cepstrum_db<T: Float>(&self, frame: &[T]) -> Vec<f64> {
+        let fft_len = frame.len().next_power_of_two();
+        real_cepstrum_of(&RealFft::new(fft_len), frame)
+            .iter()
+            .map(|c| 10. * (c * c).max(1.0e-30).log10())
+            .collect()
+    }
+
+    /// CPP of a cepstrum in dB, as from `cepstrum_db`. Gives zero when the pitch range doesn't
+    /// fit in the cepstrum.
+    pub fn prominence(&self, cepstrum_db: &[f64]) -> Db {
+        let bin = |seconds: f64| (seconds * self.sample_rate).round().max(0.) as usize;
+        let last = cepstrum_db.len().saturating_sub(1);
+        let (low, high) = (
+            bin(1. / self.max_pitch).max(1),
+            bin(1. / self.min_pitch).min(last),
+        );
+        let start = bin(self.regression_range.0 .0).min(last);
+        let end = if self.regression_range.1 .0.is_finite() {
+            bin(self.regression_range.1 .0).min(last)
+        } else {
+            last
+        };
+        if low > high || start >= end {
+            return Db(0.);
+        }
+        let peak = (low..=high).fold(low, |best, q| {
+            if cepstrum_db[q] > cepstrum_db[best] {
+                q
+            } else {
+                best
+            }
+        });
+
+        // Least squares line through the cepstrum over the regression range
+        let n = (end - start + 1) as f64;
+        let mean_q = (start + end) as f64 / 2.;
+        let mean_c = cepstrum_db[start..=end].iter().sum::<f64>() / n;
+        let (covariance, variance) = (start..=end).fold((0., 0.), |(cov, var), q| {
+            let dq = q as f64 - mean_q;
+            (cov + dq * (cepstrum_db[q] - mean_c), var + dq * dq)
+        });
+        let slope = covariance / variance;
+        let line = mean_c + slope * (peak as f64 - mean_q);
+        Db(cepstrum_db[peak] - line)
+    }
+
+    /// CPP of a single windowed frame, without smoothing.
+    pub fn cpp<T: Float>(&self, frame: &[T]) -> Db {
+        self.prominence(&self.cepstrum_db(frame))
+    }
+
+    /// Smoothed CPP of every frame of the signal, one per hop.
+    pub fn cpps<S>(&self, signal: &[S]) -> VoxBoxResult<Vec<Db>>
+    where
+        S: Sample + ToSample<f64>,
+    {
+        self.cpps_with_progress(signal, progress::ignore)
+    }
+
+    /// Like `cpps`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled` if
+    /// the callback breaks.
+    pub fn cpps_with_progress<S, F>(&self, signal: &[S], mut progress: F) -> VoxBoxResult<Vec<Db>>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        validate::positive(self.sample_rate, "Sample rate must be positive")?;
+        if !(self.min_pitch > 0. && self.min_pitch < self.max_pitch) {
+            return Err(VoxBoxError::Config(
+                "Pitch range must be positive and increasing",
+            ));
+        }
+        if self.hop == 0 || self.frame_len < 2 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        let signal = to_f64_samples(signal);
+        validate::finite(&signal)?;
+
+        let window = self.window.window(self.frame_len);
+        let n_frames = signal.len().div_ceil(self.hop);
+        let mut cepstra = Vec::with_capacity(n_frames);
+        for k in 0..n_frames {
+            let frame: Vec<f64> = centered_frame(&signal, k, self.hop, self.frame_len)
+                .iter()
+                .zip(window.iter())
+                .map(|(s, w)| s * w)
+                .collect();
+            cepstra.push(
+                self.cepstrum_db(&frame)
+                    .moving_average(self.quefrency_smoothing),
+            );
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+
+        let span = self.time_smoothing.max(1) / 2;
+        Ok((0..n_frames)
+            .map(|k| {
+                let (low, high) = (k.saturating_sub(span), (k + span + 1).min(n_frames));
+                let mut mean = vec![0.; cepstra[k].len()];
+                for cepstrum in &cepstra[low..high] {
+                    for (m, c) in mean.iter_mut().zip(cepstrum.iter()) {
+                        *m += c / (high - low) as f64;
+                    }
+                }
+                self.prominence(&mean)
+            })
+            .collect())
+    }
+}
+
+#[cfg(feature = "pitch")]
+pub trait CepstralPitch<T: Float> {
+    fn cepstral_pitch(&self, sample_rate: T, min: T, max: T) -> Pitch<T>;
+}
+
+/// Assumes that the frame is windowed, and holds at least two periods of `min`.
+#[cfg(feature = "pitch")]
+impl<T> CepstralPitch<T> for [T]
+where
+    T: Float + FromPrimitive,
+{
+    /// Finds the pitch from the highest peak of the real cepstrum between the quefrencies of
+    /// `max` and `min`, refined by parabolic interpolation. The strength is the height of that
+    /// peak. Silent frames and pitch ranges that don't fit in the frame give a frequency of zero.
+    fn cepstral_pitch(&self, sample_rate: T, min: T, max: T) -> Pitch<T> {
+        let unvoiced = Pitch::new(T::zero(), T::zero());
+        let sample_rate = sample_rate.to_f64().unwrap_or(0.);
+        let (min, max) = (min.to_f64().unwrap_or(0.), max.to_f64().unwrap_or(0.));
+        if !(sample_rate > 0. && min > 0. && max > min) {
+            return unvoiced;
+        }
+        let fft_len = self.len().next_power_of_two();
+        let low = ((sample_rate / max).floor() as usize).max(1);
+        let high = (sample_rate / min).ceil() as usize;
+        if high >= fft_len / 2 || high > self.len() / 2 || low >= high {
+            return unvoiced;
+        }
+
+        let cepstrum = real_cepstrum_of(&RealFft::new(fft_len), self);
+        let best = (low..=high).fold(low, |best, q| {
+            if cepstrum[q] > cepstrum[best] {
+                q
+            } else {
+                best
+            }
+        });
+        if cepstrum[best] <= 0. {
+            return unvoiced;
+        }
+        let (prev, peak, next) = (cepstrum[best - 1], cepstrum[best], cepstrum[best + 1]);
+        let curvature = prev - 2. * peak + next;
+        let shift = if curvature < 0. {
+            (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.
+        };
+        Pitch::new(
+            T::from_f64(sample_rate / (best as f64 + shift)).unwrap_or_else(T::zero),
+            T::from_f64(peak).unwrap_or_else(T::zero),
+        )
+    }
+}
+
+#[cfg(feature = "pitch")]
+pub trait Swipe<T: Float> {
+    fn swipe(&self, sample_rate: T, min: T, max: T) -> Pitch<T>;
+}
+
+/// Frequencies on the ERB scale from `low` to `high`, `step` ERBs apart.
+#[cfg(feature = "pitch")]
+fn erb_space(low: f64, high: f64, step: f64) -> Vec<f64> {
+    let to_erb = |hz: f64| 21.4 * (1. + hz / 229.).log10();
+    let from_erb = |erb: f64| (10f64.powf(erb / 21.4) - 1.) * 229.;
+    let (low, high) = (to_erb(low), to_erb(high));
+    let n = ((high - low) / step).floor() as usize + 1;
+    (0..n).map(|i| from_erb(low + i as f64 * step)).collect()
+}
+
+#[cfg(feature = "pitch")]
+fn is_prime(n: usize) -> bool {
+    n >= 2
+        && (2..)
+            .take_while(|d| d * d <= n)
+            .all(|d| !n.is_multiple_of(d))
+}
+
+/// Assumes that the frame is windowed, and holds at least two periods of `min`.
+#[cfg(feature = "pitch")]
+impl<T> Swipe<T> for [T]
+where
+    T: Float + FromPrimitive,
+{
+    /// Estimates the pitch of a frame with SWIPE' (Camacho and Harris 2008). Each candidate
+    /// pitch is scored by the correlation between the square root of the magnitude spectrum,
+    /// sampled on an ERB scale, and a kernel with cosine lobes at the fundamental and its prime
+    /// harmonics, decaying as one over the square root of frequency. The best candidate is
+    /// refined by parabolic interpolation in log frequency, and its score, from 0 to 1, is the
+    /// strength.
+    ///
+    /// Only one window size is used, where the full algorithm blends several. Silent frames
+    /// give a frequency of zero.
+    fn swipe(&self, sample_rate: T, min: T, max: T) -> Pitch<T> {
+        let unvoiced = Pitch::new(T::zero(), T::zero());
+        let sample_rate = sample_rate.to_f64().unwrap_or(0.);
+        let (min, max) = (min.to_f64().unwrap_or(0.), max.to_f64().unwrap_or(0.));
+        let nyquist = sample_rate / 2.;
+        if !(sample_rate > 0. && min > 0. && max > min && max < nyquist) || self.len() < 2 {
+            return unvoiced;
+        }
+
+        let frame: Vec<f64> = self.iter().map(|s| s.to_f64().unwrap_or(0.)).collect();
+        let fft_len = (2 * frame.len()).next_power_of_two();
+        let loudness: Vec<f64> = RealFft::new(fft_len)
+            .power_spectrum(&frame)
+            .iter()
+            .map(|p| p.max(0.).sqrt().sqrt())
+            .collect();
+        let bin_hz = sample_rate / fft_len as f64;
+        let frequencies = erb_space(min / 4., nyquist, 0.1);
+        // The square root of the magnitude spectrum, interpolated onto the ERB scale
+        let spectrum: Vec<f64> = frequencies
+            .iter()
+            .map(|f| {
+                let position = f / bin_hz;
+                let bin = (position.floor() as usize).min(loudness.len() - 2);
+                let frac = position - bin as f64;
+                loudness[bin] * (1. - frac) + loudness[bin + 1] * frac
+            })
+            .collect();
+        let spectrum_norm = spectrum.iter().map(|a| a * a).sum::<f64>().sqrt();
+        if spectrum_norm <= 0. || !spectrum_norm.is_finite() {
+            return unvoiced;
+        }
+
+        let score = |pitch: f64| -> f64 {
+            let mut dot = 0.;
+            let mut norm = 0.;
+            for (f, a) in frequencies.iter().zip(spectrum.iter()) {
+                let q = f / pitch;
+                if q < 0.75 {
+                    continue;
+                }
+                let harmonic = q.round() as usize;
+                let distance = (q - harmonic as f64).abs();
+                let lobe = if harmonic != 1 && !is_prime(harmonic) {
+                    0.
+                } else if distance < 0.25 {
+                    (2. * PI * q).cos()
+                } else {
+                    0.5 * (2. * PI * q).cos()
+                };
+                let k = lobe / f.sqrt();
+                dot += k * a;
+                norm += k * k;
+            }
+            if norm > 0. {
+                dot / (norm.sqrt() * spectrum_norm)
+            } else {
+                0.
+            }
+        };
+
+        let steps_per_octave = 96.;
+        let n_candidates = ((max / min).log2() * steps_per_octave).ceil() as usize + 1;
+        let candidate = |i: usize| min * 2f64.powf(i as f64 / steps_per_octave);
+        let scores: Vec<f64> = (0..n_candidates).map(|i| score(candidate(i))).collect();
+        let best =
+            (0..n_candidates).fold(0, |best, i| if scores[i] > scores[best] { i } else { best });
+        if scores[best] <= 0. {
+            return unvoiced;
+        }
+        let shift = if best > 0 && best + 1 < n_candidates {
+            let (prev, peak, next) = (scores[best - 1], scores[best], scores[best + 1]);
+            let curvature = prev - 2. * peak + next;
+            if curvature < 0. {
+                (0.5 * (prev - next) / curvature).clamp(-0.5, 0.5)
+            } else {
+                0.
+            }
+        } else {
+            0.
+        };
+        Pitch::new(
+            T::from_f64(min * 2f64.powf((best as f64 + shift) / steps_per_octave))
+                .unwrap_or_else(T::zero),
+            T::from_f64(scores[best]).unwrap_or_else(T::zero),
+        )
+    }
+}
+
+/// Settings that fix the FFT and filterbank of an `MFCC` call.
+#[cfg(feature = "mfcc")]
+#[derive(Clone, Copy, PartialEq)]
+struct MfccKey {
+    fft_len: usize,
+    n_filters: usize,
+    freq_bounds: (f64, f64),
+    sample_rate: f64,
+    scale: MelScale,
+}
+
+#[cfg(feature = "mfcc")]
+thread_local! {
+    /// The FFT and filterbank of the last `MFCC` settings used on this thread, so that frame
+    /// after frame with the same settings builds them once.
+    static MFCC_CACHE: RefCell<Option<(MfccKey, RealFft, MelFilterBank)>> =
+        const { RefCell::new(None) };
+}
+
+/// `num_coeffs` MFCCs of `frame`, zero-padded to `key.fft_len`, through the cached FFT and
+/// filterbank for `key`.
+#[cfg(feature = "mfcc")]
+fn cached_mfcc(key: MfccKey, frame: &[f64], num_coeffs: usize) -> VoxBoxResult<Vec<f64>> {
+    MFCC_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.as_ref().is_none_or(|(cached, _, _)| *cached != key) {
+            let filterbank = MelFilterBank::with_scale(
+                key.n_filters,
+                key.fft_len,
+                key.freq_bounds,
+                key.sample_rate,
+                key.scale,
+            )?;
+            *cache = Some((key, RealFft::new(key.fft_len), filterbank));
+        }
+        let (_, fft, filterbank) = cache.as_ref().unwrap();
+        filterbank.mfcc(&fft.power_spectrum(frame), num_coeffs)
+    })
+}
+
+/// MFCC assumes that it is a windowed signal
+#[cfg(feature = "mfcc")]
+impl<T: ?Sized> MFCC<T> for [T]
+where
+    T: fft::FFTnum + Debug + Float + ToPrimitive + FromPrimitive + Into<Complex<T>> + Zero + Signed,
+{
+    /// `num_coeffs` MFCCs from as many HTK filters over the frame's own length, with the
+    /// bounds clamped to Nyquist. Gives NaNs if that leaves no range to cover.
+    fn mfcc(&self, num_coeffs: usize, freq_bounds: (f64, f64), sample_rate: f64) -> Vec<T> {
+        let nyquist = sample_rate / 2.;
+        let bounds = (freq_bounds.0.max(0.), freq_bounds.1.min(nyquist));
+        let frame: Vec<f64> = self.iter().map(|s| s.to_f64().unwrap_or(0.)).collect();
+        let key = MfccKey {
+            fft_len: self.len(),
+            n_filters: num_coeffs,
+            freq_bounds: bounds,
+            sample_rate,
+            scale: MelScale::Htk,
+        };
+        cached_mfcc(key, &frame, num_coeffs)
+            .map(|coeffs| {
+                coeffs
+                    .into_iter()
+                    .map(|c| T::from_f64(c).unwrap())
+                    .collect()
+            })
+            .unwrap_or_else(|_| vec![T::nan(); num_coeffs])
+    }
+
+    fn mfcc_with_config(&self, config: &MfccConfig) -> VoxBoxResult<Vec<T>> {
+        if self.len() > config.fft_size {
+            return Err(VoxBoxError::Input("Frame is longer than the FFT size"));
+        }
+        validate::finite(self)?;
+        if config.fft_size == 0 {
+            return Err(VoxBoxError::Config(
+                "FFT length must be nonzero and at least the frame length",
+            ));
+        }
+        let windowed: Vec<f64> = self
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s.to_f64().unwrap() * config.window.at(i, self.len()))
+            .collect();
+        let key = MfccKey {
+            fft_len: config.fft_size,
+            n_filters: config.num_filters,
+            freq_bounds: config.freq_bounds,
+            sample_rate: config.sample_rate,
+            scale: config.scale,
+        };
+        Ok(cached_mfcc(key, &windowed, config.num_coeffs)?
+            .into_iter()
+            .map(|c| T::from_f64(c).unwrap())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate rand;
     extern crate sample;
 
     use super::*;
+    #[cfg(feature = "pitch")]
     use crate::periodic::*;
     use crate::polynomial::Polynomial;
+    #[cfg(any(feature = "mfcc", feature = "pitch"))]
     use crate::waves::*;
     use num_complex::Complex;
+    #[cfg(feature = "mfcc")]
     use rand::{thread_rng, Rng};
-    use sample::{window, Signal, ToSampleSlice};
+    #[cfg(feature = "mfcc")]
+    use sample::window;
+    use sample::{Signal, ToSampleSlice};
 
+    #[cfg(feature = "pitch")]
     fn sine(len: usize) -> Vec<f64> {
         let rate = sample::signal::rate(len as f64).const_hz(1.0);
         rate.sine()
@@ -503,9 +2511,20 @@ mod test {
         println!("Resonances: {:?}", res);
         assert!((res[0].frequency - 100.0).abs() < 1e-8);
         assert!((res[0].bandwidth - 0.0).abs() < 1e-8);
+
+        // A pole at radius 0.9, and its reflection outside the unit circle
+        let pole = Complex::<f64>::from_polar(&0.9, &(PI / 4.));
+        let expected = -(0.9f64).ln() * 8000. / PI;
+        for root in [pole, pole.conj().inv()].iter() {
+            let res = Resonance::from_root(root, 8000.).unwrap();
+            println!("Resonance: {:?}", res);
+            assert!((res.frequency - 1000.).abs() < 1e-8);
+            assert!((res.bandwidth - expected).abs() < 1e-8);
+        }
     }
 
     #[test]
+    #[cfg(feature = "pitch")]
     fn test_lpc() {
         let sine = sine(8);
         let mut auto = sine.autocorrelate(8);
@@ -523,7 +2542,7 @@ mod test {
         ];
         // Rust output:
         let lpc_exp = vec![1.0, -1.3122, 0.8660, -0.0875, -0.0103];
-        let lpc = auto.lpc(4);
+        let lpc = auto.lpc(4).unwrap();
         println!("LPC coeffs: {:?}", &lpc);
         for (a, b) in auto.iter().zip(auto_exp.iter()) {
             assert![(a - b).abs() < 0.0001];
@@ -586,6 +2605,94 @@ mod test {
     }
 
     #[test]
+    fn test_lsf() {
+        // A single real pole at 0.5 sits a sixth of the way round
+        let lsf = [-0.5].lsf().unwrap();
+        assert!((lsf[0] - PI / 3.).abs() < 1e-12);
+
+        // Two resonances, at 0.2 and 0.6 radians
+        let resonator = |w: f64, r: f64| [-2. * r * w.cos(), r * r];
+        let (a, b) = (resonator(0.2, 0.95), resonator(0.6, 0.9));
+        let lpc = [
+            a[0] + b[0],
+            a[1] + b[1] + a[0] * b[0],
+            a[0] * b[1] + a[1] * b[0],
+            a[1] * b[1],
+        ];
+        let source: Vec<f64> = (1..11).chain((1..11).rev()).map(|v| v as f64).collect();
+        let odd = source.lpc_praat(5).unwrap();
+        for coeffs in [&lpc[..], &odd[..]].iter() {
+            let lsf = coeffs.lsf().unwrap();
+            println!("lsf: {:?}", lsf);
+            assert_eq!(lsf.len(), coeffs.len());
+            assert!(lsf.windows(2).all(|w| w[0] < w[1]));
+            let back = lsf_to_lpc(&lsf).unwrap();
+            for (x, y) in back.iter().zip(coeffs.iter()) {
+                assert!((x - y).abs() < 1e-9);
+            }
+        }
+
+        // Roots at 2 and 0.5
+        assert!([-2.5, 1.].lsf().is_err());
+        assert!(lsf_to_lpc(&[0.5, 0.4]).is_err());
+        assert!(lsf_to_lpc(&[0.5, PI]).is_err());
+    }
+
+    #[test]
+    fn test_lpc_residual() {
+        // An impulse train through a two-pole resonator comes back out of its inverse
+        let coeffs = [-1.8 * 0.3f64.cos(), 0.81];
+        let excitation: Vec<f64> = (0..64).map(|i| if i % 20 == 0 { 1. } else { 0. }).collect();
+        let mut signal = excitation.clone();
+        for i in 0..signal.len() {
+            for (k, c) in coeffs.iter().enumerate().take(i) {
+                signal[i] -= c * signal[i - k - 1];
+            }
+        }
+        let residual = signal.lpc_residual(&coeffs).unwrap();
+        println!("residual: {:?}", residual);
+        for (r, e) in residual.iter().zip(excitation.iter()) {
+            assert!((r - e).abs() < 1e-12);
+        }
+
+        let mut short = [0.; 10];
+        assert!(signal.lpc_residual_mut(&coeffs, &mut short).is_err());
+        assert!(signal.lpc_residual(&[f64::NAN]).is_err());
+    }
+
+    #[test]
+    fn test_all_pole_filter() {
+        let source: Vec<f64> = (0..200)
+            .map(|i| ((i * i) % 37) as f64 / 37. - 0.5)
+            .collect();
+        let first = source[..100].lpc_praat(6).unwrap();
+        let second = source[100..].lpc_praat(4).unwrap();
+        let mut residual = source.lpc_residual(&first).unwrap();
+        // Switch filters halfway, keeping the history from the first half
+        for (i, e) in residual.iter_mut().enumerate().skip(100) {
+            *e = second
+                .iter()
+                .enumerate()
+                .fold(source[i], |acc, (k, c)| acc + c * source[i - k - 1]);
+        }
+
+        let mut filter = AllPoleFilter::new(6);
+        let mut resynthesized = filter.filter(&first, &residual[..100]).unwrap();
+        resynthesized.extend(filter.filter(&second, &residual[100..]).unwrap());
+        for (r, s) in resynthesized.iter().zip(source.iter()) {
+            assert!((r - s).abs() < 1e-9);
+        }
+
+        filter.reset();
+        assert_eq!(
+            filter.filter(&[0.5], &[1., 0., 0.]).unwrap(),
+            vec![1., -0.5, 0.25]
+        );
+        assert!(filter.filter(&[0.; 7], &[1.]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "formant")]
     fn test_formant_extractor() {
         let resonances: Vec<Vec<Resonance<f64>>> = vec![
             vec![100.0, 150.0, 200.0, 240.0, 300.0],
@@ -615,7 +2722,7 @@ mod test {
         // First cycle has initial guesses
         match extractor.next() {
             Some(r) => {
-                let freqs: Vec<f64> = r.iter().map(|f| f.frequency).collect();
+                let freqs: Vec<f64> = r.formants.iter().map(|f| f.frequency).collect();
                 // Post-step-3 should be: 150, 240, 300
                 assert_eq!(freqs, vec![150.0, 240.0, 300.0])
             }
@@ -625,7 +2732,7 @@ mod test {
         // Second cycle should be different
         match extractor.next() {
             Some(r) => {
-                let freqs: Vec<f64> = r.iter().map(|f| f.frequency).collect();
+                let freqs: Vec<f64> = r.formants.iter().map(|f| f.frequency).collect();
                 // Post-step-3 should be: 180, 230, 310
                 assert_eq!(freqs, vec![180.0, 230.0, 310.0])
             }
@@ -635,7 +2742,7 @@ mod test {
         // Third cycle should have removed duplicates and shifted to fill all slots
         match extractor.next() {
             Some(r) => {
-                let freqs: Vec<f64> = r.iter().map(|f| f.frequency).collect();
+                let freqs: Vec<f64> = r.formants.iter().map(|f| f.frequency).collect();
                 // Post-step-3 should be: None, 230, 290
                 assert_eq!(freqs, vec![230.0, 270.0, 290.0])
             }
@@ -643,21 +2750,360 @@ mod test {
         }
     }
 
+    #[test]
+    #[cfg(feature = "formant")]
+    fn test_formant_extractor_slots() {
+        let frames: Vec<Vec<Resonance<f64>>> = vec![
+            vec![Resonance::new(510., 60.), Resonance::new(1490., 80.)],
+            vec![Resonance::new(520., 60.)],
+            vec![],
+        ];
+        let estimates = vec![Resonance::new(500., 0.), Resonance::new(1500., 0.)];
+        let extractor = FormantExtractor::new(3, frames, estimates).timing(0.5, 0.25);
+        let tracked: Vec<FormantFrame<f64>> = extractor.collect();
+        let freqs: Vec<Vec<f64>> = tracked
+            .iter()
+            .map(|frame| frame.formants.iter().map(|r| r.frequency).collect())
+            .collect();
+        println!("slots: {:?}", freqs);
+        assert!(freqs.iter().all(|f| f.len() == 3));
+        assert_eq!(&freqs[0][..2], &[510., 1490.]);
+        assert_eq!(freqs[1][0], 520.);
+        assert!(freqs[1][1].is_nan() && freqs[0][2].is_nan());
+        assert!(freqs[2].iter().all(|f| f.is_nan()));
+
+        let times: Vec<f64> = tracked.iter().map(|frame| frame.time).collect();
+        assert_eq!(times, vec![0.5, 0.75, 1.]);
+        let timed = FormantExtractor::from_config(
+            &AnalysisConfig::new(100.).hop(25),
+            1,
+            vec![vec![Resonance::new(500., 0.)]; 2],
+            vec![Resonance::new(500., 0.)],
+        );
+        let times: Vec<f64> = timed.map(|frame| frame.time).collect();
+        assert_eq!(times, vec![0.125, 0.375]);
+        println!("amplitudes: {:?}", tracked[0].amplitudes);
+        assert_eq!(tracked[0].amplitudes[0], 0.);
+        assert!(tracked[0].amplitudes[1] < -10.);
+        assert!(tracked[0].amplitudes[2].is_nan());
+        assert_eq!(tracked[1].amplitudes[0], 0.);
+    }
+
+    #[test]
+    #[cfg(feature = "formant")]
+    fn test_streaming_formant_extractor() {
+        let frames: Vec<Vec<Resonance<f64>>> = vec![
+            vec![Resonance::new(510., 60.), Resonance::new(1490., 80.)],
+            vec![Resonance::new(520., 60.), Resonance::new(1200., 80.)],
+            vec![Resonance::new(540., 60.)],
+        ];
+        let estimates = vec![Resonance::new(500., 0.), Resonance::new(1500., 0.)];
+        let batch: Vec<FormantFrame<f64>> =
+            FormantExtractor::new(2, &frames, estimates.clone()).collect();
+        let mut streaming = StreamingFormantExtractor::new(2, estimates);
+        for (frame, expected) in frames.iter().zip(batch.iter()) {
+            let pushed = streaming.push_frame(frame);
+            println!("pushed: {:?}", pushed);
+            for (p, e) in pushed.iter().zip(expected.formants.iter()) {
+                assert!(
+                    p.frequency == e.frequency || (p.frequency.is_nan() && e.frequency.is_nan())
+                );
+            }
+        }
+        assert_eq!(streaming.estimates[0].frequency, 540.);
+    }
+
+    #[test]
+    #[cfg(feature = "formant")]
+    fn test_formant_viterbi() {
+        // F2 goes missing in frame 2, and frame 3 has a broad spurious pole between F1 and F2
+        let frames: Vec<Vec<Resonance<f64>>> = vec![
+            vec![Resonance::new(500., 60.), Resonance::new(1500., 80.)],
+            vec![Resonance::new(520., 60.), Resonance::new(1480., 80.)],
+            vec![Resonance::new(530., 60.)],
+            vec![
+                Resonance::new(540., 60.),
+                Resonance::new(900., 600.),
+                Resonance::new(1460., 80.),
+            ],
+        ];
+        let estimates = vec![Resonance::new(500., 0.), Resonance::new(1500., 0.)];
+        let extractor = FormantExtractor::new(2, frames.iter().map(|f| &f[..]), estimates);
+        let tracks = extractor.track(&FormantCosts::default()).unwrap();
+        let freqs: Vec<Vec<f64>> = tracks
+            .iter()
+            .map(|t| t.formants.iter().map(|r| r.frequency).collect())
+            .collect();
+        println!("tracks: {:?}", freqs);
+        assert_eq!(freqs[1], vec![520., 1480.]);
+        assert_eq!(freqs[2][0], 530.);
+        assert!(freqs[2][1].is_nan());
+        assert_eq!(freqs[3], vec![540., 1460.]);
+
+        let frames = [vec![Resonance::new(500., 60.)]];
+        let estimates = vec![Resonance::new(0., 0.), Resonance::new(1500., 0.)];
+        let extractor = FormantExtractor::new(2, frames.iter().map(|f| &f[..]), estimates);
+        assert!(extractor.track(&FormantCosts::default()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "formant")]
+    fn test_formant_viterbi_many_candidates() {
+        // 32 resonances a frame, as from an order-64 LPC, with the formants among them
+        let formants = [500., 1500., 2500., 3500., 4500.];
+        let frames: Vec<Vec<Resonance<f64>>> = (0..100)
+            .map(|k| {
+                let drift = k as f64;
+                (0..32)
+                    .map(|i| Resonance::new(150. + 160. * i as f64 + drift, 900.))
+                    .chain(formants.iter().map(|f| Resonance::new(f + drift, 50.)))
+                    .collect()
+            })
+            .collect();
+        let estimates: Vec<Resonance<f64>> =
+            formants.iter().map(|f| Resonance::new(*f, 0.)).collect();
+        let extractor = FormantExtractor::new(5, frames.iter().map(|f| &f[..]), estimates);
+        let tracks = extractor.track(&FormantCosts::default()).unwrap();
+        assert_eq!(tracks.len(), 100);
+        for (k, track) in tracks.iter().enumerate() {
+            for (r, f) in track.formants.iter().zip(formants.iter()) {
+                assert_eq!(r.frequency, f + k as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stft() {
+        // 500 Hz at 8 kHz falls on bin 16 of a 256-point transform
+        let signal: Vec<f64> = (0..2000)
+            .map(|n| (2. * PI * 500. * n as f64 / 8000.).sin())
+            .collect();
+        let mut stft = Stft::new(200, 80);
+        stft.fft_len = 256;
+        assert_eq!(stft.n_bins(), 129);
+        assert_eq!(stft.bin_frequency(16, 8000.), 500.);
+        let frames: Vec<Vec<Complex<f64>>> = stft.frames(&signal).unwrap().collect();
+        assert_eq!(frames.len(), 25);
+        for frame in &frames[..20] {
+            let peak = (0..frame.len())
+                .max_by(|a, b| frame[*a].norm().partial_cmp(&frame[*b].norm()).unwrap())
+                .unwrap();
+            assert_eq!(peak, 16);
+        }
+
+        let windowed: Vec<f64> = signal[80..280]
+            .iter()
+            .zip(WindowType::Hanning.window(200).iter())
+            .map(|(s, w)| s * w)
+            .collect();
+        let power = power_spectrum(&windowed[..], 256).unwrap();
+        for (bin, p) in frames[1].iter().zip(power.iter()) {
+            assert!((bin.norm_sqr() - p).abs() < 1e-9);
+        }
+
+        // Corrected levels read the sinusoid's amplitude, and its mean square of one half
+        stft.scaling = SpectrumScaling::Amplitude;
+        let frame = stft.frames(&signal).unwrap().nth(5).unwrap();
+        println!("corrected amplitude: {}", frame[16].norm());
+        assert!((frame[16].norm() - 1.).abs() < 0.02);
+        stft.scaling = SpectrumScaling::Energy;
+        let frame = stft.frames(&signal).unwrap().nth(5).unwrap();
+        let energy = frame.iter().map(|bin| bin.norm_sqr()).sum::<f64>();
+        println!("corrected energy: {}", energy);
+        assert!((energy - 0.5).abs() < 0.02);
+
+        stft.fft_len = 128;
+        assert!(stft.frames(&signal).is_err());
+    }
+
+    #[test]
+    fn test_stft_inverse() {
+        // Unchanged spectra give the signal back, whatever the scaling
+        let signal: Vec<f64> = (0..1000)
+            .map(|n| (n as f64 * 0.07).sin() + 0.3 * (n as f64 * 0.9).cos())
+            .collect();
+        for scaling in [SpectrumScaling::Unscaled, SpectrumScaling::Energy].iter() {
+            let stft = Stft {
+                scaling: *scaling,
+                ..Stft::new(200, 50)
+            };
+            let spectra: Vec<Vec<Complex<f64>>> = stft.frames(&signal).unwrap().collect();
+            let back = stft.inverse(&spectra, signal.len()).unwrap();
+            assert_eq!(back.len(), signal.len());
+            // Only the first sample meets nothing but the zero of the periodic window
+            assert_eq!(back[0], 0.);
+            for (x, y) in signal.iter().zip(back.iter()).skip(1) {
+                assert!((x - y).abs() < 1e-9);
+            }
+        }
+
+        let stft = Stft::new(200, 50);
+        assert!(stft
+            .inverse(&[vec![Complex::new(0., 0.); 10]], 100)
+            .is_err());
+        assert!(Stft::new(200, 0).inverse(&[], 100).is_err());
+    }
+
+    #[test]
+    fn test_spectral_moments() {
+        // Equal weight on 1, 2 and 3 kHz: centered on 2 kHz, symmetric and flat-topped
+        let mut spectrum = vec![0.; 5];
+        spectrum[1] = 1.;
+        spectrum[2] = 1.;
+        spectrum[3] = 1.;
+        let moments = SpectralMoments::from_spectrum(&spectrum, 1000.).unwrap();
+        println!("moments: {:?}", moments);
+        assert!((moments.centroid - 2000.).abs() < 1e-9);
+        assert!((moments.spread - (2e6f64 / 3.).sqrt()).abs() < 1e-6);
+        assert!(moments.skewness.abs() < 1e-12);
+        assert!((moments.kurtosis - -1.5).abs() < 1e-9);
+        // Energy in the top bin pulls the centroid up and skews the distribution left
+        spectrum[4] = 3.;
+        let moments = SpectralMoments::from_spectrum(&spectrum, 1000.).unwrap();
+        assert!(moments.centroid > 2000. && moments.skewness < 0.);
+        assert!(SpectralMoments::from_spectrum(&[0.; 5], 1000.).is_none());
+
+        let mut signal: Vec<f64> = (0..1600)
+            .map(|n| (2. * PI * 1500. * n as f64 / 8000.).sin())
+            .collect();
+        signal.extend(vec![0.; 800]);
+        let track = spectral_moments(&signal, &Stft::new(256, 80), 8000.).unwrap();
+        assert_eq!(track.len(), 30);
+        assert!((track[5].centroid - 1500.).abs() < 50.);
+        assert!(track[29].centroid.is_nan());
+    }
+
+    #[test]
+    fn test_spectral_rolloff() {
+        let power = [1., 1., 1., 1., 6.];
+        assert_eq!(rolloff(&power, 100., 0.3), Some(200.));
+        assert_eq!(rolloff(&power, 100., 0.4), Some(300.));
+        assert_eq!(rolloff(&power, 100., 0.85), Some(400.));
+        assert_eq!(rolloff(&[0.; 5], 100., 0.85), None);
+
+        // Two tones with the upper one quieter: 85% lands on the lower, 95% on the upper
+        let mut signal: Vec<f64> = (0..1600)
+            .map(|n| {
+                let t = n as f64 / 8000.;
+                (2. * PI * 500. * t).sin() + 0.3 * (2. * PI * 2500. * t).sin()
+            })
+            .collect();
+        signal.extend(vec![0.; 800]);
+        let stft = Stft::new(256, 80);
+        let low = spectral_rolloff(&signal, &stft, 8000., 0.85).unwrap();
+        let high = spectral_rolloff(&signal, &stft, 8000., 0.95).unwrap();
+        println!("rolloff: {:?} {:?}", &low[5..8], &high[5..8]);
+        assert_eq!(low.len(), 30);
+        assert!((low[5] - 500.).abs() < 100. && (high[5] - 2500.).abs() < 100.);
+        assert!(low[29].is_nan());
+        assert!(spectral_rolloff(&signal, &stft, 8000., 1.5).is_err());
+    }
+
     #[test]
     fn test_hz_to_mel() {
         assert!(hz_to_mel(300.) - 401.25 < 1.0e-2);
     }
 
+    #[test]
+    fn test_mel_spectrogram() {
+        let filterbank = MelFilterBank::new(10, 256, (0., 4000.), 8000.).unwrap();
+        assert_eq!((filterbank.n_filters(), filterbank.n_bins()), (10, 129));
+        assert!(MelFilterBank::new(10, 256, (0., 5000.), 8000.).is_err());
+        // Neighbouring filters cross at half height, so a flat spectrum comes out flat-ish
+        let (first, weights) = filterbank.filter(4).unwrap();
+        assert!(first > 0 && weights.iter().all(|w| *w > 0. && *w <= 1.));
+        let flat = filterbank.apply(&[1.; 129]).unwrap();
+        println!("flat: {:?}", flat);
+        assert!(flat.windows(2).all(|w| w[1] >= w[0]));
+        assert!(filterbank.apply(&[1.; 128]).is_err());
+        assert!(filterbank.mfcc(&[1.; 129], 11).is_err());
+
+        // Slaney filters have unit area, so a flat spectrum of 1 per Hz gives about 1 per band
+        assert!((MelScale::Slaney.to_mel(2000.) - 25.081_880_157_308_8).abs() < 1e-9);
+        assert!((MelScale::Slaney.to_hz(MelScale::Slaney.to_mel(3000.)) - 3000.).abs() < 1e-9);
+        assert!((MelScale::Slaney.to_mel(600.) - 9.).abs() < 1e-12);
+        let slaney =
+            MelFilterBank::with_scale(10, 1024, (0., 4000.), 8000., MelScale::Slaney).unwrap();
+        let areas = slaney.apply(&[8000. / 1024.; 513]).unwrap();
+        println!("areas: {:?}", areas);
+        assert!(areas.iter().all(|a| (a - 1.).abs() < 0.02));
+
+        // 1 kHz should light up the band centered nearest to it
+        let signal: Vec<f64> = (0..4000)
+            .map(|n| (2. * PI * 1000. * n as f64 / 8000.).sin())
+            .collect();
+        let mut config = MelConfig::new(8000.);
+        config.n_mels = 20;
+        let spectrogram = mel_spectrogram(&signal, &config).unwrap();
+        assert_eq!(spectrogram.n_rows(), 50);
+        assert_eq!(spectrogram.n_cols(), 20);
+        let frame = spectrogram.row(10).unwrap();
+        println!("log-mel: {:?}", frame);
+        let loudest = (0..20)
+            .max_by(|a, b| frame[*a].partial_cmp(&frame[*b]).unwrap())
+            .unwrap();
+        let edges = |m: usize| mel_to_hz(hz_to_mel(4000.) * m as f64 / 21.);
+        assert!(edges(loudest) < 1000. && edges(loudest + 2) > 1000.);
+
+        config.log = false;
+        let power = mel_spectrogram(&signal, &config).unwrap();
+        assert!((power.row(10).unwrap()[loudest].ln() - frame[loudest]).abs() < 1e-9);
+    }
+
+    #[test]
+    #[cfg(feature = "mfcc")]
+    fn test_mfcc_with_config() {
+        let signal: Vec<f64> = (0..2000)
+            .map(|n| (n as f64 * 0.3).sin() + 0.5 * (n as f64 * 1.1).sin())
+            .collect();
+        let config = MfccConfig::from_config(&AnalysisConfig::new(16000.));
+        assert_eq!(config.fft_size, 512);
+
+        // The same frame through the mel spectrogram and a DCT
+        let mel = MelConfig {
+            sample_rate: 16000.,
+            stft: Stft::new(400, 160),
+            n_mels: 26,
+            freq_bounds: config.freq_bounds,
+            scale: MelScale::Htk,
+            log: true,
+        };
+        let expected = dct(mel_spectrogram(&signal, &mel).unwrap().row(3).unwrap());
+        let mfcc = signal[480..880].mfcc_with_config(&config).unwrap();
+        println!("mfcc: {:?}", mfcc);
+        assert_eq!(mfcc.len(), 13);
+        for (c, e) in mfcc.iter().zip(expected.iter()) {
+            assert!((c - e).abs() < 1e-9);
+        }
+
+        // Shorter frames are zero-padded, longer ones rejected
+        assert!(signal[..100].mfcc_with_config(&config).is_ok());
+        assert!(signal[..600].mfcc_with_config(&config).is_err());
+
+        // Switching settings rebuilds the cached filterbank rather than reusing the last one
+        let slaney = MfccConfig {
+            scale: MelScale::Slaney,
+            ..config
+        };
+        let other = signal[480..880].mfcc_with_config(&slaney).unwrap();
+        assert!(other
+            .iter()
+            .zip(mfcc.iter())
+            .any(|(a, b)| (a - b).abs() > 1e-6));
+        assert_eq!(signal[480..880].mfcc_with_config(&config).unwrap(), mfcc);
+    }
+
     #[test]
     fn test_mel_to_hz() {
         assert!(mel_to_hz(401.25) - 300. < 1.0e-2);
     }
 
     #[test]
+    #[cfg(feature = "mfcc")]
     fn test_mfcc() {
         let mut rng = thread_rng();
         let mut vec: Vec<f64> = (0..256).map(|_| rng.gen_range::<f64>(-1., 1.)).collect();
-        vec.preemphasis(0.1f64 * 22_050.);
+        vec.preemphasis_coeff(0.97);
         let hanning_window: Vec<[f64; 1]> = window::hanning(256).take(256).collect();
         for (v, w) in vec.iter_mut().zip(hanning_window.to_sample_slice().iter()) {
             *v *= *w;
@@ -667,6 +3113,7 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "mfcc")]
     fn test_mfcc_not_nan() {
         use num::Float;
         let vec = vec![0.; 512];
@@ -689,6 +3136,312 @@ mod test {
         }
     }
 
+    #[test]
+    #[cfg(feature = "mfcc")]
+    fn test_mfcc_any_length() {
+        let vec: Vec<f64> = (0..300).map(|i| (i as f64 * 0.3).sin()).collect();
+        let mfccs = vec.mfcc(13, (100., 20_000.), 16_000.);
+        assert_eq!(mfccs.len(), 13);
+        assert!(mfccs.iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn test_lpc_degenerate() {
+        let silence = vec![0f64; 32];
+        assert!(silence.lpc(8).unwrap().iter().all(|c| c.is_finite()));
+        assert!(silence.lpc_praat(8).is_err());
+        assert!([1f64, 2., 3.].lpc_praat(8).is_err());
+    }
+
+    #[test]
+    fn test_lpc_analysis() {
+        // The autocorrelation of a first-order process is fully predicted at order one
+        let auto = [1f64, 0.5, 0.25, 0.125];
+        let analysis = auto.lpc_analysis(3).unwrap();
+        println!("analysis: {:?}", analysis);
+        assert_eq!(analysis.coeffs, vec![1., -0.5, 0., 0.]);
+        assert_eq!(analysis.reflection, vec![-0.5, 0., 0.]);
+        assert!((analysis.error - 0.75).abs() < 1e-12);
+
+        let mut work = [0f64; 10];
+        let mut solver = LPCSolver::new(3, &mut work).unwrap();
+        solver.solve(&auto).unwrap();
+        assert_eq!(solver.reflection(), &analysis.reflection[..]);
+        assert_eq!(solver.prediction_error(), analysis.error);
+
+        assert_eq!([0f64; 4].lpc_analysis(2).unwrap().error, 0.);
+    }
+
+    #[test]
+    fn test_lpc_options() {
+        // A pure tone leaves the recursion with poles right on the unit circle
+        let tone: Vec<f64> = (0..400).map(|i| (i as f64 * 0.3).sin()).collect();
+        let auto: Vec<f64> = (0..=6)
+            .map(|lag| {
+                tone.iter()
+                    .zip(tone[lag..].iter())
+                    .map(|(a, b)| a * b)
+                    .sum()
+            })
+            .collect();
+        let radii = |coeffs: &[f64]| {
+            let poly: Vec<Complex<f64>> = coeffs
+                .iter()
+                .rev()
+                .map(|c| Complex::<f64>::new(*c, 0.))
+                .collect();
+            let roots = poly.find_roots().unwrap();
+            roots.iter().map(|r| r.norm()).collect::<Vec<f64>>()
+        };
+        let plain = auto.lpc(6).unwrap();
+        println!("plain radii: {:?}", radii(&plain));
+
+        let options = LPCOptions::stable(8000.);
+        assert_eq!(
+            LPCOptions::from_config(&AnalysisConfig::new(8000.)),
+            options
+        );
+        let gamma = options.bandwidth_expansion.unwrap();
+        let conditioned = auto.lpc_with_options(6, &options).unwrap();
+        println!("conditioned radii: {:?}", radii(&conditioned.coeffs));
+        assert!(radii(&conditioned.coeffs).iter().all(|r| *r < gamma));
+        assert_eq!(
+            auto.lpc_with_options(6, &LPCOptions::default())
+                .unwrap()
+                .coeffs,
+            plain
+        );
+
+        let mut windowed = vec![1f64; 3];
+        lag_window(&mut windowed, 0.1);
+        assert_eq!(windowed[0], 1.);
+        assert!((windowed[1] - (-0.5 * (0.2 * PI).powi(2)).exp()).abs() < 1e-12);
+        let bad = LPCOptions {
+            bandwidth_expansion: Some(1.5),
+            ..LPCOptions::default()
+        };
+        assert!(auto.lpc_with_options(6, &bad).is_err());
+    }
+
+    #[test]
+    fn test_select_lpc_order() {
+        use rand::distributions::{IndependentSample, Normal};
+        use rand::{SeedableRng, XorShiftRng};
+
+        // A second-order process driven by white noise
+        let mut rng = XorShiftRng::from_seed([3, 1, 4, 1]);
+        let normal = Normal::new(0., 1.);
+        let mut signal = vec![0f64; 4000];
+        for i in 2..signal.len() {
+            signal[i] = 1.3 * signal[i - 1] - 0.6 * signal[i - 2] + normal.ind_sample(&mut rng);
+        }
+        let auto: Vec<f64> = (0..=20)
+            .map(|lag| {
+                signal
+                    .iter()
+                    .zip(signal[lag..].iter())
+                    .map(|(a, b)| a * b)
+                    .sum()
+            })
+            .collect();
+        for criterion in [OrderCriterion::Akaike, OrderCriterion::FinalPredictionError].iter() {
+            let order = select_lpc_order(&auto, signal.len(), 20, *criterion).unwrap();
+            println!("{:?}: {}", criterion, order);
+            assert!((2..=4).contains(&order));
+        }
+
+        // Further orders add nothing to a first-order process
+        let auto = [1f64, 0.5, 0.25, 0.125];
+        assert_eq!(
+            select_lpc_order(&auto, 100, 3, OrderCriterion::Akaike).unwrap(),
+            1
+        );
+        assert_eq!(
+            select_lpc_order(&[0f64; 4], 100, 3, OrderCriterion::Akaike).unwrap(),
+            0
+        );
+        assert!(select_lpc_order(&auto, 4, 3, OrderCriterion::Akaike).is_err());
+    }
+
+    #[test]
+    fn test_lpc_validation() {
+        let mut ac = [0f64; 3];
+        let mut kc = [0f64; 2];
+        let mut tmp = [0f64; 1];
+        let auto = [1f64, 0.5, 0.25, 0.125];
+        assert!(matches!(
+            auto.lpc_mut(2, &mut ac, &mut kc, &mut tmp),
+            Err(VoxBoxError::Workspace)
+        ));
+        assert!(matches!(auto.lpc(4), Err(VoxBoxError::LPC(_))));
+        assert!(matches!(
+            [1f64, f64::NAN, 0.].lpc(2),
+            Err(VoxBoxError::Input(_))
+        ));
+        assert!(matches!(
+            [0.5f64, f64::INFINITY, 0.25, 0.1].lpc_praat(2),
+            Err(VoxBoxError::Input(_))
+        ));
+        let mut work = [0f64; 4];
+        assert!(LPCSolver::new(2, &mut work).is_err());
+        assert!(dct_mut(&[1f64, 2.], &mut [0f64; 1]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "formant")]
+    fn test_estimate_formants_degenerate() {
+        let mut estimates = [Resonance::new(500f64, 0.), Resonance::new(1500., 0.)];
+        estimates.estimate_formants(&[]);
+        estimates.estimate_formants(&[Resonance::new(f64::NAN, 0.)]);
+        assert_eq!(estimates[0].frequency, 500.);
+        assert_eq!(estimates[1].frequency, 1500.);
+    }
+
+    #[test]
+    fn test_power_spectrum() {
+        // One cycle every 8 samples lands in bin 4 of a 32 point spectrum
+        let signal: Vec<f64> = (0..32)
+            .map(|i| (2. * std::f64::consts::PI * i as f64 / 8.).cos())
+            .collect();
+        let power = power_spectrum(&signal[..], 32).unwrap();
+        assert_eq!(power.len(), 17);
+        assert!((power[4] - 256.).abs() < 1.0e-6);
+        assert!(power[3] < 1.0e-6 && power[5] < 1.0e-6);
+        assert!(power_spectrum(&signal[..], 16).is_err());
+        assert!(power_spectrum::<f64>(&[], 0).is_err());
+    }
+
+    #[test]
+    fn test_real_fft() {
+        // Against a direct DFT, at even, odd and degenerate lengths
+        let signal: Vec<f64> = (0..13)
+            .map(|n| (n as f64 * 0.7).sin() + 0.1 * n as f64)
+            .collect();
+        for len in [1, 2, 13, 16, 18].iter() {
+            let frame = &signal[..(*len).min(signal.len())];
+            let spectrum = RealFft::new(*len).transform(frame);
+            assert_eq!(spectrum.len(), len / 2 + 1);
+            for (k, bin) in spectrum.iter().enumerate() {
+                let expected =
+                    frame
+                        .iter()
+                        .enumerate()
+                        .fold(Complex::new(0., 0.), |acc, (n, s)| {
+                            let phase = -2. * PI * (k * n) as f64 / *len as f64;
+                            acc + Complex::from_polar(s, &phase)
+                        });
+                assert!((bin - expected).norm() < 1e-9);
+            }
+            let back = RealFft::new(*len).inverse(&spectrum);
+            assert_eq!(back.len(), *len);
+            for (a, b) in back.iter().zip(frame.iter().chain(std::iter::repeat(&0.))) {
+                assert!((a - b).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pitch")]
+    fn test_cepstral_pitch() {
+        let sample_rate = 8000.;
+        let len = 512;
+        let window = WindowType::Hanning.window(len);
+        // A buzz at 160 Hz with harmonics up to 3.2 kHz
+        let frame: Vec<f64> = (0..len)
+            .map(|n| {
+                let t = n as f64 / sample_rate;
+                (1..=20)
+                    .map(|h| (2. * PI * 160. * h as f64 * t).cos() / h as f64)
+                    .sum::<f64>()
+                    * window[n]
+            })
+            .collect();
+        let pitch = frame.cepstral_pitch(sample_rate, 75., 500.);
+        println!("cepstral pitch: {:?}", pitch);
+        assert!((pitch.frequency - 160.).abs() < 2.);
+        assert!(pitch.strength > 0.);
+
+        let silence = vec![0f64; len];
+        assert_eq!(silence.cepstral_pitch(sample_rate, 75., 500.).frequency, 0.);
+        assert!(real_cepstrum(&silence[..], len)
+            .unwrap()
+            .iter()
+            .all(|c| *c == 0.));
+    }
+
+    #[test]
+    #[cfg(feature = "pitch")]
+    fn test_swipe() {
+        let sample_rate = 16000.;
+        let len = 1024;
+        let window = WindowType::Hanning.window(len);
+        // A breathy 130 Hz voice: harmonics falling off quickly, plus noise
+        let mut state = 3u32;
+        let frame: Vec<f64> = (0..len)
+            .map(|n| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (state as f64 / u32::MAX as f64 - 0.5) * 0.2;
+                let t = n as f64 / sample_rate;
+                let voice = (1..=10)
+                    .map(|h| (2. * PI * 130. * h as f64 * t).sin() / (h * h) as f64)
+                    .sum::<f64>();
+                (voice + noise) * window[n]
+            })
+            .collect();
+        let pitch = frame.swipe(sample_rate, 75., 500.);
+        println!("swipe: {:?}", pitch);
+        assert!((pitch.frequency - 130.).abs() < 2.);
+        assert!(pitch.strength > 0.3);
+
+        let silence = vec![0f64; len];
+        assert_eq!(silence.swipe(sample_rate, 75., 500.).frequency, 0.);
+    }
+
+    #[test]
+    fn test_cpp() {
+        let sample_rate = 16000.;
+        let mut cpp = CepstralPeakProminence::new(sample_rate);
+        let mut state = 5u32;
+        let mut noise = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            state as f64 / u32::MAX as f64 - 0.5
+        };
+        // A second of 140 Hz buzz, then a second of noise
+        let signal: Vec<f64> = (0..32000)
+            .map(|n| {
+                if n < 16000 {
+                    let t = n as f64 / sample_rate;
+                    (1..=30)
+                        .map(|h| (2. * std::f64::consts::PI * 140. * h as f64 * t).sin() / h as f64)
+                        .sum::<f64>()
+                        + 0.05 * noise()
+                } else {
+                    noise()
+                }
+            })
+            .collect();
+
+        let window = WindowType::Hanning.window(cpp.frame_len);
+        let frame: Vec<f64> = signal[4000..4000 + cpp.frame_len]
+            .iter()
+            .zip(window.iter())
+            .map(|(s, w)| s * w)
+            .collect();
+        let single = cpp.cpp(&frame);
+        println!("cpp: {:?}", single);
+        assert!(single.0 > 10.);
+
+        // Every 10 ms, smoothed over 30 ms, to keep the test quick
+        cpp.hop = 160;
+        cpp.time_smoothing = 3;
+        let smoothed = cpp.cpps(&signal).unwrap();
+        assert_eq!(smoothed.len(), 200);
+        let voiced = smoothed[20..80].iter().map(|d| d.0).sum::<f64>() / 60.;
+        let unvoiced = smoothed[120..180].iter().map(|d| d.0).sum::<f64>() / 60.;
+        println!("cpps: voiced {}, noise {}", voiced, unvoiced);
+        assert!(voiced > unvoiced + 5.);
+    }
+
     #[test]
     fn test_resonances_from_coeffs() {
         // this is exactly what lpc_praat should spit out for a given frame
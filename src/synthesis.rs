@@ -0,0 +1,509 @@
+extern crate num;
+
+use num::{Float, FromPrimitive};
+use num_complex::Complex;
+use std::f64::consts::PI;
+use std::iter::once;
+
+use crate::error::*;
+use crate::polynomial::{from_roots, Polynomial};
+use crate::resample::{ResampleQuality, Resampler};
+use crate::spectrum::{Resonance, LPC};
+
+/// Periodic Hanning window, which sums to unity when overlapped at half its length.
+fn hanning<T: Float + FromPrimitive>(len: usize) -> Vec<T> {
+    (0..len)
+        .map(|n| T::from_f64(0.5 - 0.5 * (2. * PI * n as f64 / len as f64).cos()).unwrap())
+        .collect()
+}
+
+/// Reads a sample, treating everything outside of the buffer as silence.
+fn sample_at<T: Float>(buf: &[T], idx: isize) -> T {
+    if idx < 0 || idx as usize >= buf.len() {
+        T::zero()
+    } else {
+        buf[idx as usize]
+    }
+}
+
+/// Runs `process` over half-overlapping frames of `buf` and overlap-adds the resynthesized frames.
+///
+/// Each frame of `frame_len` samples is split into its LPC envelope of order `n_coeffs` and the
+/// prediction residual. `process` is given the frame's first sample index, and may rewrite both
+/// in place before the residual is filtered back through the envelope.
+fn resynthesize<T, F>(
+    buf: &[T],
+    n_coeffs: usize,
+    frame_len: usize,
+    mut process: F,
+) -> VoxBoxResult<Vec<T>>
+where
+    T: Float + FromPrimitive,
+    F: FnMut(isize, &mut [T], &mut [T]) -> VoxBoxResult<()>,
+{
+    if frame_len < 2 * (n_coeffs + 1) {
+        return Err(VoxBoxError::Synthesis(
+            "Frame length must be at least twice the LPC order",
+        ));
+    }
+
+    let hop = frame_len / 2;
+    let window: Vec<T> = hanning(frame_len);
+
+    let mut out = vec![T::zero(); buf.len()];
+    let mut windowed = vec![T::zero(); frame_len];
+    let mut residual = vec![T::zero(); frame_len];
+    let mut coeffs = vec![T::zero(); n_coeffs];
+    let mut envelope = vec![T::zero(); n_coeffs];
+    let mut work = vec![T::zero(); frame_len * 2 + n_coeffs];
+
+    // Start half a frame early so that the first samples get full window coverage
+    let mut start = -(hop as isize);
+    while start < buf.len() as isize {
+        for (i, (s, w)) in windowed.iter_mut().zip(window.iter()).enumerate() {
+            *s = sample_at(buf, start + i as isize) * *w;
+        }
+        if windowed
+            .lpc_praat_mut(n_coeffs, &mut coeffs, &mut work)
+            .is_err()
+        {
+            // Silent frames have no envelope to preserve
+            for c in coeffs.iter_mut() {
+                *c = T::zero();
+            }
+        }
+
+        // Inverse filter the raw signal, using the preceding samples as filter history
+        for (i, e) in residual.iter_mut().enumerate() {
+            let idx = start + i as isize;
+            *e = coeffs
+                .iter()
+                .enumerate()
+                .fold(sample_at(buf, idx), |acc, (k, c)| {
+                    acc + *c * sample_at(buf, idx - k as isize - 1)
+                });
+        }
+
+        envelope.clone_from_slice(&coeffs);
+        process(start, &mut envelope, &mut residual)?;
+
+        // All-pole synthesis, in place. The filter history before the frame is taken from the
+        // input so that an unmodified frame is reconstructed exactly.
+        for i in 0..frame_len {
+            let y = envelope
+                .iter()
+                .enumerate()
+                .fold(residual[i], |acc, (k, c)| {
+                    let prev = if k < i {
+                        residual[i - k - 1]
+                    } else {
+                        sample_at(buf, start + i as isize - k as isize - 1)
+                    };
+                    acc - *c * prev
+                });
+            residual[i] = y;
+        }
+
+        for (i, (y, w)) in residual.iter().zip(window.iter()).enumerate() {
+            let idx = start + i as isize;
+            if idx >= 0 && (idx as usize) < out.len() {
+                out[idx as usize] = out[idx as usize] + *y * *w;
+            }
+        }
+        start += hop as isize;
+    }
+
+    Ok(out)
+}
+
+/// Changes the pitch of a signal while keeping its formants in place.
+pub trait PitchShift<T> {
+    /// Shifts the pitch by `ratio` (2.0 is an octave up) and returns a signal of the same length.
+    ///
+    /// Each frame of `frame_len` samples is split into an LPC envelope of order `n_coeffs` and the
+    /// prediction residual. The residual is read at `ratio` times the rate around the frame's
+    /// center, from the signal itself rather than just the frame, so glottal pulses are
+    /// resampled instead of cut off at the frame's ends. It is then filtered back through the
+    /// original envelope and overlap-added at half-frame hops.
+    fn pitch_shift(&self, ratio: T, n_coeffs: usize, frame_len: usize) -> VoxBoxResult<Vec<T>>;
+}
+
+impl<T: Float + FromPrimitive> PitchShift<T> for [T] {
+    fn pitch_shift(&self, ratio: T, n_coeffs: usize, frame_len: usize) -> VoxBoxResult<Vec<T>> {
+        if !(ratio > T::zero() && ratio.is_finite()) {
+            return Err(VoxBoxError::Synthesis(
+                "Pitch shift ratio must be positive and finite",
+            ));
+        }
+        let ratio = ratio.to_f64().unwrap();
+        if ratio == 1. {
+            return resynthesize(self, n_coeffs, frame_len, |_, _, _| Ok(()));
+        }
+
+        // Band-limited reading at the new rate, which low-passes first when raising the pitch
+        let resampler = Resampler::new(ratio, 1., ResampleQuality::Balanced)?;
+        let reach = resampler.half_width().ceil() as isize + 1;
+        let half = frame_len as f64 / 2.;
+        let mut local = Vec::new();
+        resynthesize(self, n_coeffs, frame_len, |start, envelope, residual| {
+            // Inverse filter the stretch of signal that the frame reads from
+            let center = start as f64 + half;
+            let low = (center - half * ratio).floor() as isize - reach;
+            let high = (center + half * ratio).ceil() as isize + reach;
+            local.clear();
+            local.extend((low..=high).map(|idx| {
+                envelope
+                    .iter()
+                    .enumerate()
+                    .fold(sample_at(self, idx), |acc, (k, c)| {
+                        acc + *c * sample_at(self, idx - k as isize - 1)
+                    })
+                    .to_f64()
+                    .unwrap_or(0.)
+            }));
+            for (i, e) in residual.iter_mut().enumerate() {
+                let t = center + (i as f64 - half) * ratio - low as f64;
+                *e = T::from_f64(resampler.at(&local, t)).unwrap();
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Describes how the selected poles are moved by `FormantShift`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PoleShift<T> {
+    /// Adds a constant offset, in Hz, to the pole frequency
+    Offset(T),
+    /// Multiplies the pole frequency
+    Ratio(T),
+}
+
+/// Moves the poles of the LPC coefficients `coeffs` in place. Only the formants whose indices
+/// appear in `formants` are moved, counting upwards from the lowest pole; an empty slice moves
+/// all of them.
+fn shift_poles<T: Float + FromPrimitive>(
+    coeffs: &mut [T],
+    shift: PoleShift<T>,
+    formants: &[usize],
+    sample_rate: T,
+) -> VoxBoxResult<()> {
+    let order = coeffs.len();
+    let poly: Vec<Complex<T>> = coeffs
+        .iter()
+        .rev()
+        .chain(once(&T::one()))
+        .map(|c| Complex::<T>::new(*c, T::zero()))
+        .collect();
+    let roots = poly.find_roots()?;
+
+    let eps = T::from_f64(1.0e-10).unwrap();
+    let two_pi = T::from_f64(2. * PI).unwrap();
+    let min_theta = T::from_f64(1.0e-3).unwrap();
+    let max_theta = T::from_f64(PI).unwrap() - min_theta;
+
+    let mut upper: Vec<Complex<T>> = roots.iter().filter(|r| r.im > eps).cloned().collect();
+    upper.sort_by(|a, b| {
+        a.arg()
+            .partial_cmp(&b.arg())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut moved: Vec<Complex<T>> = roots
+        .iter()
+        .filter(|r| r.im.abs() <= eps)
+        .cloned()
+        .collect();
+    for (idx, root) in upper.iter().enumerate() {
+        let (r, theta) = root.to_polar();
+        let theta = if formants.is_empty() || formants.contains(&idx) {
+            let freq = theta * sample_rate / two_pi;
+            let freq = match shift {
+                PoleShift::Offset(offset) => freq + offset,
+                PoleShift::Ratio(ratio) => freq * ratio,
+            };
+            (freq * two_pi / sample_rate).max(min_theta).min(max_theta)
+        } else {
+            theta
+        };
+        let pole = Complex::<T>::from_polar(&r, &theta);
+        moved.push(pole);
+        moved.push(pole.conj());
+    }
+
+    // Leave the frame untouched if the roots did not pair up into a filter of the same order
+    if moved.len() != order {
+        return Ok(());
+    }
+
+    let rebuilt = from_roots(&moved);
+    for (k, c) in coeffs.iter_mut().enumerate() {
+        *c = rebuilt[order - k - 1].re;
+    }
+    Ok(())
+}
+
+/// Moves formants by manipulating the poles of each frame's LPC envelope.
+pub trait FormantShift<T> {
+    /// Shifts the formants listed in `formants` (0 is F1; empty shifts all of them) and returns a
+    /// signal of the same length.
+    ///
+    /// The poles of each frame's envelope are moved according to `shift`, the coefficients are
+    /// rebuilt from the new roots, and the unchanged prediction residual is filtered through the
+    /// new envelope.
+    fn formant_shift(
+        &self,
+        shift: PoleShift<T>,
+        formants: &[usize],
+        sample_rate: T,
+        n_coeffs: usize,
+        frame_len: usize,
+    ) -> VoxBoxResult<Vec<T>>;
+}
+
+impl<T: Float + FromPrimitive> FormantShift<T> for [T] {
+    fn formant_shift(
+        &self,
+        shift: PoleShift<T>,
+        formants: &[usize],
+        sample_rate: T,
+        n_coeffs: usize,
+        frame_len: usize,
+    ) -> VoxBoxResult<Vec<T>> {
+        if sample_rate <= T::zero() {
+            return Err(VoxBoxError::Synthesis("Sample rate must be positive"));
+        }
+
+        resynthesize(self, n_coeffs, frame_len, |_, envelope, _| {
+            // Silent frames have no poles to move
+            if envelope.iter().all(|c| c.is_zero()) {
+                return Ok(());
+            }
+            shift_poles(envelope, shift, formants, sample_rate)
+        })
+    }
+}
+
+/// Number of formants rendered by `sine_wave_speech`.
+pub const SINE_WAVE_SPEECH_TONES: usize = 3;
+
+/// Renders formant tracks as sine-wave speech, replacing each of the first three formants with a
+/// single sinusoid that follows its frequency.
+///
+/// `formants` holds one frame of resonances per `hop` samples, lowest formant first (as produced
+/// by `FormantExtractor`), and `amplitudes` holds the overall amplitude of each frame, e.g. its
+/// RMS. Each tone is half the amplitude of the one below it. Frequencies and amplitudes are
+/// interpolated linearly between frames, and formants missing from a frame are silent.
+pub fn sine_wave_speech<T, F>(
+    formants: &[F],
+    amplitudes: &[T],
+    hop: usize,
+    sample_rate: T,
+) -> VoxBoxResult<Vec<T>>
+where
+    T: Float + FromPrimitive,
+    F: AsRef<[Resonance<T>]>,
+{
+    if formants.len() != amplitudes.len() {
+        return Err(VoxBoxError::Synthesis(
+            "Need exactly one amplitude per formant frame",
+        ));
+    }
+    if hop == 0 || sample_rate <= T::zero() {
+        return Err(VoxBoxError::Synthesis(
+            "Hop size and sample rate must be positive",
+        ));
+    }
+
+    let nyquist = sample_rate * T::from_f64(0.5).unwrap();
+    let two_pi = T::from_f64(2. * PI).unwrap();
+    let hop_t = T::from_usize(hop).unwrap();
+
+    // (frequency, amplitude) of the tone for `formant` in frame `idx`
+    let tone = |idx: usize, formant: usize| -> (T, T) {
+        let idx = idx.min(formants.len() - 1);
+        match formants[idx].as_ref().get(formant) {
+            Some(r) if r.frequency > T::zero() && r.frequency < nyquist => {
+                let gain = T::from_f64(0.5f64.powi(formant as i32)).unwrap();
+                (r.frequency, amplitudes[idx] * gain)
+            }
+            _ => (T::zero(), T::zero()),
+        }
+    };
+
+    let mut out = vec![T::zero(); formants.len() * hop];
+    for formant in 0..SINE_WAVE_SPEECH_TONES {
+        let mut phase = T::zero();
+        for (idx, frame) in out.chunks_mut(hop).enumerate() {
+            let (f0, a0) = tone(idx, formant);
+            let (f1, a1) = tone(idx + 1, formant);
+            // Glide from a silent formant at its neighbour's frequency, rather than from 0 Hz
+            let f0 = if a0.is_zero() { f1 } else { f0 };
+            let f1 = if a1.is_zero() { f0 } else { f1 };
+            for (n, s) in frame.iter_mut().enumerate() {
+                let frac = T::from_usize(n).unwrap() / hop_t;
+                let freq = f0 + (f1 - f0) * frac;
+                let amp = a0 + (a1 - a0) * frac;
+                *s = *s + amp * phase.sin();
+                phase = phase + two_pi * freq / sample_rate;
+                if phase > two_pi {
+                    phase = phase - two_pi;
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An impulse train with the given period, run through a single resonance at `freq`.
+    fn vowel(len: usize, period: usize, freq: f64, sample_rate: f64) -> Vec<f64> {
+        let r: f64 = 0.97;
+        let theta = 2. * PI * freq / sample_rate;
+        let mut out = vec![0f64; len];
+        for n in 0..len {
+            let x = if n % period == 0 { 1. } else { 0. };
+            let y1 = if n > 0 { out[n - 1] } else { 0. };
+            let y2 = if n > 1 { out[n - 2] } else { 0. };
+            out[n] = x + 2. * r * theta.cos() * y1 - r * r * y2;
+        }
+        out
+    }
+
+    /// Returns the lag between `min` and `max` with the strongest autocorrelation.
+    fn best_lag(buf: &[f64], min: usize, max: usize) -> usize {
+        (min..max)
+            .map(|lag| {
+                let sum: f64 = buf.iter().zip(buf[lag..].iter()).map(|(a, b)| a * b).sum();
+                (lag, sum)
+            })
+            .fold((min, f64::MIN), |acc, x| if x.1 > acc.1 { x } else { acc })
+            .0
+    }
+
+    #[test]
+    fn test_pitch_shift() {
+        let signal = vowel(8000, 80, 1000., 8000.);
+        let shifted = signal.pitch_shift(1.25, 10, 512).unwrap();
+        assert_eq!(shifted.len(), signal.len());
+        assert!(shifted.iter().all(|s| s.is_finite()));
+        let lag = best_lag(&shifted[1000..7000], 40, 120);
+        println!("lag: {}", lag);
+        assert!((lag as i64 - 64).abs() <= 2);
+
+        let lowered = signal.pitch_shift(0.8, 10, 512).unwrap();
+        let lag = best_lag(&lowered[1000..7000], 60, 140);
+        println!("lowered lag: {}", lag);
+        assert!((lag as i64 - 100).abs() <= 2);
+    }
+
+    #[test]
+    fn test_pitch_shift_identity() {
+        let signal = vowel(4000, 80, 1000., 8000.);
+        let shifted = signal.pitch_shift(1.0, 10, 512).unwrap();
+        for (a, b) in signal[512..3500].iter().zip(shifted[512..3500].iter()) {
+            assert!((a - b).abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn test_formant_shift() {
+        use crate::polynomial::Polynomial;
+        use crate::spectrum::Resonance;
+
+        let signal = vowel(8000, 80, 1000., 8000.);
+        let shifted = signal
+            .formant_shift(PoleShift::Ratio(1.2), &[], 8000., 10, 512)
+            .unwrap();
+        assert_eq!(shifted.len(), signal.len());
+
+        // The pitch should stay where it was
+        let lag = best_lag(&shifted[1000..7000], 40, 120);
+        assert!((lag as i64 - 80).abs() <= 2);
+
+        // The sharpest resonance should have moved up to 1200 Hz
+        let coeffs = shifted[2000..2512].lpc_praat(10).unwrap();
+        let poly: Vec<Complex<f64>> = coeffs
+            .iter()
+            .rev()
+            .chain(once(&1.0))
+            .map(|c| Complex::<f64>::new(*c, 0.))
+            .collect();
+        let sharpest = poly
+            .find_roots()
+            .unwrap()
+            .iter()
+            .filter_map(|r| Resonance::from_root(r, 8000.))
+            .fold(None, |acc: Option<Resonance<f64>>, r| match acc {
+                Some(a) if a.bandwidth < r.bandwidth => Some(a),
+                _ => Some(r),
+            })
+            .unwrap();
+        println!("sharpest: {:?}", sharpest);
+        assert!((sharpest.frequency - 1200.).abs() < 60.);
+    }
+
+    #[test]
+    fn test_formant_shift_offset_selected() {
+        let signal = vowel(4000, 80, 1000., 8000.);
+        // Shifting a formant that does not exist leaves the signal alone
+        let shifted = signal
+            .formant_shift(PoleShift::Offset(300.), &[9], 8000., 10, 512)
+            .unwrap();
+        for (a, b) in signal[512..3500].iter().zip(shifted[512..3500].iter()) {
+            assert!((a - b).abs() < 1.0e-6);
+        }
+    }
+
+    /// Magnitude of the DFT of `buf` at `freq`.
+    fn magnitude_at(buf: &[f64], freq: f64, sample_rate: f64) -> f64 {
+        let (re, im) = buf.iter().enumerate().fold((0., 0.), |acc, (n, s)| {
+            let phase = 2. * PI * freq * n as f64 / sample_rate;
+            (acc.0 + s * phase.cos(), acc.1 - s * phase.sin())
+        });
+        (re * re + im * im).sqrt() / buf.len() as f64
+    }
+
+    #[test]
+    fn test_sine_wave_speech() {
+        let frame: Vec<Resonance<f64>> = [500., 1500., 2500., 3500.]
+            .iter()
+            .map(|f| Resonance::new(*f, 50.))
+            .collect();
+        let formants = vec![frame; 50];
+        let amplitudes = vec![1.0; 50];
+        let out = sine_wave_speech(&formants, &amplitudes, 80, 8000.).unwrap();
+        assert_eq!(out.len(), 4000);
+
+        // Three tones at 1, 1/2 and 1/4 amplitude, and nothing for the fourth formant
+        assert!((magnitude_at(&out, 500., 8000.) - 0.5).abs() < 0.01);
+        assert!((magnitude_at(&out, 1500., 8000.) - 0.25).abs() < 0.01);
+        assert!((magnitude_at(&out, 2500., 8000.) - 0.125).abs() < 0.01);
+        assert!(magnitude_at(&out, 3500., 8000.) < 0.01);
+    }
+
+    #[test]
+    fn test_sine_wave_speech_missing_formants() {
+        let formants = vec![
+            vec![Resonance::new(500., 50.)],
+            vec![],
+            vec![Resonance::new(500., 50.), Resonance::new(1500., 50.)],
+        ];
+        let out = sine_wave_speech(&formants, &[1.0, 1.0, 1.0], 80, 8000.).unwrap();
+        assert_eq!(out.len(), 240);
+        assert!(out.iter().all(|s| s.is_finite()));
+        assert!(sine_wave_speech(&formants, &[1.0], 80, 8000.).is_err());
+    }
+
+    #[test]
+    fn test_pitch_shift_bad_ratio() {
+        let signal = vowel(1000, 80, 1000., 8000.);
+        assert!(signal.pitch_shift(0.0, 10, 512).is_err());
+        assert!(signal.pitch_shift(f64::NAN, 10, 512).is_err());
+        assert!(signal.pitch_shift(f64::INFINITY, 10, 512).is_err());
+    }
+}
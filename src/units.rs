@@ -0,0 +1,146 @@
+//! Lightweight newtypes for the quantities that are easy to mix up when everything is an `f64`.
+
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// A frequency or sample rate, in Hertz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Hz(pub f64);
+
+/// A duration or point in time, in seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Seconds(pub f64);
+
+/// A level in decibels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Db(pub f64);
+
+/// Loudness in loudness units relative to full scale, as ITU-R BS.1770 defines it. A 1 kHz
+/// sinusoid at full scale is at -3 LUFS.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Lufs(pub f64);
+
+macro_rules! impl_unit {
+    ($unit:ident, $suffix:expr) => {
+        impl From<f64> for $unit {
+            fn from(value: f64) -> Self {
+                $unit(value)
+            }
+        }
+
+        impl From<$unit> for f64 {
+            fn from(value: $unit) -> f64 {
+                value.0
+            }
+        }
+
+        impl Add for $unit {
+            type Output = $unit;
+            fn add(self, other: $unit) -> $unit {
+                $unit(self.0 + other.0)
+            }
+        }
+
+        impl Sub for $unit {
+            type Output = $unit;
+            fn sub(self, other: $unit) -> $unit {
+                $unit(self.0 - other.0)
+            }
+        }
+
+        impl Mul<f64> for $unit {
+            type Output = $unit;
+            fn mul(self, factor: f64) -> $unit {
+                $unit(self.0 * factor)
+            }
+        }
+
+        impl Div<f64> for $unit {
+            type Output = $unit;
+            fn div(self, divisor: f64) -> $unit {
+                $unit(self.0 / divisor)
+            }
+        }
+
+        impl fmt::Display for $unit {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} {}", self.0, $suffix)
+            }
+        }
+    };
+}
+
+impl_unit!(Hz, "Hz");
+impl_unit!(Seconds, "s");
+impl_unit!(Db, "dB");
+impl_unit!(Lufs, "LUFS");
+
+impl Hz {
+    /// The duration of one cycle.
+    pub fn period(self) -> Seconds {
+        Seconds(1. / self.0)
+    }
+
+    /// Converts to mels, with the same scale as `spectrum::hz_to_mel`.
+    pub fn to_mel(self) -> f64 {
+        crate::spectrum::hz_to_mel(self.0)
+    }
+
+    pub fn from_mel(mel: f64) -> Hz {
+        Hz(crate::spectrum::mel_to_hz(mel))
+    }
+}
+
+impl Seconds {
+    /// The nearest whole number of samples at `sample_rate`.
+    pub fn to_samples(self, sample_rate: Hz) -> usize {
+        (self.0 * sample_rate.0).round().max(0.) as usize
+    }
+
+    pub fn from_samples(samples: usize, sample_rate: Hz) -> Seconds {
+        Seconds(samples as f64 / sample_rate.0)
+    }
+}
+
+impl Db {
+    /// Level of an amplitude ratio, `20 log10(ratio)`.
+    pub fn from_amplitude(ratio: f64) -> Db {
+        Db(20. * ratio.log10())
+    }
+
+    /// Level of a power ratio, `10 log10(ratio)`.
+    pub fn from_power(ratio: f64) -> Db {
+        Db(10. * ratio.log10())
+    }
+
+    pub fn to_amplitude(self) -> f64 {
+        10f64.powf(self.0 / 20.)
+    }
+
+    pub fn to_power(self) -> f64 {
+        10f64.powf(self.0 / 10.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let rate = Hz(16000.);
+        assert_eq!(Seconds(0.01).to_samples(rate), 160);
+        assert_eq!(Seconds::from_samples(8000, rate), Seconds(0.5));
+        assert_eq!(Hz(200.).period(), Seconds(0.005));
+        assert!((Hz::from_mel(Hz(1000.).to_mel()).0 - 1000.).abs() < 1.0e-9);
+
+        assert!((Db::from_amplitude(10.).0 - 20.).abs() < 1.0e-12);
+        assert!((Db::from_power(10.).0 - 10.).abs() < 1.0e-12);
+        assert!((Db(-6.).to_amplitude() - 0.501187).abs() < 1.0e-6);
+        assert!((Db(3.).to_power() - 1.995262).abs() < 1.0e-6);
+
+        assert_eq!(Seconds(1.) - Seconds(0.25), Seconds(0.75));
+        assert_eq!(f64::from(Hz(5.) * 2.), 10.);
+        assert_eq!(format!("{}", Db(3.)), "3 dB");
+    }
+}
@@ -0,0 +1,806 @@
+use sample::{Sample, ToSample};
+use std::collections::VecDeque;
+
+use crate::config::AnalysisConfig;
+use crate::spectrum::RealFft;
+use crate::units::{Db, Hz};
+use crate::waves::{WindowType, ZeroCrossingRate};
+
+/// A Sohn-style statistical voice activity detector.
+///
+/// Each frame's power spectrum is compared bin by bin against a running noise estimate, and
+/// the per-bin likelihood ratios of "speech plus noise" against "noise only" are averaged into
+/// one decision. The noise estimate keeps adapting during non-speech frames, so the detector
+/// can be fed one frame at a time while the background drifts.
+#[derive(Clone, Debug)]
+pub struct SohnVad {
+    /// Smoothing of the decision-directed a priori SNR estimate
+    pub snr_smoothing: f64,
+    /// How slowly the noise spectrum follows non-speech frames, between 0 and 1
+    pub noise_smoothing: f64,
+    /// Mean log likelihood ratio above which a frame counts as speech
+    pub threshold: f64,
+    /// Number of frames to keep reporting speech after the likelihood drops
+    pub hangover: usize,
+    /// Number of initial frames that are assumed to be noise
+    pub init_frames: usize,
+    fft: RealFft,
+    window: Vec<f64>,
+    noise: Vec<f64>,
+    prev_speech: Vec<f64>,
+    frames_seen: usize,
+    hang: usize,
+}
+
+/// The outcome of one frame of `SohnVad`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VadDecision {
+    /// Whether the frame is speech, including hangover
+    pub speech: bool,
+    /// Mean log likelihood ratio over all bins
+    pub log_likelihood: f64,
+}
+
+impl SohnVad {
+    /// Creates a detector for Hanning-windowed frames of `frame_len` samples.
+    pub fn new(frame_len: usize) -> Self {
+        Self::with_window(WindowType::Hanning.window(frame_len))
+    }
+
+    /// Creates a detector for the frame length and window of `config`.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Self::with_window(config.window.window(config.frame_len))
+    }
+
+    fn with_window(window: Vec<f64>) -> Self {
+        let frame_len = window.len();
+        let fft_len = frame_len.next_power_of_two();
+        SohnVad {
+            snr_smoothing: 0.98,
+            noise_smoothing: 0.95,
+            threshold: 0.15,
+            hangover: 8,
+            init_frames: 10,
+            fft: RealFft::new(fft_len),
+            window,
+            noise: vec![0.; fft_len / 2 + 1],
+            prev_speech: vec![0.; fft_len / 2 + 1],
+            frames_seen: 0,
+            hang: 0,
+        }
+    }
+
+    /// The current noise power estimate, one value per FFT bin.
+    pub fn noise_spectrum(&self) -> &[f64] {
+        &self.noise[..]
+    }
+
+    /// Forgets the noise estimate, so that the next `init_frames` frames are learned as noise.
+    pub fn reset(&mut self) {
+        for n in self.noise.iter_mut() {
+            *n = 0.;
+        }
+        for p in self.prev_speech.iter_mut() {
+            *p = 0.;
+        }
+        self.frames_seen = 0;
+        self.hang = 0;
+    }
+
+    /// Classifies the next frame and updates the noise model. Frames shorter than the frame
+    /// length are zero-padded.
+    pub fn process<S: Sample + ToSample<f64>>(&mut self, frame: &[S]) -> VadDecision {
+        let windowed: Vec<f64> = frame
+            .iter()
+            .zip(self.window.iter())
+            .map(|(s, w)| s.to_sample::<f64>() * w)
+            .collect();
+        let power = self.fft.power_spectrum(&windowed);
+
+        // Learn the initial noise spectrum as a plain average
+        if self.frames_seen < self.init_frames {
+            self.frames_seen += 1;
+            let weight = 1. / self.frames_seen as f64;
+            for (n, p) in self.noise.iter_mut().zip(power.iter()) {
+                *n += (p - *n) * weight;
+            }
+            return VadDecision {
+                speech: false,
+                log_likelihood: 0.,
+            };
+        }
+        self.frames_seen += 1;
+
+        let mut log_likelihood = 0.;
+        let mut likelihoods = Vec::with_capacity(power.len());
+        for (k, p) in power.iter().enumerate() {
+            let noise = self.noise[k].max(1.0e-12);
+            let post_snr = (p / noise).min(1.0e6);
+            let prio_snr = self.snr_smoothing * self.prev_speech[k] / noise
+                + (1. - self.snr_smoothing) * (post_snr - 1.).max(0.);
+            let prio_snr = prio_snr.max(1.0e-3);
+            let ll = post_snr * prio_snr / (1. + prio_snr) - prio_snr.ln_1p();
+            log_likelihood += ll;
+            likelihoods.push(ll);
+
+            // Wiener estimate of the clean speech power, for the next frame's a priori SNR
+            let gain = prio_snr / (1. + prio_snr);
+            self.prev_speech[k] = gain * gain * p;
+        }
+        log_likelihood /= power.len() as f64;
+
+        let active = log_likelihood > self.threshold;
+        if active {
+            self.hang = self.hangover;
+        } else if self.hang > 0 {
+            self.hang -= 1;
+        }
+
+        // Update the noise in proportion to how unlikely each bin is to contain speech
+        for ((n, p), ll) in self.noise.iter_mut().zip(power.iter()).zip(likelihoods) {
+            let speech_prob = 1. / (1. + (-ll.min(50.)).exp());
+            let rate = (1. - self.noise_smoothing) * (1. - speech_prob);
+            let rate = if active { rate * 0.1 } else { rate };
+            *n += (p - *n) * rate;
+        }
+
+        VadDecision {
+            speech: active || self.hang > 0,
+            log_likelihood,
+        }
+    }
+}
+
+/// A cheap voice activity detector on frame energy and zero-crossing rate.
+///
+/// A frame is speech if its energy is `energy_threshold` above the tracked noise floor, or if
+/// it is at least half that far above and crosses zero more often than `zcr_threshold`, which
+/// catches quiet fricatives. The noise floor is learned from the first `init_frames` frames
+/// and then follows non-speech frames, dropping at once to any quieter frame.
+#[derive(Clone, Debug)]
+pub struct EnergyVad {
+    /// Level above the noise floor at which a frame counts as speech
+    pub energy_threshold: Db,
+    /// Zero crossings per sample above which a quieter frame still counts as speech
+    pub zcr_threshold: f64,
+    /// Number of frames to keep reporting speech after the energy drops
+    pub hangover: usize,
+    /// How slowly the noise floor follows non-speech frames, between 0 and 1
+    pub noise_smoothing: f64,
+    /// Number of initial frames that are assumed to be noise
+    pub init_frames: usize,
+    noise_floor: f64,
+    frames_seen: usize,
+    hang: usize,
+}
+
+impl Default for EnergyVad {
+    fn default() -> Self {
+        EnergyVad {
+            energy_threshold: Db(9.),
+            zcr_threshold: 0.25,
+            hangover: 8,
+            noise_smoothing: 0.95,
+            init_frames: 10,
+            noise_floor: 0.,
+            frames_seen: 0,
+            hang: 0,
+        }
+    }
+}
+
+impl EnergyVad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current noise floor, as mean power per sample in dB.
+    pub fn noise_floor(&self) -> Db {
+        Db::from_power(self.noise_floor.max(MIN_POWER))
+    }
+
+    /// Forgets the noise floor, so that the next `init_frames` frames are learned as noise.
+    pub fn reset(&mut self) {
+        self.noise_floor = 0.;
+        self.frames_seen = 0;
+        self.hang = 0;
+    }
+
+    /// Classifies the next frame and updates the noise floor.
+    pub fn process<S: Sample + ToSample<f64>>(&mut self, frame: &[S]) -> bool {
+        if frame.is_empty() {
+            return self.hang > 0;
+        }
+        let samples: Vec<f64> = frame.iter().map(|s| s.to_sample::<f64>()).collect();
+        let power = samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64;
+        let crossings = samples.zero_crossing_rate();
+
+        if self.frames_seen < self.init_frames {
+            self.frames_seen += 1;
+            self.noise_floor += (power - self.noise_floor) / self.frames_seen as f64;
+            return false;
+        }
+        self.frames_seen += 1;
+
+        let level = Db::from_power(power.max(MIN_POWER)).0 - self.noise_floor().0;
+        let threshold = self.energy_threshold.0;
+        let active =
+            level > threshold || (level > threshold / 2. && crossings > self.zcr_threshold);
+        if active {
+            self.hang = self.hangover;
+            return true;
+        }
+        if power < self.noise_floor {
+            self.noise_floor = power;
+        } else {
+            self.noise_floor += (power - self.noise_floor) * (1. - self.noise_smoothing);
+        }
+        if self.hang > 0 {
+            self.hang -= 1;
+            return true;
+        }
+        false
+    }
+
+    /// Classifies every frame of a sequence, such as `signal.chunks(hop)`.
+    pub fn labels<'a, S, I>(&mut self, frames: I) -> Vec<bool>
+    where
+        S: 'a + Sample + ToSample<f64>,
+        I: Iterator<Item = &'a [S]>,
+    {
+        frames.map(|frame| self.process(frame)).collect()
+    }
+}
+
+/// Power floor for frame energies, so that digital silence comes out at -120 dB.
+const MIN_POWER: f64 = 1.0e-12;
+
+/// Keeps the frames labeled as speech, so that feature extractors can skip the rest:
+///
+/// ```
+/// use vox_box::vad::{skip_non_speech, EnergyVad};
+///
+/// let signal: Vec<f64> = (0..4000).map(|n| if n > 2000 { (n as f64).sin() } else { 0. }).collect();
+/// let labels = EnergyVad::new().labels(signal.chunks(200));
+/// let speech: Vec<&[f64]> = skip_non_speech(signal.chunks(200), &labels).collect();
+/// assert_eq!(speech.len(), 10);
+/// ```
+pub fn skip_non_speech<'a, T, I>(frames: I, labels: &'a [bool]) -> impl Iterator<Item = &'a [T]>
+where
+    T: 'a,
+    I: Iterator<Item = &'a [T]> + 'a,
+{
+    frames
+        .zip(labels.iter())
+        .filter(|(_, speech)| **speech)
+        .map(|(frame, _)| frame)
+}
+
+/// Band edges of `GmmVad` in Hz, as in the WebRTC detector.
+const GMM_BANDS: [f64; 7] = [80., 250., 500., 1000., 2000., 3000., 4000.];
+
+/// A two-component Gaussian mixture over one band's log energy.
+#[derive(Clone, Copy, Debug)]
+struct Mixture {
+    means: [f64; 2],
+    variances: [f64; 2],
+}
+
+impl Mixture {
+    fn new(mean: f64, spread: f64, variance: f64) -> Self {
+        Mixture {
+            means: [mean - spread, mean + spread],
+            variances: [variance; 2],
+        }
+    }
+
+    /// Log density of `x` under each component, each weighted by a half.
+    fn component_log_densities(&self, x: f64) -> [f64; 2] {
+        let density = |m: f64, v: f64| {
+            -0.5 * ((x - m) * (x - m) / v + (2. * std::f64::consts::PI * v).ln()) + 0.5f64.ln()
+        };
+        [
+            density(self.means[0], self.variances[0]),
+            density(self.means[1], self.variances[1]),
+        ]
+    }
+
+    fn log_density(&self, x: f64) -> f64 {
+        let [a, b] = self.component_log_densities(x);
+        let top = a.max(b);
+        top + ((a - top).exp() + (b - top).exp()).ln()
+    }
+
+    /// Moves each component towards `x` in proportion to its responsibility for it.
+    fn adapt(&mut self, x: f64, rate: f64, min_variance: f64) {
+        let [a, b] = self.component_log_densities(x);
+        let top = a.max(b);
+        let (wa, wb) = ((a - top).exp(), (b - top).exp());
+        for (i, responsibility) in [wa / (wa + wb), wb / (wa + wb)].iter().enumerate() {
+            let step = rate * responsibility;
+            let diff = x - self.means[i];
+            self.means[i] += step * diff;
+            self.variances[i] += step * (diff * diff - self.variances[i]);
+            self.variances[i] = self.variances[i].max(min_variance);
+        }
+    }
+}
+
+/// A model-based voice activity detector in the style of WebRTC's.
+///
+/// The log energies of six frequency bands are each scored against a noise and a speech
+/// Gaussian mixture, and the summed log likelihood ratio makes the decision. Whichever model
+/// wins is adapted towards the frame. Since a background that gets louder could otherwise be
+/// taken for speech for good, the noise model is also pulled towards the quietest frames of the
+/// last `min_window`. The noise model starts from the first `init_frames` frames, and the speech
+/// model a fixed level above.
+#[derive(Clone, Debug)]
+pub struct GmmVad {
+    /// Summed log likelihood ratio above which a frame counts as speech
+    pub threshold: f64,
+    /// Log likelihood ratio of a single band above which the frame counts as speech anyway
+    pub band_threshold: f64,
+    /// Number of frames to keep reporting speech after the likelihood drops
+    pub hangover: usize,
+    /// How fast the noise model follows non-speech frames, between 0 and 1
+    pub noise_adaptation: f64,
+    /// How fast the speech model follows speech frames, between 0 and 1
+    pub speech_adaptation: f64,
+    /// Number of initial frames that are assumed to be noise
+    pub init_frames: usize,
+    /// Number of recent frames whose quietest sets a floor for the noise model
+    pub min_window: usize,
+    /// How fast the noise model follows that floor, between 0 and 1
+    pub floor_tracking: f64,
+    sample_rate: f64,
+    fft: RealFft,
+    window: Vec<f64>,
+    noise: Vec<Mixture>,
+    speech: Vec<Mixture>,
+    init_sums: Vec<(f64, f64)>,
+    /// Distance of the noise mean above the quietest frame, per band
+    floor_offsets: Vec<f64>,
+    history: VecDeque<Vec<f64>>,
+    frames_seen: usize,
+    hang: usize,
+}
+
+/// Lowest variance of a mixture component, in dB squared.
+const MIN_VARIANCE: f64 = 1.;
+
+impl GmmVad {
+    /// Creates a detector for Hanning-windowed frames of `frame_len` samples.
+    pub fn new<R: Into<Hz>>(frame_len: usize, sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        Self::with_window(WindowType::Hanning.window(frame_len), sample_rate)
+    }
+
+    /// Creates a detector for the frame length, window and sample rate of `config`.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        Self::with_window(config.window.window(config.frame_len), config.sample_rate.0)
+    }
+
+    fn with_window(window: Vec<f64>, sample_rate: f64) -> Self {
+        let fft_len = window.len().next_power_of_two();
+        let n_bands = GMM_BANDS.len() - 1;
+        GmmVad {
+            threshold: 6.,
+            band_threshold: 4.,
+            hangover: 8,
+            noise_adaptation: 0.02,
+            speech_adaptation: 0.05,
+            init_frames: 10,
+            min_window: 50,
+            floor_tracking: 0.1,
+            sample_rate,
+            fft: RealFft::new(fft_len),
+            window,
+            noise: vec![Mixture::new(0., 0., MIN_VARIANCE); n_bands],
+            speech: vec![Mixture::new(0., 0., MIN_VARIANCE); n_bands],
+            init_sums: vec![(0., 0.); n_bands],
+            floor_offsets: vec![0.; n_bands],
+            history: VecDeque::new(),
+            frames_seen: 0,
+            hang: 0,
+        }
+    }
+
+    /// Forgets both models, so that the next `init_frames` frames are learned as noise.
+    pub fn reset(&mut self) {
+        for sums in self.init_sums.iter_mut() {
+            *sums = (0., 0.);
+        }
+        self.history.clear();
+        self.frames_seen = 0;
+        self.hang = 0;
+    }
+
+    /// Mean of the noise model in each band, in dB.
+    pub fn noise_levels(&self) -> Vec<Db> {
+        self.noise
+            .iter()
+            .map(|m| Db((m.means[0] + m.means[1]) / 2.))
+            .collect()
+    }
+
+    /// Log energy of each band of a frame, in dB. Bands above Nyquist get the floor.
+    fn band_energies<S: Sample + ToSample<f64>>(&self, frame: &[S]) -> Vec<f64> {
+        let windowed: Vec<f64> = frame
+            .iter()
+            .zip(self.window.iter())
+            .map(|(s, w)| s.to_sample::<f64>() * w)
+            .collect();
+        let power = self.fft.power_spectrum(&windowed);
+        let bin_hz = self.sample_rate / self.fft.len() as f64;
+        GMM_BANDS
+            .windows(2)
+            .map(|band| {
+                let low = (band[0] / bin_hz).ceil() as usize;
+                let high = ((band[1] / bin_hz).ceil() as usize).min(power.len());
+                let energy: f64 = power[low.min(high)..high].iter().sum();
+                Db::from_power(energy.max(MIN_POWER)).0
+            })
+            .collect()
+    }
+
+    /// Classifies the next frame and adapts the models. Frames shorter than the frame length
+    /// are zero-padded.
+    pub fn process<S: Sample + ToSample<f64>>(&mut self, frame: &[S]) -> VadDecision {
+        let energies = self.band_energies(frame);
+        self.history.push_back(energies.clone());
+        while self.history.len() > self.min_window.max(1) {
+            self.history.pop_front();
+        }
+        let floors: Vec<f64> = (0..energies.len())
+            .map(|b| {
+                self.history
+                    .iter()
+                    .fold(f64::INFINITY, |floor, e| floor.min(e[b]))
+            })
+            .collect();
+
+        if self.frames_seen < self.init_frames {
+            self.frames_seen += 1;
+            for ((sum, sum_sq), e) in self.init_sums.iter_mut().zip(energies.iter()) {
+                *sum += e;
+                *sum_sq += e * e;
+            }
+            let n = self.frames_seen as f64;
+            for (i, (sum, sum_sq)) in self.init_sums.iter().enumerate() {
+                let mean = sum / n;
+                let variance = (sum_sq / n - mean * mean).max(MIN_VARIANCE);
+                self.noise[i] = Mixture::new(mean, variance.sqrt() / 2., variance);
+                // Speech is assumed to sit well above the noise, and to vary more
+                self.speech[i] = Mixture::new(mean + 20., 5., 36.);
+                self.floor_offsets[i] = mean - floors[i];
+            }
+            return VadDecision {
+                speech: false,
+                log_likelihood: 0.,
+            };
+        }
+        self.frames_seen += 1;
+
+        let ratios: Vec<f64> = energies
+            .iter()
+            .zip(self.noise.iter().zip(self.speech.iter()))
+            .map(|(e, (noise, speech))| speech.log_density(*e) - noise.log_density(*e))
+            .collect();
+        let log_likelihood: f64 = ratios.iter().sum();
+        let active =
+            log_likelihood > self.threshold || ratios.iter().any(|r| *r > self.band_threshold);
+
+        let (models, rate) = if active {
+            (&mut self.speech, self.speech_adaptation)
+        } else {
+            (&mut self.noise, self.noise_adaptation)
+        };
+        for (model, e) in models.iter_mut().zip(energies.iter()) {
+            model.adapt(*e, rate, MIN_VARIANCE);
+        }
+        for ((noise, floor), offset) in self
+            .noise
+            .iter_mut()
+            .zip(floors.iter())
+            .zip(self.floor_offsets.iter_mut())
+        {
+            let center = (noise.means[0] + noise.means[1]) / 2.;
+            // The floor drops as the window fills, so keep the offset calibrated on noise
+            if !active {
+                *offset += self.noise_adaptation * (center - floor - *offset);
+            }
+            let shift = self.floor_tracking * (floor + *offset - center);
+            for mean in noise.means.iter_mut() {
+                *mean += shift;
+            }
+        }
+        // Keep the speech model above the noise model, so the two can't swap roles
+        for (speech, noise) in self.speech.iter_mut().zip(self.noise.iter()) {
+            for (s, n) in speech.means.iter_mut().zip(noise.means.iter()) {
+                *s = s.max(n + 3.);
+            }
+        }
+
+        if active {
+            self.hang = self.hangover;
+        } else if self.hang > 0 {
+            self.hang -= 1;
+            return VadDecision {
+                speech: true,
+                log_likelihood,
+            };
+        }
+        VadDecision {
+            speech: active,
+            log_likelihood,
+        }
+    }
+}
+
+/// A boundary found by `EndpointDetector`, as a frame index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endpoint {
+    /// The utterance starts at this frame, leading padding included
+    Begin(usize),
+    /// The utterance ends just before this frame, trailing padding included
+    End(usize),
+}
+
+/// Turns a stream of per-frame speech decisions into begin and end of utterance events.
+///
+/// A begin is reported once `min_speech` consecutive speech frames have been seen, and placed
+/// `leading` frames before the first of them. An end is reported after `trailing` consecutive
+/// non-speech frames, so `trailing` is both the padding kept after speech and the longest pause
+/// that does not end the utterance.
+#[derive(Clone, Debug)]
+pub struct EndpointDetector {
+    pub leading: usize,
+    pub trailing: usize,
+    pub min_speech: usize,
+    frame: usize,
+    run_start: usize,
+    run: usize,
+    silence: usize,
+    in_utterance: bool,
+}
+
+impl EndpointDetector {
+    pub fn new(leading: usize, trailing: usize, min_speech: usize) -> Self {
+        EndpointDetector {
+            leading,
+            trailing,
+            min_speech: min_speech.max(1),
+            frame: 0,
+            run_start: 0,
+            run: 0,
+            silence: 0,
+            in_utterance: false,
+        }
+    }
+
+    /// Whether an utterance has begun and not yet ended.
+    pub fn in_utterance(&self) -> bool {
+        self.in_utterance
+    }
+
+    /// Feeds the decision for the next frame, returning an endpoint if one was found.
+    pub fn push(&mut self, speech: bool) -> Option<Endpoint> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        if self.in_utterance {
+            if speech {
+                self.silence = 0;
+                return None;
+            }
+            self.silence += 1;
+            if self.silence >= self.trailing {
+                // Only `trailing` of the silent frames belong to the utterance, none if it is zero
+                let end = frame + 1 - (self.silence - self.trailing);
+                self.in_utterance = false;
+                self.silence = 0;
+                return Some(Endpoint::End(end));
+            }
+            return None;
+        }
+
+        if !speech {
+            self.run = 0;
+            return None;
+        }
+        if self.run == 0 {
+            self.run_start = frame;
+        }
+        self.run += 1;
+        if self.run >= self.min_speech {
+            self.run = 0;
+            self.silence = 0;
+            self.in_utterance = true;
+            return Some(Endpoint::Begin(self.run_start.saturating_sub(self.leading)));
+        }
+        None
+    }
+
+    /// Ends the stream, closing an open utterance at the current frame.
+    pub fn finish(&mut self) -> Option<Endpoint> {
+        self.run = 0;
+        if self.in_utterance {
+            self.in_utterance = false;
+            Some(Endpoint::End(self.frame))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+    use std::f64::consts::PI;
+
+    fn noise(rng: &mut XorShiftRng, len: usize, level: f64) -> Vec<f64> {
+        let normal = Normal::new(0., level);
+        (0..len).map(|_| normal.ind_sample(rng)).collect()
+    }
+
+    #[test]
+    fn test_energy_vad() {
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+        let mut vad = EnergyVad::new();
+        vad.hangover = 2;
+        let mut labels = vec![];
+        for i in 0..80 {
+            let mut frame = noise(&mut rng, 160, 0.01);
+            if (30..40).contains(&i) {
+                // Voiced: loud and low
+                for (n, s) in frame.iter_mut().enumerate() {
+                    *s += 0.3 * (2. * PI * 150. * (i * 160 + n) as f64 / 16000.).sin();
+                }
+            } else if (50..60).contains(&i) {
+                // Fricative: quiet but busy
+                frame = noise(&mut rng, 160, 0.022);
+            }
+            labels.push(vad.process(&frame[..]));
+        }
+        println!("labels: {:?}", labels);
+        assert!(labels[..30].iter().all(|l| !l));
+        assert!(labels[30..40].iter().all(|l| *l));
+        assert!(labels[40..42].iter().all(|l| *l));
+        assert!(labels[43..50].iter().all(|l| !l));
+        assert!(labels[50..60].iter().filter(|l| **l).count() >= 8);
+        assert!(labels[63..].iter().all(|l| !l));
+        println!("noise floor: {:?}", vad.noise_floor());
+        assert!((vad.noise_floor().0 + 40.).abs() < 3.);
+    }
+
+    #[test]
+    fn test_gmm_vad() {
+        let mut rng = XorShiftRng::from_seed([9, 8, 7, 6]);
+        let mut vad = GmmVad::new(320, 16000.);
+        vad.hangover = 0;
+        let mut labels = vec![];
+        for i in 0..180 {
+            // The background doubles in level a third of the way through
+            let level = if i < 60 { 0.01 } else { 0.02 };
+            let mut frame = noise(&mut rng, 320, level);
+            if (30..45).contains(&i) || (140..155).contains(&i) {
+                for (n, s) in frame.iter_mut().enumerate() {
+                    let t = (i * 320 + n) as f64 / 16000.;
+                    *s += (1..8)
+                        .map(|h| 0.1 * (2. * PI * 140. * h as f64 * t).sin() / h as f64)
+                        .sum::<f64>();
+                }
+            }
+            labels.push(vad.process(&frame[..]).speech);
+        }
+        println!("labels: {:?}", labels);
+        println!("noise: {:?}", vad.noise_levels());
+        assert!(labels[30..45].iter().all(|l| *l));
+        assert!(labels[140..155].iter().all(|l| *l));
+        assert!(labels[10..30].iter().filter(|l| **l).count() <= 1);
+        // Once the noise floor has caught up with the louder background
+        assert!(labels[120..140].iter().filter(|l| **l).count() <= 1);
+        assert!(labels[160..].iter().filter(|l| **l).count() <= 1);
+    }
+
+    #[test]
+    fn test_sohn_vad() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut vad = SohnVad::new(256);
+        vad.hangover = 0;
+        let mut decisions = vec![];
+        for i in 0..80 {
+            let mut frame = noise(&mut rng, 256, 0.01);
+            if (40..60).contains(&i) {
+                for (n, s) in frame.iter_mut().enumerate() {
+                    *s += 0.2 * (2. * PI * 440. * (i * 256 + n) as f64 / 16000.).sin();
+                }
+            }
+            decisions.push(vad.process(&frame[..]).speech);
+        }
+        println!("decisions: {:?}", decisions);
+        assert!(decisions[12..40].iter().filter(|d| **d).count() <= 2);
+        assert!(decisions[40..60].iter().all(|d| *d));
+        assert!(decisions[62..].iter().filter(|d| **d).count() <= 2);
+    }
+
+    #[test]
+    fn test_sohn_vad_i16() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut float_vad = SohnVad::new(256);
+        let mut int_vad = SohnVad::new(256);
+        for i in 0..30 {
+            let level = if i < 20 { 0.01 } else { 0.3 };
+            let frame: Vec<i16> = noise(&mut rng, 256, level)
+                .iter()
+                .map(|s| (s * 32768.) as i16)
+                .collect();
+            let as_float: Vec<f64> = frame.iter().map(|s| *s as f64 / 32768.).collect();
+            assert_eq!(
+                int_vad.process(&frame[..]).speech,
+                float_vad.process(&as_float[..]).speech
+            );
+        }
+    }
+
+    #[test]
+    fn test_sohn_vad_drifting_noise() {
+        let mut rng = XorShiftRng::from_seed([5, 6, 7, 8]);
+        let mut vad = SohnVad::new(256);
+        let mut false_alarms = 0;
+        for i in 0..300 {
+            // The noise floor rises by a factor of four over the run
+            let level = 0.01 * (1. + 3. * i as f64 / 300.);
+            let frame = noise(&mut rng, 256, level);
+            if vad.process(&frame[..]).speech {
+                false_alarms += 1;
+            }
+        }
+        println!("false alarms: {}", false_alarms);
+        assert!(false_alarms < 15);
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut detector = EndpointDetector::new(2, 3, 3);
+        // A one-frame blip, then speech from frame 5 with a short pause at frame 9
+        let decisions = [
+            false, true, false, false, false, true, true, true, true, false, true, true, false,
+            false, false, false,
+        ];
+        let endpoints: Vec<(usize, Endpoint)> = decisions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, d)| detector.push(*d).map(|e| (i, e)))
+            .collect();
+        assert_eq!(
+            endpoints,
+            vec![(7, Endpoint::Begin(3)), (14, Endpoint::End(15))]
+        );
+        assert_eq!(detector.finish(), None);
+    }
+
+    #[test]
+    fn test_endpoints_finish() {
+        let mut detector = EndpointDetector::new(5, 10, 1);
+        assert_eq!(detector.push(true), Some(Endpoint::Begin(0)));
+        assert!(detector.in_utterance());
+        assert_eq!(detector.push(false), None);
+        assert_eq!(detector.finish(), Some(Endpoint::End(2)));
+    }
+
+    #[test]
+    fn test_endpoints_no_trailing() {
+        // Without trailing padding the utterance ends at its first silent frame
+        let mut detector = EndpointDetector::new(0, 0, 1);
+        assert_eq!(detector.push(false), None);
+        assert_eq!(detector.push(true), Some(Endpoint::Begin(1)));
+        assert_eq!(detector.push(true), None);
+        assert_eq!(detector.push(false), Some(Endpoint::End(3)));
+        assert!(!detector.in_utterance());
+    }
+}
@@ -0,0 +1,54 @@
+//! Checks shared by the public entry points, so that bad sizes and samples come back as errors
+//! instead of panics or meaningless output.
+
+// Not every feature set uses every check
+#![allow(dead_code)]
+
+use num::Float;
+use num_complex::Complex;
+
+use crate::error::*;
+
+/// Fails if `buf` has no elements.
+pub(crate) fn non_empty<T>(buf: &[T]) -> VoxBoxResult<()> {
+    if buf.is_empty() {
+        return Err(VoxBoxError::Input("Input must not be empty"));
+    }
+    Ok(())
+}
+
+/// Fails if any sample is NaN or infinite.
+pub(crate) fn finite<T: Float>(buf: &[T]) -> VoxBoxResult<()> {
+    if buf.iter().any(|s| !s.is_finite()) {
+        return Err(VoxBoxError::Input(
+            "Input must not contain NaN or infinite samples",
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if any coefficient has a NaN or infinite part.
+pub(crate) fn finite_complex<T: Float>(buf: &[Complex<T>]) -> VoxBoxResult<()> {
+    if buf.iter().any(|c| !c.re.is_finite() || !c.im.is_finite()) {
+        return Err(VoxBoxError::Input(
+            "Input must not contain NaN or infinite samples",
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if a workspace or output slice holds fewer than `needed` elements.
+pub(crate) fn workspace<T>(work: &[T], needed: usize) -> VoxBoxResult<()> {
+    if work.len() < needed {
+        return Err(VoxBoxError::Workspace);
+    }
+    Ok(())
+}
+
+/// Fails unless `value` is finite and greater than zero.
+pub(crate) fn positive<T: Float>(value: T, message: &'static str) -> VoxBoxResult<()> {
+    if !value.is_finite() || value <= T::zero() {
+        return Err(VoxBoxError::Input(message));
+    }
+    Ok(())
+}
@@ -0,0 +1,582 @@
+extern crate rand;
+extern crate rustfft as fft;
+
+use num_complex::Complex;
+use rand::distributions::{IndependentSample, Normal};
+use rand::{SeedableRng, XorShiftRng};
+use std::f64::consts::PI;
+
+use sample::{Sample, ToSample};
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::periodic::{Hanning, Pitched};
+use crate::progress::{self, ControlFlow, Progress};
+use crate::units::Hz;
+use crate::waves::to_f64_samples;
+
+/// f0 used to size the analysis window of unvoiced frames, as in WORLD.
+const DEFAULT_F0: f64 = 500.;
+/// Shape of the CheapTrick lifter.
+const LIFTER_Q1: f64 = -0.15;
+/// Floor for aperiodicity, so that voiced bands always carry a little noise.
+const MIN_APERIODICITY: f64 = 0.001;
+
+/// Settings for `WorldParameters::analyze`.
+#[derive(Clone, Debug)]
+pub struct WorldConfig {
+    /// Distance between analysis frames, in samples
+    pub hop: usize,
+    /// Lowest f0 to search for, in Hz. This also sets the FFT size.
+    pub f0_floor: f64,
+    /// Highest f0 to search for, in Hz
+    pub f0_ceil: f64,
+    /// Autocorrelation strength a pitch candidate needs for the frame to count as voiced
+    pub voicing_threshold: f64,
+    /// Width of each aperiodicity band, in Hz
+    pub band_width: f64,
+}
+
+impl WorldConfig {
+    /// Default settings for the given sample rate: 5 ms frames, f0 between 71 and 800 Hz, and
+    /// aperiodicity bands 3 kHz wide.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        let sample_rate = sample_rate.into().0;
+        WorldConfig {
+            hop: (sample_rate * 0.005).round() as usize,
+            f0_floor: 71.,
+            f0_ceil: 800.,
+            voicing_threshold: 0.45,
+            band_width: 3000.,
+        }
+    }
+
+    /// Default settings, analyzing a frame every `config.hop` samples. The analysis windows are
+    /// sized from f0, so the rest of the framing is not used.
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        WorldConfig {
+            hop: config.hop,
+            ..WorldConfig::new(config.sample_rate)
+        }
+    }
+
+    /// FFT size for the spectral envelope: enough to hold three periods of the lowest f0.
+    pub fn fft_size<R: Into<Hz>>(&self, sample_rate: R) -> usize {
+        let sample_rate = sample_rate.into().0;
+        ((3. * sample_rate / self.f0_floor).ceil() as usize).next_power_of_two()
+    }
+}
+
+/// Forward and inverse FFTs of a fixed size, planned once.
+struct Transform {
+    forward: std::sync::Arc<dyn fft::FFT<f64>>,
+    inverse: std::sync::Arc<dyn fft::FFT<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Transform {
+    fn new(size: usize) -> Self {
+        Transform {
+            forward: fft::FFTplanner::new(false).plan_fft(size),
+            inverse: fft::FFTplanner::new(true).plan_fft(size),
+            scratch: vec![Complex::new(0., 0.); size],
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.scratch.len()
+    }
+
+    /// Transforms `buf` in place. The inverse transform is scaled by `1 / size`.
+    fn process(&mut self, buf: &mut [Complex<f64>], inverse: bool) {
+        if inverse {
+            self.inverse.process(buf, &mut self.scratch);
+            let scale = 1. / self.scratch.len() as f64;
+            for (b, s) in buf.iter_mut().zip(self.scratch.iter()) {
+                *b = s * scale;
+            }
+        } else {
+            self.forward.process(buf, &mut self.scratch);
+            buf.clone_from_slice(&self.scratch);
+        }
+    }
+
+    /// Returns the power spectrum (`size / 2 + 1` bins) of `frame`, zero-padded.
+    fn power_spectrum(&mut self, frame: &[f64]) -> Vec<f64> {
+        let mut buf = vec![Complex::new(0., 0.); self.size()];
+        for (b, s) in buf.iter_mut().zip(frame.iter()) {
+            b.re = *s;
+        }
+        self.process(&mut buf, false);
+        buf.iter()
+            .take(self.size() / 2 + 1)
+            .map(|c| c.norm_sqr())
+            .collect()
+    }
+
+    /// Turns a magnitude response (`size / 2 + 1` bins) into the impulse response of the
+    /// minimum-phase filter with that magnitude, by folding its real cepstrum.
+    fn minimum_phase(&mut self, magnitude: &[f64]) -> Vec<f64> {
+        let n = self.size();
+        let mut buf: Vec<Complex<f64>> = (0..n)
+            .map(|k| Complex::new((magnitude[k.min(n - k)] + 1.0e-12).ln(), 0.))
+            .collect();
+        self.process(&mut buf, true);
+        for (q, c) in buf.iter_mut().enumerate() {
+            if q > 0 && q < n / 2 {
+                *c *= 2.;
+            } else if q > n / 2 {
+                *c = Complex::new(0., 0.);
+            }
+        }
+        self.process(&mut buf, false);
+        for c in buf.iter_mut() {
+            *c = c.exp();
+        }
+        self.process(&mut buf, true);
+        buf.iter().map(|c| c.re).collect()
+    }
+}
+
+/// Reads a sample, treating everything outside of the signal as silence.
+fn sample_at(signal: &[f64], idx: isize) -> f64 {
+    if idx < 0 || idx as usize >= signal.len() {
+        0.
+    } else {
+        signal[idx as usize]
+    }
+}
+
+/// Hanning-windowed excerpt of `len` samples centered on `center`.
+fn windowed(signal: &[f64], center: usize, len: usize) -> Vec<f64> {
+    let start = center as isize - (len / 2) as isize;
+    (0..len)
+        .map(|i| {
+            let w = 0.5 - 0.5 * (2. * PI * (i as f64 + 0.5) / len as f64).cos();
+            sample_at(signal, start + i as isize) * w
+        })
+        .collect()
+}
+
+/// The WORLD decomposition of a signal: an f0 track, a smoothed spectral envelope and a band
+/// aperiodicity per frame.
+#[derive(Clone, Debug)]
+pub struct WorldParameters {
+    pub sample_rate: f64,
+    /// Distance between frames, in samples
+    pub hop: usize,
+    /// Number of samples in the analyzed signal
+    pub length: usize,
+    /// FFT size of the spectral envelope
+    pub fft_size: usize,
+    /// f0 of each frame in Hz, or 0 for unvoiced frames
+    pub f0: Vec<f64>,
+    /// Power spectral envelope of each frame, `fft_size / 2 + 1` bins
+    pub envelope: Vec<Vec<f64>>,
+    /// Ratio of aperiodic to total power (0 to 1) in each band of each frame
+    pub aperiodicity: Vec<Vec<f64>>,
+    /// Center frequency of each aperiodicity band
+    pub band_frequencies: Vec<f64>,
+}
+
+impl WorldParameters {
+    /// Analyzes a signal into its f0 track, CheapTrick-style spectral envelope and band
+    /// aperiodicity. Frame `k` is centered on sample `k * config.hop`.
+    pub fn analyze<S: Sample + ToSample<f64>, R: Into<Hz>>(
+        signal: &[S],
+        sample_rate: R,
+        config: &WorldConfig,
+    ) -> VoxBoxResult<WorldParameters> {
+        WorldParameters::analyze_with_progress(signal, sample_rate, config, progress::ignore)
+    }
+
+    /// Like `analyze`, calling `progress` after every frame. Returns `VoxBoxError::Cancelled`
+    /// if the callback breaks.
+    pub fn analyze_with_progress<S, F, R: Into<Hz>>(
+        signal: &[S],
+        sample_rate: R,
+        config: &WorldConfig,
+        mut progress: F,
+    ) -> VoxBoxResult<WorldParameters>
+    where
+        S: Sample + ToSample<f64>,
+        F: FnMut(Progress) -> ControlFlow<()>,
+    {
+        let sample_rate = sample_rate.into().0;
+        if signal.is_empty() || sample_rate <= 0. {
+            return Err(VoxBoxError::Vocoder(
+                "Signal must not be empty and the sample rate must be positive",
+            ));
+        }
+        if config.hop == 0 || config.f0_floor <= 0. || config.f0_ceil <= config.f0_floor {
+            return Err(VoxBoxError::Vocoder("Invalid hop size or f0 range"));
+        }
+
+        let signal = &to_f64_samples(signal)[..];
+        let fft_size = config.fft_size(sample_rate);
+        let nyquist = sample_rate / 2.;
+        let n_bands = (nyquist / config.band_width).ceil().max(1.) as usize;
+        let band_frequencies = (0..n_bands)
+            .map(|b| ((b as f64 + 0.5) * config.band_width).min(nyquist))
+            .collect();
+
+        let mut envelope_fft = Transform::new(fft_size);
+        let mut aperiodicity_fft = Transform::new(fft_size * 2);
+
+        let n_frames = signal.len() / config.hop + 1;
+        let mut f0 = Vec::with_capacity(n_frames);
+        let mut envelope = Vec::with_capacity(n_frames);
+        let mut aperiodicity = Vec::with_capacity(n_frames);
+        for k in 0..n_frames {
+            let center = k * config.hop;
+            let frame_f0 = estimate_f0(signal, center, sample_rate, config);
+            envelope.push(cheaptrick(
+                &mut envelope_fft,
+                signal,
+                center,
+                frame_f0,
+                sample_rate,
+            ));
+            aperiodicity.push(band_aperiodicity(
+                &mut aperiodicity_fft,
+                signal,
+                center,
+                frame_f0,
+                sample_rate,
+                config.band_width,
+                n_bands,
+            ));
+            f0.push(frame_f0);
+            progress::report(&mut progress, k + 1, n_frames)?;
+        }
+
+        Ok(WorldParameters {
+            sample_rate,
+            hop: config.hop,
+            length: signal.len(),
+            fft_size,
+            f0,
+            envelope,
+            aperiodicity,
+            band_frequencies,
+        })
+    }
+
+    /// Aperiodicity of frame `k` at every envelope bin, interpolated linearly between bands.
+    fn aperiodicity_bins(&self, k: usize) -> Vec<f64> {
+        let bands = &self.aperiodicity[k];
+        let centers = &self.band_frequencies;
+        (0..=self.fft_size / 2)
+            .map(|bin| {
+                let freq = bin as f64 * self.sample_rate / self.fft_size as f64;
+                match centers.iter().position(|c| *c > freq) {
+                    Some(0) => bands[0],
+                    Some(b) => {
+                        let frac = (freq - centers[b - 1]) / (centers[b] - centers[b - 1]);
+                        bands[b - 1] + (bands[b] - bands[b - 1]) * frac
+                    }
+                    None => bands[bands.len() - 1],
+                }
+            })
+            .collect()
+    }
+
+    /// Resynthesizes a signal from the parameters: a pulse train through the periodic part of
+    /// the envelope, plus white noise through the aperiodic part.
+    pub fn synthesize(&self) -> VoxBoxResult<Vec<f64>> {
+        let n_frames = self.f0.len();
+        if n_frames == 0 || self.envelope.len() != n_frames || self.aperiodicity.len() != n_frames {
+            return Err(VoxBoxError::Vocoder(
+                "Need an envelope and aperiodicity for every frame",
+            ));
+        }
+        if self.hop == 0 || self.fft_size < 2 * self.hop {
+            return Err(VoxBoxError::Vocoder(
+                "FFT size must be at least twice the hop size",
+            ));
+        }
+
+        let n = self.fft_size;
+        let mut transform = Transform::new(n);
+        let mut out = vec![0f64; self.length];
+        let frame_at = |t: f64| ((t / self.hop as f64).round() as usize).min(n_frames - 1);
+
+        // Periodic part: one minimum-phase pulse per period
+        let mut t = 0f64;
+        while (t as usize) < self.length {
+            let k = frame_at(t);
+            if self.f0[k] <= 0. {
+                t += self.hop as f64;
+                continue;
+            }
+            let period = self.sample_rate / self.f0[k];
+            let magnitude: Vec<f64> = self.envelope[k]
+                .iter()
+                .zip(self.aperiodicity_bins(k))
+                .map(|(e, a)| (e * (1. - a) * period / 2.).sqrt())
+                .collect();
+            let response = transform.minimum_phase(&magnitude);
+            for (o, r) in out.iter_mut().skip(t as usize).zip(response.iter()) {
+                *o += *r;
+            }
+            t += period;
+        }
+
+        // Aperiodic part: overlapping blocks of white noise, filtered in the frequency domain
+        let mut rng = XorShiftRng::from_seed([0x193a_6754, 0xa8a7_d469, 0x9783_0e05, 0x113b_a7bb]);
+        let normal = Normal::new(0., 1.);
+        let block = 2 * self.hop;
+        let offset = n / 2 - self.hop;
+        let mut buf = vec![Complex::new(0., 0.); n];
+        for k in 0..n_frames {
+            for c in buf.iter_mut() {
+                *c = Complex::new(0., 0.);
+            }
+            // Square-root Hanning blocks keep the noise power constant when overlap-added
+            for (i, c) in buf.iter_mut().skip(offset).take(block).enumerate() {
+                let w = (PI * i as f64 / block as f64).sin();
+                c.re = normal.ind_sample(&mut rng) * w;
+            }
+            transform.process(&mut buf, false);
+            let aperiodicity = self.aperiodicity_bins(k);
+            for (bin, c) in buf.iter_mut().enumerate() {
+                let b = bin.min(n - bin);
+                *c *= (self.envelope[k][b] * aperiodicity[b]).sqrt();
+            }
+            transform.process(&mut buf, true);
+
+            let start = (k * self.hop) as isize - (n / 2) as isize;
+            for (i, c) in buf.iter().enumerate() {
+                let idx = start + i as isize;
+                if idx >= 0 && (idx as usize) < out.len() {
+                    out[idx as usize] += c.re;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Estimates the f0 of the frame centered on `center`, or returns 0 if it is unvoiced.
+fn estimate_f0(signal: &[f64], center: usize, sample_rate: f64, config: &WorldConfig) -> f64 {
+    let len = (3. * sample_rate / config.f0_floor).ceil() as usize;
+    let frame = windowed(signal, center, len);
+    // Silence has no autocorrelation peak to normalize against
+    if frame.iter().all(|s| s.abs() < 1.0e-10) {
+        return 0.;
+    }
+    let candidates = frame.pitch::<Hanning>(
+        sample_rate,
+        config.voicing_threshold,
+        1.,
+        1.,
+        config.f0_floor,
+        config.f0_ceil,
+    );
+    match candidates.first() {
+        Some(p) if p.frequency.is_finite() && p.frequency > 0. => p.frequency,
+        _ => 0.,
+    }
+}
+
+/// CheapTrick spectral envelope: the power spectrum of a pitch-adaptive window, smoothed over
+/// frequency and liftered so that the harmonic structure disappears.
+fn cheaptrick(
+    transform: &mut Transform,
+    signal: &[f64],
+    center: usize,
+    f0: f64,
+    sample_rate: f64,
+) -> Vec<f64> {
+    let n = transform.size();
+    let f0 = if f0 > 0. { f0 } else { DEFAULT_F0 };
+    let len = ((3. * sample_rate / f0).round() as usize).min(n);
+
+    let mut frame = windowed(signal, center, len);
+    let energy = frame.iter().map(|s| s * s).sum::<f64>();
+    let norm = frame
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            let w = 0.5 - 0.5 * (2. * PI * (i as f64 + 0.5) / len as f64).cos();
+            w * w
+        })
+        .sum::<f64>()
+        .sqrt();
+    for s in frame.iter_mut() {
+        *s /= norm;
+    }
+    let mut power = transform.power_spectrum(&frame);
+    let bins = power.len();
+    let bin_hz = sample_rate / n as f64;
+
+    // Fold the energy below f0 back into the spectrum, as there are no harmonics there
+    let f0_bin = f0 / bin_hz;
+    let dc: Vec<f64> = (0..(f0_bin as usize).min(bins))
+        .map(|k| power[((f0_bin - k as f64).round() as usize).min(bins - 1)])
+        .collect();
+    for (p, d) in power.iter_mut().zip(dc) {
+        *p += d;
+    }
+
+    // Rectangular smoothing over 2/3 of f0, mirrored at both ends of the spectrum
+    let half = ((f0 * 2. / 3.) / bin_hz / 2.).round().max(1.) as isize;
+    let mirror = |k: isize| -> f64 {
+        let k = k.abs();
+        let k = if k >= bins as isize {
+            2 * (bins as isize - 1) - k
+        } else {
+            k
+        };
+        power[k.max(0) as usize]
+    };
+    let smoothed: Vec<f64> = (0..bins as isize)
+        .map(|k| ((k - half)..=(k + half)).map(mirror).sum::<f64>() / (2 * half + 1) as f64)
+        .collect();
+
+    // A silent frame gets a flat, tiny envelope
+    let floor = if energy > 0. { 1.0e-12 } else { 1.0e-16 };
+
+    // Lifter the cepstrum to remove what is left of the harmonics
+    let mut buf: Vec<Complex<f64>> = (0..n)
+        .map(|k| Complex::new((smoothed[k.min(n - k)] + floor).ln(), 0.))
+        .collect();
+    transform.process(&mut buf, true);
+    for (i, c) in buf.iter_mut().enumerate() {
+        let q = i.min(n - i) as f64 / sample_rate;
+        if q > 0. {
+            let x = PI * f0 * q;
+            let lifter = (x.sin() / x) * ((1. - 2. * LIFTER_Q1) + 2. * LIFTER_Q1 * (2. * x).cos());
+            *c *= lifter;
+        }
+    }
+    transform.process(&mut buf, false);
+    buf.iter().take(bins).map(|c| c.re.exp()).collect()
+}
+
+/// Ratio of aperiodic to total power in each band, measured as the power that falls between
+/// the harmonics of a window six periods long. Unvoiced frames are entirely aperiodic.
+fn band_aperiodicity(
+    transform: &mut Transform,
+    signal: &[f64],
+    center: usize,
+    f0: f64,
+    sample_rate: f64,
+    band_width: f64,
+    n_bands: usize,
+) -> Vec<f64> {
+    if f0 <= 0. {
+        return vec![1.; n_bands];
+    }
+    let n = transform.size();
+    let len = ((6. * sample_rate / f0).round() as usize).min(n);
+    let power = transform.power_spectrum(&windowed(signal, center, len));
+    let bin_hz = sample_rate / n as f64;
+
+    let mut harmonic = vec![0f64; n_bands];
+    let mut total = vec![0f64; n_bands];
+    for (k, p) in power.iter().enumerate() {
+        let freq = k as f64 * bin_hz;
+        let band = ((freq / band_width) as usize).min(n_bands - 1);
+        let nearest = (freq / f0).round().max(1.) * f0;
+        total[band] += p;
+        if (freq - nearest).abs() < f0 / 4. {
+            harmonic[band] += p;
+        }
+    }
+
+    harmonic
+        .iter()
+        .zip(total.iter())
+        .map(|(h, t)| {
+            if *t > 0. {
+                (1. - h / t).clamp(MIN_APERIODICITY, 1.)
+            } else {
+                1.
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An impulse train with the given period, run through a single resonance at `freq`.
+    fn vowel(len: usize, period: usize, freq: f64, sample_rate: f64) -> Vec<f64> {
+        let r: f64 = 0.97;
+        let theta = 2. * PI * freq / sample_rate;
+        let mut out = vec![0f64; len];
+        for n in 0..len {
+            let x = if n % period == 0 { 1. } else { 0. };
+            let y1 = if n > 0 { out[n - 1] } else { 0. };
+            let y2 = if n > 1 { out[n - 2] } else { 0. };
+            out[n] = x + 2. * r * theta.cos() * y1 - r * r * y2;
+        }
+        out
+    }
+
+    fn rms(buf: &[f64]) -> f64 {
+        (buf.iter().map(|s| s * s).sum::<f64>() / buf.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_world_analysis() {
+        let signal = vowel(4000, 80, 1000., 8000.);
+        let config = WorldConfig::new(8000.);
+        let params = WorldParameters::analyze(&signal, 8000., &config).unwrap();
+        assert_eq!(params.f0.len(), 4000 / config.hop + 1);
+        assert_eq!(params.envelope[0].len(), params.fft_size / 2 + 1);
+
+        // Steady 100 Hz voicing away from the edges
+        for f0 in params.f0[10..params.f0.len() - 10].iter() {
+            assert!((f0 - 100.).abs() < 2., "f0 was {}", f0);
+        }
+
+        // The envelope peaks at the resonance
+        let env = &params.envelope[40];
+        let peak = (0..env.len())
+            .max_by(|a, b| env[*a].partial_cmp(&env[*b]).unwrap())
+            .unwrap();
+        let peak_hz = peak as f64 * 8000. / params.fft_size as f64;
+        assert!((peak_hz - 1000.).abs() < 100., "peak at {}", peak_hz);
+
+        // An impulse train is almost perfectly periodic
+        assert!(params.aperiodicity[40][0] < 0.2);
+    }
+
+    #[test]
+    fn test_world_resynthesis() {
+        let signal = vowel(4000, 80, 1000., 8000.);
+        let params = WorldParameters::analyze(&signal, 8000., &WorldConfig::new(8000.)).unwrap();
+        let out = params.synthesize().unwrap();
+        assert_eq!(out.len(), signal.len());
+
+        let ratio = rms(&out[500..3500]) / rms(&signal[500..3500]);
+        println!("rms ratio: {}", ratio);
+        assert!(ratio > 0.5 && ratio < 2.);
+    }
+
+    #[test]
+    fn test_world_silence() {
+        let signal = vec![0f64; 2000];
+        let params = WorldParameters::analyze(&signal, 8000., &WorldConfig::new(8000.)).unwrap();
+        assert!(params.f0.iter().all(|f| *f == 0.));
+        let out = params.synthesize().unwrap();
+        assert!(out.iter().all(|s| s.is_finite() && s.abs() < 1.0e-3));
+    }
+    #[test]
+    fn test_world_cancel() {
+        let signal = vec![0f64; 2000];
+        let config = WorldConfig::new(8000.);
+        let result = WorldParameters::analyze_with_progress(&signal, 8000., &config, |p| {
+            if p.done == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(result, Err(VoxBoxError::Cancelled)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Perturbation measures of voice quality, following the definitions in Praat.
+//!
+//! The measures take per-period values that a pitch tracker or epoch detector has already
+//! found. As in Praat, terms spanning two consecutive periods whose lengths differ by more
+//! than `MAX_PERIOD_FACTOR`, or whose amplitudes differ by more than `MAX_AMPLITUDE_FACTOR`,
+//! are left out, since they usually straddle a voice break or a tracking error rather than
+//! measure perturbation.
+
+use crate::error::*;
+use crate::units::{Db, Seconds};
+
+/// Largest ratio between consecutive periods that still counts as the same stretch of voice.
+pub const MAX_PERIOD_FACTOR: f64 = 1.3;
+
+/// Largest ratio between the amplitudes of consecutive periods that still counts as the same
+/// stretch of voice.
+pub const MAX_AMPLITUDE_FACTOR: f64 = 1.6;
+
+/// Period-to-period variability of the pitch. The relative measures are fractions of the mean
+/// period; Praat shows them as percentages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Jitter {
+    /// Mean absolute difference between consecutive periods, relative to the mean period
+    pub local: f64,
+    /// Mean absolute difference between consecutive periods
+    pub local_absolute: Seconds,
+    /// Relative average perturbation: mean absolute difference between each period and the
+    /// average of it and its two neighbours, relative to the mean period
+    pub rap: f64,
+    /// Five-point period perturbation quotient: like `rap`, over five periods
+    pub ppq5: f64,
+}
+
+/// Whether `values[start..start + n]` form one stretch of voice, with no two consecutive
+/// values further apart than `factor`.
+fn continuous(values: &[f64], start: usize, n: usize, factor: f64) -> bool {
+    values[start..start + n].windows(2).all(|w| {
+        let ratio = w[0].max(w[1]) / w[0].min(w[1]);
+        ratio <= factor
+    })
+}
+
+/// Mean absolute difference between each value and the mean of the `n` values centered on
+/// it, over the windows where the values are continuous under `factor`. `n` is odd. NaN if
+/// there are no such windows.
+fn perturbation(values: &[f64], n: usize, factor: f64) -> f64 {
+    if values.len() < n {
+        return f64::NAN;
+    }
+    let (sum, count) = (0..=values.len() - n)
+        .filter(|&i| continuous(values, i, n, factor))
+        .fold((0., 0), |(sum, count), i| {
+            let mean = values[i..i + n].iter().sum::<f64>() / n as f64;
+            (sum + (values[i + n / 2] - mean).abs(), count + 1)
+        });
+    if count == 0 {
+        f64::NAN
+    } else {
+        sum / count as f64
+    }
+}
+
+impl Jitter {
+    /// Computes jitter over a sequence of consecutive pitch periods. Needs at least five
+    /// positive periods. Measures whose terms were all left out for exceeding
+    /// `MAX_PERIOD_FACTOR` are NaN.
+    pub fn from_periods(periods: &[Seconds]) -> VoxBoxResult<Jitter> {
+        let periods: Vec<f64> = periods.iter().map(|p| p.0).collect();
+        if periods.len() < 5 {
+            return Err(VoxBoxError::Input("Jitter needs at least five periods"));
+        }
+        if periods.iter().any(|p| !p.is_finite() || *p <= 0.) {
+            return Err(VoxBoxError::Input("Periods must be positive"));
+        }
+        let mean = periods.iter().sum::<f64>() / periods.len() as f64;
+
+        let (sum, count) = (0..periods.len() - 1)
+            .filter(|&i| continuous(&periods, i, 2, MAX_PERIOD_FACTOR))
+            .fold((0., 0), |(sum, count), i| {
+                (sum + (periods[i + 1] - periods[i]).abs(), count + 1)
+            });
+        let local_absolute = if count == 0 {
+            f64::NAN
+        } else {
+            sum / count as f64
+        };
+
+        Ok(Jitter {
+            local: local_absolute / mean,
+            local_absolute: Seconds(local_absolute),
+            rap: perturbation(&periods, 3, MAX_PERIOD_FACTOR) / mean,
+            ppq5: perturbation(&periods, 5, MAX_PERIOD_FACTOR) / mean,
+        })
+    }
+}
+
+/// Period-to-period variability of the amplitude. The relative measures are fractions of the
+/// mean amplitude; Praat shows them as percentages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shimmer {
+    /// Mean absolute difference between the amplitudes of consecutive periods, relative to the
+    /// mean amplitude
+    pub local: f64,
+    /// Mean absolute level difference between consecutive periods
+    pub local_db: Db,
+    /// Three-point amplitude perturbation quotient: mean absolute difference between each
+    /// amplitude and the average of it and its two neighbours, relative to the mean amplitude
+    pub apq3: f64,
+    /// Like `apq3`, over five periods
+    pub apq5: f64,
+    /// Like `apq3`, over eleven periods
+    pub apq11: f64,
+}
+
+impl Shimmer {
+    /// Computes shimmer over the peak amplitudes of consecutive pitch periods. Needs at least
+    /// five positive amplitudes; `apq11` is NaN for fewer than eleven. Measures whose terms
+    /// were all left out for exceeding `MAX_AMPLITUDE_FACTOR` are NaN.
+    pub fn from_amplitudes(amplitudes: &[f64]) -> VoxBoxResult<Shimmer> {
+        if amplitudes.len() < 5 {
+            return Err(VoxBoxError::Input("Shimmer needs at least five periods"));
+        }
+        if amplitudes.iter().any(|a| !a.is_finite() || *a <= 0.) {
+            return Err(VoxBoxError::Input("Amplitudes must be positive"));
+        }
+        let mean = amplitudes.iter().sum::<f64>() / amplitudes.len() as f64;
+
+        let pairs: Vec<usize> = (0..amplitudes.len() - 1)
+            .filter(|&i| continuous(amplitudes, i, 2, MAX_AMPLITUDE_FACTOR))
+            .collect();
+        let mean_over_pairs = |term: &dyn Fn(usize) -> f64| {
+            if pairs.is_empty() {
+                f64::NAN
+            } else {
+                pairs.iter().map(|&i| term(i)).sum::<f64>() / pairs.len() as f64
+            }
+        };
+        let local = mean_over_pairs(&|i| (amplitudes[i + 1] - amplitudes[i]).abs());
+        let local_db = mean_over_pairs(&|i| {
+            Db::from_amplitude(amplitudes[i + 1] / amplitudes[i])
+                .0
+                .abs()
+        });
+
+        Ok(Shimmer {
+            local: local / mean,
+            local_db: Db(local_db),
+            apq3: perturbation(amplitudes, 3, MAX_AMPLITUDE_FACTOR) / mean,
+            apq5: perturbation(amplitudes, 5, MAX_AMPLITUDE_FACTOR) / mean,
+            apq11: perturbation(amplitudes, 11, MAX_AMPLITUDE_FACTOR) / mean,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter() {
+        // Alternating 10 and 10.2 ms
+        let periods: Vec<Seconds> = (0..20)
+            .map(|i| Seconds(if i % 2 == 0 { 0.01 } else { 0.0102 }))
+            .collect();
+        let jitter = Jitter::from_periods(&periods).unwrap();
+        println!("jitter: {:?}", jitter);
+        assert!((jitter.local_absolute.0 - 0.0002).abs() < 1e-12);
+        assert!((jitter.local - 0.0002 / 0.0101).abs() < 1e-9);
+        // Each period is 2/3 of a difference away from the mean of three
+        assert!((jitter.rap - 0.0002 * 2. / 3. / 0.0101).abs() < 1e-9);
+        assert!((jitter.ppq5 - 0.0002 * 2. / 5. / 0.0101).abs() < 1e-9);
+
+        let steady = [Seconds(0.005); 8];
+        assert_eq!(Jitter::from_periods(&steady).unwrap().local, 0.);
+
+        // The octave jump is left out
+        let mut broken = steady;
+        broken[4] = Seconds(0.01);
+        assert_eq!(Jitter::from_periods(&broken).unwrap().local, 0.);
+
+        assert!(Jitter::from_periods(&steady[..4]).is_err());
+        assert!(Jitter::from_periods(&[Seconds(0.); 5]).is_err());
+    }
+
+    #[test]
+    fn test_shimmer() {
+        // Alternating amplitudes 1 dB apart
+        let quieter = Db(-1.).to_amplitude();
+        let amplitudes: Vec<f64> = (0..20)
+            .map(|i| if i % 2 == 0 { 1. } else { quieter })
+            .collect();
+        let shimmer = Shimmer::from_amplitudes(&amplitudes).unwrap();
+        println!("shimmer: {:?}", shimmer);
+        let (difference, mean) = (1. - quieter, (1. + quieter) / 2.);
+        assert!((shimmer.local_db.0 - 1.).abs() < 1e-9);
+        assert!((shimmer.local - difference / mean).abs() < 1e-9);
+        assert!((shimmer.apq3 - difference * 2. / 3. / mean).abs() < 1e-9);
+        assert!((shimmer.apq5 - difference * 2. / 5. / mean).abs() < 1e-9);
+        assert!((shimmer.apq11 - difference * 6. / 11. / mean).abs() < 1e-9);
+
+        let short = Shimmer::from_amplitudes(&amplitudes[..6]).unwrap();
+        assert!(short.apq11.is_nan());
+        assert!(Shimmer::from_amplitudes(&amplitudes[..4]).is_err());
+        assert!(Shimmer::from_amplitudes(&[1., 1., -1., 1., 1.]).is_err());
+    }
+}
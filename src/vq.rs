@@ -0,0 +1,349 @@
+//! Vector quantization: a codebook of representative frames trained by the Linde-Buzo-Gray
+//! algorithm, for classic VQ speaker and utterance models, which score a recording by how well a
+//! speaker's codebook fits its frames.
+//!
+//! ```
+//! use vox_box::features::FeatureMatrix;
+//! use vox_box::vq::Lbg;
+//!
+//! let frames = FeatureMatrix::from_rows(1, &[[0.], [0.2], [10.], [10.2]]).unwrap();
+//! let codebook = Lbg::new(2).train(&frames).unwrap();
+//! let codes = codebook.encode(&frames).unwrap();
+//! assert_eq!(codes[0], codes[1]);
+//! assert_ne!(codes[1], codes[2]);
+//! ```
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use crate::distance::{self, Distance, SquaredEuclidean};
+use crate::error::*;
+use crate::features::FeatureMatrix;
+
+/// Codewords, one per row, and the squared Euclidean distance they quantize by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Codebook {
+    pub codewords: FeatureMatrix,
+}
+
+impl Codebook {
+    pub fn new(codewords: FeatureMatrix) -> VoxBoxResult<Self> {
+        if codewords.is_empty() {
+            return Err(VoxBoxError::Config("Codebook must not be empty"));
+        }
+        Ok(Codebook { codewords })
+    }
+
+    /// Number of codewords.
+    pub fn len(&self) -> usize {
+        self.codewords.n_rows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codewords.is_empty()
+    }
+
+    /// Index of the nearest codeword to `frame`, and the squared distance to it.
+    pub fn quantize(&self, frame: &[f64]) -> VoxBoxResult<(usize, f64)> {
+        self.quantize_with(frame, &SquaredEuclidean)
+    }
+
+    /// Index of the nearest codeword to `frame` by another distance, such as a Mahalanobis
+    /// distance over the training frames' covariance, and the distance to it.
+    pub fn quantize_with<D: Distance>(
+        &self,
+        frame: &[f64],
+        distance: &D,
+    ) -> VoxBoxResult<(usize, f64)> {
+        if frame.len() != self.codewords.n_cols() {
+            return Err(VoxBoxError::Input("Frame length must match the codebook"));
+        }
+        distance::nearest(&self.codewords, frame, distance)
+            .ok_or(VoxBoxError::Config("Codebook must not be empty"))
+    }
+
+    /// Index of the nearest codeword to each frame.
+    pub fn encode(&self, frames: &FeatureMatrix) -> VoxBoxResult<Vec<usize>> {
+        frames
+            .rows()
+            .map(|frame| self.quantize(frame).map(|(k, _)| k))
+            .collect()
+    }
+
+    /// Mean squared distance from each frame to its nearest codeword. The lower it is, the better
+    /// the codebook fits, so the speaker whose codebook gives the least distortion is the likeliest.
+    pub fn distortion(&self, frames: &FeatureMatrix) -> VoxBoxResult<f64> {
+        if frames.is_empty() {
+            return Err(VoxBoxError::Input("No frames to quantize"));
+        }
+        let mut total = 0.;
+        for frame in frames.rows() {
+            total += self.quantize(frame)?.1;
+        }
+        Ok(total / frames.n_rows() as f64)
+    }
+
+    /// Writes the codebook as text: a line of `codebook`, the number of codewords and their
+    /// length, then a line per codeword.
+    pub fn write<W: Write>(&self, mut writer: W) -> VoxBoxResult<()> {
+        let write_error = |_| VoxBoxError::Io("Could not write the codebook");
+        writeln!(
+            writer,
+            "codebook {} {}",
+            self.len(),
+            self.codewords.n_cols()
+        )
+        .map_err(write_error)?;
+        for codeword in self.codewords.rows() {
+            let values: Vec<String> = codeword.iter().map(|x| x.to_string()).collect();
+            writeln!(writer, "{}", values.join(" ")).map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a codebook written by `write`.
+    pub fn read<R: Read>(reader: R) -> VoxBoxResult<Self> {
+        let format_error = VoxBoxError::Io("Not a codebook");
+        let mut lines = BufReader::new(reader).lines();
+        let header = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return Err(format_error),
+        };
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        let (size, n_cols) = match fields[..] {
+            ["codebook", size, n_cols] => match (size.parse::<usize>(), n_cols.parse::<usize>()) {
+                (Ok(size), Ok(n_cols)) => (size, n_cols),
+                _ => return Err(format_error),
+            },
+            _ => return Err(format_error),
+        };
+        let mut codewords = FeatureMatrix::new(n_cols);
+        for _ in 0..size {
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => return Err(format_error),
+            };
+            let codeword: Result<Vec<f64>, _> = line.split_whitespace().map(str::parse).collect();
+            match codeword {
+                Ok(codeword) => codewords
+                    .push_row(&codeword)
+                    .map_err(|_| VoxBoxError::Io("Not a codebook"))?,
+                Err(_) => return Err(format_error),
+            }
+        }
+        Codebook::new(codewords)
+    }
+}
+
+/// Settings for training a codebook by LBG: start from the mean of all frames, split every
+/// codeword in two by nudging it either way, refine the codebook by k-means, and repeat until it
+/// is big enough.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lbg {
+    size: usize,
+    split: f64,
+    threshold: f64,
+    max_iterations: usize,
+}
+
+impl Lbg {
+    /// Training for `size` codewords. Sizes that aren't a power of two are reached by only
+    /// splitting the codewords with the most distortion in the last round.
+    pub fn new(size: usize) -> Self {
+        Lbg {
+            size,
+            split: 0.01,
+            threshold: 1.0e-3,
+            max_iterations: 100,
+        }
+    }
+
+    /// How far each half of a split codeword moves, as a fraction of the standard deviation of
+    /// each column of the training frames.
+    pub fn split(mut self, split: f64) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Refinement stops when the distortion improves by less than this fraction.
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Most k-means iterations for each codebook size.
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn train(&self, frames: &FeatureMatrix) -> VoxBoxResult<Codebook> {
+        if self.size == 0 {
+            return Err(VoxBoxError::Config("Codebook size must be nonzero"));
+        }
+        if frames.n_rows() < self.size {
+            return Err(VoxBoxError::Config(
+                "Codebook can't be larger than the training set",
+            ));
+        }
+        if frames.as_slice().iter().any(|x| !x.is_finite()) {
+            return Err(VoxBoxError::Input("Frames must be finite"));
+        }
+
+        let offset: Vec<f64> = frames
+            .column_variances()
+            .iter()
+            .map(|v| v.sqrt() * self.split)
+            .collect();
+        let mut codewords = FeatureMatrix::new(frames.n_cols());
+        codewords.push_row(&frames.column_means())?;
+        let mut cell_distortion = self.refine(frames, &mut codewords)?;
+        while codewords.n_rows() < self.size {
+            // Split the worst cells first, as many as there is room for
+            let mut order: Vec<usize> = (0..codewords.n_rows()).collect();
+            order.sort_by(|a, b| cell_distortion[*b].total_cmp(&cell_distortion[*a]));
+            order.truncate(self.size - codewords.n_rows());
+            let mut split = FeatureMatrix::new(frames.n_cols());
+            for (k, codeword) in codewords.rows().enumerate() {
+                if order.contains(&k) {
+                    split.push_row(&nudge(codeword, &offset, 1.))?;
+                    split.push_row(&nudge(codeword, &offset, -1.))?;
+                } else {
+                    split.push_row(codeword)?;
+                }
+            }
+            codewords = split;
+            cell_distortion = self.refine(frames, &mut codewords)?;
+        }
+        Codebook::new(codewords)
+    }
+
+    /// Runs k-means from `codewords` until the distortion settles, and returns the total
+    /// distortion of each cell.
+    fn refine(
+        &self,
+        frames: &FeatureMatrix,
+        codewords: &mut FeatureMatrix,
+    ) -> VoxBoxResult<Vec<f64>> {
+        let n_cols = frames.n_cols();
+        let mut previous = f64::INFINITY;
+        let mut cell_distortion = vec![0.; codewords.n_rows()];
+        for _ in 0..self.max_iterations.max(1) {
+            let mut sums = vec![0.; codewords.n_rows() * n_cols];
+            let mut counts = vec![0usize; codewords.n_rows()];
+            cell_distortion.iter_mut().for_each(|d| *d = 0.);
+            for frame in frames.rows() {
+                let (k, distance) = distance::nearest(codewords, frame, &SquaredEuclidean).unwrap();
+                counts[k] += 1;
+                cell_distortion[k] += distance;
+                for (s, x) in sums[k * n_cols..(k + 1) * n_cols].iter_mut().zip(frame) {
+                    *s += x;
+                }
+            }
+            // Cells nobody falls in keep their codeword
+            for (k, count) in counts.iter().enumerate() {
+                if *count > 0 {
+                    let codeword = codewords.row_mut(k).unwrap();
+                    for (c, s) in codeword.iter_mut().zip(&sums[k * n_cols..(k + 1) * n_cols]) {
+                        *c = s / *count as f64;
+                    }
+                }
+            }
+            let distortion: f64 = cell_distortion.iter().sum();
+            if previous - distortion <= self.threshold * distortion {
+                break;
+            }
+            previous = distortion;
+        }
+        Ok(cell_distortion)
+    }
+}
+
+fn nudge(codeword: &[f64], offset: &[f64], sign: f64) -> Vec<f64> {
+    codeword
+        .iter()
+        .zip(offset)
+        .map(|(c, o)| c + sign * o)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::{Euclidean, Mahalanobis};
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, XorShiftRng};
+
+    /// Frames scattered around each of `centers`.
+    fn clusters(centers: &[[f64; 2]], per_cluster: usize) -> FeatureMatrix {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let noise = Normal::new(0., 0.1);
+        let mut frames = FeatureMatrix::new(2);
+        for _ in 0..per_cluster {
+            for center in centers.iter() {
+                let frame = [
+                    center[0] + noise.ind_sample(&mut rng),
+                    center[1] + noise.ind_sample(&mut rng),
+                ];
+                frames.push_row(&frame).unwrap();
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn test_lbg() {
+        let centers = [[0., 0.], [5., 1.], [1., 6.]];
+        let frames = clusters(&centers, 50);
+        let codebook = Lbg::new(3).train(&frames).unwrap();
+        println!("{:?}", codebook.codewords);
+        assert_eq!(codebook.len(), 3);
+        for center in centers.iter() {
+            let (_, distance) = codebook.quantize(center).unwrap();
+            assert!(distance < 0.01);
+        }
+        // Frames from one cluster all share a codeword
+        let codes = codebook.encode(&frames).unwrap();
+        assert!(codes.iter().step_by(3).all(|k| *k == codes[0]));
+        assert!(codebook.distortion(&frames).unwrap() < 0.05);
+
+        // A bigger codebook fits the training frames better
+        let bigger = Lbg::new(8).train(&frames).unwrap();
+        assert_eq!(bigger.len(), 8);
+        assert!(bigger.distortion(&frames).unwrap() < codebook.distortion(&frames).unwrap());
+
+        // Splitting works the same around zero, as for mean-normalized features
+        let centered = clusters(&[[-2., 0.], [2., 0.]], 20);
+        let codebook = Lbg::new(2).train(&centered).unwrap();
+        assert!(codebook.quantize(&[-2., 0.]).unwrap().1 < 0.01);
+        assert!(codebook.quantize(&[2., 0.]).unwrap().1 < 0.01);
+
+        assert!(Lbg::new(0).train(&frames).is_err());
+        assert!(Lbg::new(200).train(&frames).is_err());
+        assert!(codebook.quantize(&[0.]).is_err());
+
+        // Discounting the second dimension moves a frame to the other codeword
+        let codebook =
+            Codebook::new(FeatureMatrix::from_rows(2, &[[0., 0.], [3., 3.]]).unwrap()).unwrap();
+        assert_eq!(codebook.quantize(&[2., 0.]).unwrap(), (0, 4.));
+        let mahalanobis = Mahalanobis::diagonal(&[1., 100.]).unwrap();
+        let (k, distance) = codebook.quantize_with(&[2., 0.], &mahalanobis).unwrap();
+        assert_eq!(k, 1);
+        assert!((distance - 1.09f64.sqrt()).abs() < 1e-12);
+        assert!(codebook.quantize_with(&[0.], &Euclidean).is_err());
+    }
+
+    #[test]
+    fn test_codebook_serialization() {
+        let codebook = Lbg::new(2)
+            .train(&clusters(&[[0., 0.], [3., 3.]], 10))
+            .unwrap();
+        let mut text = Vec::new();
+        codebook.write(&mut text).unwrap();
+        assert!(text.starts_with(b"codebook 2 2\n"));
+        assert_eq!(Codebook::read(&text[..]).unwrap(), codebook);
+
+        assert!(Codebook::read(&b"codebook 2 2\n1 2\n"[..]).is_err());
+        assert!(Codebook::read(&b"codebook 1 2\n1 2 3\n"[..]).is_err());
+        assert!(Codebook::read(&b"something else\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! Continuous wavelet transform with a Morlet wavelet.
+//!
+//! Where an STFT resolves every frequency over the same frame, each band of the CWT is analyzed
+//! over a fixed number of its own cycles, so low bands are sharp in frequency and high bands
+//! are sharp in time. This suits transients and pitch-synchronous detail that a single frame
+//! length smears.
+
+use num_complex::Complex;
+use sample::{Sample, ToSample};
+use std::f64::consts::PI;
+
+use crate::config::AnalysisConfig;
+use crate::error::*;
+use crate::features::FeatureMatrix;
+use crate::units::{Hz, Seconds};
+use crate::validate;
+use crate::waves::to_f64_samples;
+
+/// How far either side of its center a wavelet is kept, in standard deviations of its envelope.
+const MORLET_SUPPORT: f64 = 4.;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CwtConfig {
+    pub sample_rate: f64,
+    /// Lowest and highest band center, in Hz
+    pub freq_bounds: (f64, f64),
+    /// Number of bands per doubling of frequency
+    pub voices_per_octave: usize,
+    /// Angular frequency of the Morlet wavelet in radians per unit scale, which sets how many
+    /// cycles each band is analyzed over. 6 is the usual balance of time and frequency.
+    pub omega0: f64,
+    /// Samples between the analyzed points
+    pub hop: usize,
+}
+
+impl CwtConfig {
+    /// 12 voices per octave from 50 Hz to a quarter of the sample rate, every 10 ms.
+    pub fn new<R: Into<Hz>>(sample_rate: R) -> Self {
+        CwtConfig::from_config(&AnalysisConfig::new(sample_rate))
+    }
+
+    pub fn from_config(config: &AnalysisConfig) -> Self {
+        CwtConfig {
+            sample_rate: config.sample_rate.0,
+            freq_bounds: (50., config.sample_rate.0 / 4.),
+            voices_per_octave: 12,
+            omega0: 6.,
+            hop: config.hop,
+        }
+    }
+
+    /// Band centers in Hz, rising geometrically from the lower bound and not past the upper.
+    pub fn frequencies(&self) -> Vec<f64> {
+        let (low, high) = self.freq_bounds;
+        if self.voices_per_octave == 0 || !(low > 0. && high >= low) {
+            return Vec::new();
+        }
+        let voices = self.voices_per_octave as f64;
+        let n_bands = (voices * (high / low).log2() + 1e-9).floor() as usize + 1;
+        (0..n_bands)
+            .map(|j| low * (j as f64 / voices).exp2())
+            .collect()
+    }
+
+    fn validate(&self) -> VoxBoxResult<()> {
+        validate::positive(self.sample_rate, "Sample rate must be positive")?;
+        validate::positive(self.omega0, "Morlet omega0 must be positive")?;
+        let (low, high) = self.freq_bounds;
+        if !(low > 0. && low <= high && high <= self.sample_rate / 2.) {
+            return Err(VoxBoxError::Config(
+                "Frequency bounds must be positive, ordered and below Nyquist",
+            ));
+        }
+        if self.voices_per_octave == 0 || self.hop == 0 {
+            return Err(VoxBoxError::Config(
+                "Voices per octave and hop must be nonzero",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Wavelet coefficients of a signal, one row per analyzed point and one column per band.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scalogram {
+    /// Band centers in Hz, one per column
+    pub frequencies: Vec<f64>,
+    pub coefficients: FeatureMatrix<Complex<f64>>,
+    sample_rate: f64,
+    hop: usize,
+}
+
+impl Scalogram {
+    /// Time of each row, at the wavelet's center.
+    pub fn times(&self) -> Vec<Seconds> {
+        (0..self.coefficients.n_rows())
+            .map(|k| Seconds((k * self.hop) as f64 / self.sample_rate))
+            .collect()
+    }
+
+    /// Magnitude of every coefficient. A sinusoid of amplitude `a` at a band's center frequency
+    /// has magnitude close to `a` in that band.
+    pub fn magnitudes(&self) -> FeatureMatrix {
+        let mut magnitudes = FeatureMatrix::new(self.coefficients.n_cols());
+        for row in self
+            .coefficients
+            .as_slice()
+            .chunks(self.coefficients.n_cols().max(1))
+        {
+            let row: Vec<f64> = row.iter().map(|c| c.norm()).collect();
+            // Rows of the same length as the scalogram's
+            let _ = magnitudes.push_row(&row);
+        }
+        magnitudes
+    }
+}
+
+/// Continuous wavelet transform of a signal with a Morlet wavelet, taken at every `hop`
+/// samples starting from the first. The signal is taken as zero past either end.
+///
+/// Each band convolves the signal with `exp(-t^2 / 2s^2) exp(2 pi i f t)` for `s = omega0 / 2
+/// pi f`, scaled by the envelope's sum so that the band's response to a sinusoid at `f` has the
+/// sinusoid's amplitude.
+pub fn cwt<S>(signal: &[S], config: &CwtConfig) -> VoxBoxResult<Scalogram>
+where
+    S: Sample + ToSample<f64>,
+{
+    config.validate()?;
+    let frequencies = config.frequencies();
+    let kernels: Vec<Vec<Complex<f64>>> = frequencies
+        .iter()
+        .map(|f| morlet(*f, config.omega0, config.sample_rate))
+        .collect();
+    let signal = to_f64_samples(signal);
+    let mut coefficients = FeatureMatrix::new(frequencies.len());
+    let mut row = Vec::with_capacity(frequencies.len());
+    for center in (0..signal.len()).step_by(config.hop) {
+        row.clear();
+        for kernel in kernels.iter() {
+            // Kernels have odd length, centered on their middle tap
+            let half = kernel.len() / 2;
+            let first = center.saturating_sub(half);
+            let last = (center + half + 1).min(signal.len());
+            let offset = first + half - center;
+            let coeff = signal[first..last]
+                .iter()
+                .zip(kernel[offset..].iter())
+                .fold(Complex::new(0., 0.), |acc, (s, k)| acc + k * s);
+            row.push(coeff);
+        }
+        coefficients.push_row(&row)?;
+    }
+    Ok(Scalogram {
+        frequencies,
+        coefficients,
+        sample_rate: config.sample_rate,
+        hop: config.hop,
+    })
+}
+
+/// Taps of the conjugated Morlet wavelet at `frequency`, normalized as described in `cwt`.
+fn morlet(frequency: f64, omega0: f64, sample_rate: f64) -> Vec<Complex<f64>> {
+    let sigma = omega0 / (2. * PI * frequency) * sample_rate;
+    let half = (MORLET_SUPPORT * sigma).ceil() as isize;
+    let envelope: Vec<f64> = (-half..=half)
+        .map(|m| (-(m * m) as f64 / (2. * sigma * sigma)).exp())
+        .collect();
+    let norm = 2. / envelope.iter().sum::<f64>();
+    envelope
+        .iter()
+        .zip(-half..=half)
+        .map(|(g, m)| {
+            Complex::from_polar(
+                &(g * norm),
+                &(-2. * PI * frequency * m as f64 / sample_rate),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak_band(row: &[f64]) -> usize {
+        (0..row.len())
+            .max_by(|a, b| row[*a].partial_cmp(&row[*b]).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cwt() {
+        let sr = 8000.;
+        // 200 Hz for half a second, then 1 kHz at half the amplitude
+        let signal: Vec<f64> = (0..8000)
+            .map(|n| {
+                let t = n as f64 / sr;
+                if n < 4000 {
+                    (2. * PI * 200. * t).sin()
+                } else {
+                    0.5 * (2. * PI * 1000. * t).sin()
+                }
+            })
+            .collect();
+        let mut config = CwtConfig::new(sr);
+        config.freq_bounds = (100., 2000.);
+        let scalogram = cwt(&signal, &config).unwrap();
+        assert_eq!(scalogram.coefficients.n_rows(), 100);
+        assert_eq!(scalogram.frequencies.len(), 52);
+        assert_eq!(scalogram.times()[25], Seconds(0.25));
+
+        let magnitudes = scalogram.magnitudes();
+        let early = magnitudes.row(25).unwrap();
+        let late = magnitudes.row(75).unwrap();
+        let (f_early, f_late) = (
+            scalogram.frequencies[peak_band(early)],
+            scalogram.frequencies[peak_band(late)],
+        );
+        println!("early peak: {} Hz, late peak: {} Hz", f_early, f_late);
+        assert!((f_early / 200.).log2().abs() < 1. / 24.);
+        assert!((f_late / 1000.).log2().abs() < 1. / 24.);
+        assert!((early[peak_band(early)] - 1.).abs() < 0.02);
+        assert!((late[peak_band(late)] - 0.5).abs() < 0.01);
+
+        // High bands see the switch over fewer points than low ones
+        config.hop = 8;
+        let fine = cwt(&signal, &config).unwrap().magnitudes();
+        let transition = |band: usize| {
+            let values: Vec<f64> = (400..600).map(|k| fine.row(k).unwrap()[band]).collect();
+            let max = values.iter().cloned().fold(0., f64::max);
+            values
+                .iter()
+                .filter(|m| **m > 0.05 * max && **m < 0.95 * max)
+                .count()
+        };
+        let band = |f: f64| {
+            let position = scalogram.frequencies.iter().position(|b| *b >= f);
+            position.unwrap()
+        };
+        println!(
+            "transitions: {} at 200 Hz, {} at 1 kHz",
+            transition(band(200.)),
+            transition(band(1000.))
+        );
+        assert!(transition(band(1000.)) * 3 < transition(band(200.)));
+
+        config.freq_bounds = (100., 5000.);
+        assert!(cwt(&signal, &config).is_err());
+        config.freq_bounds = (100., 2000.);
+        assert!(cwt::<f64>(&[], &config).unwrap().coefficients.is_empty());
+    }
+}
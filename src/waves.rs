@@ -2,10 +2,21 @@ extern crate num;
 extern crate sample;
 
 use std::cmp::Ordering::*;
+use std::collections::VecDeque;
 use std::f64::consts::PI;
-use std::iter::Iterator;
+use std::iter::{Copied, Iterator};
+use std::slice;
 
-use sample::{FloatSample, FromSample, Sample};
+use sample::{FloatSample, FromSample, Sample, ToSample};
+
+use crate::error::*;
+use crate::units::{Db, Hz};
+use crate::validate;
+
+/// Converts a buffer of any sample type to `f64` samples between -1.0 and 1.0.
+pub fn to_f64_samples<S: Sample + ToSample<f64>>(signal: &[S]) -> Vec<f64> {
+    signal.iter().map(|s| s.to_sample::<f64>()).collect()
+}
 
 pub trait RMS<S> {
     fn rms(&self) -> S;
@@ -22,6 +33,62 @@ impl<S: Sample> RMS<S> for [S] {
     }
 }
 
+/// Rate at which a signal changes sign, as a fraction of consecutive sample pairs. High for
+/// fricatives and noise, low for voiced speech. Zero counts as positive.
+pub trait ZeroCrossingRate<S> {
+    /// Rate over the whole slice, or 0 for fewer than two samples.
+    fn zero_crossing_rate(&self) -> f64;
+
+    /// Rate of each full frame of `frame_len` samples, one every `hop` samples.
+    fn zero_crossing_rates(&self, frame_len: usize, hop: usize) -> ZeroCrossingRates<'_, S>;
+}
+
+impl<S: Sample> ZeroCrossingRate<S> for [S] {
+    fn zero_crossing_rate(&self) -> f64 {
+        if self.len() < 2 {
+            return 0.;
+        }
+        let crossings = self
+            .windows(2)
+            .filter(|w| (w[0] >= S::equilibrium()) != (w[1] >= S::equilibrium()))
+            .count();
+        crossings as f64 / (self.len() - 1) as f64
+    }
+
+    fn zero_crossing_rates(&self, frame_len: usize, hop: usize) -> ZeroCrossingRates<'_, S> {
+        ZeroCrossingRates {
+            signal: self,
+            frame_len,
+            hop: hop.max(1),
+            position: 0,
+        }
+    }
+}
+
+/// Iterator over the zero-crossing rates of the frames of a signal. See
+/// `ZeroCrossingRate::zero_crossing_rates`.
+#[derive(Clone, Debug)]
+pub struct ZeroCrossingRates<'a, S: 'a> {
+    signal: &'a [S],
+    frame_len: usize,
+    hop: usize,
+    position: usize,
+}
+
+impl<'a, S: Sample> Iterator for ZeroCrossingRates<'a, S> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let end = self.position + self.frame_len;
+        if self.frame_len == 0 || end > self.signal.len() {
+            return None;
+        }
+        let rate = self.signal[self.position..end].zero_crossing_rate();
+        self.position += self.hop;
+        Some(rate)
+    }
+}
+
 pub trait Amplitude<S> {
     fn amplitude(self) -> S;
 }
@@ -40,11 +107,13 @@ pub trait MaxAmplitude<S> {
     fn max_amplitude(&self) -> S;
 }
 
-/// Returns the maximum peak amplitude in a given slice of samples
+/// Returns the maximum peak amplitude in a given slice of samples, or equilibrium if the slice is
+/// empty
 impl<S: Sample> MaxAmplitude<S> for [S] {
     fn max_amplitude(&self) -> S {
-        assert!(!self.is_empty());
-        if self.len() == 1 {
+        if self.is_empty() {
+            S::equilibrium()
+        } else if self.len() == 1 {
             self[0].amplitude()
         } else {
             self[1..].iter().fold(self[0].amplitude(), |acc, elem| {
@@ -58,23 +127,86 @@ impl<S: Sample> MaxAmplitude<S> for [S] {
     }
 }
 
+/// Level to scale a signal to, relative to full scale at 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    /// Peak at full scale, as `normalize`
+    Peak,
+    /// Peak at a level in dBFS, below zero for headroom
+    PeakDbfs(Db),
+    /// RMS at a level in dB relative to an RMS of 1.0, on which a full-scale sinusoid is at
+    /// -3 dB
+    Rms(Db),
+}
+
 pub trait Normalize<S> {
     fn normalize_with_max(&mut self, max: Option<S>);
     fn normalize(&mut self) {
         self.normalize_with_max(None);
     }
+    /// Scales to the level of `mode`, and returns the gain applied. Silence is left as it is,
+    /// with a gain of one.
+    fn normalize_to(&mut self, mode: Normalization) -> f64;
+    /// Scales each frame of `frame_len` samples, the last of which may be shorter, to the level
+    /// of `mode` on its own. Returns the gain applied to each frame.
+    fn normalize_frames(&mut self, mode: Normalization, frame_len: usize)
+        -> VoxBoxResult<Vec<f64>>;
 }
 
+/// A slice whose maximum is zero is left as it is.
 impl<S: Sample> Normalize<S> for [S] {
     fn normalize_with_max(&mut self, max: Option<S>) {
-        let scale_factor: <S as Sample>::Float = <S as Sample>::identity()
-            / max
-                .unwrap_or_else(|| self.max_amplitude())
-                .to_float_sample();
+        let max = max.unwrap_or_else(|| self.max_amplitude());
+        if max == S::equilibrium() {
+            return;
+        }
+        let scale_factor: <S as Sample>::Float = <S as Sample>::identity() / max.to_float_sample();
         for elem in self.iter_mut() {
             *elem = elem.mul_amp(scale_factor);
         }
     }
+
+    fn normalize_to(&mut self, mode: Normalization) -> f64 {
+        let level = match mode {
+            Normalization::Peak | Normalization::PeakDbfs(_) => {
+                self.max_amplitude().to_float_sample().to_sample::<f64>()
+            }
+            Normalization::Rms(_) => {
+                let energy = self.iter().fold(0., |acc, s| {
+                    let s = s.to_float_sample().to_sample::<f64>();
+                    acc + s * s
+                });
+                (energy / self.len().max(1) as f64).sqrt()
+            }
+        };
+        let target = match mode {
+            Normalization::Peak => 1.,
+            Normalization::PeakDbfs(db) | Normalization::Rms(db) => db.to_amplitude(),
+        };
+        if level <= 0. {
+            return 1.;
+        }
+        let gain = target / level;
+        let scale_factor = gain.to_sample::<S::Float>();
+        for elem in self.iter_mut() {
+            *elem = elem.mul_amp(scale_factor);
+        }
+        gain
+    }
+
+    fn normalize_frames(
+        &mut self,
+        mode: Normalization,
+        frame_len: usize,
+    ) -> VoxBoxResult<Vec<f64>> {
+        if frame_len == 0 {
+            return Err(VoxBoxError::Config("Frame length must be nonzero"));
+        }
+        Ok(self
+            .chunks_mut(frame_len)
+            .map(|frame| frame.normalize_to(mode))
+            .collect())
+    }
 }
 
 /// Filter
@@ -82,11 +214,40 @@ impl<S: Sample> Normalize<S> for [S] {
 /// Preemphasis should give a 6db/oct boost above a particular center frequency
 /// Factor is center `frequency / sample_rate`
 pub trait Filter {
+    /// Mixes in the following sample scaled by `2 pi factor`. Kept for older callers; prefer
+    /// `preemphasis_coeff`, whose coefficient is the usual one.
     fn preemphasis(&mut self, factor: f64) -> &mut Self;
+    /// `y[n] = x[n] - coefficient * x[n - 1]`, in place. The first sample is kept as it is.
+    fn preemphasis_coeff(&mut self, coefficient: f64) -> &mut Self;
+    /// Pre-emphasis that boosts by 6 dB per octave from about `frequency`, as in Praat.
+    fn preemphasis_from(&mut self, frequency: f64, sample_rate: f64) -> &mut Self;
+    /// `y[n] = x[n] + coefficient * y[n - 1]`, in place, which undoes `preemphasis_coeff`.
+    fn deemphasis(&mut self, coefficient: f64) -> &mut Self;
+    /// Filters by `B(z) / A(z)` forwards and then backwards, in place, which squares the
+    /// magnitude response and cancels its phase, so peaks and edges stay where they were.
+    ///
+    /// The denominator leaves out the leading one, as in LPC, and is empty for a FIR filter.
+    /// Both ends are extended by odd reflection, and the filter starts from the steady state
+    /// for the first sample, so a constant signal comes through unchanged.
+    fn filtfilt(&mut self, numerator: &[f64], denominator: &[f64]) -> VoxBoxResult<&mut Self>;
+    /// Subtracts the mean, in place. For a stream, or a DC offset that drifts, see
+    /// `processor::DcBlocker`.
+    fn remove_dc(&mut self) -> &mut Self;
 }
 
-impl<S: Sample + FromSample<f64>> Filter for [S] {
+/// The pre-emphasis coefficient, `exp(-2 pi frequency / sample_rate)`, that boosts by 6 dB per
+/// octave from about `frequency`: 0.97 is roughly 80 Hz at 16 kHz.
+pub fn preemphasis_coefficient<F: Into<Hz>, R: Into<Hz>>(frequency: F, sample_rate: R) -> f64 {
+    let frequency = frequency.into().0;
+    let sample_rate = sample_rate.into().0;
+    (-2. * PI * frequency / sample_rate).exp()
+}
+
+impl<S: Sample + FromSample<f64> + ToSample<f64>> Filter for [S] {
     fn preemphasis<'a>(&'a mut self, factor: f64) -> &'a mut [S] {
+        if self.is_empty() {
+            return self;
+        }
         let mut last = self[self.len() - 1];
         let filter = 2.0 * PI * factor;
         for x in self.iter_mut().rev().skip(1) {
@@ -98,18 +259,477 @@ impl<S: Sample + FromSample<f64>> Filter for [S] {
         }
         self
     }
+
+    fn preemphasis_coeff(&mut self, coefficient: f64) -> &mut [S] {
+        let mut last = match self.first() {
+            Some(s) => s.to_sample::<f64>(),
+            None => return self,
+        };
+        for x in self.iter_mut().skip(1) {
+            let current = x.to_sample::<f64>();
+            *x = (current - coefficient * last).to_sample::<S>();
+            last = current;
+        }
+        self
+    }
+
+    fn preemphasis_from(&mut self, frequency: f64, sample_rate: f64) -> &mut [S] {
+        self.preemphasis_coeff(preemphasis_coefficient(frequency, sample_rate))
+    }
+
+    fn deemphasis(&mut self, coefficient: f64) -> &mut [S] {
+        // Carry the output unrounded, so that integer samples don't accumulate rounding error
+        let mut last = 0.;
+        for x in self.iter_mut() {
+            last = x.to_sample::<f64>() + coefficient * last;
+            *x = last.to_sample::<S>();
+        }
+        self
+    }
+
+    fn filtfilt(&mut self, numerator: &[f64], denominator: &[f64]) -> VoxBoxResult<&mut [S]> {
+        validate::non_empty(numerator)?;
+        validate::finite(numerator)?;
+        validate::finite(denominator)?;
+        let signal = to_f64_samples(self);
+        validate::finite(&signal)?;
+        if signal.is_empty() {
+            return Ok(self);
+        }
+
+        let n_state = (numerator.len() - 1).max(denominator.len());
+        let pad = (3 * (n_state + 1)).min(signal.len() - 1);
+        let (first, last) = (signal[0], signal[signal.len() - 1]);
+        let mut extended: Vec<f64> = signal[1..=pad]
+            .iter()
+            .rev()
+            .map(|x| 2. * first - x)
+            .collect();
+        extended.extend_from_slice(&signal);
+        extended.extend(
+            signal[signal.len() - 1 - pad..signal.len() - 1]
+                .iter()
+                .rev()
+                .map(|x| 2. * last - x),
+        );
+
+        let steady = steady_state(numerator, denominator, n_state);
+        let mut forward = filter_from(numerator, denominator, &steady, &extended);
+        forward.reverse();
+        let mut backward = filter_from(numerator, denominator, &steady, &forward);
+        backward.reverse();
+        for (x, y) in self.iter_mut().zip(backward[pad..].iter()) {
+            *x = y.to_sample::<S>();
+        }
+        Ok(self)
+    }
+
+    fn remove_dc(&mut self) -> &mut [S] {
+        if self.is_empty() {
+            return self;
+        }
+        let mean = self.iter().map(|x| x.to_sample::<f64>()).sum::<f64>() / self.len() as f64;
+        for x in self.iter_mut() {
+            *x = (x.to_sample::<f64>() - mean).to_sample::<S>();
+        }
+        self
+    }
+}
+
+/// State of a transposed direct-form II filter that has settled on a constant input of one, or
+/// zeros if the filter has a pole at DC.
+fn steady_state(numerator: &[f64], denominator: &[f64], n_state: usize) -> Vec<f64> {
+    let mut state = vec![0.; n_state];
+    let poles = 1. + denominator.iter().sum::<f64>();
+    if poles.abs() < 1e-12 {
+        return state;
+    }
+    let gain = numerator.iter().sum::<f64>() / poles;
+    let mut next = 0.;
+    for i in (0..n_state).rev() {
+        let b = numerator.get(i + 1).cloned().unwrap_or(0.);
+        let a = denominator.get(i).cloned().unwrap_or(0.);
+        next += b - a * gain;
+        state[i] = next;
+    }
+    state
+}
+
+/// Filters `input` by `B(z) / A(z)` in transposed direct form II, starting from `steady` scaled
+/// by the first sample.
+fn filter_from(numerator: &[f64], denominator: &[f64], steady: &[f64], input: &[f64]) -> Vec<f64> {
+    let start = input.first().cloned().unwrap_or(0.);
+    let mut state: Vec<f64> = steady.iter().map(|z| z * start).collect();
+    let n_state = state.len();
+    input
+        .iter()
+        .map(|x| {
+            let y = numerator[0] * x + state.first().cloned().unwrap_or(0.);
+            for i in 0..n_state {
+                let b = numerator.get(i + 1).cloned().unwrap_or(0.);
+                let a = denominator.get(i).cloned().unwrap_or(0.);
+                let carried = state.get(i + 1).cloned().unwrap_or(0.);
+                state[i] = b * x - a * y + carried;
+            }
+            y
+        })
+        .collect()
+}
+
+/// Window shapes for framing a signal, whose coefficients come from `at` or `window`. All
+/// windows but `Gaussian` are periodic, so that they overlap-add evenly at hops that divide
+/// their length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowType {
+    Rectangular,
+    Hanning,
+    Hamming,
+    Blackman,
+    /// Four-term Blackman-Harris, with sidelobes below -92 dB
+    BlackmanHarris,
+    /// Nuttall's four-term window with a continuous first derivative, sidelobes below -93 dB
+    Nuttall,
+    /// Kaiser window with shape `beta`: 0 is rectangular, and larger values trade a wider main
+    /// lobe for lower sidelobes. About 8.6 matches Blackman.
+    Kaiser(f64),
+    /// Praat's Gaussian window for formant analysis: symmetric, and shifted and scaled so that
+    /// it just reaches zero past either end
+    Gaussian,
+    /// Flat in the middle with cosine tapers covering `alpha` of the window, between 0 for
+    /// rectangular and 1 for Hanning
+    Tukey(f64),
+    /// Flat-top window, SciPy's five-term one, whose flat peak reads the amplitude of a
+    /// sinusoid off its nearest bin within 0.01 dB
+    FlatTop,
+}
+
+impl WindowType {
+    /// Value of the window at index `i` of `len`.
+    pub fn at(&self, i: usize, len: usize) -> f64 {
+        let phase = 2.0 * PI * i as f64 / len as f64;
+        match *self {
+            WindowType::Rectangular => 1.0,
+            WindowType::Hanning => 0.5 - 0.5 * phase.cos(),
+            WindowType::Hamming => 0.54 - 0.46 * phase.cos(),
+            WindowType::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+            WindowType::BlackmanHarris => cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], phase),
+            WindowType::Nuttall => cosine_sum(&[0.355768, 0.487396, 0.144232, 0.012604], phase),
+            WindowType::Kaiser(beta) => {
+                let x = 2. * i as f64 / len as f64 - 1.;
+                bessel_i0(beta * (1. - x * x).max(0.).sqrt()) / bessel_i0(beta)
+            }
+            WindowType::Gaussian => {
+                let x = (i as f64 - (len as f64 - 1.) / 2.) / (len as f64 + 1.);
+                let edge = (-12.0f64).exp();
+                ((-48. * x * x).exp() - edge) / (1. - edge)
+            }
+            WindowType::Tukey(alpha) => {
+                let alpha = alpha.clamp(0., 1.);
+                // Distance into the window from the nearer end, as a fraction of its length
+                let x = (i as f64 / len as f64).min(1. - i as f64 / len as f64);
+                if x < alpha / 2. {
+                    0.5 - 0.5 * (2. * PI * x / alpha).cos()
+                } else {
+                    1.
+                }
+            }
+            WindowType::FlatTop => cosine_sum(
+                &[
+                    0.21557895,
+                    0.41663158,
+                    0.277263158,
+                    0.083578947,
+                    0.006947368,
+                ],
+                phase,
+            ),
+        }
+    }
+
+    /// The whole window, `len` samples long.
+    pub fn window(&self, len: usize) -> Vec<f64> {
+        (0..len).map(|i| self.at(i, len)).collect()
+    }
+
+    /// Mean of the window over `len` samples, the factor it scales the peak of a sinusoid on a
+    /// bin by: 1 for `Rectangular`, 0.5 for `Hanning`.
+    pub fn coherent_gain(&self, len: usize) -> f64 {
+        self.window(len).iter().sum::<f64>() / len as f64
+    }
+
+    /// Equivalent noise bandwidth in bins: the width of the rectangular filter that passes as
+    /// much white noise as one bin does. 1 for `Rectangular`, 1.5 for `Hanning`.
+    pub fn enbw(&self, len: usize) -> f64 {
+        let window = self.window(len);
+        let sum = window.iter().sum::<f64>();
+        len as f64 * window.iter().map(|w| w * w).sum::<f64>() / (sum * sum)
+    }
+
+    /// How much lower a sinusoid halfway between two bins reads than one on a bin, with a
+    /// transform as long as the window: 3.92 dB for `Rectangular`, 1.42 dB for `Hanning`.
+    pub fn scallop_loss(&self, len: usize) -> Db {
+        let window = self.window(len);
+        let (re, im) = window
+            .iter()
+            .enumerate()
+            .fold((0., 0.), |(re, im), (n, w)| {
+                let phase = PI * n as f64 / len as f64;
+                (re + w * phase.cos(), im - w * phase.sin())
+            });
+        let half_bin = (re * re + im * im).sqrt();
+        Db(-20. * (half_bin / window.iter().sum::<f64>()).log10())
+    }
+}
+
+/// `a0 - a1 cos(phase) + a2 cos(2 phase) - ...`, the form of the Blackman family.
+fn cosine_sum(coeffs: &[f64], phase: f64) -> f64 {
+    coeffs
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1. } else { -1. };
+            sign * a * (k as f64 * phase).cos()
+        })
+        .sum()
+}
+
+/// Modified Bessel function of the first kind and order zero, from its power series.
+pub(crate) fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let mut k = 1.;
+    while term > sum * 1e-16 {
+        term *= (x / (2. * k)).powi(2);
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+/// Overlapping frames of `len` samples every `hop` samples, read from an iterator as they are
+/// needed, as `f64` samples between -1.0 and 1.0.
+///
+/// Frame `k` starts at sample `k * hop`, and frames continue as long as they start inside the
+/// signal, zero-padded past its end: a signal of `n` samples gives `n / hop` frames, rounded
+/// up, as with `AnalysisConfig::n_frames`.
+///
+/// ```
+/// use vox_box::waves::{Frames, WindowType};
+///
+/// let signal = [1.0f64; 10];
+/// let frames: Vec<Vec<f64>> = Frames::from_slice(&signal, 4, 3).unwrap().collect();
+/// assert_eq!(frames.len(), 4);
+/// assert_eq!(frames[3], vec![1., 0., 0., 0.]);
+///
+/// let hanning = Frames::from_slice(&signal, 4, 3).unwrap().window(WindowType::Hanning);
+/// assert_eq!(hanning.last().unwrap(), vec![0., 0., 0., 0.]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Frames<I> {
+    source: I,
+    len: usize,
+    hop: usize,
+    window: Option<Vec<f64>>,
+    /// Samples from the start of the next frame on
+    buffer: VecDeque<f64>,
+    /// Samples to drop from the source before the next frame starts, when the hop is longer
+    /// than the frame
+    skip: usize,
+}
+
+impl<I> Frames<I>
+where
+    I: Iterator,
+    I::Item: Sample + ToSample<f64>,
+{
+    pub fn new<J: IntoIterator<IntoIter = I>>(
+        source: J,
+        len: usize,
+        hop: usize,
+    ) -> VoxBoxResult<Self> {
+        if len == 0 || hop == 0 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        Ok(Frames {
+            source: source.into_iter(),
+            len,
+            hop,
+            window: None,
+            buffer: VecDeque::with_capacity(len),
+            skip: 0,
+        })
+    }
+
+    /// Multiplies each frame by `window`.
+    pub fn window(mut self, window: WindowType) -> Self {
+        self.window = Some(window.window(self.len));
+        self
+    }
+}
+
+impl<'a, S> Frames<Copied<slice::Iter<'a, S>>>
+where
+    S: Sample + ToSample<f64>,
+{
+    pub fn from_slice(signal: &'a [S], len: usize, hop: usize) -> VoxBoxResult<Self> {
+        Frames::new(signal.iter().copied(), len, hop)
+    }
+}
+
+impl<I> Iterator for Frames<I>
+where
+    I: Iterator,
+    I::Item: Sample + ToSample<f64>,
+{
+    type Item = Vec<f64>;
+
+    fn next(&mut self) -> Option<Vec<f64>> {
+        while self.skip > 0 {
+            self.source.next()?;
+            self.skip -= 1;
+        }
+        while self.buffer.len() < self.len {
+            match self.source.next() {
+                Some(s) => self.buffer.push_back(s.to_sample::<f64>()),
+                None => break,
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let mut frame: Vec<f64> = self.buffer.iter().cloned().collect();
+        frame.resize(self.len, 0.);
+        if let Some(ref window) = self.window {
+            for (s, w) in frame.iter_mut().zip(window.iter()) {
+                *s *= w;
+            }
+        }
+        let dropped = self.hop.min(self.buffer.len());
+        self.buffer.drain(..dropped);
+        self.skip = self.hop - dropped;
+        Some(frame)
+    }
+}
+
+/// Smallest summed window weight `OverlapAdd` divides by. Samples with less are left at zero.
+const OVERLAP_ADD_FLOOR: f64 = 1.0e-10;
+
+/// Reassembles a signal from frames of `len` samples every `hop` samples, such as those from
+/// `Frames` after processing.
+///
+/// Each frame is multiplied by the synthesis window, if any, and added in at its start. Every
+/// sample is then divided by the sum of the analysis times synthesis windows over the frames
+/// covering it, so that unprocessed frames give back the signal they were taken from.
+///
+/// ```
+/// use vox_box::waves::{Frames, OverlapAdd, WindowType};
+///
+/// let signal: Vec<f64> = (0..100).map(|n| (n as f64 * 0.1).sin()).collect();
+/// let mut overlap_add = OverlapAdd::new(32, 8).unwrap().window(WindowType::Hamming);
+/// for frame in Frames::from_slice(&signal, 32, 8).unwrap().window(WindowType::Hamming) {
+///     overlap_add.push(&frame).unwrap();
+/// }
+/// let output = overlap_add.finish();
+/// assert!((output[50] - signal[50]).abs() < 1e-12);
+/// ```
+#[derive(Clone, Debug)]
+pub struct OverlapAdd {
+    len: usize,
+    hop: usize,
+    analysis: Vec<f64>,
+    synthesis: Vec<f64>,
+    /// Summed frames and window weights, from the first sample not yet returned on
+    sum: VecDeque<f64>,
+    weight: VecDeque<f64>,
+    /// Start of the next frame, relative to the first sample not yet returned
+    next_start: usize,
+}
+
+impl OverlapAdd {
+    /// Reassembles frames taken without a window, added without one.
+    pub fn new(len: usize, hop: usize) -> VoxBoxResult<Self> {
+        if len == 0 || hop == 0 {
+            return Err(VoxBoxError::Config("Frame length and hop must be nonzero"));
+        }
+        Ok(OverlapAdd {
+            len,
+            hop,
+            analysis: vec![1.; len],
+            synthesis: vec![1.; len],
+            sum: VecDeque::new(),
+            weight: VecDeque::new(),
+            next_start: 0,
+        })
+    }
+
+    /// The window the frames were taken with.
+    pub fn window(mut self, window: WindowType) -> Self {
+        self.analysis = window.window(self.len);
+        self
+    }
+
+    /// A window to taper each frame with before adding it, which smooths over the joins
+    /// between processed frames.
+    pub fn synthesis_window(mut self, window: WindowType) -> Self {
+        self.synthesis = window.window(self.len);
+        self
+    }
+
+    /// Adds the next frame, which must be `len` samples long.
+    pub fn push(&mut self, frame: &[f64]) -> VoxBoxResult<()> {
+        if frame.len() != self.len {
+            return Err(VoxBoxError::Input("Frame must be the overlap-add length"));
+        }
+        let end = self.next_start + self.len;
+        if self.sum.len() < end {
+            self.sum.resize(end, 0.);
+            self.weight.resize(end, 0.);
+        }
+        let windows = self.analysis.iter().zip(self.synthesis.iter());
+        for (i, (s, (a, w))) in frame.iter().zip(windows).enumerate() {
+            self.sum[self.next_start + i] += s * w;
+            self.weight[self.next_start + i] += a * w;
+        }
+        self.next_start += self.hop;
+        Ok(())
+    }
+
+    /// The samples before the start of the next frame, which no later frame adds to. Each is
+    /// returned once.
+    pub fn ready(&mut self) -> Vec<f64> {
+        let n = self.next_start.min(self.sum.len());
+        self.next_start -= n;
+        self.drain(n)
+    }
+
+    /// Every sample left, up to the end of the last frame.
+    pub fn finish(mut self) -> Vec<f64> {
+        let n = self.sum.len();
+        self.drain(n)
+    }
+
+    fn drain(&mut self, n: usize) -> Vec<f64> {
+        self.sum
+            .drain(..n)
+            .zip(self.weight.drain(..n))
+            .map(|(s, w)| if w > OVERLAP_ADD_FLOOR { s / w } else { 0. })
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     extern crate sample;
 
+    #[cfg(feature = "pitch")]
     use super::super::periodic::*;
-    use super::super::*;
     use super::*;
 
     use sample::conv::ToSampleSlice;
+    #[cfg(feature = "pitch")]
     use sample::window::Window;
+    use sample::Signal;
 
     fn sine(len: usize) -> Vec<f64> {
         let rate = sample::signal::rate(len as f64).const_hz(1.0);
@@ -127,6 +747,94 @@ mod tests {
     }
 
     #[test]
+    fn test_normalize_to() {
+        let quiet: Vec<f64> = (0..400)
+            .map(|n| 0.1 * (2. * PI * n as f64 / 40.).sin())
+            .collect();
+        let mut peak = quiet.clone();
+        let gain = peak.normalize_to(Normalization::PeakDbfs(Db(-6.)));
+        assert!((peak.max_amplitude() - Db(-6.).to_amplitude()).abs() < 1e-12);
+        assert!((gain * quiet.max_amplitude() - peak.max_amplitude()).abs() < 1e-12);
+
+        // A sinusoid at full scale has an RMS 3 dB down
+        let mut rms = quiet.clone();
+        rms.normalize_to(Normalization::Rms(Db(-3.0103)));
+        assert!((rms.max_amplitude() - 1.).abs() < 1e-3);
+        assert_eq!([0f64; 4].normalize_to(Normalization::Peak), 1.);
+
+        // Frames are leveled on their own, and integer samples scale too
+        let mut frames: Vec<i16> = vec![100, -200, 1000, 4000, 0, 0];
+        let gains = frames
+            .normalize_frames(Normalization::PeakDbfs(Db::from_amplitude(0.5)), 2)
+            .unwrap();
+        assert_eq!(gains.len(), 3);
+        assert_eq!(gains[2], 1.);
+        assert_eq!(&frames[..4], &[8192, -16384, 4096, 16384]);
+        assert!(frames.normalize_frames(Normalization::Peak, 0).is_err());
+    }
+
+    #[test]
+    fn test_preemphasis_coeff() {
+        let signal: Vec<f64> = (0..64).map(|n| (n as f64 * 0.3).sin()).collect();
+        let mut filtered = signal.clone();
+        filtered.preemphasis_coeff(0.97);
+        assert_eq!(filtered[0], signal[0]);
+        assert!((filtered[5] - (signal[5] - 0.97 * signal[4])).abs() < 1e-12);
+        filtered.deemphasis(0.97);
+        for (a, b) in filtered.iter().zip(signal.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+
+        assert!((preemphasis_coefficient(80., 16000.) - 0.969).abs() < 0.001);
+        let mut from = signal.clone();
+        from.preemphasis_from(80., 16000.);
+        let mut coeff = signal.clone();
+        coeff.preemphasis_coeff(preemphasis_coefficient(80., 16000.));
+        assert_eq!(from, coeff);
+    }
+
+    #[test]
+    fn test_remove_dc() {
+        let mut signal: Vec<f64> = (0..100).map(|n| 0.3 + (n as f64 * 0.7).sin()).collect();
+        let mean = signal.iter().sum::<f64>() / 100.;
+        let expected: Vec<f64> = signal.iter().map(|x| x - mean).collect();
+        signal.remove_dc();
+        assert_eq!(signal, expected);
+        let mut offset: Vec<i16> = vec![110, 90, 130, 70];
+        assert_eq!(offset.remove_dc(), &[10, -10, 30, -30]);
+    }
+
+    #[test]
+    fn test_filtfilt() {
+        // A one-pole low-pass scales a sinusoid by its squared gain without shifting it
+        let (p, w) = (0.5, 2. * PI / 50.);
+        let signal: Vec<f64> = (0..300).map(|n| (w * n as f64).sin()).collect();
+        let mut filtered = signal.clone();
+        filtered.filtfilt(&[1. - p], &[-p]).unwrap();
+        let gain = (1. - p) * (1. - p) / (1. - 2. * p * w.cos() + p * p);
+        for (n, (y, x)) in filtered.iter().zip(signal.iter()).enumerate() {
+            // The ends settle within a few time constants
+            let tolerance = if !(40..260).contains(&n) { 1e-2 } else { 1e-9 };
+            assert!((y - gain * x).abs() < tolerance);
+        }
+
+        // Constants pass unchanged, and a step is smoothed symmetrically about its edge
+        let mut constant = [3f64; 20];
+        constant.filtfilt(&[0.1], &[-0.9]).unwrap();
+        assert!(constant.iter().all(|c| (c - 3.).abs() < 1e-9));
+        let mut step: Vec<f64> = (0..40).map(|n| if n < 20 { 0. } else { 1. }).collect();
+        step.filtfilt(&[0.2; 5], &[]).unwrap();
+        println!("step: {:?}", &step[14..26]);
+        for k in 0..20 {
+            assert!((step[19 - k] + step[20 + k] - 1.).abs() < 1e-12);
+        }
+
+        assert!(signal.clone().filtfilt(&[], &[]).is_err());
+        assert!([0f64; 0].filtfilt(&[1.], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "pitch")]
     fn test_window_autocorr() {
         let lag_window: Window<[f64; 1], HanningLag> = Window::new(16);
         let data: Vec<[f64; 1]> = lag_window.take(16).collect();
@@ -151,4 +859,184 @@ mod tests {
         println!("rms is {:?}", rms);
         assert!((rms - 0.707).abs() < 0.001);
     }
+
+    #[test]
+    fn test_zero_crossing_rate() {
+        let sine = sine(64);
+        println!("zcr is {:?}", sine.zero_crossing_rate());
+        // One cycle crosses zero twice, but the first sample is already zero
+        assert!((sine.zero_crossing_rate() - 1. / 63.).abs() < 1e-12);
+        let alternating = [1i16, -1, 1, -1, 1];
+        assert_eq!(alternating.zero_crossing_rate(), 1.);
+        assert_eq!([0.5f32].zero_crossing_rate(), 0.);
+
+        let mut signal = vec![0.5f64; 8];
+        signal.extend_from_slice(&alternating.iter().map(|s| *s as f64).collect::<Vec<_>>());
+        let rates: Vec<f64> = signal.zero_crossing_rates(5, 3).collect();
+        assert_eq!(rates, vec![0., 0., 0.5]);
+        assert_eq!(signal.zero_crossing_rates(20, 4).count(), 0);
+    }
+
+    #[test]
+    fn test_to_f64_samples() {
+        assert_eq!(to_f64_samples(&[0i16, 16384, -32768]), vec![0.0, 0.5, -1.0]);
+        assert_eq!(to_f64_samples(&[32768u16]), vec![0.0]);
+        assert_eq!(to_f64_samples(&[0.25f32]), vec![0.25]);
+    }
+
+    #[test]
+    fn test_window_types() {
+        let hanning = WindowType::Hanning.window(8);
+        assert_eq!(hanning[0], 0.0);
+        assert!((hanning[4] - 1.0).abs() < 1e-12);
+        // Periodic windows are symmetric about the middle sample
+        for i in 1..4 {
+            assert!((hanning[i] - hanning[8 - i]).abs() < 1e-12);
+        }
+        assert!((WindowType::Hamming.at(0, 8) - 0.08).abs() < 1e-12);
+        assert!(WindowType::Blackman.at(0, 8).abs() < 1e-12);
+        assert_eq!(WindowType::Rectangular.window(3), vec![1.0; 3]);
+        let gaussian = WindowType::Gaussian.window(9);
+        assert!((gaussian[4] - 1.0).abs() < 1e-12);
+        assert!((gaussian[0] - gaussian[8]).abs() < 1e-12);
+        assert!(gaussian[0] > 0. && gaussian[0] < 0.01);
+
+        for window in [
+            WindowType::BlackmanHarris,
+            WindowType::Nuttall,
+            WindowType::Kaiser(8.6),
+            WindowType::Tukey(0.5),
+            WindowType::FlatTop,
+        ]
+        .iter()
+        {
+            let coeffs = window.window(16);
+            println!("{:?}: {:?}", window, coeffs);
+            assert!((coeffs[8] - 1.).abs() < 1e-6);
+            for i in 1..8 {
+                assert!((coeffs[i] - coeffs[16 - i]).abs() < 1e-12);
+            }
+        }
+        assert!(WindowType::BlackmanHarris.at(0, 16) < 1e-4);
+        assert!(WindowType::Nuttall.at(0, 16).abs() < 1e-12);
+        // Kaiser at beta 0 is rectangular, and its edge falls as beta grows
+        assert_eq!(WindowType::Kaiser(0.).window(4), vec![1.0; 4]);
+        assert!(WindowType::Kaiser(8.6).at(0, 16) < WindowType::Kaiser(4.).at(0, 16));
+        assert!((WindowType::Kaiser(8.6).at(0, 16) - 1. / bessel_i0(8.6)).abs() < 1e-12);
+        // Tukey runs from rectangular to Hanning
+        assert_eq!(WindowType::Tukey(0.).window(8), vec![1.0; 8]);
+        for (t, h) in WindowType::Tukey(1.)
+            .window(8)
+            .iter()
+            .zip(WindowType::Hanning.window(8).iter())
+        {
+            assert!((t - h).abs() < 1e-12);
+        }
+        let tukey = WindowType::Tukey(0.5).window(16);
+        assert!(tukey[4..=12].iter().all(|w| *w == 1.));
+        // The flat top dips below zero
+        assert!(WindowType::FlatTop.window(64).iter().any(|w| *w < 0.));
+    }
+
+    #[test]
+    fn test_frames() {
+        let signal: Vec<i16> = (0..10).map(|n| n * 3276).collect();
+        let frames: Vec<Vec<f64>> = Frames::from_slice(&signal, 4, 2).unwrap().collect();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[1], to_f64_samples(&signal[2..6]));
+        assert_eq!(&frames[4][..2], &to_f64_samples(&signal[8..])[..]);
+        assert_eq!(&frames[4][2..], &[0., 0.]);
+
+        // Hops longer than the frame skip samples, from any iterator
+        let frames: Vec<Vec<f64>> = Frames::new((0..10).map(|n| n as f64), 2, 3)
+            .unwrap()
+            .collect();
+        assert_eq!(
+            frames,
+            vec![vec![0., 1.], vec![3., 4.], vec![6., 7.], vec![9., 0.]]
+        );
+
+        // Windowed frames match AnalysisConfig's
+        let signal: Vec<f64> = (0..100).map(|n| (n as f64 * 0.3).sin()).collect();
+        let config = crate::config::AnalysisConfig::new(1000.)
+            .frame_len(30)
+            .hop(20)
+            .window(WindowType::Hamming);
+        let frames: Vec<Vec<f64>> = Frames::from_slice(&signal, 30, 20)
+            .unwrap()
+            .window(WindowType::Hamming)
+            .collect();
+        assert_eq!(frames.len(), config.n_frames(signal.len()));
+        for (k, frame) in frames.iter().enumerate() {
+            assert_eq!(frame, &config.frame(&signal, k));
+        }
+
+        assert_eq!(Frames::from_slice(&[0f64; 0], 4, 2).unwrap().count(), 0);
+        assert!(Frames::from_slice(&signal, 4, 0).is_err());
+    }
+
+    #[test]
+    fn test_overlap_add() {
+        let signal: Vec<f64> = (0..500)
+            .map(|n| (n as f64 * 0.05).sin() + 0.3 * (n as f64 * 0.7).cos())
+            .collect();
+        let frames = || {
+            Frames::from_slice(&signal, 64, 16)
+                .unwrap()
+                .window(WindowType::Hanning)
+        };
+
+        // Unprocessed frames come back as the signal, streamed or all at once
+        let mut streamed = OverlapAdd::new(64, 16)
+            .unwrap()
+            .window(WindowType::Hanning)
+            .synthesis_window(WindowType::Hanning);
+        let mut whole = streamed.clone();
+        let mut output = Vec::new();
+        for frame in frames() {
+            streamed.push(&frame).unwrap();
+            whole.push(&frame).unwrap();
+            output.extend(streamed.ready());
+        }
+        output.extend(streamed.finish());
+        assert_eq!(output, whole.finish());
+        assert_eq!(output.len(), 31 * 16 + 64);
+        // The first sample is where the Hanning window is zero
+        assert_eq!(output[0], 0.);
+        for (a, b) in output[1..500].iter().zip(signal[1..].iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        // Scaling every frame scales the output
+        let mut overlap_add = OverlapAdd::new(64, 16).unwrap().window(WindowType::Hanning);
+        for frame in frames() {
+            let halved: Vec<f64> = frame.iter().map(|s| 0.5 * s).collect();
+            overlap_add.push(&halved).unwrap();
+        }
+        let output = overlap_add.finish();
+        assert!((output[250] - 0.5 * signal[250]).abs() < 1e-9);
+        assert!(OverlapAdd::new(64, 16).unwrap().push(&[0.; 8]).is_err());
+    }
+
+    #[test]
+    fn test_window_metadata() {
+        let cases = [
+            (WindowType::Rectangular, 1., 1., 3.92),
+            (WindowType::Hanning, 0.5, 1.5, 1.42),
+            (WindowType::Hamming, 0.54, 1.36, 1.75),
+            (WindowType::BlackmanHarris, 0.36, 2.00, 0.83),
+            (WindowType::FlatTop, 0.22, 3.77, 0.01),
+        ];
+        for &(window, gain, enbw, loss) in cases.iter() {
+            let measured = (
+                window.coherent_gain(1024),
+                window.enbw(1024),
+                window.scallop_loss(1024).0,
+            );
+            println!("{:?}: {:?}", window, measured);
+            assert!((measured.0 - gain).abs() < 0.01);
+            assert!((measured.1 - enbw).abs() < 0.01);
+            assert!((measured.2 - loss).abs() < 0.01);
+        }
+    }
 }
@@ -28,8 +28,7 @@ fn test_against_praat() {
     let _segment: Vec<f64> = samples[start..end].iter().map(|s| s - mean).collect();
 
     let sample_rate = reader.spec().sample_rate as f64;
-    let resample_ratio = 1.0;
-    let resampled_len = (resample_ratio * samples.len() as f64).ceil() as usize;
+    let resampled_len = samples.len();
     let mut resampled_buf = vec![0f64; resampled_len];
     let mut work = vec![0f64; vox_box::find_formants_real_work_size(resampled_len, n_coeffs)];
     let mut complex_work =
@@ -42,7 +41,7 @@ fn test_against_praat() {
     vox_box::find_formants(
         &mut samples[..],
         sample_rate,
-        resample_ratio,
+        None,
         &mut resampled_buf[..],
         n_coeffs,
         &mut work[..],
@@ -64,12 +63,10 @@ fn test_formant_calculation() {
 
     let n_coeffs = 10;
 
-    let resample_ratio = 1.0;
-
     let bin = 1024;
     let hop = 512;
     let sample_rate = reader.spec().sample_rate as f64;
-    let resampled_len = (samples.len() as f64 * resample_ratio).ceil() as usize;
+    let resampled_len = samples.len();
     let mut formants: Vec<Resonance<f64>> = vox_box::MALE_FORMANT_ESTIMATES
         .iter()
         .map(|f| Resonance::new(*f, 1.0))
@@ -92,7 +89,7 @@ fn test_formant_calculation() {
         vox_box::find_formants(
             &mut frame_buffer[..],
             sample_rate,
-            resample_ratio,
+            None,
             &mut resampled_buf[..],
             n_coeffs,
             &mut work[..],
@@ -112,3 +109,57 @@ fn test_formant_calculation() {
         // assert!((frame[0].frequency - 662.0).abs() < 10.0);
     }
 }
+
+#[test]
+fn test_find_formants_validation() {
+    let n_coeffs = 10;
+    let mut buf: Vec<f64> = (0..256).map(|i| (i as f64 * 0.3).sin()).collect();
+    let mut resampled_buf = vec![0f64; 256];
+    let mut work = vec![0f64; vox_box::find_formants_real_work_size(256, n_coeffs)];
+    let mut complex_work =
+        vec![Complex::new(0f64, 0.); vox_box::find_formants_complex_work_size(n_coeffs)];
+    let mut formants: Vec<Resonance<f64>> = vox_box::MALE_FORMANT_ESTIMATES
+        .iter()
+        .map(|f| Resonance::new(*f, 1.0))
+        .collect();
+
+    // Too little complex workspace
+    assert!(vox_box::find_formants(
+        &mut buf[..],
+        8000.,
+        None,
+        &mut resampled_buf[..],
+        n_coeffs,
+        &mut work[..],
+        &mut complex_work[..10],
+        &mut formants[..],
+    )
+    .is_err());
+
+    // Non-finite samples
+    buf[10] = f64::NAN;
+    assert!(vox_box::find_formants(
+        &mut buf[..],
+        8000.,
+        None,
+        &mut resampled_buf[..],
+        n_coeffs,
+        &mut work[..],
+        &mut complex_work[..],
+        &mut formants[..],
+    )
+    .is_err());
+
+    // Empty frame
+    assert!(vox_box::find_formants(
+        &mut [],
+        8000.,
+        None,
+        &mut resampled_buf[..],
+        n_coeffs,
+        &mut work[..],
+        &mut complex_work[..],
+        &mut formants[..],
+    )
+    .is_err());
+}